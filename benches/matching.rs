@@ -0,0 +1,71 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tempdir::TempDir;
+
+use inference_store::bench::{populate, synthetic_request, DATATYPES, HASH_ALGORITHMS};
+use inference_store::parsing::input::{MatchConfig, ProcessedInput};
+
+const TENSOR_SIZE: usize = 64 * 1024;
+
+fn hashing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hashing");
+    group.throughput(Throughput::Bytes(TENSOR_SIZE as u64));
+
+    for datatype in DATATYPES {
+        let request = synthetic_request(datatype, TENSOR_SIZE, 0);
+        for hash_algorithm in HASH_ALGORITHMS {
+            let config = MatchConfig {
+                hash_algorithm,
+                ..Default::default()
+            };
+            group.bench_function(
+                BenchmarkId::new(datatype, format!("{hash_algorithm:?}")),
+                |b| b.iter(|| ProcessedInput::from_infer_request(request.clone(), &config)),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn lookup(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("lookup");
+    let config = MatchConfig::default();
+
+    for store_size in [100, 1000, 10000] {
+        let dir = TempDir::new("inference_store_bench").unwrap();
+        let store = runtime
+            .block_on(populate(dir.path(), store_size, 64))
+            .unwrap();
+        let input = ProcessedInput::from_infer_request(
+            synthetic_request("UINT8", 64, store_size as u64 / 2),
+            &config,
+        );
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(store_size),
+            &input,
+            |b, input| b.iter(|| runtime.block_on(store.find(input, &config))),
+        );
+    }
+
+    group.finish();
+}
+
+fn load(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let dir = TempDir::new("inference_store_bench").unwrap();
+    runtime.block_on(populate(dir.path(), 1000, 64)).unwrap();
+
+    c.bench_function("load/1000", |b| {
+        b.iter(|| {
+            let store = inference_store::caching::cachestore::CacheStore::<
+                inference_store::caching::cachable_modelinfer::CachableModelInfer,
+            >::new(dir.path().to_path_buf());
+            runtime.block_on(store.load()).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, hashing, lookup, load);
+criterion_main!(benches);
@@ -1,14 +1,48 @@
+use std::env;
+
+// The environment variable that points to another directory with the Triton protocol, e.g. a
+// vendored checkout of a newer Triton release, instead of the `common` submodule.
+const PROTO_DIR_VAR: &str = "INFERENCE_STORE_PROTO_DIR";
+
+// The newer Triton protocol that is vendored in this repository, used with the `vendored-proto`
+// feature.
+const VENDORED_PROTO_DIR: &str = "proto/triton";
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-env-changed={PROTO_DIR_VAR}");
+    let proto_dir = match env::var(PROTO_DIR_VAR) {
+        Ok(proto_dir) => proto_dir,
+        Err(_) if env::var_os("CARGO_FEATURE_VENDORED_PROTO").is_some() => {
+            VENDORED_PROTO_DIR.to_string()
+        }
+        Err(_) => "common/protobuf".to_string(),
+    };
+
     tonic_build::configure()
         .type_attribute(
             ".inference",
             "#[derive(serde::Serialize, serde::Deserialize)]",
         )
         .type_attribute(".inference", "#[serde(rename_all = \"camelCase\")]")
+        // Recorded messages lack the fields that were added in newer protocol versions, those get
+        // their default value. Unknown fields of newer versions are ignored.
+        .message_attribute(".inference", "#[serde(default)]")
+        // Cached output tensors are served from memory-mapped entries without copying them.
+        .bytes([".inference.ModelInferResponse.raw_output_contents"])
+        // The admin protocol returns recorded inference messages, so it is compiled together
+        // with the inference protocol.
         .compile(
-            &["common/protobuf/grpc_service.proto"],
-            &["common/protobuf"],
+            &[
+                &format!("{proto_dir}/grpc_service.proto"),
+                "proto/admin.proto",
+            ],
+            &[proto_dir.as_str(), "proto"],
         )?;
 
+    tonic_build::compile_protos("proto/replication.proto")?;
+    tonic_build::compile_protos("proto/health.proto")?;
+    tonic_build::compile_protos("proto/remote_cache.proto")?;
+    tonic_build::compile_protos("proto/error_details.proto")?;
+
     Ok(())
 }
@@ -0,0 +1,446 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use log::info;
+use tonic::{Request, Response, Status};
+
+use crate::caching::cachable::Cachable;
+use crate::caching::cachable_modelconfig::CachableModelConfig;
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachable_modelmetadata::CachableModelMetadata;
+use crate::caching::cachestore::{CacheStore, Usage};
+use crate::caching::metadata::has_labels;
+use crate::coverage::Coverage;
+use admin_protocol::admin_server::Admin;
+use admin_protocol::{
+    EntryHits, GetCoverageRequest, GetCoverageResponse, GetEntryRequest, GetEntryResponse,
+    GetStoreStatsRequest, GetStoreStatsResponse, InvalidateEntriesRequest,
+    InvalidateEntriesResponse, ListEntriesRequest, ListEntriesResponse, MissedRequest,
+    ModelStoreStats, ResetUsageRequest, ResetUsageResponse,
+};
+
+pub mod admin_protocol {
+    tonic::include_proto!("inferencestore.admin");
+}
+
+/// Lists and invalidates cache entries while the server is running, e.g. to refresh fixtures
+/// after a model is redeployed.
+pub struct AdminService {
+    inference_store: Arc<CacheStore<CachableModelInfer>>,
+    config_store: Arc<CacheStore<CachableModelConfig>>,
+    metadata_store: Arc<CacheStore<CachableModelMetadata>>,
+    coverage: Arc<Coverage>,
+}
+
+impl AdminService {
+    pub fn new(
+        inference_store: Arc<CacheStore<CachableModelInfer>>,
+        config_store: Arc<CacheStore<CachableModelConfig>>,
+        metadata_store: Arc<CacheStore<CachableModelMetadata>>,
+    ) -> Self {
+        Self {
+            inference_store,
+            config_store,
+            metadata_store,
+            coverage: Default::default(),
+        }
+    }
+
+    /// Report the hits and misses counted by the inference service.
+    pub fn with_coverage(self, coverage: Arc<Coverage>) -> Self {
+        Self { coverage, ..self }
+    }
+}
+
+/// Check if a cachable was recorded with all labels. Only infer entries have labels, so other
+/// entries only match when no labels are requested.
+fn has_requested_labels<T: Cachable>(cachable: &T, labels: &HashMap<String, String>) -> bool {
+    labels.is_empty()
+        || cachable
+            .labels()
+            .is_some_and(|cachable_labels| has_labels(cachable_labels, labels))
+}
+
+/// Check if a cachable matches all filters of the invalidation request.
+fn should_invalidate<T: Cachable>(cachable: &T, request: &InvalidateEntriesRequest) -> bool {
+    let path = cachable.path();
+
+    if !request.model_name.is_empty() || !request.model_version.is_empty() {
+        let (model_name, model_version) = match cachable.model() {
+            Some(model) => model,
+            None => return false,
+        };
+
+        if !request.model_name.is_empty() && request.model_name != model_name {
+            return false;
+        }
+        if !request.model_version.is_empty() && request.model_version != model_version {
+            return false;
+        }
+    }
+
+    if request.recorded_before > 0 {
+        let recorded_at = cachable.recorded_or_modified_at();
+
+        if recorded_at.is_none_or(|recorded_at| recorded_at >= request.recorded_before) {
+            return false;
+        }
+    }
+
+    if !request.hash.is_empty() {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        if !file_name.contains(&request.hash) {
+            return false;
+        }
+    }
+
+    has_requested_labels(cachable, &request.labels)
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    async fn list_entries(
+        &self,
+        request: Request<ListEntriesRequest>,
+    ) -> Result<Response<ListEntriesResponse>, Status> {
+        let labels = &request.get_ref().labels;
+        let mut file_names = self
+            .inference_store
+            .file_names_where(|c| has_requested_labels(c, labels))
+            .await;
+        file_names.extend(
+            self.config_store
+                .file_names_where(|c| has_requested_labels(c, labels))
+                .await,
+        );
+        file_names.extend(
+            self.metadata_store
+                .file_names_where(|c| has_requested_labels(c, labels))
+                .await,
+        );
+
+        let hits = self.inference_store.hits();
+        let mut hits: Vec<EntryHits> = file_names
+            .iter()
+            .filter_map(|file_name| {
+                hits.get(file_name).map(|hits| EntryHits {
+                    file_name: file_name.clone(),
+                    count: hits.count,
+                    last_hit: hits.last_hit,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+        Ok(Response::new(ListEntriesResponse { file_names, hits }))
+    }
+
+    async fn invalidate_entries(
+        &self,
+        request: Request<InvalidateEntriesRequest>,
+    ) -> Result<Response<InvalidateEntriesResponse>, Status> {
+        let request = request.into_inner();
+
+        if request == InvalidateEntriesRequest::default() {
+            return Err(Status::invalid_argument(
+                "at least one filter is required to invalidate entries",
+            ));
+        }
+
+        let removed = async {
+            anyhow::Ok(
+                self.inference_store
+                    .prune(|c| should_invalidate(c, &request))
+                    .await?
+                    + self
+                        .config_store
+                        .prune(|c| should_invalidate(c, &request))
+                        .await?
+                    + self
+                        .metadata_store
+                        .prune(|c| should_invalidate(c, &request))
+                        .await?,
+            )
+        }
+        .await
+        .map_err(|err| Status::failed_precondition(err.to_string()))?;
+
+        info!("Invalidated {removed} cache entries");
+
+        Ok(Response::new(InvalidateEntriesResponse {
+            removed: removed as u64,
+        }))
+    }
+
+    async fn get_entry(
+        &self,
+        request: Request<GetEntryRequest>,
+    ) -> Result<Response<GetEntryResponse>, Status> {
+        let hash = request.into_inner().hash;
+        if hash.is_empty() {
+            return Err(Status::invalid_argument(
+                "a hash is required to get an entry",
+            ));
+        }
+
+        let mut entries = self
+            .inference_store
+            .entries_by_file_name(|file_name| file_name.contains(&hash))
+            .await;
+        let (path, input, output) = match entries.len() {
+            0 => return Err(Status::not_found(format!("no infer entry matches {hash}"))),
+            1 => entries.remove(0),
+            n => {
+                return Err(Status::invalid_argument(format!(
+                    "{n} infer entries match {hash}, use a longer hash"
+                )))
+            }
+        };
+
+        let request = input.to_request();
+        let (response, error_code, error_message) = match &output.error {
+            Some(error) => (None, error.code, error.message.clone()),
+            None => (Some(output.to_response(request.clone())), 0, String::new()),
+        };
+
+        Ok(Response::new(GetEntryResponse {
+            file_name: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            has_input_contents: input.raw_input_contents.is_some(),
+            request: Some(request),
+            response,
+            error_code,
+            error_message,
+        }))
+    }
+
+    async fn get_store_stats(
+        &self,
+        _request: Request<GetStoreStatsRequest>,
+    ) -> Result<Response<GetStoreStatsResponse>, Status> {
+        let mut usage: BTreeMap<(String, String), Usage> = BTreeMap::new();
+        for store_usage in [
+            self.inference_store.usage().await,
+            self.config_store.usage().await,
+            self.metadata_store.usage().await,
+        ] {
+            for (model, store_usage) in store_usage {
+                let model_usage = usage.entry(model).or_default();
+                model_usage.entries += store_usage.entries;
+                model_usage.bytes += store_usage.bytes;
+                model_usage.hits += store_usage.hits;
+            }
+        }
+
+        let mut models: Vec<ModelStoreStats> = usage
+            .into_iter()
+            .map(|((model_name, model_version), usage)| ModelStoreStats {
+                model_name,
+                model_version,
+                entries: usage.entries,
+                bytes: usage.bytes,
+                hits: usage.hits,
+            })
+            .collect();
+        models.sort_by_key(|model| Reverse(model.bytes));
+
+        let store_failures = self.inference_store.store_failures()
+            + self.config_store.store_failures()
+            + self.metadata_store.store_failures();
+
+        Ok(Response::new(GetStoreStatsResponse {
+            models,
+            store_failures,
+        }))
+    }
+
+    async fn reset_usage(
+        &self,
+        request: Request<ResetUsageRequest>,
+    ) -> Result<Response<ResetUsageResponse>, Status> {
+        let model_name = &request.get_ref().model_name;
+        let reset = self.inference_store.reset_usage(model_name).await;
+        self.coverage.reset(model_name);
+
+        Ok(Response::new(ResetUsageResponse {
+            reset: reset as u64,
+        }))
+    }
+
+    async fn get_coverage(
+        &self,
+        request: Request<GetCoverageRequest>,
+    ) -> Result<Response<GetCoverageResponse>, Status> {
+        let model_name = &request.get_ref().model_name;
+        let file_names = self
+            .inference_store
+            .file_names_where(|cachable| {
+                model_name.is_empty()
+                    || cachable.model().is_some_and(|(name, _)| name == model_name)
+            })
+            .await;
+        let report = self.coverage.report(file_names, model_name);
+
+        Ok(Response::new(GetCoverageResponse {
+            unused_entries: report.unused_entries,
+            misses: report
+                .misses
+                .into_iter()
+                .map(|miss| MissedRequest {
+                    model_name: miss.model_name,
+                    model_version: miss.model_version,
+                    request_hash: miss.request_hash,
+                    count: miss.count,
+                })
+                .collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::parsing::input::tests::BASE_INFER_INPUT;
+    use crate::parsing::output::tests::BASE_INFER_OUTPUT;
+    use crate::service::inference_protocol::{ModelConfigRequest, ModelConfigResponse};
+
+    fn store_config(dir: &TempDir, name: &str, version: &str) -> CachableModelConfig {
+        let request = ModelConfigRequest {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+
+        *CachableModelConfig::new(
+            dir.path(),
+            request,
+            ModelConfigResponse::default(),
+            Default::default(),
+        )
+        .unwrap()
+        .1
+    }
+
+    #[tokio::test]
+    async fn it_gets_entries() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        let service = AdminService::new(
+            Arc::new(CacheStore::new(dir.clone())),
+            Arc::new(CacheStore::new(dir.clone())),
+            Arc::new(CacheStore::new(dir)),
+        );
+
+        let (path, _) = service
+            .inference_store
+            .store(
+                BASE_INFER_INPUT.clone(),
+                BASE_INFER_OUTPUT.clone(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+
+        let get = |hash: &str| {
+            service.get_entry(Request::new(GetEntryRequest {
+                hash: hash.to_string(),
+            }))
+        };
+        let entry = get(&BASE_INFER_INPUT.request_hash())
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(path.file_name().unwrap().to_string_lossy(), entry.file_name);
+        assert_eq!(Some(BASE_INFER_INPUT.to_request()), entry.request);
+        assert!(!entry.has_input_contents);
+        assert_eq!(
+            BASE_INFER_OUTPUT.outputs[0].name,
+            entry.response.unwrap().outputs[0].name
+        );
+        assert_eq!(tonic::Code::NotFound, get("0000").await.unwrap_err().code());
+        assert_eq!(
+            tonic::Code::InvalidArgument,
+            get("").await.unwrap_err().code()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reports_store_stats() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        let service = AdminService::new(
+            Arc::new(CacheStore::new(dir.clone())),
+            Arc::new(CacheStore::new(dir.clone())),
+            Arc::new(CacheStore::new(dir)),
+        );
+
+        service
+            .inference_store
+            .store(
+                BASE_INFER_INPUT.clone(),
+                BASE_INFER_OUTPUT.clone(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        store_config(&tmp_dir, "other", "1");
+        service.config_store.load().await.unwrap();
+
+        let models = service
+            .get_store_stats(Request::new(GetStoreStatsRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .models;
+
+        assert_eq!(2, models.len());
+        assert_eq!(BASE_INFER_INPUT.model_name, models[0].model_name);
+        assert_eq!(1, models[0].entries);
+        assert!(models[0].bytes > models[1].bytes);
+        assert_eq!("other", models[1].model_name);
+    }
+
+    #[test]
+    fn it_invalidates_by_model() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let config = store_config(&tmp_dir, "test", "1");
+
+        let request = |model_name: &str, model_version: &str| InvalidateEntriesRequest {
+            model_name: model_name.to_string(),
+            model_version: model_version.to_string(),
+            ..Default::default()
+        };
+
+        assert!(should_invalidate(&config, &request("test", "")));
+        assert!(should_invalidate(&config, &request("test", "1")));
+        assert!(!should_invalidate(&config, &request("test", "2")));
+        assert!(!should_invalidate(&config, &request("other", "")));
+    }
+
+    #[test]
+    fn it_invalidates_by_age_and_hash() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let config = store_config(&tmp_dir, "test", "1");
+
+        let recorded_before = |recorded_before| InvalidateEntriesRequest {
+            recorded_before,
+            ..Default::default()
+        };
+        let hash = |hash: &str| InvalidateEntriesRequest {
+            hash: hash.to_string(),
+            ..Default::default()
+        };
+
+        assert!(should_invalidate(&config, &recorded_before(i64::MAX)));
+        assert!(!should_invalidate(&config, &recorded_before(1)));
+        assert!(should_invalidate(&config, &hash("test#1")));
+        assert!(!should_invalidate(&config, &hash("test#2")));
+    }
+}
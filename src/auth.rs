@@ -0,0 +1,92 @@
+use log::warn;
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// The metadata key of the token of a request to an internal service of InferenceStore.
+const AUTHORIZATION_HEADER: &str = "authorization";
+
+/// Create a request that presents the token as `authorization: Bearer <token>` metadata, when a
+/// token is set.
+pub fn with_token<T>(message: T, token: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    if token.is_empty() {
+        return request;
+    }
+
+    match format!("Bearer {token}").parse() {
+        Ok(value) => {
+            request.metadata_mut().insert(AUTHORIZATION_HEADER, value);
+        }
+        Err(_) => warn!("the token is not a valid metadata value, it is not sent"),
+    }
+
+    request
+}
+
+/// The error of a request that does not present the token, see `with_token`. Every request is
+/// accepted when no token is set.
+pub fn token_error(metadata: &MetadataMap, token: &str) -> Option<Status> {
+    if token.is_empty() {
+        return None;
+    }
+
+    let presented = metadata
+        .get(AUTHORIZATION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => None,
+        Some(_) => Some(Status::permission_denied("invalid token")),
+        None => Some(Status::unauthenticated("no token")),
+    }
+}
+
+/// An interceptor that rejects the requests to a service that do not present the token, see
+/// `token_error`.
+#[derive(Clone)]
+pub struct TokenInterceptor {
+    token: String,
+}
+
+impl TokenInterceptor {
+    pub fn new(token: String) -> Self {
+        Self { token }
+    }
+}
+
+impl Interceptor for TokenInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        match token_error(request.metadata(), &self.token) {
+            Some(status) => Err(status),
+            None => Ok(request),
+        }
+    }
+}
+
+/// Compare two byte strings in a time that does not depend on where they differ, so a token can
+/// not be guessed byte by byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_checks_tokens() {
+        let request = with_token((), "secret");
+        assert!(token_error(request.metadata(), "secret").is_none());
+        assert!(token_error(request.metadata(), "other").is_some());
+        assert!(token_error(Request::new(()).metadata(), "secret").is_some());
+
+        // Without a token every request is accepted.
+        assert!(token_error(Request::new(()).metadata(), "").is_none());
+        assert!(with_token((), "").metadata().is_empty());
+
+        let mut interceptor = TokenInterceptor::new("secret".to_string());
+        assert!(interceptor.call(with_token((), "secret")).is_ok());
+        assert!(interceptor.call(Request::new(())).is_err());
+    }
+}
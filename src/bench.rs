@@ -0,0 +1,208 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tempdir::TempDir;
+
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachestore::CacheStore;
+use crate::parsing::input::{HashAlgorithm, MatchConfig, ProcessedInput};
+use crate::parsing::output::{Output, ProcessedOutput};
+use crate::parsing::synthesize::{element_size, Random};
+use crate::parsing::tensor::encode_bytes_elements;
+use crate::service::inference_protocol::model_infer_request::InferInputTensor;
+use crate::service::inference_protocol::ModelInferRequest;
+
+/// The datatypes of which the hashing throughput is measured.
+pub const DATATYPES: [&str; 5] = ["UINT8", "INT64", "FP16", "FP32", "BYTES"];
+
+/// The content hash algorithms of which the hashing throughput is measured.
+pub const HASH_ALGORITHMS: [HashAlgorithm; 3] = [
+    HashAlgorithm::Blake2,
+    HashAlgorithm::Blake3,
+    HashAlgorithm::Xxh3,
+];
+
+// The length of the synthesized BYTES elements.
+const BYTES_ELEMENT_LENGTH: usize = 16;
+
+/// A request to the `bench` model with a single input tensor of about `size` bytes of random
+/// content. Requests with different seeds have different contents.
+pub fn synthetic_request(datatype: &str, size: usize, seed: u64) -> ModelInferRequest {
+    // Shifted, as the generator ignores the lowest bit of the seed.
+    let mut random = Random::from_seed(seed << 1);
+    let (elements, content) = match datatype {
+        "BYTES" => {
+            let elements: Vec<Vec<u8>> = (0..size / (BYTES_ELEMENT_LENGTH + 4))
+                .map(|_| random.content("UINT8", BYTES_ELEMENT_LENGTH))
+                .collect();
+            (elements.len(), encode_bytes_elements(&elements))
+        }
+        datatype => {
+            let elements = size / element_size(datatype);
+            (elements, random.content(datatype, elements))
+        }
+    };
+
+    ModelInferRequest {
+        model_name: "bench".to_string(),
+        model_version: "1".to_string(),
+        inputs: vec![InferInputTensor {
+            name: "INPUT0".to_string(),
+            datatype: datatype.to_string(),
+            shape: vec![elements as i64],
+            ..Default::default()
+        }],
+        raw_input_contents: vec![content],
+        ..Default::default()
+    }
+}
+
+/// An output with a single FP32 tensor of `size` bytes.
+pub fn synthetic_output(size: usize) -> ProcessedOutput {
+    ProcessedOutput {
+        parameters: Default::default(),
+        outputs: vec![Output {
+            parameters: Default::default(),
+            name: "OUTPUT0".to_string(),
+            datatype: "FP32".to_string(),
+            shape: vec![(size / 4) as i64],
+        }],
+        raw_output_contents: vec![Bytes::from(vec![0; size])],
+        error: None,
+        metadata: Default::default(),
+        raw_response: None,
+    }
+}
+
+/// Record `entries` synthetic UINT8 entries with tensors of `size` bytes in the directory, where
+/// the entry with seed `n` is recorded for the request `synthetic_request("UINT8", size, n)`.
+pub async fn populate(
+    dir: &Path,
+    entries: usize,
+    size: usize,
+) -> anyhow::Result<CacheStore<CachableModelInfer>> {
+    let store = CacheStore::new(dir.to_path_buf());
+    let config = MatchConfig::default();
+
+    for seed in 0..entries as u64 {
+        let input =
+            ProcessedInput::from_infer_request(synthetic_request("UINT8", size, seed), &config);
+        store
+            .store(input, synthetic_output(size), Default::default())
+            .await?;
+    }
+
+    Ok(store)
+}
+
+/// Latency statistics of repeated measurements.
+struct Latencies(Vec<Duration>);
+
+impl Latencies {
+    fn percentile(&self, percentile: usize) -> Duration {
+        let mut sorted = self.0.clone();
+        sorted.sort();
+        sorted[(sorted.len() - 1) * percentile / 100]
+    }
+
+    fn mean(&self) -> Duration {
+        self.0.iter().sum::<Duration>() / self.0.len() as u32
+    }
+}
+
+/// Measure the hashing throughput per datatype and algorithm, and the startup load time and lookup
+/// latency for every store size, and print the results.
+pub async fn run(
+    store_sizes: Vec<usize>,
+    tensor_size: usize,
+    iterations: usize,
+) -> anyhow::Result<()> {
+    let iterations = iterations.max(1);
+
+    println!("hashing throughput ({tensor_size} byte tensors)");
+    for datatype in DATATYPES {
+        let request = synthetic_request(datatype, tensor_size, 0);
+        for hash_algorithm in HASH_ALGORITHMS {
+            let config = MatchConfig {
+                hash_algorithm,
+                ..Default::default()
+            };
+
+            let started = Instant::now();
+            for _ in 0..iterations {
+                ProcessedInput::from_infer_request(request.clone(), &config);
+            }
+            let elapsed = started.elapsed().as_secs_f64();
+
+            println!(
+                "  {datatype:<6} {:<7} {:>10.1} MB/s",
+                format!("{hash_algorithm:?}"),
+                (tensor_size * iterations) as f64 / elapsed / 1e6
+            );
+        }
+    }
+
+    println!("store size      load time    lookup mean     p50     p99");
+    for store_size in store_sizes {
+        let dir = TempDir::new("inference_store_bench")?;
+        populate(dir.path(), store_size, tensor_size).await?;
+
+        let store = CacheStore::<CachableModelInfer>::new(dir.path().to_path_buf());
+        let started = Instant::now();
+        store.load().await?;
+        let load_time = started.elapsed();
+
+        let config = MatchConfig::default();
+        let mut random = Random::from_seed(store_size as u64);
+        let mut latencies = Latencies(Vec::with_capacity(iterations));
+        for _ in 0..iterations {
+            let seed = random.next() % store_size.max(1) as u64;
+            let input = ProcessedInput::from_infer_request(
+                synthetic_request("UINT8", tensor_size, seed),
+                &config,
+            );
+
+            let started = Instant::now();
+            let found = store.find(&input, &config).await;
+            latencies.0.push(started.elapsed());
+
+            if store_size > 0 && found.is_none() {
+                anyhow::bail!("entry {seed} was not found");
+            }
+        }
+
+        println!(
+            "{store_size:>10} {:>12.1?} {:>12.1?} {:>7.1?} {:>7.1?}",
+            load_time,
+            latencies.mean(),
+            latencies.percentile(50),
+            latencies.percentile(99),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_finds_populated_entries() {
+        let dir = TempDir::new("inference_store_test").unwrap();
+        let store = populate(dir.path(), 3, 64).await.unwrap();
+        let config = MatchConfig::default();
+
+        let input = ProcessedInput::from_infer_request(synthetic_request("UINT8", 64, 2), &config);
+        let other = ProcessedInput::from_infer_request(synthetic_request("UINT8", 64, 3), &config);
+
+        assert!(store.find(&input, &config).await.is_some());
+        assert!(store.find(&other, &config).await.is_none());
+        // Three BYTES elements of 16 bytes, each with a 4 byte length prefix.
+        assert_eq!(
+            60,
+            synthetic_request("BYTES", 64, 0).raw_input_contents[0].len()
+        );
+    }
+}
@@ -1,4 +1,13 @@
+pub mod backend;
 pub mod cachable;
 pub mod cachable_modelconfig;
 pub mod cachable_modelinfer;
+pub mod cachable_modelmetadata;
 pub mod cachestore;
+pub mod coalesce;
+pub mod flush;
+pub mod lock;
+pub mod metadata;
+pub mod migration;
+pub mod mmap;
+pub mod pack;
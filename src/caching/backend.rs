@@ -0,0 +1,104 @@
+use std::fs;
+use std::io::ErrorKind::NotFound;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::caching::lock;
+
+/// The persistent storage of cache entry files behind a `CacheStore`, e.g. an object store or a
+/// database. The cache directory holds the working copy of the stored files, as the cachables are
+/// read from and memory-mapped from files. Entry files are never modified after they are written,
+/// so backends only need to support creating, reading and removing them.
+#[tonic::async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// The names of all stored files.
+    async fn list(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Read a stored file, or None when it is not stored.
+    async fn read(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Store a file, unless a file with the name is stored already. Returns false when it was.
+    async fn write(&self, name: &str, content: &[u8]) -> anyhow::Result<bool>;
+
+    /// Remove a stored file. Returns false when it was not stored.
+    async fn delete(&self, name: &str) -> anyhow::Result<bool>;
+
+    /// Resolves when the stored files may have changed, so the cache store loads them before its
+    /// next refresh. Backends without change notifications never resolve.
+    async fn watch(&self) -> anyhow::Result<()> {
+        std::future::pending().await
+    }
+}
+
+/// A storage backend that stores the files in a directory, e.g. a network mount shared by
+/// instances that each keep their own cache directory.
+pub struct DirectoryBackend {
+    dir: PathBuf,
+}
+
+impl DirectoryBackend {
+    pub fn new(dir: PathBuf) -> anyhow::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+}
+
+#[tonic::async_trait]
+impl StorageBackend for DirectoryBackend {
+    async fn list(&self) -> anyhow::Result<Vec<String>> {
+        Ok(fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            // Temporary files of writes in progress are hidden.
+            .filter(|name| !name.starts_with('.'))
+            .collect())
+    }
+
+    async fn read(&self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match fs::read(self.dir.join(name)) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn write(&self, name: &str, content: &[u8]) -> anyhow::Result<bool> {
+        match lock::write_new(self.dir.join(name), |writer| Ok(writer.write_all(content)?)) {
+            Err(err) if lock::is_already_exists(&err) => Ok(false),
+            Err(err) => Err(err),
+            Ok(()) => Ok(true),
+        }
+    }
+
+    async fn delete(&self, name: &str) -> anyhow::Result<bool> {
+        match fs::remove_file(self.dir.join(name)) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_stores_files_in_a_directory() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let backend = DirectoryBackend::new(tmp_dir.path().join("backend")).unwrap();
+
+        assert!(backend.write("1.test", b"1").await.unwrap());
+        assert!(!backend.write("1.test", b"2").await.unwrap());
+        assert_eq!(vec!["1.test".to_string()], backend.list().await.unwrap());
+        assert_eq!(Some(b"1".to_vec()), backend.read("1.test").await.unwrap());
+
+        assert!(backend.delete("1.test").await.unwrap());
+        assert!(!backend.delete("1.test").await.unwrap());
+        assert_eq!(None, backend.read("1.test").await.unwrap());
+    }
+}
@@ -1,23 +1,123 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
+use crate::caching::metadata::{unix_millis, EntryMetadata};
+use crate::caching::pack;
+
 pub trait Cachable {
-    type Input;
+    type Input: Clone;
     type Output: Clone;
     type Config;
 
-    fn get_input(&self) -> anyhow::Result<&Self::Input>;
+    /// The cached input, which is read from the file again when the cachable was shrunk.
+    fn get_input(&self) -> anyhow::Result<Cow<'_, Self::Input>>;
 
     fn get_output(&self) -> anyhow::Result<Self::Output>;
 
+    /// The path of the file this cachable is stored in.
+    fn path(&self) -> PathBuf;
+
     fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Box<Self>>;
 
+    /// The paths of the other files this cachable is stored in, which are removed together with
+    /// it.
+    fn referenced_paths(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+
+    /// The content of this cachable as a single file, as it is replicated to other instances and
+    /// written to storage backends. It is loaded with `from_file` after `migrate_file`.
+    fn read_file(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(pack::read(&self.path())?.to_vec())
+    }
+
+    /// The key of the shard this cachable is stored in. Only the shard of the input is searched
+    /// for matches, so cachables in other shards must never match the input.
+    fn shard_key(&self) -> &str {
+        ""
+    }
+
+    /// The key of the shard that contains the matches of the input, see `shard_key`.
+    fn input_shard_key(_input: &Self::Input) -> &str {
+        ""
+    }
+
+    /// Drop the parts of this cachable that can be read from its file again, to reduce the memory
+    /// usage of large caches.
+    fn shrink(&mut self) {}
+
+    /// An estimate of the memory this cachable uses, in bytes.
+    fn memory_size(&self) -> usize {
+        size_of_val(self)
+    }
+
+    /// The name and version of the model the cached request was made to.
+    fn model(&self) -> Option<(&str, &str)> {
+        None
+    }
+
+    /// The time the entry was recorded, in milliseconds since the unix epoch, when known.
+    fn recorded_at(&self) -> Option<i64> {
+        None
+    }
+
+    /// The time the entry was recorded, or else the modification time of its file, in
+    /// milliseconds since the unix epoch.
+    fn recorded_or_modified_at(&self) -> Option<i64> {
+        self.recorded_at()
+            .or_else(|| pack::modified(&self.path()).ok().map(unix_millis))
+    }
+
+    /// The labels the entry was recorded with, see `EntryMetadata::labels`.
+    fn labels(&self) -> Option<&BTreeMap<String, String>> {
+        None
+    }
+
+    /// The name and version of the model the input is requested from, see `model`.
+    fn input_model(_input: &Self::Input) -> Option<(&str, &str)> {
+        None
+    }
+
+    /// Verify that the stored file is intact, e.g. that the hashes in its file name match its
+    /// content.
+    fn verify(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Upgrade the file at the path to the current format version. Returns the path of the
+    /// rewritten file, which has another file name when the name is derived from what was
+    /// migrated, or None when the file has the current format version.
+    fn migrate_file<P: AsRef<Path>>(_path: P) -> anyhow::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
     fn new<P: AsRef<Path>>(
         cache_dir: P,
         input: Self::Input,
         output: Self::Output,
+        metadata: EntryMetadata,
     ) -> anyhow::Result<(PathBuf, Box<Self>)>;
 
     fn matches(&self, input: &Self::Input, config: &Self::Config) -> bool;
 
+    /// Whether this cachable can match the input, checked in memory before `matches` and
+    /// `distance`, which read the cached input from disk when the cachable was shrunk.
+    fn is_candidate(&self, _input: &Self::Input, _config: &Self::Config) -> bool {
+        true
+    }
+
+    /// The rank of this cachable compared to other matching cachables, where the highest rank is
+    /// preferred. Ranks are compared lexicographically.
+    fn rank(&self, _input: &Self::Input, _config: &Self::Config) -> Vec<i64> {
+        vec![]
+    }
+
+    /// The distance between the cached input and the provided input, when approximate matching
+    /// is supported and the inputs are close enough to be considered an approximate match.
+    fn distance(&self, _input: &Self::Input, _config: &Self::Config) -> Option<f64> {
+        None
+    }
+
     fn matches_file_name(file_name: String) -> bool;
 }
@@ -1,33 +1,94 @@
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 use urlencoding::{decode, encode};
 
 use crate::caching::cachable::Cachable;
+use crate::caching::lock;
+use crate::caching::metadata::EntryMetadata;
+use crate::caching::migration::{self, Migration, FORMAT_VERSION};
+use crate::service::inference_protocol::model_config::SchedulingChoice;
 use crate::service::inference_protocol::{ModelConfigRequest, ModelConfigResponse};
 
+/// The migrations of stored model config entries, see `migration::migrate`.
+const MIGRATIONS: &[Migration] = &[
+    // Version 0 entries are a bare model config response.
+    |entry| Ok(migration::wrap_in("output", entry)),
+    // Versions 2 to 5 only changed inference entries.
+    Ok,
+    Ok,
+    Ok,
+    Ok,
+];
+
+/// The models composing an ensemble config, in the order of the ensemble steps and without
+/// duplicates. A step version of -1 requests the latest version. Empty for other models.
+pub fn composing_models(config: &ModelConfigResponse) -> Vec<ModelConfigRequest> {
+    let Some(SchedulingChoice::EnsembleScheduling(ensemble)) = config
+        .config
+        .as_ref()
+        .and_then(|config| config.scheduling_choice.as_ref())
+    else {
+        return vec![];
+    };
+
+    let mut models: Vec<ModelConfigRequest> = vec![];
+    for step in &ensemble.step {
+        let model = ModelConfigRequest {
+            name: step.model_name.clone(),
+            version: match step.model_version {
+                version if version < 0 => "".to_string(),
+                version => version.to_string(),
+            },
+        };
+        if !models.contains(&model) {
+            models.push(model);
+        }
+    }
+
+    models
+}
+
 #[derive(Clone)]
 pub struct CachableModelConfig {
+    path: PathBuf,
     input: ModelConfigRequest,
     output: ModelConfigResponse,
 }
 
+#[derive(Serialize, Deserialize)]
+struct OutputWrapper {
+    #[serde(default)]
+    pub format_version: u64,
+    pub output: ModelConfigResponse,
+}
+
 impl Cachable for CachableModelConfig {
     type Input = ModelConfigRequest;
     type Output = ModelConfigResponse;
     type Config = ();
 
-    fn get_input(&self) -> anyhow::Result<&ModelConfigRequest> {
-        Ok(&self.input)
+    fn get_input(&self) -> anyhow::Result<Cow<'_, ModelConfigRequest>> {
+        Ok(Cow::Borrowed(&self.input))
     }
 
     fn get_output(&self) -> anyhow::Result<ModelConfigResponse> {
         Ok(self.output.clone())
     }
 
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn model(&self) -> Option<(&str, &str)> {
+        Some((&self.input.name, &self.input.version))
+    }
+
     fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Box<Self>> {
-        let file = File::open(&path)?;
-        let model_config_response: ModelConfigResponse = serde_json::from_reader(file)?;
+        let OutputWrapper {
+            output: model_config_response,
+            ..
+        } = migration::read_entry(&path, MIGRATIONS)?;
 
         let file_stem = path.as_ref().file_stem().unwrap().to_str().unwrap();
         let mut parts = file_stem[7..file_stem.len()].split('#');
@@ -38,35 +99,64 @@ impl Cachable for CachableModelConfig {
         };
 
         Ok(Box::new(CachableModelConfig {
+            path: path.as_ref().to_path_buf(),
             input: model_config_request,
             output: model_config_response,
         }))
     }
 
+    fn migrate_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Option<PathBuf>> {
+        let migrated = migration::migrate_file(&path, MIGRATIONS)?;
+
+        Ok(migrated.then(|| path.as_ref().to_path_buf()))
+    }
+
     fn new<P: AsRef<Path>>(
         dir: P,
         input: ModelConfigRequest,
         output: ModelConfigResponse,
+        _metadata: EntryMetadata,
     ) -> anyhow::Result<(PathBuf, Box<Self>)> {
-        let cachable = CachableModelConfig {
-            input: input.clone(),
-            output: output.clone(),
-        };
-        let ModelConfigRequest { name, version } = input;
         let file_name = format!(
             "config-{}#{}.inferstore",
-            encode(name.as_str()),
-            encode(version.as_str())
+            encode(input.name.as_str()),
+            encode(input.version.as_str())
         );
 
         let path = dir.as_ref().join(file_name);
-        let file = File::create_new(path.clone())?;
+        let cachable = CachableModelConfig {
+            path: path.clone(),
+            input,
+            output: output.clone(),
+        };
+        let written = lock::write_new(&path, |writer| {
+            migration::write_json(
+                writer,
+                &OutputWrapper {
+                    format_version: FORMAT_VERSION,
+                    output,
+                },
+            )
+        });
+
+        match written {
+            // Another process recorded the config first, use that one.
+            Err(err) if lock::is_already_exists(&err) => Ok((path.clone(), Self::from_file(path)?)),
+            Err(err) => Err(err),
+            Ok(()) => Ok((path, Box::new(cachable))),
+        }
+    }
 
-        let mut writer = BufWriter::new(file);
-        serde_json::to_writer(&mut writer, &output)?;
-        writer.flush()?;
+    fn shard_key(&self) -> &str {
+        &self.input.name
+    }
 
-        Ok((path, Box::new(cachable)))
+    fn input_shard_key(input: &ModelConfigRequest) -> &str {
+        &input.name
+    }
+
+    fn input_model(input: &ModelConfigRequest) -> Option<(&str, &str)> {
+        Some((&input.name, &input.version))
     }
 
     fn matches(&self, input: &ModelConfigRequest, _config: &()) -> bool {
@@ -86,35 +176,13 @@ mod tests {
     use once_cell::sync::Lazy;
     use tempdir::TempDir;
 
-    use crate::service::inference_protocol::ModelConfig;
+    use crate::service::inference_protocol::model_ensembling::Step;
+    use crate::service::inference_protocol::{ModelConfig, ModelEnsembling};
 
     use super::*;
 
     pub static BASE_CONFIG_OUTPUT: Lazy<ModelConfigResponse> = Lazy::new(|| ModelConfigResponse {
-        config: Some(ModelConfig {
-            name: "".to_string(),
-            platform: "".to_string(),
-            backend: "".to_string(),
-            runtime: "".to_string(),
-            version_policy: None,
-            max_batch_size: 0,
-            input: vec![],
-            output: vec![],
-            batch_input: vec![],
-            batch_output: vec![],
-            optimization: None,
-            instance_group: vec![],
-            default_model_filename: "".to_string(),
-            cc_model_filenames: Default::default(),
-            metric_tags: Default::default(),
-            parameters: Default::default(),
-            model_warmup: vec![],
-            model_operations: None,
-            model_transaction_policy: None,
-            model_repository_agents: None,
-            response_cache: None,
-            scheduling_choice: None,
-        }),
+        config: Some(ModelConfig::default()),
     });
 
     #[test]
@@ -127,9 +195,13 @@ mod tests {
             version: "1".to_string(),
         };
 
-        let (path, cachable) =
-            CachableModelConfig::new(tmp_path.clone(), req.clone(), BASE_CONFIG_OUTPUT.clone())
-                .expect("could not create cachable");
+        let (path, cachable) = CachableModelConfig::new(
+            tmp_path.clone(),
+            req.clone(),
+            BASE_CONFIG_OUTPUT.clone(),
+            Default::default(),
+        )
+        .expect("could not create cachable");
 
         let output = cachable.get_output().expect("could not get output");
         let input = cachable.get_input().expect("could not get input");
@@ -170,6 +242,24 @@ mod tests {
         assert!(tmp_path.clone().join("config-test#1.inferstore").exists());
     }
 
+    #[test]
+    fn it_loads_entries_of_other_protocol_versions() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("config-test#1.inferstore");
+        // Only a field of a newer protocol version besides the name.
+        std::fs::write(
+            &path,
+            r#"{"format_version":1,"output":{"config":{"name":"test","newerField":true}}}"#,
+        )
+        .unwrap();
+
+        let output = CachableModelConfig::from_file(&path)
+            .unwrap()
+            .get_output()
+            .unwrap();
+        assert_eq!("test", output.config.unwrap().name);
+    }
+
     #[test]
     fn it_saves_and_loads_special_characters() {
         let tmp_dir = TempDir::new("inference_store_test").unwrap();
@@ -180,9 +270,13 @@ mod tests {
             version: "_1-".to_string(),
         };
 
-        let (path, cachable) =
-            CachableModelConfig::new(tmp_path.clone(), req.clone(), BASE_CONFIG_OUTPUT.clone())
-                .expect("could not create cachable");
+        let (path, cachable) = CachableModelConfig::new(
+            tmp_path.clone(),
+            req.clone(),
+            BASE_CONFIG_OUTPUT.clone(),
+            Default::default(),
+        )
+        .expect("could not create cachable");
 
         assert_eq!("_test-", cachable.input.name);
         assert_eq!("_1-", cachable.input.version);
@@ -203,13 +297,51 @@ mod tests {
             version: "1".to_string(),
         };
 
-        let (_, cachable) =
-            CachableModelConfig::new(tmp_path, req.clone(), BASE_CONFIG_OUTPUT.clone())
-                .expect("could not create cachable");
+        let (_, cachable) = CachableModelConfig::new(
+            tmp_path,
+            req.clone(),
+            BASE_CONFIG_OUTPUT.clone(),
+            Default::default(),
+        )
+        .expect("could not create cachable");
 
         assert!(cachable.matches(&req, &Default::default()));
     }
 
+    #[test]
+    fn it_lists_composing_models() {
+        assert!(composing_models(&BASE_CONFIG_OUTPUT).is_empty());
+
+        let step = |model_name: &str, model_version: i64| Step {
+            model_name: model_name.to_string(),
+            model_version,
+            ..Default::default()
+        };
+        let mut ensemble = BASE_CONFIG_OUTPUT.clone();
+        ensemble.config.as_mut().unwrap().scheduling_choice =
+            Some(SchedulingChoice::EnsembleScheduling(ModelEnsembling {
+                step: vec![
+                    step("preprocess", -1),
+                    step("classifier", 2),
+                    step("preprocess", -1),
+                ],
+            }));
+
+        assert_eq!(
+            vec![
+                ModelConfigRequest {
+                    name: "preprocess".to_string(),
+                    version: "".to_string()
+                },
+                ModelConfigRequest {
+                    name: "classifier".to_string(),
+                    version: "2".to_string()
+                }
+            ],
+            composing_models(&ensemble)
+        );
+    }
+
     #[test]
     fn it_matches_file_name() {
         assert!(CachableModelConfig::matches_file_name(
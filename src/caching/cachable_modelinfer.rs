@@ -1,54 +1,321 @@
 use crate::caching::cachable::Cachable;
-use crate::parsing::input::{MatchConfig, ProcessedInput};
+use crate::caching::lock;
+use crate::caching::metadata::{unix_millis, EntryMetadata, SESSION_LABEL};
+use crate::caching::migration::{self, Migration, FORMAT_VERSION};
+use crate::caching::pack;
+use crate::parsing::input::{CandidateRanking, MatchConfig, ProcessedInput};
 use crate::parsing::output::ProcessedOutput;
+use blake2::{Blake2b, Digest};
+use bytes::Bytes;
+use digest::consts::U8;
+use log::warn;
+use serde::de::IgnoredAny;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The migrations of stored inference entries, see `migration::migrate`.
+const MIGRATIONS: &[Migration] = &[
+    // Version 0 entries only lack the format version.
+    Ok,
+    // Version 1 entries are single JSON documents, version 2 entries can be framed. Both are
+    // read as they are.
+    Ok,
+    // Version 3 entries store their output in a separate file. Version 2 entries that hold their
+    // output themselves are still read as they are, `migrate_file` moves it to an output file.
+    Ok,
+    // The file names of version 4 entries include their target server and session, see
+    // `input_hash`. Earlier entries keep their file name until `migrate_file` renames them.
+    Ok,
+    // Version 5 entries can store their raw response as the last blob, see `write_with_blobs`.
+    // Earlier entries store it in the header, which is still read.
+    Ok,
+];
+
+type Blake2b64 = Blake2b<U8>;
+
+/// The prefix of the files that hold the outputs of infer entries, see `output_path`.
+const OUTPUT_PREFIX: &str = "output-";
+
+/// Check if the file name is of a file that holds the output of an infer entry. Those are not
+/// entries themselves, they are read through the entry that references them.
+pub fn is_output_file_name(file_name: &str) -> bool {
+    file_name.starts_with(OUTPUT_PREFIX) && file_name.ends_with(".inferstore")
+}
+
+/// The path of the file that holds the output of the infer entry at the path. It has the same
+/// hashes in its name, so it is stored and packed next to the entry.
+fn output_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let hashes = file_name.strip_prefix("infer-").unwrap_or(&file_name);
+
+    path.with_file_name(format!("{OUTPUT_PREFIX}{hashes}"))
+}
+
+/// The hashes of the inputs, requested outputs and metadata of a request, with which the file names
+/// of its entries start. The target server and session an entry is recorded against are mixed into
+/// the metadata hash, so recording the same request against another target server or in another
+/// session stores another entry. Entries without either keep the file names of earlier versions.
+fn input_hash(
+    input: &ProcessedInput,
+    target_name: Option<&str>,
+    labels: &BTreeMap<String, String>,
+) -> Vec<u8> {
+    let mut input_hash = Vec::with_capacity(24);
+    input_hash.extend_from_slice(&input.inputs_hash());
+    input_hash.extend_from_slice(&input.outputs_hash());
+
+    let session = labels.get(SESSION_LABEL);
+    if target_name.is_none() && session.is_none() {
+        input_hash.extend_from_slice(&input.metadata_hash());
+        return input_hash;
+    }
+
+    let mut hasher = Blake2b64::new();
+    Digest::update(&mut hasher, input.metadata_hash());
+    if let Some(target_name) = target_name {
+        Digest::update(&mut hasher, (target_name.len() as u64).to_le_bytes());
+        Digest::update(&mut hasher, target_name.as_bytes());
+    }
+    if let Some(session) = session {
+        Digest::update(&mut hasher, SESSION_LABEL.as_bytes());
+        Digest::update(&mut hasher, (session.len() as u64).to_le_bytes());
+        Digest::update(&mut hasher, session.as_bytes());
+    }
+    input_hash.extend_from_slice(&hasher.finalize());
+
+    input_hash
+}
+
+fn file_name(input_hash: &[u8], output_hash: &[u8]) -> String {
+    format!(
+        "infer-{}#{}#{}#{}.inferstore",
+        hex::encode(&input_hash[0..8]),
+        hex::encode(&input_hash[8..16]),
+        hex::encode(&input_hash[16..24]),
+        hex::encode(output_hash),
+    )
+}
+
+/// The input and output hashes in the file name of an entry, see `file_name`.
+fn file_name_hashes(file_name: &str) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let hashes = match file_name.get(6..73) {
+        Some(hashes) => hex::decode(hashes.replace('#', ""))?,
+        None => anyhow::bail!("invalid cache file name {file_name}"),
+    };
+    if hashes.len() != 32 {
+        anyhow::bail!("invalid cache file name {file_name}");
+    }
+
+    Ok((hashes[..24].to_vec(), hashes[24..].to_vec()))
+}
+
+/// The file names are derived from the content, so another process that wrote the same file first
+/// wrote the same content.
+fn ignore_already_exists(err: anyhow::Error) -> anyhow::Result<()> {
+    match lock::is_already_exists(&err) {
+        true => Ok(()),
+        false => Err(err),
+    }
+}
+
+/// Write an entry that holds an output. The raw output contents are written as blobs after the
+/// header, so large tensors are not base64 encoded, unless entries are written as canonical JSON.
+/// A raw response is written as the last blob, as the target server encoded it.
+fn write_with_blobs<W, T, F>(
+    writer: &mut W,
+    mut output: ProcessedOutput,
+    wrap: F,
+) -> anyhow::Result<()>
+where
+    W: Write,
+    T: Serialize,
+    F: FnOnce(ProcessedOutput, bool) -> T,
+{
+    if migration::is_canonical_json() {
+        return migration::write_canonical(writer, &wrap(output, false));
+    }
+
+    let mut blobs = std::mem::take(&mut output.raw_output_contents);
+    let raw_response = output.raw_response.take();
+    let raw_response_blob = raw_response.is_some();
+    blobs.extend(raw_response);
+    migration::write_framed(writer, &wrap(output, raw_response_blob), &blobs)
+}
+
+/// Move the blobs of a framed entry back into its output, see `write_with_blobs`.
+fn restore_blobs(output: &mut ProcessedOutput, mut blobs: Vec<Bytes>, raw_response_blob: bool) {
+    if raw_response_blob {
+        output.raw_response = blobs.pop();
+    }
+    output.raw_output_contents = blobs;
+}
+
+/// Move the output of an entry that holds it itself to a separate file, see `output_path`.
+/// Returns whether the entry was rewritten.
+fn split_output(path: &Path) -> anyhow::Result<bool> {
+    let InputWrapper { output, .. } = migration::read_entry(path, MIGRATIONS)?;
+    if output.is_none() {
+        return Ok(false);
+    }
+
+    let (entry, blobs) = migration::read_entry_with_blobs(path, MIGRATIONS)?;
+    let InputOutputWrapper {
+        input,
+        mut output,
+        priority,
+        metadata,
+        raw_response_blob,
+        ..
+    } = entry;
+    if let Some(blobs) = blobs {
+        restore_blobs(&mut output, blobs, raw_response_blob);
+    }
+
+    // The output is written before the entry stops holding it, so it is never lost.
+    lock::write_new(output_path(path), |writer| {
+        write_with_blobs(writer, output, |output, raw_response_blob| OutputWrapper {
+            format_version: FORMAT_VERSION,
+            output,
+            raw_response_blob,
+        })
+    })
+    .or_else(ignore_already_exists)?;
+
+    let split_path = path.with_extension("migrating");
+    let mut writer = BufWriter::new(File::create(&split_path)?);
+    migration::write_json(
+        &mut writer,
+        &InputWrapper {
+            format_version: FORMAT_VERSION,
+            input,
+            priority,
+            metadata,
+            output: None,
+        },
+    )?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&split_path, path)?;
+
+    Ok(true)
+}
+
+/// Rename a split entry recorded by an earlier version, of which the file name lacks its target
+/// server and session, see `input_hash`. Returns the new path of the entry when it was renamed.
+fn rename_unmigrated(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let InputWrapper {
+        input, metadata, ..
+    } = migration::read_entry(path, MIGRATIONS)?;
+    let (_, output_hash) =
+        file_name_hashes(&path.file_name().unwrap_or_default().to_string_lossy())?;
+    let renamed_path = path.with_file_name(file_name(
+        &input_hash(&input, metadata.target_name.as_deref(), &metadata.labels),
+        &output_hash,
+    ));
+    if renamed_path == path {
+        return Ok(None);
+    }
+
+    // The output is moved first, so an entry is never loaded without its output.
+    fs::rename(output_path(path), output_path(&renamed_path))?;
+    fs::rename(path, &renamed_path)?;
+
+    Ok(Some(renamed_path))
+}
 
 #[derive(Clone)]
 pub struct CachableModelInfer {
     dir: PathBuf,
-    input: ProcessedInput,
+
+    // The input, or None when it was shrunk and has to be read from the file.
+    input: Option<ProcessedInput>,
+
+    // The model the request was made to, kept to filter candidates without reading their input.
+    model_name: String,
+    model_version: String,
+
+    // The hashes of the inputs, requested outputs and metadata, followed by the output hash.
+    input_hash: Vec<u8>,
     output_hash: Vec<u8>,
+
+    // The explicit priority of this entry, used to rank multiple matching entries.
+    priority: i64,
+
+    // The time this entry was recorded, in milliseconds since the unix epoch.
+    recorded_at: i64,
+
+    // The identity of the target server this entry was recorded against, if known.
+    target_name: Option<String>,
+
+    // The labels this entry was recorded with.
+    labels: BTreeMap<String, String>,
+
+    // Whether the output is stored in a separate file, see `output_path`, so reading the input
+    // does not read the output. Entries recorded by earlier versions hold their output themselves.
+    split_output: bool,
 }
 
 impl CachableModelInfer {
-    fn get_file_name(&self, output_hash: Vec<u8>) -> String {
-        let hash = self.get_hash(output_hash);
-
-        format!(
-            "infer-{}#{}#{}#{}.inferstore",
-            hex::encode(&hash[0..8]),
-            hex::encode(&hash[8..16]),
-            hex::encode(&hash[16..24]),
-            hex::encode(&hash[24..32]),
-        )
+    fn get_file_name(&self, output_hash: &[u8]) -> String {
+        file_name(&self.input_hash, output_hash)
     }
 
-    fn get_hash(&self, output_hash: Vec<u8>) -> Vec<u8> {
-        let mut hash = Vec::with_capacity(32);
+    fn from_input(
+        dir: PathBuf,
+        input: ProcessedInput,
+        output_hash: Vec<u8>,
+        priority: i64,
+        recorded_at: i64,
+        target_name: Option<String>,
+        labels: BTreeMap<String, String>,
+    ) -> Self {
+        let input_hash = input_hash(&input, target_name.as_deref(), &labels);
 
-        hash.extend_from_slice(&self.input.inputs_hash());
-        hash.extend_from_slice(&self.input.outputs_hash());
-        hash.extend_from_slice(&self.input.metadata_hash());
-        hash.extend_from_slice(&output_hash);
+        CachableModelInfer {
+            dir,
+            model_name: input.model_name.clone(),
+            model_version: input.model_version.clone(),
+            input: Some(input),
+            input_hash,
+            output_hash,
+            priority,
+            recorded_at,
+            target_name,
+            labels,
+            split_output: true,
+        }
+    }
 
-        hash
+    // Check the model and target server of a candidate, before its input is read to match it.
+    fn read_input(&self) -> Option<Cow<'_, ProcessedInput>> {
+        self.get_input()
+            .map_err(|err| warn!("could not read input of {}: {err}", self.path().display()))
+            .ok()
     }
 
     fn new<P: AsRef<Path>>(
         path: P,
         input: ProcessedInput,
         output_hash: Vec<u8>,
+        target_name: Option<String>,
+        labels: BTreeMap<String, String>,
     ) -> (PathBuf, Self) {
-        let cachable_model_infer = CachableModelInfer {
-            dir: path.as_ref().to_path_buf(),
+        let cachable_model_infer = CachableModelInfer::from_input(
+            path.as_ref().to_path_buf(),
             input,
-            output_hash: output_hash.clone(),
-        };
+            output_hash.clone(),
+            0,
+            unix_millis(SystemTime::now()),
+            target_name,
+            labels,
+        );
 
-        let file_name = cachable_model_infer.get_file_name(output_hash);
+        let file_name = cachable_model_infer.get_file_name(&output_hash);
 
         (path.as_ref().join(file_name), cachable_model_infer)
     }
@@ -56,18 +323,41 @@ impl CachableModelInfer {
 
 #[derive(Serialize, Deserialize)]
 pub struct InputOutputWrapper {
+    #[serde(default)]
+    pub format_version: u64,
     pub input: ProcessedInput,
     pub output: ProcessedOutput,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default)]
+    pub metadata: EntryMetadata,
+    // Whether the raw response of the output is the last blob of the framed entry.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw_response_blob: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 struct OutputWrapper {
+    #[serde(default)]
+    pub format_version: u64,
     pub output: ProcessedOutput,
+    // Whether the raw response of the output is the last blob of the framed entry.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw_response_blob: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 struct InputWrapper {
+    #[serde(default)]
+    pub format_version: u64,
     pub input: ProcessedInput,
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default)]
+    pub metadata: EntryMetadata,
+    // The output of entries that hold it themselves, it is skipped without being parsed.
+    #[serde(default, skip_serializing)]
+    pub output: Option<IgnoredAny>,
 }
 
 impl Cachable for CachableModelInfer {
@@ -75,50 +365,270 @@ impl Cachable for CachableModelInfer {
     type Output = ProcessedOutput;
     type Config = MatchConfig;
 
-    fn get_input(&self) -> anyhow::Result<&ProcessedInput> {
-        Ok(&self.input)
+    fn get_input(&self) -> anyhow::Result<Cow<'_, ProcessedInput>> {
+        if let Some(input) = &self.input {
+            return Ok(Cow::Borrowed(input));
+        }
+
+        let InputWrapper { input, .. } = migration::read_entry(self.path(), MIGRATIONS)?;
+
+        Ok(Cow::Owned(input))
     }
 
     fn get_output(&self) -> anyhow::Result<ProcessedOutput> {
-        let file_name = self.get_file_name(self.output_hash.clone());
-        let file = File::open(self.dir.join(file_name))?;
-        let OutputWrapper { output } = serde_json::from_reader(file)?;
+        let path = match self.split_output {
+            true => output_path(&self.path()),
+            false => self.path(),
+        };
+        let (
+            OutputWrapper {
+                mut output,
+                raw_response_blob,
+                ..
+            },
+            blobs,
+        ) = migration::read_entry_with_blobs(path, MIGRATIONS)?;
+
+        // The raw output contents of framed entries are stored as blobs after the header.
+        if let Some(blobs) = blobs {
+            restore_blobs(&mut output, blobs, raw_response_blob);
+        }
 
         Ok(output)
     }
 
+    fn path(&self) -> PathBuf {
+        self.dir.join(self.get_file_name(&self.output_hash))
+    }
+
+    fn model(&self) -> Option<(&str, &str)> {
+        Some((&self.model_name, &self.model_version))
+    }
+
     fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Box<Self>> {
-        let file = File::open(&path)?;
-        let InputWrapper { input } = serde_json::from_reader(file)?;
+        let modified_at = unix_millis(pack::modified(path.as_ref())?);
+        let InputWrapper {
+            input,
+            priority,
+            metadata,
+            output,
+            ..
+        } = migration::read_entry(&path, MIGRATIONS)?;
 
-        let output_hash =
-            hex::decode(path.as_ref().file_name().unwrap().to_str().unwrap()[57..73].to_string())
-                .unwrap();
+        let (input_hash, output_hash) = file_name_hashes(
+            &path
+                .as_ref()
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy(),
+        )?;
 
-        Ok(Box::new(CachableModelInfer {
-            dir: path.as_ref().parent().unwrap().to_path_buf(),
+        let mut cachable_model_infer = CachableModelInfer::from_input(
+            path.as_ref().parent().unwrap().to_path_buf(),
             input,
             output_hash,
-        }))
+            priority,
+            metadata.recorded_at.unwrap_or(modified_at),
+            metadata.target_name,
+            metadata.labels,
+        );
+        cachable_model_infer.split_output = output.is_none();
+        // Entries that are not migrated yet can be named without their target server and session.
+        cachable_model_infer.input_hash = input_hash;
+
+        Ok(Box::new(cachable_model_infer))
+    }
+
+    fn referenced_paths(&self) -> Vec<PathBuf> {
+        match self.split_output {
+            true => vec![output_path(&self.path())],
+            false => vec![],
+        }
+    }
+
+    fn read_file(&self) -> anyhow::Result<Vec<u8>> {
+        if !self.split_output {
+            return Ok(pack::read(&self.path())?.to_vec());
+        }
+
+        // Other instances and storage backends get a single file that holds the output as well.
+        let InputWrapper {
+            input,
+            priority,
+            metadata,
+            ..
+        } = migration::read_entry(self.path(), MIGRATIONS)?;
+        let mut content = vec![];
+        write_with_blobs(
+            &mut content,
+            self.get_output()?,
+            |output, raw_response_blob| InputOutputWrapper {
+                format_version: FORMAT_VERSION,
+                input,
+                output,
+                priority,
+                metadata,
+                raw_response_blob,
+            },
+        )?;
+
+        Ok(content)
+    }
+
+    fn shard_key(&self) -> &str {
+        &self.model_name
+    }
+
+    fn input_shard_key(input: &ProcessedInput) -> &str {
+        &input.model_name
+    }
+
+    fn input_model(input: &ProcessedInput) -> Option<(&str, &str)> {
+        Some((&input.model_name, &input.model_version))
+    }
+
+    fn recorded_at(&self) -> Option<i64> {
+        Some(self.recorded_at)
+    }
+
+    fn labels(&self) -> Option<&BTreeMap<String, String>> {
+        Some(&self.labels)
+    }
+
+    fn shrink(&mut self) {
+        self.input = None;
+    }
+
+    fn memory_size(&self) -> usize {
+        size_of::<Self>()
+            + self.model_name.len()
+            + self.model_version.len()
+            + self.input_hash.len()
+            + self.output_hash.len()
+            + self
+                .labels
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum::<usize>()
+            + self.input.as_ref().map_or(0, ProcessedInput::memory_size)
+    }
+
+    fn verify(&self) -> anyhow::Result<()> {
+        let output = self.get_output()?;
+        let input = self.get_input()?;
+        let expected_file_name = file_name(
+            &input_hash(&input, self.target_name.as_deref(), &self.labels),
+            &output.hash(),
+        );
+        // Entries recorded by earlier versions are named without their target server and session
+        // until they are migrated.
+        let unmigrated_file_name =
+            file_name(&input_hash(&input, None, &BTreeMap::new()), &output.hash());
+
+        let path = self.path();
+        let actual_file_name = path.file_name().unwrap().to_string_lossy();
+        if actual_file_name != expected_file_name && actual_file_name != unmigrated_file_name {
+            anyhow::bail!("content does not match the file name, expected {expected_file_name}");
+        }
+
+        Ok(())
+    }
+
+    /// Upgrade the file like other entries, move the output of entries that hold it themselves
+    /// to a separate file, and rename entries of which the file name lacks their target server.
+    fn migrate_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Option<PathBuf>> {
+        let path = path.as_ref();
+        let migrated = migration::migrate_file(path, MIGRATIONS)?;
+        let split = split_output(path)?;
+
+        match rename_unmigrated(path)? {
+            Some(renamed_path) => Ok(Some(renamed_path)),
+            None => Ok((migrated || split).then(|| path.to_path_buf())),
+        }
     }
 
     fn new<P: AsRef<Path>>(
         dir: P,
         input: ProcessedInput,
         output: ProcessedOutput,
+        metadata: EntryMetadata,
     ) -> anyhow::Result<(PathBuf, Box<Self>)> {
-        let (path, cachable_model_infer) =
-            CachableModelInfer::new(dir, input.clone(), output.hash().into());
-        let file = File::create_new(path.clone())?;
-        let mut writer = BufWriter::new(file);
-        serde_json::to_writer(&mut writer, &InputOutputWrapper { input, output })?;
-        writer.flush()?;
+        let (path, cachable_model_infer) = CachableModelInfer::new(
+            dir,
+            input.clone(),
+            output.hash().into(),
+            metadata.target_name.clone(),
+            metadata.labels.clone(),
+        );
+
+        // Canonical entries are written without the metadata that differs every time the same
+        // entry is recorded.
+        let metadata = match migration::is_canonical_json() {
+            true => metadata.without_volatile_fields(),
+            false => metadata,
+        };
+
+        // The output is stored in a separate file, so loading the entries at startup does not
+        // read the outputs. It is written first, so an entry is never loaded without its output.
+        lock::write_new(output_path(&path), |writer| {
+            write_with_blobs(writer, output, |output, raw_response_blob| OutputWrapper {
+                format_version: FORMAT_VERSION,
+                output,
+                raw_response_blob,
+            })
+        })
+        .or_else(ignore_already_exists)
+        .and_then(|()| {
+            lock::write_new(&path, |writer| {
+                migration::write_json(
+                    writer,
+                    &InputWrapper {
+                        format_version: FORMAT_VERSION,
+                        input,
+                        priority: 0,
+                        metadata,
+                        output: None,
+                    },
+                )
+            })
+        })
+        .or_else(ignore_already_exists)?;
 
         Ok((path, Box::new(cachable_model_infer)))
     }
 
     fn matches(&self, input: &ProcessedInput, config: &MatchConfig) -> bool {
-        self.input.matches(input, config.clone())
+        self.is_candidate(input, config)
+            && self
+                .read_input()
+                .is_some_and(|cached| cached.matches(input, config))
+    }
+
+    fn is_candidate(&self, input: &ProcessedInput, config: &MatchConfig) -> bool {
+        self.model_name == input.model_name
+            && (config.target_name.is_none() || config.target_name == self.target_name)
+    }
+
+    fn rank(&self, input: &ProcessedInput, config: &MatchConfig) -> Vec<i64> {
+        config
+            .candidate_ranking
+            .iter()
+            .map(|ranking| match ranking {
+                CandidateRanking::ExactVersion => {
+                    (self.model_version == input.model_version) as i64
+                }
+                CandidateRanking::MostRecent => self.recorded_at,
+                CandidateRanking::Priority => self.priority,
+            })
+            .collect()
+    }
+
+    fn distance(&self, input: &ProcessedInput, config: &MatchConfig) -> Option<f64> {
+        if !self.is_candidate(input, config) {
+            return None;
+        }
+
+        self.read_input()?.embedding_distance(input, config)
     }
 
     fn matches_file_name(file_name: String) -> bool {
@@ -130,9 +640,11 @@ impl Cachable for CachableModelInfer {
 
 #[cfg(test)]
 mod tests {
-    use std::fs::File;
+    use std::fs::{self, File};
     use std::io::{BufWriter, Write};
+    use std::time::Duration;
 
+    use crate::caching::cachestore::CacheStore;
     use crate::parsing::input::tests::BASE_INFER_INPUT;
     use crate::parsing::output::tests::BASE_INFER_OUTPUT;
     use tempdir::TempDir;
@@ -148,6 +660,7 @@ mod tests {
             tmp_path.clone(),
             BASE_INFER_INPUT.clone(),
             BASE_INFER_OUTPUT.clone(),
+            Default::default(),
         )
         .expect("could not create cachable");
 
@@ -160,6 +673,169 @@ mod tests {
         assert!(tmp_path.join("infer-c9b7e475dd69fa72#bf645d11f6b25b6f#192d91107cec4716#111f49954e134b85.inferstore").exists());
     }
 
+    #[tokio::test]
+    async fn it_keeps_distinct_outputs_as_samples() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let store = CacheStore::<CachableModelInfer>::new(tmp_dir.path().to_path_buf());
+        let config = MatchConfig::default();
+
+        let mut other_output = BASE_INFER_OUTPUT.clone();
+        other_output.raw_output_contents = vec![vec![1, 2, 3, 4].into()];
+        for output in [
+            BASE_INFER_OUTPUT.clone(),
+            other_output,
+            BASE_INFER_OUTPUT.clone(),
+        ] {
+            store
+                .store(BASE_INFER_INPUT.clone(), output, Default::default())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(2, store.count(&BASE_INFER_INPUT, &config).await);
+        let entries = std::fs::read_dir(tmp_dir.path())
+            .unwrap()
+            .filter(|entry| {
+                CachableModelInfer::matches_file_name(
+                    entry
+                        .as_ref()
+                        .unwrap()
+                        .file_name()
+                        .to_string_lossy()
+                        .to_string(),
+                )
+            })
+            .count();
+        assert_eq!(2, entries);
+    }
+
+    #[tokio::test]
+    async fn it_partitions_by_target_server() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let store = CacheStore::<CachableModelInfer>::new(tmp_dir.path().to_path_buf());
+        let metadata = EntryMetadata::default().with_target_name("triton-a");
+        store
+            .store(
+                BASE_INFER_INPUT.clone(),
+                BASE_INFER_OUTPUT.clone(),
+                metadata,
+            )
+            .await
+            .unwrap();
+
+        let partitioned = |target_name: &str| MatchConfig {
+            target_name: Some(target_name.to_string()),
+            ..Default::default()
+        };
+        let default_config = MatchConfig::default();
+        assert!(store
+            .find(&BASE_INFER_INPUT, &default_config)
+            .await
+            .is_some());
+        assert!(store
+            .find(&BASE_INFER_INPUT, &partitioned("triton-a"))
+            .await
+            .is_some());
+        assert!(store
+            .find(&BASE_INFER_INPUT, &partitioned("triton-b"))
+            .await
+            .is_none());
+
+        // The same request recorded against another target server is stored as another entry.
+        store
+            .store(
+                BASE_INFER_INPUT.clone(),
+                BASE_INFER_OUTPUT.clone(),
+                EntryMetadata::default().with_target_name("triton-b"),
+            )
+            .await
+            .unwrap();
+        assert!(store
+            .find(&BASE_INFER_INPUT, &partitioned("triton-b"))
+            .await
+            .is_some());
+        assert_eq!(2, store.count(&BASE_INFER_INPUT, &default_config).await);
+
+        // The target server identity is kept when the entry is loaded.
+        let loaded = CacheStore::<CachableModelInfer>::new(tmp_dir.path().to_path_buf());
+        loaded.load().await.unwrap();
+        assert!(loaded
+            .find(&BASE_INFER_INPUT, &partitioned("triton-a"))
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn it_stores_the_entries_of_sessions_apart() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let store = CacheStore::<CachableModelInfer>::new(tmp_dir.path().to_path_buf());
+        for session in ["a", "b"] {
+            let metadata = EntryMetadata {
+                labels: BTreeMap::from([(SESSION_LABEL.to_string(), session.to_string())]),
+                ..Default::default()
+            };
+            store
+                .store(
+                    BASE_INFER_INPUT.clone(),
+                    BASE_INFER_OUTPUT.clone(),
+                    metadata,
+                )
+                .await
+                .unwrap();
+        }
+
+        let session_b = CacheStore::<CachableModelInfer>::new(tmp_dir.path().to_path_buf())
+            .with_required_labels(BTreeMap::from([(
+                SESSION_LABEL.to_string(),
+                "b".to_string(),
+            )]));
+        session_b.load().await.unwrap();
+        assert_eq!(
+            1,
+            session_b
+                .count(&BASE_INFER_INPUT, &MatchConfig::default())
+                .await
+        );
+        assert_eq!(
+            2,
+            store
+                .count(&BASE_INFER_INPUT, &MatchConfig::default())
+                .await
+        );
+    }
+
+    #[test]
+    fn it_renames_entries_named_without_their_target_server() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let (path, _): (PathBuf, Box<CachableModelInfer>) = Cachable::new(
+            tmp_dir.path(),
+            BASE_INFER_INPUT.clone(),
+            BASE_INFER_OUTPUT.clone(),
+            EntryMetadata::default().with_target_name("triton-a"),
+        )
+        .unwrap();
+
+        // Earlier versions named the entry without its target server.
+        let unmigrated_path = tmp_dir.path().join(file_name(
+            &input_hash(&BASE_INFER_INPUT, None, &BTreeMap::new()),
+            &BASE_INFER_OUTPUT.hash(),
+        ));
+        fs::rename(output_path(&path), output_path(&unmigrated_path)).unwrap();
+        fs::rename(&path, &unmigrated_path).unwrap();
+
+        let unmigrated = CachableModelInfer::from_file(&unmigrated_path).unwrap();
+        assert_eq!(unmigrated_path, unmigrated.path());
+        assert!(unmigrated.verify().is_ok());
+
+        assert_eq!(
+            Some(path.clone()),
+            CachableModelInfer::migrate_file(&unmigrated_path).unwrap()
+        );
+        let migrated = CachableModelInfer::from_file(&path).unwrap();
+        assert_eq!(BASE_INFER_OUTPUT.clone(), migrated.get_output().unwrap());
+        assert!(!unmigrated_path.exists());
+    }
+
     #[test]
     fn it_loads() {
         let tmp_dir = TempDir::new("inference_store_test").unwrap();
@@ -174,8 +850,12 @@ mod tests {
         serde_json::to_writer(
             &mut writer,
             &InputOutputWrapper {
+                format_version: FORMAT_VERSION,
                 input: BASE_INFER_INPUT.clone(),
                 output: BASE_INFER_OUTPUT.clone(),
+                priority: 0,
+                metadata: Default::default(),
+                raw_response_blob: false,
             },
         )
         .unwrap();
@@ -193,6 +873,125 @@ mod tests {
         assert!(tmp_path.clone().join("infer-c9b7e475dd69fa72#bf645d11f6b25b6f#192d91107cec4716#111f49954e134b85.inferstore").exists());
     }
 
+    #[test]
+    fn it_stores_the_output_in_a_separate_file() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let (path, cachable): (PathBuf, Box<CachableModelInfer>) = Cachable::new(
+            tmp_dir.path(),
+            BASE_INFER_INPUT.clone(),
+            BASE_INFER_OUTPUT.clone(),
+            Default::default(),
+        )
+        .unwrap();
+
+        let output_file = output_path(&path);
+        assert!(is_output_file_name(
+            &output_file.file_name().unwrap().to_string_lossy()
+        ));
+        assert_eq!(vec![output_file.clone()], cachable.referenced_paths());
+        let InputWrapper { output, .. } = migration::read_entry(&path, MIGRATIONS).unwrap();
+        assert!(output.is_none());
+
+        let loaded = CachableModelInfer::from_file(&path).unwrap();
+        assert_eq!(BASE_INFER_OUTPUT.clone(), loaded.get_output().unwrap());
+        assert!(loaded.verify().is_ok());
+
+        // Other instances get a single file, which is split again when it is migrated.
+        let other_dir = TempDir::new("inference_store_test").unwrap();
+        let other_path = other_dir.path().join(path.file_name().unwrap());
+        fs::write(&other_path, loaded.read_file().unwrap()).unwrap();
+        let combined = CachableModelInfer::from_file(&other_path).unwrap();
+        assert!(combined.referenced_paths().is_empty());
+        assert_eq!(BASE_INFER_OUTPUT.clone(), combined.get_output().unwrap());
+
+        assert_eq!(
+            Some(other_path.clone()),
+            CachableModelInfer::migrate_file(&other_path).unwrap()
+        );
+        assert_eq!(None, CachableModelInfer::migrate_file(&other_path).unwrap());
+        let split = CachableModelInfer::from_file(&other_path).unwrap();
+        assert_eq!(
+            fs::read(&output_file).unwrap(),
+            fs::read(output_path(&other_path)).unwrap()
+        );
+        assert_eq!(BASE_INFER_INPUT.clone(), *split.get_input().unwrap());
+        assert_eq!(BASE_INFER_OUTPUT.clone(), split.get_output().unwrap());
+
+        // The input is read without the output.
+        fs::remove_file(&output_file).unwrap();
+        assert!(loaded.get_output().is_err());
+        assert_eq!(BASE_INFER_INPUT.clone(), *loaded.get_input().unwrap());
+    }
+
+    #[test]
+    fn it_stores_raw_responses_as_blobs() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let mut output = BASE_INFER_OUTPUT.clone();
+        output.raw_response = Some(vec![0xfa, 0x01, 0x01, 0x07].into());
+        let (path, cachable): (PathBuf, Box<CachableModelInfer>) = Cachable::new(
+            tmp_dir.path(),
+            BASE_INFER_INPUT.clone(),
+            output.clone(),
+            Default::default(),
+        )
+        .unwrap();
+
+        let content = fs::read(output_path(&path)).unwrap();
+        assert!(content.ends_with(&[0xfa, 0x01, 0x01, 0x07]));
+        let header = content.split(|byte| *byte == b'\n').next().unwrap();
+        assert!(!String::from_utf8_lossy(header).contains("\"raw_response\""));
+        assert_eq!(output, cachable.get_output().unwrap());
+
+        // The raw response stays a blob in the single file of other instances.
+        let other_dir = TempDir::new("inference_store_test").unwrap();
+        let other_path = other_dir.path().join(path.file_name().unwrap());
+        fs::write(&other_path, cachable.read_file().unwrap()).unwrap();
+        let combined = CachableModelInfer::from_file(&other_path).unwrap();
+        assert_eq!(output, combined.get_output().unwrap());
+        CachableModelInfer::migrate_file(&other_path).unwrap();
+        let split = CachableModelInfer::from_file(&other_path).unwrap();
+        assert_eq!(output, split.get_output().unwrap());
+    }
+
+    #[test]
+    fn it_loads_canonical_entries() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let (path, _): (PathBuf, Box<CachableModelInfer>) = Cachable::new(
+            tmp_dir.path(),
+            BASE_INFER_INPUT.clone(),
+            BASE_INFER_OUTPUT.clone(),
+            Default::default(),
+        )
+        .unwrap();
+
+        // Rewrite the entry as canonical JSON, with the raw output contents inline.
+        let metadata = EntryMetadata::new("localhost:8001", Duration::from_millis(5), None)
+            .without_volatile_fields();
+        let mut content = vec![];
+        migration::write_canonical(
+            &mut content,
+            &InputOutputWrapper {
+                format_version: FORMAT_VERSION,
+                input: BASE_INFER_INPUT.clone(),
+                output: BASE_INFER_OUTPUT.clone(),
+                priority: 0,
+                metadata: metadata.clone(),
+                raw_response_blob: false,
+            },
+        )
+        .unwrap();
+        fs::write(&path, &content).unwrap();
+        assert!(content.ends_with(b"}\n"));
+
+        let cachable = CachableModelInfer::from_file(&path).unwrap();
+        assert_eq!(BASE_INFER_INPUT.clone(), *cachable.get_input().unwrap());
+        assert_eq!(BASE_INFER_OUTPUT.clone(), cachable.get_output().unwrap());
+        assert!(cachable.verify().is_ok());
+        // The recording time is kept, the modification time is lost in version control.
+        assert!(metadata.recorded_at.is_some());
+        assert_eq!(metadata.recorded_at, cachable.recorded_at());
+    }
+
     #[test]
     fn it_matches_input() {
         let tmp_dir = TempDir::new("inference_store_test").unwrap();
@@ -202,12 +1001,106 @@ mod tests {
             tmp_path.clone(),
             BASE_INFER_INPUT.clone(),
             BASE_INFER_OUTPUT.clone(),
+            Default::default(),
         )
         .expect("could not create cachable");
 
         assert!(cachable.matches(&BASE_INFER_INPUT.clone(), &Default::default()));
     }
 
+    #[test]
+    fn it_reads_the_input_when_shrunk() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+
+        let (path, mut cachable): (PathBuf, Box<CachableModelInfer>) = Cachable::new(
+            tmp_path.clone(),
+            BASE_INFER_INPUT.clone(),
+            BASE_INFER_OUTPUT.clone(),
+            Default::default(),
+        )
+        .expect("could not create cachable");
+        cachable.shrink();
+
+        let mut other_model = BASE_INFER_INPUT.clone();
+        other_model.model_name = "other".to_string();
+
+        assert!(cachable.input.is_none());
+        assert_eq!(path, cachable.path());
+        assert_eq!(Some(("test", "1")), cachable.model());
+        assert_eq!(BASE_INFER_INPUT.clone(), *cachable.get_input().unwrap());
+        assert!(cachable.matches(&BASE_INFER_INPUT.clone(), &Default::default()));
+        assert!(!cachable.matches(&other_model, &Default::default()));
+        assert!(cachable.verify().is_ok());
+    }
+
+    #[test]
+    fn it_ranks_candidates() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+
+        let (_, mut cachable): (PathBuf, Box<CachableModelInfer>) = Cachable::new(
+            tmp_path.clone(),
+            BASE_INFER_INPUT.clone(),
+            BASE_INFER_OUTPUT.clone(),
+            Default::default(),
+        )
+        .expect("could not create cachable");
+        cachable.priority = 5;
+
+        let mut other_version = BASE_INFER_INPUT.clone();
+        other_version.model_version = "2".to_string();
+
+        let config = MatchConfig {
+            candidate_ranking: vec![CandidateRanking::ExactVersion, CandidateRanking::Priority],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            vec![1, 5],
+            cachable.rank(&BASE_INFER_INPUT.clone(), &config)
+        );
+        assert_eq!(vec![0, 5], cachable.rank(&other_version, &config));
+        assert!(cachable
+            .rank(&BASE_INFER_INPUT.clone(), &Default::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn it_verifies_the_content() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+
+        let (path, cachable): (PathBuf, Box<CachableModelInfer>) = Cachable::new(
+            tmp_path.clone(),
+            BASE_INFER_INPUT.clone(),
+            BASE_INFER_OUTPUT.clone(),
+            Default::default(),
+        )
+        .expect("could not create cachable");
+
+        assert!(cachable.verify().is_ok());
+
+        let mut tampered_input = BASE_INFER_INPUT.clone();
+        tampered_input.model_name = "tampered".to_string();
+        serde_json::to_writer(
+            File::create(&path).unwrap(),
+            &InputOutputWrapper {
+                format_version: FORMAT_VERSION,
+                input: tampered_input,
+                output: BASE_INFER_OUTPUT.clone(),
+                priority: 0,
+                metadata: Default::default(),
+                raw_response_blob: false,
+            },
+        )
+        .unwrap();
+
+        let cachable = CachableModelInfer::from_file(&path).expect("could not load cachable");
+
+        assert!(cachable.verify().is_err());
+    }
+
     #[test]
     fn it_matches_file_name() {
         assert!(CachableModelInfer::matches_file_name(
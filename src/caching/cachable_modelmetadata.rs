@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use urlencoding::{decode, encode};
+
+use crate::caching::cachable::Cachable;
+use crate::caching::lock;
+use crate::caching::metadata::EntryMetadata;
+use crate::caching::migration::{self, Migration, FORMAT_VERSION};
+use crate::service::inference_protocol::{ModelMetadataRequest, ModelMetadataResponse};
+
+/// The migrations of stored model metadata entries, see `migration::migrate`.
+const MIGRATIONS: &[Migration] = &[
+    // Model metadata entries were introduced in version 1, versions 2 to 5 only changed
+    // inference entries.
+    Ok, Ok, Ok, Ok, Ok,
+];
+
+#[derive(Clone)]
+pub struct CachableModelMetadata {
+    path: PathBuf,
+    input: ModelMetadataRequest,
+    output: ModelMetadataResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OutputWrapper {
+    #[serde(default)]
+    pub format_version: u64,
+    pub output: ModelMetadataResponse,
+}
+
+impl Cachable for CachableModelMetadata {
+    type Input = ModelMetadataRequest;
+    type Output = ModelMetadataResponse;
+    type Config = ();
+
+    fn get_input(&self) -> anyhow::Result<Cow<'_, ModelMetadataRequest>> {
+        Ok(Cow::Borrowed(&self.input))
+    }
+
+    fn get_output(&self) -> anyhow::Result<ModelMetadataResponse> {
+        Ok(self.output.clone())
+    }
+
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn model(&self) -> Option<(&str, &str)> {
+        Some((&self.input.name, &self.input.version))
+    }
+
+    fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Box<Self>> {
+        let OutputWrapper {
+            output: model_metadata_response,
+            ..
+        } = migration::read_entry(&path, MIGRATIONS)?;
+
+        let file_stem = path.as_ref().file_stem().unwrap().to_str().unwrap();
+        let mut parts = file_stem[9..file_stem.len()].split('#');
+
+        let model_metadata_request = ModelMetadataRequest {
+            name: decode(parts.next().unwrap_or_default())?.to_string(),
+            version: decode(parts.next().unwrap_or_default())?.to_string(),
+        };
+
+        Ok(Box::new(CachableModelMetadata {
+            path: path.as_ref().to_path_buf(),
+            input: model_metadata_request,
+            output: model_metadata_response,
+        }))
+    }
+
+    fn migrate_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Option<PathBuf>> {
+        let migrated = migration::migrate_file(&path, MIGRATIONS)?;
+
+        Ok(migrated.then(|| path.as_ref().to_path_buf()))
+    }
+
+    fn new<P: AsRef<Path>>(
+        dir: P,
+        input: ModelMetadataRequest,
+        output: ModelMetadataResponse,
+        _metadata: EntryMetadata,
+    ) -> anyhow::Result<(PathBuf, Box<Self>)> {
+        let file_name = format!(
+            "metadata-{}#{}.inferstore",
+            encode(input.name.as_str()),
+            encode(input.version.as_str())
+        );
+
+        let path = dir.as_ref().join(file_name);
+        let cachable = CachableModelMetadata {
+            path: path.clone(),
+            input,
+            output: output.clone(),
+        };
+
+        let written = lock::write_new(&path, |writer| {
+            migration::write_json(
+                writer,
+                &OutputWrapper {
+                    format_version: FORMAT_VERSION,
+                    output,
+                },
+            )
+        });
+
+        match written {
+            // Another process recorded the metadata first, use that one.
+            Err(err) if lock::is_already_exists(&err) => Ok((path.clone(), Self::from_file(path)?)),
+            Err(err) => Err(err),
+            Ok(()) => Ok((path, Box::new(cachable))),
+        }
+    }
+
+    fn shard_key(&self) -> &str {
+        &self.input.name
+    }
+
+    fn input_shard_key(input: &ModelMetadataRequest) -> &str {
+        &input.name
+    }
+
+    fn input_model(input: &ModelMetadataRequest) -> Option<(&str, &str)> {
+        Some((&input.name, &input.version))
+    }
+
+    fn matches(&self, input: &ModelMetadataRequest, _config: &()) -> bool {
+        self.input.name == input.name && self.input.version == input.version
+    }
+
+    fn matches_file_name(file_name: String) -> bool {
+        file_name.starts_with("metadata-") && file_name.ends_with(".inferstore")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use tempdir::TempDir;
+
+    use crate::service::inference_protocol::model_metadata_response::TensorMetadata;
+
+    use super::*;
+
+    pub static BASE_METADATA_OUTPUT: Lazy<ModelMetadataResponse> =
+        Lazy::new(|| ModelMetadataResponse {
+            name: "test".to_string(),
+            versions: vec!["1".to_string()],
+            platform: "onnxruntime_onnx".to_string(),
+            inputs: vec![TensorMetadata {
+                name: "INPUT0".to_string(),
+                datatype: "FP32".to_string(),
+                shape: vec![-1, 4],
+            }],
+            outputs: vec![],
+        });
+
+    #[test]
+    fn it_creates_and_loads() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+
+        let req = ModelMetadataRequest {
+            name: "_test-".to_string(),
+            version: "1".to_string(),
+        };
+
+        let (path, cachable) = CachableModelMetadata::new(
+            tmp_path.clone(),
+            req.clone(),
+            BASE_METADATA_OUTPUT.clone(),
+            Default::default(),
+        )
+        .expect("could not create cachable");
+
+        assert_eq!(path, tmp_path.join("metadata-_test-#1.inferstore"));
+        assert!(cachable.matches(&req, &()));
+
+        let cachable = CachableModelMetadata::from_file(path).expect("could not load cachable");
+
+        assert_eq!(req, *cachable.get_input().unwrap());
+        assert_eq!(BASE_METADATA_OUTPUT.clone(), cachable.get_output().unwrap());
+    }
+
+    #[test]
+    fn it_matches_file_name() {
+        assert!(CachableModelMetadata::matches_file_name(
+            "metadata-test#1.inferstore".to_string()
+        ));
+        assert!(!CachableModelMetadata::matches_file_name(
+            "config-test#1.inferstore".to_string()
+        ));
+    }
+}
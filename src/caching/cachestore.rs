@@ -1,11 +1,66 @@
 use log::warn;
+use serde::{Deserialize, Serialize};
 use std::any::type_name;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::ops::Deref;
-use std::path::PathBuf;
+use std::io::ErrorKind::NotFound;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 use tokio::sync::RwLock;
 
+use crate::caching::backend::StorageBackend;
 use crate::caching::cachable::Cachable;
+use crate::caching::lock::{self, DirLock};
+use crate::caching::metadata::{has_labels, unix_millis, EntryMetadata, SINGLE_USE_LABEL};
+use crate::caching::pack;
+
+/// The sidecar manifest in the cache directory that lists the file names of pinned entries.
+/// Pinned entries are never removed by pruning.
+pub const PINNED_MANIFEST: &str = "pinned.json";
+
+/// The version directory of the entries of requests without a model version, which are handled by
+/// the latest version of the model.
+pub const LATEST_VERSION_DIR: &str = "latest";
+
+type Shard<T> = RwLock<Vec<Box<T>>>;
+
+/// The cache entry a lookup is answered from.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Source {
+    pub path: PathBuf,
+
+    // The time the entry was recorded, in milliseconds since the unix epoch, when known.
+    pub recorded_at: Option<i64>,
+}
+
+impl Source {
+    fn of<T: Cachable>(cachable: &T) -> Self {
+        Self {
+            path: cachable.path(),
+            recorded_at: cachable.recorded_at(),
+        }
+    }
+}
+
+/// The number of entries of a model, the bytes their files use on disk and the responses they
+/// served.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Usage {
+    pub entries: u64,
+    pub bytes: u64,
+    pub hits: u64,
+}
+
+/// How often and when an entry served a response.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub struct Hits {
+    pub count: u64,
+    // The time of the last hit, in milliseconds since the unix epoch.
+    pub last_hit: i64,
+}
 
 pub struct CacheStore<T>
 where
@@ -14,88 +69,1484 @@ where
     // The path where cache is stored on disk.
     dir: PathBuf,
 
-    // The in-memory store.
-    store: RwLock<Vec<Box<T>>>,
-}
+    // The in-memory store, sharded by the shard key of the cachables so lookups and stores of
+    // different models do not contend.
+    store: RwLock<HashMap<String, Arc<Shard<T>>>>,
+
+    // When true, nothing is written to or removed from disk and every file is verified at load.
+    read_only: bool,
+
+    // When true, the loaded cachables are shrunk and their inputs are read from disk when needed.
+    // Set while loading when the index exceeds its budget.
+    low_memory: AtomicBool,
+
+    // The maximum estimated memory in bytes of the loaded cachables. 0 is unlimited.
+    max_index_bytes: u64,
+
+    // When true, loading fails when the index exceeds its budget, instead of switching to low
+    // memory mode.
+    refuse_over_budget: bool,
+
+    // The estimated memory in bytes of the loaded cachables, shared with the fallbacks as they
+    // share the index budget.
+    index_bytes: Arc<AtomicU64>,
+
+    // The persistent storage of the entries, of which the cache directory holds a working copy.
+    // When None, the cache directory is the storage.
+    backend: Option<Arc<dyn StorageBackend>>,
+
+    // When true, new entries are stored in a directory per model and version,
+    // `<dir>/<model name>/<model version>/`, instead of in the cache directory itself.
+    model_dirs: bool,
+
+    // The models of which the model directory is loaded. Model directories are loaded at the
+    // first lookup of their model, or when all entries are needed.
+    loaded_models: RwLock<HashSet<String>>,
+
+    // The labels entries must be recorded with to be loaded, e.g. to serve one of multiple
+    // fixture sets in a cache directory. Entries without labels, like configs, are always loaded.
+    required_labels: BTreeMap<String, String>,
+
+    // Only the entries recorded before this time, in milliseconds since the unix epoch, are loaded,
+    // e.g. to reproduce the cache used by an older test run. Entries without a recording time,
+    // like configs, are always loaded.
+    recorded_before: Option<i64>,
+
+    // The paths of the entries that are not loaded as they lack the required labels or were
+    // recorded too late, so they are not read again at every refresh.
+    unselected: RwLock<HashSet<PathBuf>>,
+
+    // The models of which the entries are single-use, besides the entries recorded with the
+    // single-use label.
+    single_use_models: HashSet<String>,
+
+    // The consumed single-use entries with the name of their model. They miss until their usage is
+    // reset.
+    consumed: RwLock<HashMap<PathBuf, String>>,
+
+    // The hits per entry file name.
+    hits: Mutex<HashMap<String, Hits>>,
+
+    // The file the hits are persisted to, separate from the entries so these are not rewritten at
+    // every hit. When None, the hits are only kept in memory.
+    usage_file: Option<PathBuf>,
+
+    // The store of the next cache directory in the fallback chain, consulted for the lookups that
+    // miss this store.
+    fallback: Option<Box<CacheStore<T>>>,
+
+    // The number of entries that could not be stored since starting.
+    store_failures: AtomicU64,
+}
+
+/// Check if a model name or version can be used as a directory name in the cache directory.
+fn is_dir_name(name: &str) -> bool {
+    !name.starts_with('.') && Path::new(name).file_name() == Some(name.as_ref())
+}
+
+/// Read entry files on the blocking thread pool, so lookups do not stall the async runtime while
+/// the files are read.
+async fn read_blocking<R, F>(read: F) -> anyhow::Result<R>
+where
+    F: FnOnce() -> anyhow::Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(read).await?
+}
+
+impl<T> CacheStore<T>
+where
+    T: Cachable,
+    T: Clone + Send + Sync + 'static,
+    T::Input: Send + 'static,
+    T::Output: Send,
+    T::Config: Clone + Send + 'static,
+{
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            store: Default::default(),
+            read_only: false,
+            low_memory: AtomicBool::new(false),
+            max_index_bytes: 0,
+            refuse_over_budget: false,
+            index_bytes: Default::default(),
+            backend: None,
+            model_dirs: false,
+            loaded_models: Default::default(),
+            required_labels: BTreeMap::new(),
+            recorded_before: None,
+            unselected: Default::default(),
+            single_use_models: HashSet::new(),
+            consumed: Default::default(),
+            hits: Default::default(),
+            usage_file: None,
+            fallback: None,
+            store_failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Keep only what is needed to find candidates in memory, and read the inputs of the
+    /// candidates from disk while matching.
+    pub fn with_low_memory(self, low_memory: bool) -> Self {
+        Self {
+            low_memory: AtomicBool::new(low_memory),
+            ..self
+        }
+    }
+
+    /// Limit the estimated memory of the loaded entries of this store and its fallbacks, which
+    /// share the budget. When loading exceeds it, the store that is loading switches to low memory
+    /// mode, or loading fails when `refuse` is true. 0 is unlimited.
+    pub fn with_index_budget(self, max_index_bytes: u64, refuse: bool) -> Self {
+        self.with_shared_index_budget(max_index_bytes, refuse, Default::default())
+    }
+
+    fn with_shared_index_budget(
+        self,
+        max_index_bytes: u64,
+        refuse: bool,
+        index_bytes: Arc<AtomicU64>,
+    ) -> Self {
+        let fallback = self.fallback.map(|fallback| {
+            Box::new(fallback.with_shared_index_budget(
+                max_index_bytes,
+                refuse,
+                index_bytes.clone(),
+            ))
+        });
+
+        Self {
+            max_index_bytes,
+            refuse_over_budget: refuse,
+            index_bytes,
+            fallback,
+            ..self
+        }
+    }
+
+    /// The estimated memory in bytes of the loaded entries, of this store and its fallbacks when
+    /// they share the index budget.
+    pub fn index_bytes(&self) -> u64 {
+        self.index_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Stop counting removed cachables against the index budget.
+    fn release(&self, size: u64) {
+        let _ =
+            self.index_bytes
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |index_bytes| {
+                    Some(index_bytes.saturating_sub(size))
+                });
+    }
+
+    pub fn is_low_memory(&self) -> bool {
+        self.low_memory.load(Ordering::Relaxed)
+    }
+
+    /// Store the entries in a storage backend. Stored entries are written to the backend as well,
+    /// and entries in the backend that are not in the cache directory are loaded from it.
+    pub fn with_backend(self, backend: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            backend: Some(backend),
+            ..self
+        }
+    }
+
+    /// Store new entries in a directory per model and version. Entries in model directories are
+    /// loaded either way.
+    pub fn with_model_dirs(self, model_dirs: bool) -> Self {
+        Self { model_dirs, ..self }
+    }
+
+    /// Only load the entries recorded with all labels, see `has_labels`.
+    pub fn with_required_labels(self, required_labels: BTreeMap<String, String>) -> Self {
+        Self {
+            required_labels,
+            ..self
+        }
+    }
+
+    /// Consume the entries of these models at their first hit, so identical requests that follow
+    /// miss, e.g. to assert a request is made exactly once. Entries recorded with the single-use
+    /// label set to `true` are single-use as well.
+    pub fn with_single_use_models(self, single_use_models: Vec<String>) -> Self {
+        Self {
+            single_use_models: single_use_models.into_iter().collect(),
+            ..self
+        }
+    }
+
+    fn is_single_use(&self, cachable: &T) -> bool {
+        cachable
+            .model()
+            .is_some_and(|(name, _)| self.single_use_models.contains(name))
+            || cachable
+                .labels()
+                .and_then(|labels| labels.get(SINGLE_USE_LABEL))
+                .is_some_and(|value| value == "true")
+    }
+
+    /// Consume a single-use entry. Returns false when the entry was consumed already.
+    async fn consume(&self, cachable: &T) -> bool {
+        let model_name = cachable.model().map_or("", |(name, _)| name).to_string();
+        self.consumed
+            .write()
+            .await
+            .insert(cachable.path(), model_name)
+            .is_none()
+    }
+
+    /// Serve the consumed single-use entries of a model again, or of all models when the name is
+    /// empty, in this store and its fallbacks. Returns the number of entries of which the usage
+    /// was reset.
+    pub async fn reset_usage(&self, model_name: &str) -> usize {
+        let reset = {
+            let mut consumed = self.consumed.write().await;
+            let before = consumed.len();
+            consumed.retain(|_, model| !model_name.is_empty() && model != model_name);
+            before - consumed.len()
+        };
+
+        match &self.fallback {
+            Some(fallback) => reset + Box::pin(fallback.reset_usage(model_name)).await,
+            None => reset,
+        }
+    }
+
+    /// Persist the hits of the entries to a file, see `save_usage`. The hits already in the file
+    /// are loaded.
+    pub fn with_usage_file(self, usage_file: PathBuf) -> Self {
+        let hits = match fs::read(&usage_file) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|err| {
+                warn!("ignoring usage file {}: {err}", usage_file.display());
+                HashMap::new()
+            }),
+            Err(err) if err.kind() == NotFound => HashMap::new(),
+            Err(err) => {
+                warn!("could not read usage file {}: {err}", usage_file.display());
+                HashMap::new()
+            }
+        };
+
+        Self {
+            hits: Mutex::new(hits),
+            usage_file: Some(usage_file),
+            ..self
+        }
+    }
+
+    fn record_hit(&self, cachable: &T) {
+        let path = cachable.path();
+        let Some(file_name) = path.file_name() else {
+            return;
+        };
+        let mut hits = self.hits.lock().unwrap();
+        let hits = hits
+            .entry(file_name.to_string_lossy().to_string())
+            .or_default();
+        hits.count += 1;
+        hits.last_hit = unix_millis(SystemTime::now());
+    }
+
+    /// The hits per file name of the entries that served a response, in this store and its
+    /// fallbacks.
+    pub fn hits(&self) -> HashMap<String, Hits> {
+        let mut hits = self.hits.lock().unwrap().clone();
+        if let Some(fallback) = &self.fallback {
+            for (file_name, fallback_hits) in fallback.hits() {
+                let hits = hits.entry(file_name).or_default();
+                hits.count += fallback_hits.count;
+                hits.last_hit = hits.last_hit.max(fallback_hits.last_hit);
+            }
+        }
+
+        hits
+    }
+
+    /// Write the hits to the usage file, when set, on the blocking thread pool. The file is
+    /// replaced atomically.
+    pub async fn save_usage(&self) -> anyhow::Result<()> {
+        let Some(usage_file) = self.usage_file.clone() else {
+            return Ok(());
+        };
+        let contents = serde_json::to_vec(&*self.hits.lock().unwrap())?;
+
+        tokio::task::spawn_blocking(move || {
+            lock::write_replace(usage_file, |writer| Ok(writer.write_all(&contents)?))
+        })
+        .await?
+    }
+
+    pub async fn save_usage_periodically(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately, when nothing was hit yet.
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.save_usage().await {
+                warn!("could not save the usage of the cache entries: {err}");
+            }
+        }
+    }
+
+    /// The file names of the `count` least frequently used entries. Entries with an equal number
+    /// of hits are ordered by their last hit, or by the time they were recorded when never hit.
+    /// The entries of read-only stores in the fallback chain are not included, as they can not be
+    /// removed.
+    pub async fn least_used(&self, count: usize) -> HashSet<String> {
+        let mut entries = self.usage_order().await;
+        entries.sort();
+
+        entries
+            .into_iter()
+            .take(count)
+            .map(|(_, file_name)| file_name)
+            .collect()
+    }
+
+    /// The order of the entries of this store and its writable fallbacks by their usage, see
+    /// `least_used`.
+    async fn usage_order(&self) -> Vec<((u64, i64), String)> {
+        let mut entries = vec![];
+        if !self.read_only {
+            self.load_models().await;
+            let hits = self.hits.lock().unwrap().clone();
+            for shard in self.shards().await {
+                for cachable in shard.read().await.iter() {
+                    let path = cachable.path();
+                    let Some(file_name) = path.file_name() else {
+                        continue;
+                    };
+                    let file_name = file_name.to_string_lossy().to_string();
+                    let order = match hits.get(&file_name) {
+                        Some(hits) => (hits.count, hits.last_hit),
+                        None => (0, cachable.recorded_at().unwrap_or_default()),
+                    };
+                    entries.push((order, file_name));
+                }
+            }
+        }
+        if let Some(fallback) = &self.fallback {
+            entries.extend(Box::pin(fallback.usage_order()).await);
+        }
+
+        entries
+    }
+
+    /// Consult the store of another cache directory for the lookups that miss this store, after
+    /// the fallbacks added before. Entries are only stored in this store. The fallback shares the
+    /// index budget of this store.
+    pub fn with_fallback(self, fallback: CacheStore<T>) -> Self {
+        let fallback = match self.fallback {
+            Some(existing) => existing.with_fallback(fallback),
+            None => fallback.with_shared_index_budget(
+                self.max_index_bytes,
+                self.refuse_over_budget,
+                self.index_bytes.clone(),
+            ),
+        };
+
+        Self {
+            fallback: Some(Box::new(fallback)),
+            ..self
+        }
+    }
+
+    /// Only load the entries recorded before the time, in milliseconds since the unix epoch. None
+    /// loads the entries regardless of their recording time.
+    pub fn with_recorded_before(self, recorded_before: Option<i64>) -> Self {
+        Self {
+            recorded_before,
+            ..self
+        }
+    }
+
+    /// Check if an entry has the required labels and was recorded before the selected time.
+    /// Entries without labels or recording time are always selected.
+    fn is_selected(&self, cachable: &T) -> bool {
+        let has_required_labels = match cachable.labels() {
+            Some(labels) => has_labels(labels, &self.required_labels),
+            None => true,
+        };
+        let recorded_in_time = match (cachable.recorded_at(), self.recorded_before) {
+            (Some(recorded_at), Some(recorded_before)) => recorded_at < recorded_before,
+            _ => true,
+        };
+
+        has_required_labels && recorded_in_time
+    }
+
+    /// The directory new entries of a model are stored in. Entries of models of which the name or
+    /// version can not be used as a directory name are stored in the cache directory.
+    fn entry_dir(&self, model: Option<(&str, &str)>) -> PathBuf {
+        match model {
+            Some((name, "")) if self.model_dirs && is_dir_name(name) => {
+                self.dir.join(name).join(LATEST_VERSION_DIR)
+            }
+            Some((name, version))
+                if self.model_dirs && is_dir_name(name) && is_dir_name(version) =>
+            {
+                self.dir.join(name).join(version)
+            }
+            _ => self.dir.clone(),
+        }
+    }
+
+    /// The names of the model directories in the cache directory.
+    fn model_dir_names(&self) -> anyhow::Result<Vec<String>> {
+        Ok(fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| is_dir_name(name))
+            .collect())
+    }
+
+    /// The version directories in the model directory of a model.
+    fn version_dirs(&self, model_name: &str) -> anyhow::Result<Vec<PathBuf>> {
+        match fs::read_dir(self.dir.join(model_name)) {
+            Ok(entries) => Ok(entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+                .map(|entry| entry.path())
+                .collect()),
+            Err(err) if err.kind() == NotFound => Ok(vec![]),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Load the entries in the model directory of a model, when they are not loaded yet.
+    async fn load_model(&self, model_name: &str) {
+        if !is_dir_name(model_name) || self.loaded_models.read().await.contains(model_name) {
+            return;
+        }
+
+        if let Err(err) = self.load_model_dir(model_name).await {
+            warn!("could not load the cache files of model {model_name}: {err}");
+        }
+        self.loaded_models
+            .write()
+            .await
+            .insert(model_name.to_string());
+    }
+
+    /// Load the entries in all model directories that are not loaded yet.
+    async fn load_models(&self) {
+        match self.model_dir_names() {
+            Ok(model_names) => {
+                for model_name in model_names {
+                    self.load_model(&model_name).await;
+                }
+            }
+            Err(err) => warn!("could not list the model directories: {err}"),
+        }
+    }
+
+    async fn load_model_dir(&self, model_name: &str) -> anyhow::Result<()> {
+        for dir in self.version_dirs(model_name)? {
+            self.load_dir(&dir).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the shard of a key, creating it when it does not exist yet.
+    async fn shard(&self, key: &str) -> Arc<Shard<T>> {
+        if let Some(shard) = self.store.read().await.get(key) {
+            return shard.clone();
+        }
+
+        self.store
+            .write()
+            .await
+            .entry(key.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Get the shard of a key, when it exists.
+    async fn existing_shard(&self, key: &str) -> Option<Arc<Shard<T>>> {
+        self.store.read().await.get(key).cloned()
+    }
+
+    async fn shards(&self) -> Vec<Arc<Shard<T>>> {
+        self.store.read().await.values().cloned().collect()
+    }
+
+    fn shrink(&self, mut cachable: Box<T>) -> Box<T> {
+        if self.is_low_memory() {
+            cachable.shrink();
+        }
+
+        cachable
+    }
+
+    /// Count a loaded cachable against the index budget. When the budget is exceeded, loading
+    /// fails, or the store switches to low memory mode and the loaded cachables are shrunk.
+    async fn account(
+        &self,
+        size: usize,
+        loading: &mut HashMap<String, Vec<Box<T>>>,
+    ) -> anyhow::Result<()> {
+        let index_bytes = self.index_bytes.fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+        if self.max_index_bytes == 0 || index_bytes <= self.max_index_bytes {
+            return Ok(());
+        }
+        if self.refuse_over_budget {
+            anyhow::bail!(
+                "the loaded entries of {} use more than the index budget of {} bytes, raise \
+                 request_collection.max_index_bytes or enable request_collection.low_memory",
+                self.dir.display(),
+                self.max_index_bytes
+            );
+        }
+        if self.is_low_memory() {
+            return Ok(());
+        }
+
+        warn!(
+            "the loaded entries of {} use more than the index budget of {} bytes, switching to \
+             low memory mode",
+            self.dir.display(),
+            self.max_index_bytes
+        );
+        self.low_memory.store(true, Ordering::Relaxed);
+        let mut shrunk_bytes = 0;
+        let mut shrink = |cachable: &mut Box<T>| {
+            let size = cachable.memory_size();
+            cachable.shrink();
+            shrunk_bytes += (size - cachable.memory_size()) as u64;
+        };
+        for shard in self.shards().await {
+            shard.write().await.iter_mut().for_each(&mut shrink);
+        }
+        loading.values_mut().flatten().for_each(&mut shrink);
+        self.release(shrunk_bytes);
+
+        Ok(())
+    }
+
+    /// Create a store that never writes to disk, and refuses to load when a file is corrupted.
+    pub fn new_read_only(dir: PathBuf) -> Self {
+        Self {
+            read_only: true,
+            ..Self::new(dir)
+        }
+    }
+
+    fn ensure_writable(&self) -> anyhow::Result<()> {
+        if self.read_only {
+            anyhow::bail!(
+                "the {} cachestore is read-only",
+                type_name::<T>().rsplit("::").next().unwrap()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Store an entry. Failures are counted, see `store_failures`.
+    pub async fn store(
+        &self,
+        input: T::Input,
+        output: T::Output,
+        metadata: EntryMetadata,
+    ) -> anyhow::Result<(PathBuf, T)> {
+        let stored = self.store_entry(input, output, metadata).await;
+        if stored.is_err() {
+            self.store_failures.fetch_add(1, Ordering::Relaxed);
+        }
+
+        stored
+    }
+
+    /// The number of entries that could not be stored since starting, e.g. because they could not
+    /// be serialized or written.
+    pub fn store_failures(&self) -> u64 {
+        self.store_failures.load(Ordering::Relaxed)
+    }
+
+    async fn store_entry(
+        &self,
+        input: T::Input,
+        output: T::Output,
+        metadata: EntryMetadata,
+    ) -> anyhow::Result<(PathBuf, T)> {
+        self.ensure_writable()?;
+
+        let _lock = DirLock::exclusive_async(&self.dir).await?;
+        let dir = self.entry_dir(T::input_model(&input));
+        if dir != self.dir {
+            fs::create_dir_all(&dir)?;
+        }
+        let (path, cachable) = match T::new(&dir, input, output, metadata) {
+            Ok((path, cachable)) => (path, cachable),
+            Err(err) => return Err(err),
+        };
+        if let Some(backend) = &self.backend {
+            let file_name = path.file_name().unwrap().to_string_lossy();
+            // An entry that is not in the backend is not stored, as other instances can not load
+            // it.
+            let written = match cachable.read_file() {
+                Ok(content) => backend.write(&file_name, &content).await.map(|_| ()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = written {
+                fs::remove_file(&path)?;
+                for referenced_path in cachable.referenced_paths() {
+                    fs::remove_file(referenced_path)?;
+                }
+                return Err(err.context(format!(
+                    "could not write {file_name} to the storage backend"
+                )));
+            }
+        }
+
+        let shard = self.shard(cachable.shard_key()).await;
+        let mut writable_store = shard.write().await;
+        // The entry can already be loaded when another process recorded it first.
+        if !writable_store.iter().any(|c| c.path() == path) {
+            let cachable = self.shrink(cachable.clone());
+            self.index_bytes
+                .fetch_add(cachable.memory_size() as u64, Ordering::Relaxed);
+            writable_store.push(cachable);
+        }
+
+        Ok((path, *cachable))
+    }
+
+    // Loads all inference files from the inference store path that are not loaded yet, so it can
+    // be called periodically to pick up files written by other processes. Files that can not be
+    // loaded are skipped, unless the store is read-only, then every file is verified and loading
+    // fails on the first corrupted file. Read-only stores load every model directory, other stores
+    // load the model directories of the models that were looked up.
+    // The fallbacks are loaded as well, also when the cache directory does not exist yet, so the
+    // caller can create it after a NotFound error.
+    pub async fn load(&self) -> anyhow::Result<()> {
+        let loaded = self.load_own().await;
+        if let Some(fallback) = &self.fallback {
+            // A fallback directory that does not exist is not created, so its NotFound error is
+            // not passed on as the NotFound error of this directory.
+            Box::pin(fallback.load()).await.map_err(|err| {
+                anyhow::anyhow!(
+                    "could not load the fallback cache directory {}: {err:#}",
+                    fallback.dir.display()
+                )
+            })?;
+        }
+
+        loaded
+    }
+
+    async fn load_own(&self) -> anyhow::Result<()> {
+        self.load_from_backend().await?;
+        self.load_dir(&self.dir).await?;
+
+        let model_names: Vec<String> = match self.read_only {
+            true => self.model_dir_names()?,
+            false => self.loaded_models.read().await.iter().cloned().collect(),
+        };
+        for model_name in model_names {
+            self.load_model_dir(&model_name).await?;
+            self.loaded_models.write().await.insert(model_name);
+        }
+
+        Ok(())
+    }
+
+    /// Load the entry files and packed entries in a directory that are not loaded yet. Packed
+    /// entries are loaded when no loose file or loaded entry has their file name.
+    async fn load_dir(&self, dir: &Path) -> anyhow::Result<()> {
+        let mut loaded = self.unselected.read().await.clone();
+        for shard in self.shards().await {
+            loaded.extend(shard.read().await.iter().map(|c| c.path()));
+        }
+        let mut new_cachables: HashMap<String, Vec<Box<T>>> = HashMap::new();
+
+        let _lock = DirLock::shared_async(&self.dir).await?;
+        let loose: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| T::matches_file_name(entry.file_name().to_string_lossy().to_string()))
+            .map(|r| r.path())
+            .collect();
+        let mut file_names: HashSet<_> = loaded
+            .iter()
+            .chain(&loose)
+            .filter_map(|path| path.file_name().map(|file_name| file_name.to_owned()))
+            .collect();
+        let mut paths: Vec<PathBuf> = loose
+            .into_iter()
+            .filter(|path| !loaded.contains(path))
+            .collect();
+        for path in pack::entries(dir)? {
+            let file_name = path.file_name().unwrap().to_owned();
+            if T::matches_file_name(file_name.to_string_lossy().to_string())
+                && file_names.insert(file_name)
+            {
+                paths.push(path);
+            }
+        }
+
+        for path in paths {
+            let cachable = T::from_file(&path).and_then(|cachable| {
+                if self.read_only {
+                    cachable.verify()?;
+                }
+                Ok(cachable)
+            });
+
+            match cachable {
+                Ok(cachable) if !self.is_selected(&cachable) => {
+                    self.unselected.write().await.insert(path);
+                }
+                Ok(cachable) => {
+                    let cachable = self.shrink(cachable);
+                    let size = cachable.memory_size();
+                    new_cachables
+                        .entry(cachable.shard_key().to_string())
+                        .or_default()
+                        .push(cachable);
+                    self.account(size, &mut new_cachables).await?;
+                }
+                Err(err) if self.read_only => {
+                    return Err(err.context(format!("corrupted cache file {}", path.display())))
+                }
+                Err(err) => warn!("skipping cache file {}: {err}", path.display()),
+            }
+        }
+
+        for (key, cachables) in new_cachables {
+            let shard = self.shard(&key).await;
+            let mut write_store = shard.write().await;
+            // Entries can be stored while loading, those are not loaded again.
+            let stored: HashSet<PathBuf> = write_store.iter().map(|c| c.path()).collect();
+            let (cachables, already_stored): (Vec<_>, Vec<_>) = cachables
+                .into_iter()
+                .partition(|c| !stored.contains(&c.path()));
+            self.release(already_stored.iter().map(|c| c.memory_size() as u64).sum());
+            write_store.extend(cachables);
+            // Entries are ordered by the time they were recorded and their file name, so the first
+            // of equally ranked matches does not depend on the order the files are listed in.
+            write_store.sort_by_cached_key(|c| {
+                (
+                    c.recorded_at(),
+                    c.path().file_name().map(|file_name| file_name.to_owned()),
+                )
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Import the entries in the storage backend that are not in the cache directory yet. Entries
+    /// that can not be imported are skipped, unless the store is read-only.
+    async fn load_from_backend(&self) -> anyhow::Result<()> {
+        let Some(backend) = &self.backend else {
+            return Ok(());
+        };
+
+        // Entries are matched by their file name, as imported entries can be stored in a model
+        // directory.
+        self.load_models().await;
+        let mut loaded: HashSet<String> = self
+            .unselected
+            .read()
+            .await
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(|file_name| file_name.to_string_lossy().to_string())
+            .collect();
+        for shard in self.shards().await {
+            loaded.extend(shard.read().await.iter().filter_map(|cachable| {
+                Some(cachable.path().file_name()?.to_string_lossy().to_string())
+            }));
+        }
+
+        for file_name in backend.list().await? {
+            if !Self::is_entry_file_name(&file_name)
+                || loaded.contains(&file_name)
+                || self.dir.join(&file_name).exists()
+            {
+                continue;
+            }
+
+            let imported = match backend.read(&file_name).await {
+                // The entry was removed since it was listed.
+                Ok(None) => continue,
+                Ok(Some(content)) => self.import(&file_name, &content).await.map(|_| ()),
+                Err(err) => Err(err),
+            };
+            match imported {
+                Err(err) if self.read_only => {
+                    return Err(err.context(format!(
+                        "could not load {file_name} from the storage backend"
+                    )))
+                }
+                Err(err) => warn!("skipping {file_name} in the storage backend: {err:#}"),
+                Ok(()) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The file names of all entries, in this store and its fallbacks.
+    pub async fn file_names(&self) -> Vec<String> {
+        self.file_names_where(|_| true).await
+    }
+
+    /// The file names of the entries that satisfy the filter, in this store and its fallbacks.
+    pub async fn file_names_where<F>(&self, filter: F) -> Vec<String>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.load_models().await;
+        let mut file_names = vec![];
+
+        for shard in self.shards().await {
+            file_names.extend(
+                shard
+                    .read()
+                    .await
+                    .iter()
+                    .filter(|cachable| filter(cachable))
+                    .map(|cachable| {
+                        cachable
+                            .path()
+                            .file_name()
+                            .unwrap()
+                            .to_string_lossy()
+                            .to_string()
+                    }),
+            );
+        }
+        if let Some(fallback) = &self.fallback {
+            file_names.extend(Box::pin(fallback.file_names_where(filter)).await);
+        }
+
+        file_names
+    }
+
+    /// The number of entries and the bytes they use on disk per model name and version, e.g. to
+    /// see which models dominate the storage before pruning. Entries without a model are counted
+    /// under an empty name and version. The entries of the fallbacks are included.
+    pub async fn usage(&self) -> BTreeMap<(String, String), Usage> {
+        self.load_models().await;
+        let mut usage: BTreeMap<(String, String), Usage> = BTreeMap::new();
+        let hits = self.hits.lock().unwrap().clone();
+
+        for shard in self.shards().await {
+            for cachable in shard.read().await.iter() {
+                let (name, version) = cachable.model().unwrap_or_default();
+                let model_usage = usage
+                    .entry((name.to_string(), version.to_string()))
+                    .or_default();
+                model_usage.entries += 1;
+                if let Some(file_name) = cachable.path().file_name() {
+                    model_usage.hits += hits
+                        .get(file_name.to_string_lossy().as_ref())
+                        .map_or(0, |hits| hits.count);
+                }
+                for path in [cachable.path()]
+                    .into_iter()
+                    .chain(cachable.referenced_paths())
+                {
+                    match pack::size(&path) {
+                        Ok(bytes) => model_usage.bytes += bytes,
+                        Err(err) => warn!(
+                            "could not read the size of cache file {}: {err}",
+                            path.display()
+                        ),
+                    }
+                }
+            }
+        }
+
+        if let Some(fallback) = &self.fallback {
+            for (model, fallback_usage) in Box::pin(fallback.usage()).await {
+                let model_usage = usage.entry(model).or_default();
+                model_usage.entries += fallback_usage.entries;
+                model_usage.bytes += fallback_usage.bytes;
+                model_usage.hits += fallback_usage.hits;
+            }
+        }
+
+        usage
+    }
+
+    /// Check if the file name belongs to an entry of this store, and can not escape the cache
+    /// directory.
+    pub fn is_entry_file_name(file_name: &str) -> bool {
+        PathBuf::from(file_name).file_name() == Some(file_name.as_ref())
+            && T::matches_file_name(file_name.to_string())
+    }
+
+    /// The path of the loaded entry with a file name, which is in a pack when the entry is packed.
+    async fn loaded_path(&self, file_name: &str) -> Option<PathBuf> {
+        self.load_models().await;
+        for shard in self.shards().await {
+            let path = shard
+                .read()
+                .await
+                .iter()
+                .map(|cachable| cachable.path())
+                .find(|path| path.file_name().is_some_and(|name| name == file_name));
+            if path.is_some() {
+                return path;
+            }
+        }
+
+        None
+    }
+
+    /// Read the stored file of a loaded entry in this store or its fallbacks, to replicate it to
+    /// another instance.
+    pub async fn read_file(&self, file_name: &str) -> Option<Vec<u8>> {
+        self.load_models().await;
+        for shard in self.shards().await {
+            let cachable = shard
+                .read()
+                .await
+                .iter()
+                .find(|cachable| {
+                    cachable
+                        .path()
+                        .file_name()
+                        .is_some_and(|name| name == file_name)
+                })
+                .cloned();
+            if let Some(cachable) = cachable {
+                return read_blocking(move || cachable.read_file()).await.ok();
+            }
+        }
+
+        match &self.fallback {
+            Some(fallback) => Box::pin(fallback.read_file(file_name)).await,
+            None => None,
+        }
+    }
+
+    /// Add an entry that was recorded by another instance. The entry is verified before it is
+    /// loaded, and removed again when it is corrupted. Returns false when the entry already exists.
+    /// Imports are also allowed for read-only stores, replication is the only way entries are
+    /// written to them.
+    pub async fn import(&self, file_name: &str, content: &[u8]) -> anyhow::Result<bool> {
+        if !Self::is_entry_file_name(file_name) {
+            anyhow::bail!("invalid cache file name {file_name}");
+        }
+        if self.loaded_path(file_name).await.is_some() {
+            return Ok(false);
+        }
+
+        let path = self.dir.join(file_name);
+        let _lock = DirLock::exclusive_async(&self.dir).await?;
+        match lock::write_new(&path, |writer| Ok(writer.write_all(content)?)) {
+            Err(err) if lock::is_already_exists(&err) => return Ok(false),
+            Err(err) => return Err(err),
+            Ok(()) => {}
+        }
+
+        let cachable = T::from_file(&path).and_then(|cachable| {
+            cachable.verify()?;
+            Ok(cachable)
+        });
+        let cachable = match cachable {
+            Ok(cachable) => cachable,
+            Err(err) => {
+                fs::remove_file(&path)?;
+                return Err(err.context(format!("corrupted cache file {file_name}")));
+            }
+        };
+
+        // Imported entries are stored in the same directory as recorded entries, and in the same
+        // format.
+        let entry_dir = self.entry_dir(cachable.model());
+        if entry_dir != self.dir {
+            fs::create_dir_all(&entry_dir)?;
+            fs::rename(&path, entry_dir.join(file_name))?;
+        }
+        let path = entry_dir.join(file_name);
+        let path = T::migrate_file(&path)?.unwrap_or(path);
+        let cachable = T::from_file(path)?;
+
+        let path = cachable.path();
+        if !self.is_selected(&cachable) {
+            self.unselected.write().await.insert(path);
+            return Ok(true);
+        }
+        let shard = self.shard(cachable.shard_key()).await;
+        let mut writable_store = shard.write().await;
+        if !writable_store.iter().any(|c| c.path() == path) {
+            let cachable = self.shrink(cachable);
+            self.index_bytes
+                .fetch_add(cachable.memory_size() as u64, Ordering::Relaxed);
+            writable_store.push(cachable);
+        }
+
+        Ok(true)
+    }
+
+    /// Load the files written by other processes sharing the cache directory every interval, and
+    /// whenever the storage backend reports changes.
+    pub async fn refresh_periodically(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            match &self.backend {
+                Some(backend) => {
+                    tokio::select! {
+                        _ = ticker.tick() => {}
+                        watched = backend.watch() => {
+                            if let Err(err) = watched {
+                                warn!("could not watch the storage backend: {err}");
+                                ticker.tick().await;
+                            }
+                        }
+                    }
+                }
+                None => {
+                    ticker.tick().await;
+                }
+            }
+            if let Err(err) = self.load().await {
+                warn!(
+                    "could not refresh {} cachestore: {err}",
+                    type_name::<T>().rsplit("::").next().unwrap()
+                );
+            }
+        }
+    }
+
+    /// Upgrade all stored files to the current format version, without loading them. Returns the
+    /// number of rewritten files.
+    pub fn migrate(&self) -> anyhow::Result<usize> {
+        self.ensure_writable()?;
+
+        let _lock = DirLock::exclusive(&self.dir)?;
+        let mut migrated = 0;
+
+        let mut dirs = vec![self.dir.clone()];
+        for model_name in self.model_dir_names()? {
+            dirs.extend(self.version_dirs(&model_name)?);
+        }
+        let entries = dirs
+            .iter()
+            .map(fs::read_dir)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for entry in entries.into_iter().flatten().filter_map(Result::ok) {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !T::matches_file_name(file_name) {
+                continue;
+            }
+
+            match T::migrate_file(entry.path()) {
+                Ok(Some(_)) => migrated += 1,
+                Ok(None) => {}
+                Err(err) => warn!(
+                    "could not migrate cache file {}: {err}",
+                    entry.path().display()
+                ),
+            }
+        }
+
+        Ok(migrated)
+    }
+
+    pub async fn find_output(
+        &self,
+        match_input: &T::Input,
+        config: &T::Config,
+    ) -> Option<T::Output> {
+        self.find(match_input, config)
+            .await
+            .map(|(_, output, _)| output)
+    }
+
+    /// Get the inputs and outputs of the cached entries that satisfy the filter, which is checked
+    /// before their inputs are read.
+    pub async fn entries_where<F>(&self, filter: F) -> Vec<(T::Input, T::Output)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.load_models().await;
+        let mut entries = vec![];
+
+        for shard in self.shards().await {
+            let readable_store = shard.read().await;
+            entries.extend(
+                readable_store
+                    .iter()
+                    .filter(|cachable| filter(cachable))
+                    .filter_map(|cachable| {
+                        Some((
+                            cachable.get_input().ok()?.into_owned(),
+                            cachable.get_output().ok()?,
+                        ))
+                    }),
+            );
+        }
+        if let Some(fallback) = &self.fallback {
+            entries.extend(Box::pin(fallback.entries_where(filter)).await);
+        }
+
+        entries
+    }
 
-impl<T> CacheStore<T>
-where
-    T: Cachable,
-    T: Clone,
-{
-    pub fn new(dir: PathBuf) -> Self {
-        Self {
-            dir,
-            store: Default::default(),
+    /// Get the inputs and outputs of all cached entries of which the input satisfies the filter.
+    pub async fn entries<F>(&self, filter: F) -> Vec<(T::Input, T::Output)>
+    where
+        F: Fn(&T::Input) -> bool,
+    {
+        self.load_models().await;
+        let mut entries = vec![];
+
+        for shard in self.shards().await {
+            let readable_store = shard.read().await;
+            entries.extend(readable_store.iter().filter_map(
+                |cachable| match cachable.get_input() {
+                    Ok(input) if filter(&input) => {
+                        Some((input.into_owned(), cachable.get_output().ok()?))
+                    }
+                    _ => None,
+                },
+            ));
+        }
+        if let Some(fallback) = &self.fallback {
+            entries.extend(Box::pin(fallback.entries(filter)).await);
         }
+
+        entries
     }
 
-    pub async fn store(&self, input: T::Input, output: T::Output) -> anyhow::Result<(PathBuf, T)> {
-        let (path, cachable) = match T::new(&self.dir, input, output) {
-            Ok((path, cachable)) => (path, cachable),
-            Err(err) => return Err(err),
+    /// The paths, inputs and outputs of the entries of which the file name matches the filter.
+    pub async fn entries_by_file_name<F>(&self, filter: F) -> Vec<(PathBuf, T::Input, T::Output)>
+    where
+        F: Fn(&str) -> bool,
+    {
+        self.load_models().await;
+        let mut entries = vec![];
+
+        for shard in self.shards().await {
+            let readable_store = shard.read().await;
+            for cachable in readable_store.iter() {
+                let path = cachable.path();
+                if !filter(&path.file_name().unwrap_or_default().to_string_lossy()) {
+                    continue;
+                }
+                if let (Ok(input), Ok(output)) = (cachable.get_input(), cachable.get_output()) {
+                    entries.push((path, input.into_owned(), output));
+                }
+            }
+        }
+        if let Some(fallback) = &self.fallback {
+            entries.extend(Box::pin(fallback.entries_by_file_name(filter)).await);
+        }
+
+        entries
+    }
+
+    /// The number of cached entries that match the input.
+    pub async fn count(&self, match_input: &T::Input, config: &T::Config) -> usize {
+        self.load_model(T::input_shard_key(match_input)).await;
+        let count = match self.existing_shard(T::input_shard_key(match_input)).await {
+            Some(shard) => self.matching(&shard, match_input, config).await.len(),
+            None => 0,
         };
 
-        let mut writable_store = self.store.write().await;
-        writable_store.push(cachable.clone());
+        match &self.fallback {
+            Some(fallback) => count + Box::pin(fallback.count(match_input, config)).await,
+            None => count,
+        }
+    }
 
-        Ok((path, *cachable))
+    /// Find the best matching cached input together with its output and its entry, in this store
+    /// or else in its fallbacks.
+    pub async fn find(
+        &self,
+        match_input: &T::Input,
+        config: &T::Config,
+    ) -> Option<(T::Input, T::Output, Source)> {
+        if let Some(found) = self.find_in_dir(match_input, config).await {
+            return Some(found);
+        }
+
+        match &self.fallback {
+            Some(fallback) => Box::pin(fallback.find(match_input, config)).await,
+            None => None,
+        }
     }
 
-    // Loads all inference files from the inference store path.
-    pub async fn load(&self) -> anyhow::Result<()> {
-        let mut write_store = self.store.write().await;
+    async fn find_in_dir(
+        &self,
+        match_input: &T::Input,
+        config: &T::Config,
+    ) -> Option<(T::Input, T::Output, Source)> {
+        self.load_model(T::input_shard_key(match_input)).await;
+        let shard = self.existing_shard(T::input_shard_key(match_input)).await?;
 
-        fs::read_dir(&self.dir)?
-            .filter_map(Result::ok)
-            .filter(|entry| {
-                T::matches_file_name(
-                    entry
-                        .path()
-                        .file_name()
-                        .unwrap()
-                        .to_os_string()
-                        .into_string()
-                        .unwrap(),
-                )
+        let mut candidates: Vec<_> = self
+            .matching(&shard, match_input, config)
+            .await
+            .into_iter()
+            .map(|cachable| (cachable.rank(match_input, config), cachable))
+            .collect();
+
+        // The sort is stable, so candidates with an equal rank keep their iteration order.
+        candidates.sort_by(|(rank1, _), (rank2, _)| rank2.cmp(rank1));
+
+        for (_, cachable) in candidates {
+            let read = read_blocking(move || {
+                let input = cachable.get_input()?.into_owned();
+                let output = cachable.get_output()?;
+                Ok((cachable, input, output))
             })
-            .map(|r| r.path())
-            .filter_map(|p| T::from_file(p).ok())
-            .for_each(|c| write_store.push(c));
+            .await;
+            match read {
+                Ok((cachable, _, _))
+                    if self.is_single_use(&cachable) && !self.consume(&cachable).await =>
+                {
+                    continue
+                }
+                Ok((cachable, i, o)) => {
+                    self.record_hit(&cachable);
+                    return Some((i, o, Source::of(cachable.as_ref())));
+                }
+                Err(err) => warn!("error encountered during the output fetching of a match in {} cachestore: {err}", type_name::<T>().rsplit("::").next().unwrap()),
+            }
+        }
 
-        Ok(())
+        None
     }
 
-    pub async fn find_output(
+    /// The cachables in the shard that match the input. In low memory mode the inputs of the
+    /// candidates are read from disk to match them, which is done on the blocking thread pool
+    /// after the shard lock is released.
+    async fn matching(
         &self,
+        shard: &Shard<T>,
         match_input: &T::Input,
         config: &T::Config,
-    ) -> Option<T::Output> {
-        let readable_store = self.store.read().await;
+    ) -> Vec<Box<T>> {
+        let low_memory = self.is_low_memory();
+        let candidates: Vec<_> = shard
+            .read()
+            .await
+            .iter()
+            .filter(|cachable| cachable.is_candidate(match_input, config))
+            .filter(|cachable| low_memory || cachable.matches(match_input, config))
+            .cloned()
+            .collect();
+        if !low_memory {
+            return candidates;
+        }
+
+        let (match_input, config) = (match_input.clone(), config.clone());
+        let matching = read_blocking(move || {
+            Ok(candidates
+                .into_iter()
+                .filter(|cachable| cachable.matches(&match_input, &config))
+                .collect())
+        })
+        .await;
+        matching.unwrap_or_else(|err| {
+            warn!(
+                "could not match the candidates in {}: {err}",
+                self.dir.display()
+            );
+            vec![]
+        })
+    }
+
+    /// Read the file names of the pinned entries from the pinned manifest.
+    fn pinned(&self) -> anyhow::Result<HashSet<String>> {
+        match fs::File::open(self.dir.join(PINNED_MANIFEST)) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(err) if err.kind() == NotFound => Ok(HashSet::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Remove all entries for which `should_remove` returns true from memory and disk, except for
+    /// the entries that are pinned. Returns the number of removed entries.
+    pub async fn prune<F>(&self, should_remove: F) -> anyhow::Result<usize>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.ensure_writable()?;
+        self.load_models().await;
+
+        let _lock = DirLock::exclusive_async(&self.dir).await?;
+        let pinned = self.pinned()?;
+        let mut removed_file_names = vec![];
+        let mut left_behind = vec![];
+
+        for shard in self.shards().await {
+            let mut write_store = shard.write().await;
+            let paths: Vec<PathBuf> = write_store
+                .iter()
+                .filter(|cachable| {
+                    let path = cachable.path();
+                    let file_name = path.file_name().unwrap().to_string_lossy();
+                    !pinned.contains(file_name.as_ref()) && should_remove(cachable)
+                })
+                .map(|cachable| cachable.path())
+                .collect();
+
+            // Packed entries are removed from the index of their pack.
+            let removed = pack::remove(&paths);
+            let referenced_paths: Vec<PathBuf> = write_store
+                .iter()
+                .filter(|cachable| removed.contains(&cachable.path()))
+                .flat_map(|cachable| cachable.referenced_paths())
+                .collect();
+            let removed_referenced = pack::remove(&referenced_paths);
+            left_behind.extend(
+                referenced_paths
+                    .into_iter()
+                    .filter(|path| !removed_referenced.contains(path)),
+            );
+            let released: usize = write_store
+                .iter()
+                .filter(|cachable| removed.contains(&cachable.path()))
+                .map(|cachable| cachable.memory_size())
+                .sum();
+            self.release(released as u64);
+            write_store.retain(|cachable| !removed.contains(&cachable.path()));
+            removed_file_names.extend(
+                removed
+                    .iter()
+                    .map(|path| path.file_name().unwrap().to_string_lossy().to_string()),
+            );
+        }
+        let removed = removed_file_names.len();
+        {
+            let mut hits = self.hits.lock().unwrap();
+            for file_name in &removed_file_names {
+                hits.remove(file_name);
+            }
+        }
 
-        for cachable in readable_store.deref() {
-            if cachable.matches(match_input, config) {
-                match cachable.get_output() {
-                    Ok(o) => return Some(o),
-                    Err(err) => warn!("error encountered during the output fetching of a match in {} cachestore: {err}", type_name::<T>().rsplit("::").next().unwrap())
+        if let Some(backend) = &self.backend {
+            for file_name in removed_file_names {
+                if let Err(err) = backend.delete(&file_name).await {
+                    warn!("could not remove {file_name} from the storage backend: {err}");
                 }
             }
         }
 
-        None
+        // The entries are removed either way, but the files they referenced still use disk space.
+        if let Some(path) = left_behind.first() {
+            anyhow::bail!(
+                "removed {removed} entries, but could not remove {} of the files they reference, \
+                 like {}",
+                left_behind.len(),
+                path.display()
+            );
+        }
+
+        Ok(removed)
+    }
+
+    /// Find the output of the nearest cached input, for when no exact match is available.
+    /// Returns the output together with the distance to the provided input.
+    pub async fn find_nearest_output(
+        &self,
+        match_input: &T::Input,
+        config: &T::Config,
+    ) -> Option<(T::Output, f64, Source)> {
+        if let Some(found) = self.find_nearest_output_in_dir(match_input, config).await {
+            return Some(found);
+        }
+
+        match &self.fallback {
+            Some(fallback) => Box::pin(fallback.find_nearest_output(match_input, config)).await,
+            None => None,
+        }
+    }
+
+    async fn find_nearest_output_in_dir(
+        &self,
+        match_input: &T::Input,
+        config: &T::Config,
+    ) -> Option<(T::Output, f64, Source)> {
+        self.load_model(T::input_shard_key(match_input)).await;
+        let shard = self.existing_shard(T::input_shard_key(match_input)).await?;
+        let candidates: Vec<_> = {
+            let readable_store = shard.read().await;
+            let consumed = self.consumed.read().await;
+            readable_store
+                .iter()
+                .filter(|cachable| consumed.is_empty() || !consumed.contains_key(&cachable.path()))
+                .filter(|cachable| cachable.is_candidate(match_input, config))
+                .cloned()
+                .collect()
+        };
+        // The inputs of the candidates are read from disk in low memory mode.
+        let (nearest_input, nearest_config) = (match_input.clone(), config.clone());
+        let nearest = read_blocking(move || {
+            Ok(candidates
+                .into_iter()
+                .filter_map(|cachable| {
+                    let distance = cachable.distance(&nearest_input, &nearest_config)?;
+                    Some((cachable, distance))
+                })
+                .min_by(|(_, a), (_, b)| a.total_cmp(b)))
+        })
+        .await;
+        let (cachable, distance) = match nearest {
+            Ok(nearest) => nearest?,
+            Err(err) => {
+                warn!(
+                    "could not find the nearest candidate in {}: {err}",
+                    self.dir.display()
+                );
+                return None;
+            }
+        };
+        if self.is_single_use(&cachable) && !self.consume(&cachable).await {
+            return None;
+        }
+
+        let read = read_blocking(move || {
+            let output = cachable.get_output()?;
+            Ok((cachable, output))
+        })
+        .await;
+        match read {
+            Ok((cachable, o)) => {
+                self.record_hit(&cachable);
+                Some((o, distance, Source::of(cachable.as_ref())))
+            }
+            Err(err) => {
+                warn!("error encountered during the output fetching of a nearest match in {} cachestore: {err}", type_name::<T>().rsplit("::").next().unwrap());
+                None
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::caching::backend::{DirectoryBackend, StorageBackend};
     use crate::caching::cachable::Cachable;
-    use crate::caching::cachestore::CacheStore;
+    use crate::caching::cachable_modelinfer::CachableModelInfer;
+    use crate::caching::cachestore::{CacheStore, Usage, PINNED_MANIFEST};
+    use crate::caching::metadata::EntryMetadata;
+    use crate::parsing::input::tests::BASE_INFER_INPUT;
+    use crate::parsing::output::tests::BASE_INFER_OUTPUT;
+    use std::borrow::Cow;
+    use std::collections::{BTreeMap, HashSet};
     use std::fs::File;
     use std::path::{Path, PathBuf};
+    use std::sync::Arc;
     use tempdir::TempDir;
 
     #[derive(Clone)]
     struct TestCachable {
+        path: PathBuf,
         input: u8,
         output: u8,
     }
@@ -105,12 +1556,33 @@ mod tests {
         type Output = u8;
         type Config = ();
 
-        fn get_input(&self) -> anyhow::Result<&Self::Input> {
-            return Ok(&self.input);
+        fn get_input(&self) -> anyhow::Result<Cow<'_, Self::Input>> {
+            Ok(Cow::Borrowed(&self.input))
         }
 
         fn get_output(&self) -> anyhow::Result<Self::Output> {
-            return Ok(self.output.clone());
+            Ok(self.output)
+        }
+
+        fn path(&self) -> PathBuf {
+            self.path.clone()
+        }
+
+        // Inputs from 100 are requested from the large model, other inputs from no model.
+        fn shard_key(&self) -> &str {
+            Self::input_shard_key(&self.input)
+        }
+
+        fn input_shard_key(input: &Self::Input) -> &str {
+            Self::input_model(input).map_or("", |(name, _)| name)
+        }
+
+        fn model(&self) -> Option<(&str, &str)> {
+            Self::input_model(&self.input)
+        }
+
+        fn input_model(input: &Self::Input) -> Option<(&str, &str)> {
+            (*input >= 100).then_some(("large", "1"))
         }
 
         fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Box<Self>> {
@@ -126,13 +1598,18 @@ mod tests {
             // Read string content from file.
             let output = std::fs::read_to_string(&path)?.parse::<u8>()?;
 
-            Ok(Box::new(TestCachable { input, output }))
+            Ok(Box::new(TestCachable {
+                path: path.as_ref().to_path_buf(),
+                input,
+                output,
+            }))
         }
 
         fn new<P: AsRef<Path>>(
             cache_dir: P,
             input: Self::Input,
             output: Self::Output,
+            _metadata: EntryMetadata,
         ) -> anyhow::Result<(PathBuf, Box<Self>)> {
             let path = cache_dir.as_ref().join(format!("{input}.test"));
 
@@ -140,13 +1617,24 @@ mod tests {
             File::create(&path)?;
             std::fs::write(&path, output.to_string())?;
 
-            Ok((path, Box::new(TestCachable { input, output })))
+            Ok((
+                path.clone(),
+                Box::new(TestCachable {
+                    path,
+                    input,
+                    output,
+                }),
+            ))
         }
 
         fn matches(&self, input: &Self::Input, _config: &Self::Config) -> bool {
             self.input == *input
         }
 
+        fn distance(&self, input: &Self::Input, _config: &Self::Config) -> Option<f64> {
+            Some(self.input.abs_diff(*input) as f64)
+        }
+
         fn matches_file_name(file_name: String) -> bool {
             file_name.ends_with(".test")
         }
@@ -158,12 +1646,188 @@ mod tests {
         let tmp_path = tmp_dir.path().to_path_buf();
         let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone());
 
-        let (path, cachable) = cache_store.store(1, 2).await.unwrap();
+        let (path, cachable) = cache_store.store(1, 2, Default::default()).await.unwrap();
         assert_eq!(path, tmp_path.join("1.test"));
         assert_eq!(1, cachable.input);
         assert_eq!(2, cachable.output);
     }
 
+    #[tokio::test]
+    async fn it_consumes_single_use_entries() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone())
+            .with_single_use_models(vec!["large".to_string()]);
+        cache_store.store(1, 2, Default::default()).await.unwrap();
+        cache_store.store(100, 3, Default::default()).await.unwrap();
+
+        // Only the entries of the large model are single-use.
+        for _ in 0..2 {
+            assert_eq!(Some(2), cache_store.find_output(&1, &()).await);
+        }
+        assert_eq!(Some(3), cache_store.find_output(&100, &()).await);
+        assert_eq!(None, cache_store.find_output(&100, &()).await);
+        assert!(cache_store.find_nearest_output(&101, &()).await.is_none());
+
+        assert_eq!(0, cache_store.reset_usage("other").await);
+        assert_eq!(1, cache_store.reset_usage("").await);
+        assert!(cache_store.find_nearest_output(&101, &()).await.is_some());
+        assert_eq!(None, cache_store.find_output(&100, &()).await);
+
+        // The entries consumed in a fallback are served again as well.
+        let overlay = CacheStore::<TestCachable>::new(tmp_path.join("overlay")).with_fallback(
+            CacheStore::new_read_only(tmp_path.clone())
+                .with_single_use_models(vec!["large".to_string()]),
+        );
+        let _ = overlay.load().await;
+        assert_eq!(Some(3), overlay.find_output(&100, &()).await);
+        assert_eq!(None, overlay.find_output(&100, &()).await);
+        assert_eq!(1, overlay.reset_usage("large").await);
+        assert_eq!(Some(3), overlay.find_output(&100, &()).await);
+    }
+
+    #[tokio::test]
+    async fn it_persists_hits_and_finds_the_least_used_entries() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let usage_file = tmp_path.join("usage.json");
+        let cache_store =
+            CacheStore::<TestCachable>::new(tmp_path.clone()).with_usage_file(usage_file.clone());
+        for input in 1..=3 {
+            cache_store
+                .store(input, 0, Default::default())
+                .await
+                .unwrap();
+        }
+        for input in [1, 1, 3] {
+            cache_store.find_output(&input, &()).await;
+        }
+        assert_eq!(2, cache_store.hits()["1.test"].count);
+        cache_store.save_usage().await.unwrap();
+
+        let cache_store =
+            CacheStore::<TestCachable>::new(tmp_path.clone()).with_usage_file(usage_file);
+        cache_store.load().await.unwrap();
+        assert_eq!(2, cache_store.hits()["1.test"].count);
+        assert_eq!(
+            HashSet::from(["2.test".to_string(), "3.test".to_string()]),
+            cache_store.least_used(2).await
+        );
+
+        let least_used = cache_store.least_used(1).await;
+        let removed = cache_store
+            .prune(|c| least_used.contains(c.path().file_name().unwrap().to_str().unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(1, removed);
+        assert_eq!(2, cache_store.file_names().await.len());
+    }
+
+    #[tokio::test]
+    async fn it_loads_entries_in_a_deterministic_order() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        // The file names all parse to input 1.
+        for (file_name, output) in [("1.test", "5"), ("001.test", "7"), ("01.test", "6")] {
+            std::fs::write(tmp_path.join(file_name), output).unwrap();
+        }
+
+        let cache_store = CacheStore::<TestCachable>::new_read_only(tmp_path.clone());
+        cache_store.load().await.unwrap();
+        assert_eq!(Some(7), cache_store.find_output(&1, &()).await);
+    }
+
+    #[tokio::test]
+    async fn it_enforces_the_index_budget() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        for input in 1..=5 {
+            std::fs::write(tmp_path.join(format!("{input}.test")), "2").unwrap();
+        }
+        let budget = 2 * size_of::<TestCachable>() as u64;
+
+        let cache_store =
+            CacheStore::<TestCachable>::new(tmp_path.clone()).with_index_budget(budget, false);
+        cache_store.load().await.unwrap();
+        assert!(cache_store.is_low_memory());
+        assert_eq!(1, cache_store.count(&5, &()).await);
+        assert_eq!(Some(2), cache_store.find_output(&3, &()).await);
+
+        let cache_store =
+            CacheStore::<TestCachable>::new(tmp_path.clone()).with_index_budget(budget, true);
+        let err = cache_store.load().await.unwrap_err();
+        assert!(err.to_string().contains("index budget"));
+    }
+
+    #[tokio::test]
+    async fn it_shares_the_index_budget_with_the_fallbacks() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let (overlay, base) = (tmp_path.join("overlay"), tmp_path.join("base"));
+        std::fs::create_dir_all(&overlay).unwrap();
+        std::fs::create_dir_all(&base).unwrap();
+        for input in 1..=3 {
+            std::fs::write(base.join(format!("{input}.test")), "2").unwrap();
+        }
+        std::fs::write(overlay.join("4.test"), "2").unwrap();
+        let entry_bytes = size_of::<TestCachable>() as u64;
+
+        // The base fits the budget, the entry of the overlay does not.
+        let cache_store = CacheStore::<TestCachable>::new(overlay.clone())
+            .with_fallback(CacheStore::new_read_only(base.clone()))
+            .with_index_budget(3 * entry_bytes, true);
+        let err = cache_store.load().await.unwrap_err();
+        assert!(err.to_string().contains("index budget"));
+
+        let cache_store = CacheStore::<TestCachable>::new(overlay)
+            .with_index_budget(0, false)
+            .with_fallback(CacheStore::new_read_only(base));
+        cache_store.load().await.unwrap();
+        assert_eq!(4 * entry_bytes, cache_store.index_bytes());
+
+        // Stored and pruned entries are counted.
+        cache_store.store(5, 2, Default::default()).await.unwrap();
+        assert_eq!(5 * entry_bytes, cache_store.index_bytes());
+        assert_eq!(2, cache_store.prune(|c| c.input >= 4).await.unwrap());
+        assert_eq!(3 * entry_bytes, cache_store.index_bytes());
+    }
+
+    #[tokio::test]
+    async fn it_stores_entries_in_model_directories() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone()).with_model_dirs(true);
+
+        let (path, _) = cache_store.store(100, 2, Default::default()).await.unwrap();
+        assert_eq!(tmp_path.join("large").join("1").join("100.test"), path);
+        let (path, _) = cache_store.store(1, 2, Default::default()).await.unwrap();
+        assert_eq!(tmp_path.join("1.test"), path);
+
+        // Model directories are loaded at the first lookup of their model.
+        let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone());
+        cache_store.load().await.unwrap();
+        assert!(cache_store.existing_shard("large").await.is_none());
+        assert_eq!(Some(2), cache_store.find_output(&100, &()).await);
+        assert_eq!(2, cache_store.file_names().await.len());
+
+        let usage = cache_store.usage().await;
+        let large = usage[&("large".to_string(), "1".to_string())];
+        assert_eq!(
+            Usage {
+                entries: 1,
+                bytes: 1,
+                hits: 1,
+            },
+            large
+        );
+        assert_eq!(1, usage[&(String::new(), String::new())].entries);
+
+        // Read-only stores load every model directory at load.
+        let cache_store = CacheStore::<TestCachable>::new_read_only(tmp_path);
+        cache_store.load().await.unwrap();
+        assert!(cache_store.existing_shard("large").await.is_some());
+    }
+
     #[tokio::test]
     async fn it_loads() {
         let tmp_dir = TempDir::new("inference_store_test").unwrap();
@@ -178,7 +1842,8 @@ mod tests {
         let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone());
         cache_store.load().await.unwrap();
 
-        let readable_store = cache_store.store.read().await;
+        let shard = cache_store.shard("").await;
+        let readable_store = shard.read().await;
         let first_item = readable_store.first().unwrap();
         assert_eq!(1, first_item.input);
         assert_eq!(2, first_item.output);
@@ -190,10 +1855,281 @@ mod tests {
         let tmp_path = tmp_dir.path().to_path_buf();
         let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone());
 
-        let _ = cache_store.store(1, 2).await.unwrap();
+        let _ = cache_store.store(1, 2, Default::default()).await.unwrap();
 
         let output = cache_store.find_output(&1, &()).await.unwrap();
 
         assert_eq!(2, output);
     }
+
+    #[tokio::test]
+    async fn it_refuses_writes_and_corrupted_files_when_read_only() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        std::fs::write(tmp_path.join("1.test"), "2").unwrap();
+
+        let cache_store = CacheStore::<TestCachable>::new_read_only(tmp_path.clone());
+        cache_store.load().await.unwrap();
+
+        assert_eq!(Some(2), cache_store.find_output(&1, &()).await);
+        assert!(cache_store.store(3, 4, Default::default()).await.is_err());
+        assert!(!tmp_path.join("3.test").exists());
+        assert_eq!(1, cache_store.store_failures());
+
+        std::fs::write(tmp_path.join("5.test"), "corrupted").unwrap();
+
+        let cache_store = CacheStore::<TestCachable>::new_read_only(tmp_path.clone());
+        assert!(cache_store.load().await.is_err());
+
+        let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone());
+        cache_store.load().await.unwrap();
+        assert_eq!(Some(2), cache_store.find_output(&1, &()).await);
+    }
+
+    #[tokio::test]
+    async fn it_loads_entries_stored_by_another_store() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let cache_store1 = CacheStore::<TestCachable>::new(tmp_path.clone());
+        let cache_store2 = CacheStore::<TestCachable>::new(tmp_path.clone());
+
+        let _ = cache_store1.store(1, 2, Default::default()).await.unwrap();
+        assert_eq!(None, cache_store2.find_output(&1, &()).await);
+
+        cache_store2.load().await.unwrap();
+        assert_eq!(Some(2), cache_store2.find_output(&1, &()).await);
+
+        let _ = cache_store2.store(1, 2, Default::default()).await.unwrap();
+        cache_store2.load().await.unwrap();
+        assert_eq!(1, cache_store2.file_names().await.len());
+    }
+
+    #[tokio::test]
+    async fn it_imports_entries_from_another_store() {
+        let tmp_dir1 = TempDir::new("inference_store_test").unwrap();
+        let tmp_dir2 = TempDir::new("inference_store_test").unwrap();
+        let cache_store1 = CacheStore::<TestCachable>::new(tmp_dir1.path().to_path_buf());
+        let cache_store2 = CacheStore::<TestCachable>::new_read_only(tmp_dir2.path().to_path_buf());
+
+        let _ = cache_store1.store(1, 2, Default::default()).await.unwrap();
+        assert_eq!(vec!["1.test".to_string()], cache_store1.file_names().await);
+
+        let content = cache_store1.read_file("1.test").await.unwrap();
+        assert!(cache_store2.import("1.test", &content).await.unwrap());
+        assert!(!cache_store2.import("1.test", &content).await.unwrap());
+        assert_eq!(Some(2), cache_store2.find_output(&1, &()).await);
+
+        assert_eq!(None, cache_store1.read_file("3.test").await);
+        assert!(cache_store2.import("../3.test", b"4").await.is_err());
+        assert!(cache_store2.import("3.test", b"corrupted").await.is_err());
+        assert!(!tmp_dir2.path().join("3.test").exists());
+    }
+
+    #[tokio::test]
+    async fn it_only_loads_entries_with_the_required_labels() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let cache_store = CacheStore::<CachableModelInfer>::new(tmp_path.clone());
+
+        for session in ["a", "b"] {
+            let mut input = BASE_INFER_INPUT.clone();
+            input.namespace = Some(session.to_string());
+            let labels = BTreeMap::from([("session".to_string(), session.to_string())]);
+            cache_store
+                .store(
+                    input,
+                    BASE_INFER_OUTPUT.clone(),
+                    EntryMetadata::default().with_labels(labels),
+                )
+                .await
+                .unwrap();
+        }
+
+        let required_labels = BTreeMap::from([("session".to_string(), "b".to_string())]);
+        let cache_store = CacheStore::<CachableModelInfer>::new_read_only(tmp_path.clone())
+            .with_required_labels(required_labels);
+        cache_store.load().await.unwrap();
+        cache_store.load().await.unwrap();
+
+        let entries = cache_store.entries(|_| true).await;
+        assert_eq!(1, entries.len());
+        assert_eq!(Some("b".to_string()), entries[0].0.namespace);
+    }
+
+    #[tokio::test]
+    async fn it_only_loads_entries_recorded_before_the_selected_time() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let cache_store = CacheStore::<CachableModelInfer>::new(tmp_path.clone());
+
+        for (namespace, recorded_at) in [("old", 1000), ("new", 3000)] {
+            let mut input = BASE_INFER_INPUT.clone();
+            input.namespace = Some(namespace.to_string());
+            let metadata = EntryMetadata {
+                recorded_at: Some(recorded_at),
+                ..Default::default()
+            };
+            cache_store
+                .store(input, BASE_INFER_OUTPUT.clone(), metadata)
+                .await
+                .unwrap();
+        }
+
+        let cache_store = CacheStore::<CachableModelInfer>::new_read_only(tmp_path.clone())
+            .with_recorded_before(Some(2000));
+        cache_store.load().await.unwrap();
+
+        let entries = cache_store.entries(|_| true).await;
+        assert_eq!(1, entries.len());
+        assert_eq!(Some("old".to_string()), entries[0].0.namespace);
+    }
+
+    #[tokio::test]
+    async fn it_prunes_unpinned_entries() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone());
+
+        let _ = cache_store.store(1, 2, Default::default()).await.unwrap();
+        let _ = cache_store.store(3, 4, Default::default()).await.unwrap();
+        let _ = cache_store.store(5, 6, Default::default()).await.unwrap();
+        std::fs::write(tmp_path.join(PINNED_MANIFEST), r#"["1.test"]"#).unwrap();
+
+        let removed = cache_store.prune(|c| c.input != 5).await.unwrap();
+
+        assert_eq!(1, removed);
+        assert!(tmp_path.join("1.test").exists());
+        assert!(!tmp_path.join("3.test").exists());
+        assert!(tmp_path.join("5.test").exists());
+        assert_eq!(None, cache_store.find_output(&3, &()).await);
+        assert_eq!(Some(2), cache_store.find_output(&1, &()).await);
+    }
+
+    #[tokio::test]
+    async fn it_shares_entries_through_a_storage_backend() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let backend: Arc<dyn StorageBackend> =
+            Arc::new(DirectoryBackend::new(tmp_dir.path().join("backend")).unwrap());
+        let dir1 = tmp_dir.path().join("instance1");
+        let dir2 = tmp_dir.path().join("instance2");
+        std::fs::create_dir_all(&dir1).unwrap();
+        std::fs::create_dir_all(&dir2).unwrap();
+        let cache_store1 = CacheStore::<TestCachable>::new(dir1).with_backend(backend.clone());
+        let cache_store2 =
+            CacheStore::<TestCachable>::new_read_only(dir2.clone()).with_backend(backend.clone());
+
+        let _ = cache_store1.store(1, 2, Default::default()).await.unwrap();
+        let _ = cache_store1.store(3, 4, Default::default()).await.unwrap();
+        assert_eq!(Some(b"2".to_vec()), backend.read("1.test").await.unwrap());
+
+        cache_store2.load().await.unwrap();
+        assert!(dir2.join("1.test").exists());
+        assert_eq!(Some(4), cache_store2.find_output(&3, &()).await);
+
+        cache_store1.prune(|c| c.input == 1).await.unwrap();
+        assert_eq!(None, backend.read("1.test").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn it_finds_the_nearest_output() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let cache_store = CacheStore::<TestCachable>::new(tmp_path.clone());
+
+        let _ = cache_store.store(1, 2, Default::default()).await.unwrap();
+        let _ = cache_store.store(5, 6, Default::default()).await.unwrap();
+
+        assert_eq!(None, cache_store.find_output(&4, &()).await);
+        assert_eq!(
+            Some((6, 1.0)),
+            cache_store
+                .find_nearest_output(&4, &())
+                .await
+                .map(|(output, distance, _)| (output, distance))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_consults_the_fallback_directories_in_order() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let dirs: Vec<PathBuf> = ["branch", "base"]
+            .iter()
+            .map(|name| tmp_dir.path().join(name))
+            .collect();
+        for dir in &dirs {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let branch_store = CacheStore::<TestCachable>::new(dirs[0].clone());
+        let _ = branch_store.store(1, 2, Default::default()).await.unwrap();
+        let base_store = CacheStore::<TestCachable>::new(dirs[1].clone());
+        let _ = base_store.store(1, 3, Default::default()).await.unwrap();
+        let _ = base_store.store(4, 5, Default::default()).await.unwrap();
+
+        let cache_store = CacheStore::<TestCachable>::new_read_only(dirs[0].clone())
+            .with_fallback(CacheStore::new_read_only(dirs[1].clone()));
+        cache_store.load().await.unwrap();
+
+        assert_eq!(Some(2), cache_store.find_output(&1, &()).await);
+        assert_eq!(Some(5), cache_store.find_output(&4, &()).await);
+        assert_eq!(None, cache_store.find_output(&6, &()).await);
+        assert_eq!(2, cache_store.count(&1, &()).await);
+        assert_eq!(3, cache_store.entries(|_| true).await.len());
+        assert_eq!(3, cache_store.file_names().await.len());
+        assert_eq!(3, cache_store.usage().await[&Default::default()].entries);
+        assert!(cache_store.read_file("4.test").await.is_some());
+        assert_eq!(1, cache_store.hits()["4.test"].count);
+    }
+
+    #[tokio::test]
+    async fn it_records_to_the_overlay_of_a_read_only_base() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let (overlay, base) = (tmp_dir.path().join("overlay"), tmp_dir.path().join("base"));
+        std::fs::create_dir_all(&overlay).unwrap();
+        std::fs::create_dir_all(&base).unwrap();
+        let base_store = CacheStore::<TestCachable>::new(base.clone());
+        let _ = base_store.store(1, 2, Default::default()).await.unwrap();
+
+        let cache_store = CacheStore::<TestCachable>::new(overlay.clone())
+            .with_fallback(CacheStore::new_read_only(base.clone()));
+        cache_store.load().await.unwrap();
+        assert_eq!(Some(2), cache_store.find_output(&1, &()).await);
+
+        let _ = cache_store.store(3, 4, Default::default()).await.unwrap();
+        // Only the entries of the overlay can be evicted.
+        assert_eq!(
+            HashSet::from(["3.test".to_string()]),
+            cache_store.least_used(2).await
+        );
+        assert!(overlay.join("3.test").exists());
+        assert!(!base.join("3.test").exists());
+        assert_eq!(Some(4), cache_store.find_output(&3, &()).await);
+
+        cache_store.prune(|_| true).await.unwrap();
+        assert!(base.join("1.test").exists());
+        assert_eq!(Some(2), cache_store.find_output(&1, &()).await);
+    }
+
+    #[tokio::test]
+    async fn it_loads_the_base_when_the_overlay_does_not_exist_yet() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let (overlay, base) = (tmp_dir.path().join("overlay"), tmp_dir.path().join("base"));
+        std::fs::create_dir_all(&base).unwrap();
+        let base_store = CacheStore::<TestCachable>::new(base.clone());
+        let _ = base_store.store(1, 2, Default::default()).await.unwrap();
+
+        let cache_store = CacheStore::<TestCachable>::new(overlay.clone())
+            .with_fallback(CacheStore::new_read_only(base));
+        // The caller creates the overlay after the NotFound error.
+        let err = cache_store.load().await.unwrap_err();
+        assert!(err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound));
+        assert_eq!(Some(2), cache_store.find_output(&1, &()).await);
+
+        // A base that does not exist is not reported as an overlay to create.
+        let cache_store = CacheStore::<TestCachable>::new(tmp_dir.path().join("base"))
+            .with_fallback(CacheStore::new_read_only(overlay));
+        let err = cache_store.load().await.unwrap_err();
+        assert!(err.downcast_ref::<std::io::Error>().is_none());
+    }
 }
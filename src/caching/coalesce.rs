@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::watch;
+
+/// Tracks the requests that are being forwarded to the target server, so identical requests that
+/// arrive in the meantime wait for the result of the first one instead of forwarding it again.
+pub struct InFlight<T> {
+    requests: Mutex<HashMap<String, watch::Sender<Option<T>>>>,
+}
+
+impl<T> Default for InFlight<T> {
+    fn default() -> Self {
+        Self {
+            requests: Default::default(),
+        }
+    }
+}
+
+/// The role of a request among the identical requests in flight.
+pub enum Flight<T> {
+    /// The request is forwarded, identical requests wait until the guard is dropped.
+    Leader(FlightGuard<T>),
+    /// An identical request is forwarded, of which the result is sent to the receiver.
+    Follower(watch::Receiver<Option<T>>),
+}
+
+pub struct FlightGuard<T> {
+    in_flight: Arc<InFlight<T>>,
+    key: String,
+}
+
+impl<T> FlightGuard<T> {
+    /// Share the result of the leading request with the requests that wait for it. The result is
+    /// only built when any request waits.
+    pub fn finish<F: FnOnce() -> T>(&self, result: F) {
+        let requests = self
+            .in_flight
+            .requests
+            .lock()
+            .expect("in flight lock is not poisoned");
+        if let Some(sender) = requests.get(&self.key) {
+            if sender.receiver_count() > 0 {
+                sender.send_replace(Some(result()));
+            }
+        }
+    }
+}
+
+impl<T> Drop for FlightGuard<T> {
+    fn drop(&mut self) {
+        // Dropping the sender wakes up the followers.
+        self.in_flight
+            .requests
+            .lock()
+            .expect("in flight lock is not poisoned")
+            .remove(&self.key);
+    }
+}
+
+impl<T> InFlight<T> {
+    /// Lead the requests with the key, or follow the request that already leads them.
+    pub fn join(self: &Arc<Self>, key: String) -> Flight<T> {
+        let mut requests = self
+            .requests
+            .lock()
+            .expect("in flight lock is not poisoned");
+        if let Some(sender) = requests.get(&key) {
+            return Flight::Follower(sender.subscribe());
+        }
+
+        requests.insert(key.clone(), watch::channel(None).0);
+        Flight::Leader(FlightGuard {
+            in_flight: self.clone(),
+            key,
+        })
+    }
+}
+
+/// Wait until the leader of a request is done, and return the result it shared. A leader that
+/// fails before it has a result, e.g. because its client went away, shares none.
+pub async fn wait_for_leader<T: Clone>(mut leader: watch::Receiver<Option<T>>) -> Option<T> {
+    while leader.changed().await.is_ok() {}
+    let result = leader.borrow().clone();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_lets_followers_wait_for_the_leader() {
+        let in_flight = Arc::new(InFlight::<u32>::default());
+
+        let Flight::Leader(guard) = in_flight.join("a".to_string()) else {
+            panic!("first request does not lead");
+        };
+        assert!(matches!(in_flight.join("b".to_string()), Flight::Leader(_)));
+        let Flight::Follower(leader) = in_flight.join("a".to_string()) else {
+            panic!("identical request does not follow");
+        };
+
+        let waiting = tokio::spawn(wait_for_leader(leader));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+
+        guard.finish(|| 7);
+        // Followers that join after the result is shared still get it.
+        let Flight::Follower(late) = in_flight.join("a".to_string()) else {
+            panic!("identical request does not follow");
+        };
+        drop(guard);
+        assert_eq!(Some(7), waiting.await.unwrap());
+        assert_eq!(Some(7), wait_for_leader(late).await);
+
+        // A leader that is dropped without a result shares none.
+        let Flight::Leader(guard) = in_flight.join("a".to_string()) else {
+            panic!("request does not lead after the leader is done");
+        };
+        let Flight::Follower(leader) = in_flight.join("a".to_string()) else {
+            panic!("identical request does not follow");
+        };
+        drop(guard);
+        assert_eq!(None, wait_for_leader(leader).await);
+    }
+}
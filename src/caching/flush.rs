@@ -0,0 +1,139 @@
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use std::fs::File;
+use std::io::ErrorKind::NotFound;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The queue of the cache files written by this process, see `FlushQueue`.
+pub static FLUSH_QUEUE: Lazy<FlushQueue> = Lazy::new(FlushQueue::default);
+
+/// The maximum number of queued files. Files written while the queue is full are synced
+/// immediately, so the files at risk on a crash and the memory of the queue stay bounded.
+const MAX_PENDING: usize = 100_000;
+
+/// Decides when new cache files are synced to disk. By default every file is synced before it is
+/// linked into place. When syncs are deferred, the files are queued and synced in batches by
+/// `sync_pending`, trading durability for the throughput of recording.
+#[derive(Default)]
+pub struct FlushQueue {
+    // The files that are not synced yet, None when files are synced immediately.
+    pending: Mutex<Option<Vec<PathBuf>>>,
+}
+
+impl FlushQueue {
+    /// Queue the files that are written from now on, instead of syncing them immediately.
+    pub fn defer(&self) {
+        self.pending.lock().unwrap().get_or_insert_with(Vec::new);
+    }
+
+    /// Sync a newly written file now, or queue it when syncs are deferred and the queue is not
+    /// full.
+    pub fn sync(&self, file: &File, path: &Path) -> std::io::Result<()> {
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) if pending.len() < MAX_PENDING => {
+                pending.push(path.to_path_buf());
+                Ok(())
+            }
+            _ => file.sync_all(),
+        }
+    }
+
+    /// Sync all queued files. Files that were removed since they were written are skipped, files
+    /// that could not be synced stay queued for the next sync. Returns the number of synced files.
+    pub fn sync_pending(&self) -> anyhow::Result<usize> {
+        let paths = match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => std::mem::take(pending),
+            None => return Ok(0),
+        };
+
+        let mut synced = 0;
+        let mut failed = vec![];
+        let mut first_err = None;
+        for path in paths {
+            match File::open(&path).and_then(|file| file.sync_all()) {
+                Ok(()) => synced += 1,
+                Err(err) if err.kind() == NotFound => {}
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                    failed.push(path);
+                }
+            }
+        }
+
+        let Some(err) = first_err else {
+            return Ok(synced);
+        };
+        if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+            pending.extend(failed.iter().cloned());
+        }
+        anyhow::bail!(
+            "could not sync {} of the queued files, like {}: {err}",
+            failed.len(),
+            failed[0].display()
+        )
+    }
+
+    /// Sync the queued files every interval, on the blocking thread pool.
+    pub async fn sync_periodically(&'static self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            match tokio::task::spawn_blocking(|| self.sync_pending()).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => warn!("could not sync the cache files to disk: {err}"),
+                Err(err) => warn!("could not sync the cache files to disk: {err}"),
+            }
+        }
+    }
+
+    /// Sync the queued files before the process exits.
+    pub fn sync_on_shutdown(&self) {
+        match self.sync_pending() {
+            Ok(0) => {}
+            Ok(synced) => info!("Synced {synced} cache files to disk"),
+            Err(err) => warn!("could not sync the cache files to disk: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn it_defers_syncs() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("entry.inferstore");
+        fs::write(&path, "entry").unwrap();
+        let file = File::open(&path).unwrap();
+
+        let queue = FlushQueue::default();
+        queue.sync(&file, &path).unwrap();
+        assert_eq!(0, queue.sync_pending().unwrap());
+
+        queue.defer();
+        queue.sync(&file, &path).unwrap();
+        queue
+            .sync(&file, &tmp_dir.path().join("pruned.inferstore"))
+            .unwrap();
+        assert_eq!(1, queue.sync_pending().unwrap());
+        assert_eq!(0, queue.sync_pending().unwrap());
+
+        // Files that can not be synced stay queued, without dropping the other files.
+        let not_a_dir = path.join("entry.inferstore");
+        queue.sync(&file, &not_a_dir).unwrap();
+        queue.sync(&file, &path).unwrap();
+        assert!(queue.sync_pending().is_err());
+        assert_eq!(
+            vec![not_a_dir],
+            *queue.pending.lock().unwrap().as_ref().unwrap()
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind::{AlreadyExists, NotFound};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::caching::flush::FLUSH_QUEUE;
+
+/// The file in the cache directory that is used for advisory locking between processes.
+pub const LOCK_FILE: &str = ".inferencestore.lock";
+
+/// An advisory lock on a cache directory, shared by every InferenceStore process that uses the
+/// directory. The lock is released when it is dropped.
+pub struct DirLock {
+    _file: Option<File>,
+}
+
+impl DirLock {
+    /// Lock the directory for reading. Multiple readers can hold the lock at the same time. The
+    /// lock file is not created, so read-only directories can be read without any lock.
+    pub fn shared<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let file = match File::open(dir.as_ref().join(LOCK_FILE)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == NotFound => return Ok(DirLock { _file: None }),
+            Err(err) => return Err(err.into()),
+        };
+        file.lock_shared()?;
+
+        Ok(DirLock { _file: Some(file) })
+    }
+
+    /// Lock the directory for writing, waiting until no other process holds the lock.
+    pub fn exclusive<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.as_ref().join(LOCK_FILE))?;
+        file.lock()?;
+
+        Ok(DirLock { _file: Some(file) })
+    }
+
+    /// Lock the directory for reading like `shared`, waiting on the blocking thread pool so the
+    /// async runtime is not stalled while another process holds the lock.
+    pub async fn shared_async<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || Self::shared(dir)).await?
+    }
+
+    /// Lock the directory for writing like `exclusive`, waiting on the blocking thread pool, see
+    /// `shared_async`.
+    pub async fn exclusive_async<P: AsRef<Path>>(dir: P) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || Self::exclusive(dir)).await?
+    }
+}
+
+/// Write a new file by writing a temporary file first and linking it into place, so other
+/// processes never read a partially written file. Fails with an `AlreadyExists` io error when the
+/// file already exists. The file is synced to disk before it is linked, unless syncs are deferred,
+/// see `FlushQueue`.
+pub fn write_new<P, F>(path: P, write: F) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> anyhow::Result<()>,
+{
+    let path = path.as_ref();
+    let tmp_path = tmp_path(path);
+
+    let result = (|| {
+        let mut writer = BufWriter::new(File::create_new(&tmp_path)?);
+        write(&mut writer)?;
+        writer.flush()?;
+        FLUSH_QUEUE.sync(writer.get_ref(), path)?;
+
+        // Unlike a rename, a hard link fails when the destination already exists.
+        fs::hard_link(&tmp_path, path)?;
+
+        Ok(())
+    })();
+
+    let _ = fs::remove_file(&tmp_path);
+
+    result
+}
+
+/// Write a file by writing a temporary file first and renaming it into place, replacing the file
+/// when it exists. Other processes read either the old or the new file, never a partial file.
+pub fn write_replace<P, F>(path: P, write: F) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> anyhow::Result<()>,
+{
+    let path = path.as_ref();
+    let tmp_path = tmp_path(path);
+
+    let result = (|| {
+        let mut writer = BufWriter::new(File::create_new(&tmp_path)?);
+        write(&mut writer)?;
+        writer.flush()?;
+        fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+/// Check if an error is caused by a file that already exists.
+pub fn is_already_exists(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == AlreadyExists)
+}
+
+/// A temporary file next to the path, unique per process and per write, so concurrent writes of
+/// the same file never share a temporary file.
+fn tmp_path(path: &Path) -> PathBuf {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+
+    path.with_file_name(format!(".{file_name}.{}.{write}.tmp", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn it_writes_new_files_once() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("entry.inferstore");
+
+        write_new(&path, |writer| Ok(writer.write_all(b"first")?)).unwrap();
+        let err = write_new(&path, |writer| Ok(writer.write_all(b"second")?)).unwrap_err();
+
+        assert!(is_already_exists(&err));
+        assert_eq!("first", fs::read_to_string(&path).unwrap());
+        assert_eq!(1, fs::read_dir(tmp_dir.path()).unwrap().count());
+
+        write_replace(&path, |writer| Ok(writer.write_all(b"second")?)).unwrap();
+        assert_eq!("second", fs::read_to_string(&path).unwrap());
+        assert_eq!(1, fs::read_dir(tmp_dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn it_locks_the_directory() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+
+        // Without a lock file nothing is created for readers.
+        drop(DirLock::shared(tmp_dir.path()).unwrap());
+        assert!(!tmp_dir.path().join(LOCK_FILE).exists());
+
+        drop(DirLock::exclusive(tmp_dir.path()).unwrap());
+        let _first = DirLock::shared(tmp_dir.path()).unwrap();
+        let _second = DirLock::shared(tmp_dir.path()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_locks_the_directory_off_the_runtime() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+
+        drop(DirLock::exclusive_async(tmp_dir.path()).await.unwrap());
+        assert!(tmp_dir.path().join(LOCK_FILE).exists());
+        let _shared = DirLock::shared_async(tmp_dir.path()).await.unwrap();
+    }
+}
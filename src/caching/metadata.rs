@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The label that holds the session an entry was recorded in.
+pub const SESSION_LABEL: &str = "session";
+
+/// The label that marks an entry as single-use when set to `true`, see
+/// `CacheStore::with_single_use_models`.
+pub const SINGLE_USE_LABEL: &str = "single_use";
+
+/// The W3C trace context header of the request an entry was recorded for.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Information about how and when an entry was recorded, stored alongside the entry for auditing.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct EntryMetadata {
+    // The time the entry was recorded, in milliseconds since the unix epoch.
+    pub recorded_at: Option<i64>,
+
+    // The address of the target server the response was received from.
+    pub target_server: Option<String>,
+
+    // The identity of the target server, the configured name or the name and version it reports.
+    pub target_name: Option<String>,
+
+    // The time it took the target server to respond, in milliseconds.
+    pub latency_ms: Option<u64>,
+
+    // The address of the client that did the request.
+    pub client_peer: Option<String>,
+
+    // The version of InferenceStore that recorded the entry.
+    pub inference_store_version: Option<String>,
+
+    // The number of the sample among the samples forwarded for the same input, when multiple
+    // samples are recorded per input.
+    pub sample: Option<u64>,
+
+    // The W3C traceparent of the request the entry was recorded for, with the trace and span id,
+    // so the entry can be traced back to the request that produced it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+
+    // Labels describing the provenance of the entry, e.g. the git SHA or the dataset it was
+    // recorded for.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl EntryMetadata {
+    pub fn new(target_server: &str, latency: Duration, client_peer: Option<SocketAddr>) -> Self {
+        EntryMetadata {
+            recorded_at: Some(unix_millis(SystemTime::now())),
+            target_server: Some(target_server.to_string()),
+            target_name: None,
+            latency_ms: Some(latency.as_millis() as u64),
+            client_peer: client_peer.map(|peer| peer.to_string()),
+            inference_store_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            sample: None,
+            traceparent: None,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Record the identity of the target server, an empty name is not recorded.
+    pub fn with_target_name(mut self, target_name: &str) -> Self {
+        self.target_name = (!target_name.is_empty()).then(|| target_name.to_string());
+        self
+    }
+
+    /// Record the trace context of the request, a traceparent that is not in the W3C format is
+    /// not recorded.
+    pub fn with_traceparent(mut self, traceparent: Option<&str>) -> Self {
+        self.traceparent = traceparent
+            .filter(|traceparent| is_traceparent(traceparent))
+            .map(str::to_string);
+        self
+    }
+
+    pub fn with_labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Drop the latency, client, trace context and InferenceStore version, which differ every time
+    /// the same entry is recorded. The recording time is kept, as the modification time of a file
+    /// is not kept in version control.
+    pub fn without_volatile_fields(self) -> Self {
+        EntryMetadata {
+            latency_ms: None,
+            client_peer: None,
+            inference_store_version: None,
+            traceparent: None,
+            ..self
+        }
+    }
+}
+
+/// Check if a traceparent has the W3C `<version>-<trace id>-<span id>-<flags>` format, with a
+/// trace id and span id that are not all zeros.
+fn is_traceparent(traceparent: &str) -> bool {
+    let parts: Vec<&str> = traceparent.trim().split('-').collect();
+    let is_hex = |part: &str, len: usize| {
+        part.len() == len
+            && part
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+    };
+    let is_zero = |part: &str| part.chars().all(|c| c == '0');
+
+    matches!(parts.as_slice(), [version, trace_id, span_id, flags, ..]
+        if is_hex(version, 2)
+            && *version != "ff"
+            && is_hex(trace_id, 32)
+            && !is_zero(trace_id)
+            && is_hex(span_id, 16)
+            && !is_zero(span_id)
+            && is_hex(flags, 2))
+}
+
+/// Parse labels in the `key=value,key=value` format of the labels metadata header. Pairs without
+/// a key are skipped.
+pub fn parse_labels(value: &str) -> BTreeMap<String, String> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let key = key.trim();
+            (!key.is_empty()).then(|| (key.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Check if the labels of an entry contain every label of a filter, with the same value.
+pub fn has_labels<'a>(
+    labels: &BTreeMap<String, String>,
+    filter: impl IntoIterator<Item = (&'a String, &'a String)>,
+) -> bool {
+    filter
+        .into_iter()
+        .all(|(key, value)| labels.get(key) == Some(value))
+}
+
+/// Convert a time to milliseconds since the unix epoch.
+pub fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_creates_metadata() {
+        let metadata = EntryMetadata::new(
+            "http://localhost:8001",
+            Duration::from_millis(12),
+            Some("127.0.0.1:5000".parse().unwrap()),
+        );
+
+        assert!(metadata.recorded_at.is_some());
+        assert_eq!(
+            Some("http://localhost:8001".to_string()),
+            metadata.target_server
+        );
+        assert_eq!(Some(12), metadata.latency_ms);
+        assert_eq!(Some("127.0.0.1:5000".to_string()), metadata.client_peer);
+        assert_eq!(
+            Some(env!("CARGO_PKG_VERSION").to_string()),
+            metadata.inference_store_version
+        );
+        assert_eq!(None, metadata.target_name);
+        assert_eq!(
+            Some("triton-a".to_string()),
+            metadata.with_target_name("triton-a").target_name
+        );
+    }
+
+    #[test]
+    fn it_records_valid_traceparents() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        assert_eq!(
+            Some(traceparent.to_string()),
+            EntryMetadata::default()
+                .with_traceparent(Some(traceparent))
+                .traceparent
+        );
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            let metadata = EntryMetadata::default().with_traceparent(Some(invalid));
+            assert_eq!(None, metadata.traceparent, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn it_parses_and_filters_labels() {
+        let labels = parse_labels("git_sha=abc123, dataset = val ,=skipped,invalid");
+        assert_eq!(
+            BTreeMap::from([
+                ("dataset".to_string(), "val".to_string()),
+                ("git_sha".to_string(), "abc123".to_string()),
+            ]),
+            labels
+        );
+
+        assert!(has_labels(&labels, &BTreeMap::new()));
+        assert!(has_labels(&labels, &parse_labels("dataset=val")));
+        assert!(!has_labels(&labels, &parse_labels("dataset=train")));
+        assert!(!has_labels(&BTreeMap::new(), &parse_labels("dataset=val")));
+    }
+}
@@ -0,0 +1,358 @@
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::caching::pack;
+
+/// The version of the on-disk entry format that is written by this version of InferenceStore.
+/// Bump it together with adding a migration whenever the stored format changes.
+pub const FORMAT_VERSION: u64 = 5;
+
+/// The key in a stored entry that holds its format version. Entries without it have version 0.
+pub const FORMAT_VERSION_KEY: &str = "format_version";
+
+/// The key in the header of a framed entry that holds the lengths of the blobs that follow it.
+pub const BLOB_LENGTHS_KEY: &str = "blob_lengths";
+
+/// Whether new entries are written as canonical JSON, see `use_canonical_json`.
+static CANONICAL_JSON: AtomicBool = AtomicBool::new(false);
+
+/// A migration upgrades a stored entry from one format version to the next one.
+pub type Migration = fn(Value) -> anyhow::Result<Value>;
+
+/// Upgrade a stored entry to the current format version. The migration at index `i` upgrades an
+/// entry from version `i` to version `i + 1`, so `migrations` needs `FORMAT_VERSION` entries.
+/// Returns the upgraded entry and whether any migration was applied.
+pub fn migrate(entry: Value, migrations: &[Migration]) -> anyhow::Result<(Value, bool)> {
+    let version = match &entry {
+        Value::Object(map) => map.get(FORMAT_VERSION_KEY).map_or(Some(0), Value::as_u64),
+        _ => Some(0),
+    };
+    let version = match version {
+        Some(version) => version,
+        None => anyhow::bail!("entry has an invalid {FORMAT_VERSION_KEY}"),
+    };
+
+    if version > FORMAT_VERSION {
+        anyhow::bail!(
+            "entry format version {version} is newer than the supported version {FORMAT_VERSION}"
+        );
+    }
+
+    let mut entry = entry;
+    for migration in &migrations[version as usize..FORMAT_VERSION as usize] {
+        entry = migration(entry)?;
+    }
+
+    if version == FORMAT_VERSION {
+        return Ok((entry, false));
+    }
+
+    match &mut entry {
+        Value::Object(map) => {
+            map.insert(FORMAT_VERSION_KEY.to_string(), FORMAT_VERSION.into());
+        }
+        _ => anyhow::bail!("migrated entry is not an object"),
+    }
+
+    Ok((entry, true))
+}
+
+/// Write a framed entry: a JSON header line followed by the raw blobs. Large tensors are written
+/// as they are, instead of as base64 strings that have to be built in memory first.
+pub fn write_framed<W, T, B>(writer: &mut W, entry: &T, blobs: &[B]) -> anyhow::Result<()>
+where
+    W: Write,
+    T: Serialize,
+    B: AsRef<[u8]>,
+{
+    let lengths: Vec<usize> = blobs.iter().map(|blob| blob.as_ref().len()).collect();
+    write_header(writer, serde_json::to_value(entry)?, Some(lengths))?;
+
+    for blob in blobs {
+        writer.write_all(blob.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// Write new entries as canonical JSON from now on, for cache directories that are kept in
+/// version control. Recording identical traffic again then writes identical files.
+pub fn use_canonical_json() {
+    CANONICAL_JSON.store(true, Ordering::Relaxed);
+}
+
+pub fn is_canonical_json() -> bool {
+    CANONICAL_JSON.load(Ordering::Relaxed)
+}
+
+/// Write an entry as a single JSON document, canonical when `use_canonical_json` was called.
+pub fn write_json<W: Write, T: Serialize>(writer: &mut W, entry: &T) -> anyhow::Result<()> {
+    match is_canonical_json() {
+        true => write_canonical(writer, entry),
+        false => Ok(serde_json::to_writer(writer, entry)?),
+    }
+}
+
+/// Write an entry as canonical JSON: pretty-printed, with the keys of all objects sorted and a
+/// trailing newline. Floats are written in their shortest form that reads back to the same value.
+pub fn write_canonical<W: Write, T: Serialize>(writer: &mut W, entry: &T) -> anyhow::Result<()> {
+    // The fields of an object value are sorted by their keys.
+    serde_json::to_writer_pretty(&mut *writer, &serde_json::to_value(entry)?)?;
+    writer.write_all(b"\n")?;
+
+    Ok(())
+}
+
+fn write_header<W: Write>(
+    writer: &mut W,
+    mut header: Value,
+    lengths: Option<Vec<usize>>,
+) -> anyhow::Result<()> {
+    if let Some(lengths) = lengths {
+        match &mut header {
+            Value::Object(map) => map.insert(BLOB_LENGTHS_KEY.to_string(), lengths.into()),
+            _ => anyhow::bail!("framed entry is not an object"),
+        };
+    }
+
+    serde_json::to_writer(&mut *writer, &header)?;
+    writer.write_all(b"\n")?;
+
+    Ok(())
+}
+
+/// Read the header of a stored entry, together with the lengths of the blobs that follow it when
+/// the entry is framed. Entries that are not framed are a single JSON document.
+fn read_header<R: BufRead>(reader: &mut R) -> anyhow::Result<(Value, Option<Vec<usize>>)> {
+    read_header_with_size(reader).map(|(header, lengths, _)| (header, lengths))
+}
+
+/// Read the header of a stored entry like `read_header`, together with its size in bytes.
+fn read_header_with_size<R: BufRead>(
+    reader: &mut R,
+) -> anyhow::Result<(Value, Option<Vec<usize>>, usize)> {
+    let mut content = vec![];
+    reader.read_until(b'\n', &mut content)?;
+
+    let mut header: Value = match serde_json::from_slice(&content) {
+        Ok(header) => header,
+        // Entries that are not framed can span multiple lines.
+        Err(_) => {
+            reader.read_to_end(&mut content)?;
+            serde_json::from_slice(&content)?
+        }
+    };
+
+    let lengths = match &mut header {
+        Value::Object(map) => map.remove(BLOB_LENGTHS_KEY),
+        _ => None,
+    };
+
+    Ok((
+        header,
+        lengths.map(serde_json::from_value).transpose()?,
+        content.len(),
+    ))
+}
+
+/// Read a stored entry, upgrading it in memory when it has an older format version. The blobs of
+/// a framed entry are not read.
+pub fn read_entry<T, P>(path: P, migrations: &[Migration]) -> anyhow::Result<T>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let content = pack::read(path.as_ref())?;
+    let (entry, _) = read_header(&mut &content[..])?;
+    let (entry, _) = migrate(entry, migrations)?;
+
+    Ok(serde_json::from_value(entry)?)
+}
+
+/// Read a stored entry like `read_entry`, together with its blobs when the entry is framed. The
+/// file is memory-mapped, so the blobs reference the mapping instead of being copied. Packed
+/// entries reference the mapping of their pack.
+pub fn read_entry_with_blobs<T, P>(
+    path: P,
+    migrations: &[Migration],
+) -> anyhow::Result<(T, Option<Vec<Bytes>>)>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let mapped = pack::read(path.as_ref())?;
+    let (entry, lengths, header_size) = read_header_with_size(&mut &mapped[..])?;
+    let (entry, _) = migrate(entry, migrations)?;
+
+    let blobs = match lengths {
+        None => None,
+        Some(lengths) => {
+            let mut offset = header_size;
+            let mut blobs = Vec::with_capacity(lengths.len());
+            for length in lengths {
+                // Lengths that overflow can only be read from a corrupted header.
+                let end = match offset.checked_add(length) {
+                    Some(end) if end <= mapped.len() => end,
+                    _ => anyhow::bail!("framed entry is truncated"),
+                };
+                blobs.push(mapped.slice(offset..end));
+                offset = end;
+            }
+
+            Some(blobs)
+        }
+    };
+
+    Ok((serde_json::from_value(entry)?, blobs))
+}
+
+/// Upgrade a stored entry on disk when it has an older format version. Returns whether the file
+/// was rewritten. The blobs of a framed entry are copied without reading them into memory.
+pub fn migrate_file<P: AsRef<Path>>(path: P, migrations: &[Migration]) -> anyhow::Result<bool> {
+    let path = path.as_ref();
+    let mut reader = BufReader::new(File::open(path)?);
+    let (entry, lengths) = read_header(&mut reader)?;
+    let (entry, migrated) = migrate(entry, migrations)?;
+
+    if migrated {
+        let migrated_path = path.with_extension("migrating");
+        let mut writer = BufWriter::new(File::create(&migrated_path)?);
+        match lengths {
+            None => serde_json::to_writer(&mut writer, &entry)?,
+            Some(lengths) => {
+                write_header(&mut writer, entry, Some(lengths))?;
+                io::copy(&mut reader, &mut writer)?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+
+        fs::rename(&migrated_path, path)?;
+    }
+
+    Ok(migrated)
+}
+
+/// Wrap an entry in an object under the given key, for formats that used to store a bare value.
+pub fn wrap_in(key: &str, entry: Value) -> Value {
+    Value::Object(Map::from_iter([(key.to_string(), entry)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn add_name(mut entry: Value) -> anyhow::Result<Value> {
+        entry["name"] = "test".into();
+        Ok(entry)
+    }
+
+    // Adds the name when migrating from version 0, later versions are read as they are.
+    fn migrations() -> Vec<Migration> {
+        let mut migrations: Vec<Migration> = vec![add_name];
+        migrations.resize(FORMAT_VERSION as usize, Ok);
+        migrations
+    }
+
+    #[test]
+    fn it_migrates_unversioned_entries() {
+        let (entry, migrated) = migrate(json!({"input": 1}), &migrations()).unwrap();
+
+        assert!(migrated);
+        assert_eq!(
+            json!({"input": 1, "name": "test", "format_version": FORMAT_VERSION}),
+            entry
+        );
+    }
+
+    #[test]
+    fn it_keeps_current_entries() {
+        let current = json!({"input": 1, "format_version": FORMAT_VERSION});
+        let (entry, migrated) = migrate(current.clone(), &migrations()).unwrap();
+
+        assert!(!migrated);
+        assert_eq!(current, entry);
+    }
+
+    #[test]
+    fn it_rejects_newer_entries() {
+        let newer = json!({"input": 1, "format_version": FORMAT_VERSION + 1});
+
+        assert!(migrate(newer, &migrations()).is_err());
+    }
+
+    #[test]
+    fn it_reads_framed_entries() {
+        let tmp_dir = tempdir::TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("entry.inferstore");
+        let mut file = File::create(&path).unwrap();
+        let blobs: Vec<Bytes> = vec![vec![1, 10, 2].into(), vec![].into(), vec![255; 3].into()];
+
+        write_framed(&mut file, &json!({"input": 1}), &blobs).unwrap();
+        drop(file);
+
+        let content = fs::read(&path).unwrap();
+        assert!(content.ends_with(&[1, 10, 2, 255, 255, 255]));
+
+        let entry: Value = read_entry(&path, &migrations()).unwrap();
+        assert_eq!(None, entry.get(BLOB_LENGTHS_KEY));
+
+        let (entry, read_blobs): (Value, _) = read_entry_with_blobs(&path, &migrations()).unwrap();
+        assert_eq!(Some("test"), entry["name"].as_str());
+        assert_eq!(Some(blobs.clone()), read_blobs);
+
+        assert!(migrate_file(&path, &migrations()).unwrap());
+        let (_, read_blobs): (Value, _) = read_entry_with_blobs(&path, &migrations()).unwrap();
+        assert_eq!(Some(blobs), read_blobs);
+    }
+
+    #[test]
+    fn it_rejects_corrupted_blob_lengths() {
+        let tmp_dir = tempdir::TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("entry.inferstore");
+        let mut content = format!(
+            "{{\"input\":1,\"{BLOB_LENGTHS_KEY}\":[2,{}]}}\n",
+            usize::MAX
+        )
+        .into_bytes();
+        content.extend_from_slice(&[1, 2, 3]);
+        fs::write(&path, content).unwrap();
+
+        let read: anyhow::Result<(Value, _)> = read_entry_with_blobs(&path, &migrations());
+        assert!(read.is_err());
+    }
+
+    #[test]
+    fn it_writes_canonical_json() {
+        let mut content = vec![];
+        write_canonical(
+            &mut content,
+            &json!({"b": {"z": 1.5, "a": [{"y": 0.1, "x": null}]}, "a": 1}),
+        )
+        .unwrap();
+
+        let expected = "{\n  \"a\": 1,\n  \"b\": {\n    \"a\": [\n      {\n        \"x\": null,\n        \"y\": 0.1\n      }\n    ],\n    \"z\": 1.5\n  }\n}\n";
+        assert_eq!(expected, String::from_utf8(content).unwrap());
+    }
+
+    #[test]
+    fn it_migrates_files() {
+        let tmp_dir = tempdir::TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("entry.inferstore");
+        std::fs::write(&path, r#"{"input":1}"#).unwrap();
+
+        assert!(migrate_file(&path, &migrations()).unwrap());
+        assert!(!migrate_file(&path, &migrations()).unwrap());
+
+        let entry: Value = read_entry(&path, &migrations()).unwrap();
+        assert_eq!(Some("test"), entry["name"].as_str());
+    }
+}
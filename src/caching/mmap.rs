@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::path::Path;
+
+use bytes::Bytes;
+use memmap2::Mmap;
+
+/// Map a whole file into shared bytes, of which slices reference the mapping without copying.
+/// The mapping stays valid after the file is removed, and is unmapped when the last slice is
+/// dropped.
+pub fn map_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Bytes> {
+    let file = File::open(path)?;
+
+    // Empty files can not be mapped.
+    if file.metadata()?.len() == 0 {
+        return Ok(Bytes::new());
+    }
+
+    // Entries are never modified after they are written, only replaced or removed.
+    let mmap = unsafe { Mmap::map(&file)? };
+
+    Ok(Bytes::from_owner(mmap))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn it_maps_files() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("entry.inferstore");
+        std::fs::write(&path, b"header\nblob").unwrap();
+
+        let bytes = map_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(b"blob"[..], bytes.slice(7..)[..]);
+
+        std::fs::write(&path, b"").unwrap();
+        assert!(map_file(&path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+use bytes::Bytes;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::caching::lock::{self, DirLock};
+use crate::caching::mmap::map_file;
+
+/// The extension of pack files, which hold the contents of many entries to save inodes and
+/// directory scans. A pack is only read once its index, `<pack>.index`, exists.
+pub const PACK_EXTENSION: &str = "inferpack";
+
+/// The extension of loose entry files.
+const ENTRY_EXTENSION: &str = ".inferstore";
+
+/// The offset and length of every entry in a pack file, by the file name of the entry.
+type PackIndex = BTreeMap<String, (usize, usize)>;
+
+/// An opened pack file, of which the entries reference the memory-mapped pack.
+struct Pack {
+    content: Bytes,
+    index: PackIndex,
+}
+
+/// The opened pack files, so their indexes are only read once.
+static OPEN_PACKS: Lazy<Mutex<HashMap<PathBuf, Arc<Pack>>>> = Lazy::new(Default::default);
+
+fn index_path(pack_path: &Path) -> PathBuf {
+    let mut index_path = pack_path.as_os_str().to_owned();
+    index_path.push(".index");
+
+    PathBuf::from(index_path)
+}
+
+fn is_pack(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == PACK_EXTENSION)
+}
+
+/// Check if the path of an entry is in a pack file. Packed entries have the path of the pack
+/// joined with their file name, e.g. `cache/pack-1.inferpack/config-simple#1.inferstore`.
+pub fn is_packed(path: &Path) -> bool {
+    path.parent().is_some_and(is_pack)
+}
+
+fn open_pack(pack_path: &Path) -> anyhow::Result<Arc<Pack>> {
+    if let Some(pack) = OPEN_PACKS.lock().unwrap().get(pack_path) {
+        return Ok(pack.clone());
+    }
+
+    let index: PackIndex = serde_json::from_slice(&fs::read(index_path(pack_path))?)?;
+    let content = map_file(pack_path)?;
+    if index.values().any(|(offset, length)| {
+        offset
+            .checked_add(*length)
+            .is_none_or(|end| end > content.len())
+    }) {
+        anyhow::bail!("pack file {} is truncated", pack_path.display());
+    }
+
+    let pack = Arc::new(Pack { content, index });
+    OPEN_PACKS
+        .lock()
+        .unwrap()
+        .insert(pack_path.to_path_buf(), pack.clone());
+
+    Ok(pack)
+}
+
+/// Read the content of a loose or packed entry. Loose entries are memory-mapped, and packed
+/// entries reference the mapping of their pack.
+pub fn read(path: &Path) -> anyhow::Result<Bytes> {
+    if !is_packed(path) {
+        return map_file(path);
+    }
+
+    let pack = open_pack(path.parent().unwrap())?;
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    match pack.index.get(file_name.as_ref()) {
+        // The bounds of the entries are checked when the pack is opened.
+        Some(&(offset, length)) => Ok(pack.content.slice(offset..offset + length)),
+        None => anyhow::bail!(
+            "{file_name} is not in pack {}",
+            path.parent().unwrap().display()
+        ),
+    }
+}
+
+/// The modification time of a loose entry, or of the pack of a packed entry.
+pub fn modified(path: &Path) -> anyhow::Result<SystemTime> {
+    match is_packed(path) {
+        true => Ok(fs::metadata(path.parent().unwrap())?.modified()?),
+        false => Ok(fs::metadata(path)?.modified()?),
+    }
+}
+
+/// The size in bytes of a loose or packed entry.
+pub fn size(path: &Path) -> anyhow::Result<u64> {
+    if !is_packed(path) {
+        return Ok(fs::metadata(path)?.len());
+    }
+
+    let pack = open_pack(path.parent().unwrap())?;
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    match pack.index.get(file_name.as_ref()) {
+        Some(&(_, length)) => Ok(length as u64),
+        None => anyhow::bail!(
+            "{file_name} is not in pack {}",
+            path.parent().unwrap().display()
+        ),
+    }
+}
+
+/// The paths of the packs in a directory. Packs without an index are being written, and are
+/// skipped.
+fn pack_paths(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut pack_paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_pack(path) && index_path(path).exists())
+        .collect();
+    pack_paths.sort();
+
+    Ok(pack_paths)
+}
+
+/// The paths of the packed entries in a directory.
+pub fn entries(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for pack_path in pack_paths(dir)? {
+        let pack = open_pack(&pack_path)?;
+        paths.extend(pack.index.keys().map(|file_name| pack_path.join(file_name)));
+    }
+
+    Ok(paths)
+}
+
+/// Remove loose and packed entries. Packed entries are removed from the index of their pack, their
+/// content is dropped at the next compaction. Returns the paths of the removed entries, entries
+/// that could not be removed are logged.
+pub fn remove(paths: &[PathBuf]) -> HashSet<PathBuf> {
+    let mut removed = HashSet::new();
+    let mut packed: HashMap<&Path, Vec<&PathBuf>> = HashMap::new();
+
+    for path in paths {
+        if is_packed(path) {
+            packed.entry(path.parent().unwrap()).or_default().push(path);
+            continue;
+        }
+        match fs::remove_file(path) {
+            Ok(()) => {
+                removed.insert(path.clone());
+            }
+            Err(err) => warn!("could not remove cache file {}: {err}", path.display()),
+        }
+    }
+
+    for (pack_path, paths) in packed {
+        let rewritten = open_pack(pack_path).and_then(|pack| {
+            let mut index = pack.index.clone();
+            for path in &paths {
+                index.remove(path.file_name().unwrap().to_string_lossy().as_ref());
+            }
+            write_index(pack_path, &index)?;
+            OPEN_PACKS.lock().unwrap().remove(pack_path);
+            Ok(())
+        });
+
+        match rewritten {
+            Ok(()) => removed.extend(paths.into_iter().cloned()),
+            Err(err) => warn!(
+                "could not remove entries from pack {}: {err}",
+                pack_path.display()
+            ),
+        }
+    }
+
+    removed
+}
+
+/// Replace the index of a pack, by writing a temporary file and renaming it into place.
+fn write_index(pack_path: &Path, index: &PackIndex) -> anyhow::Result<()> {
+    let index_path = index_path(pack_path);
+    let tmp_path = index_path.with_extension("index.tmp");
+    fs::write(&tmp_path, serde_json::to_vec(index)?)?;
+    fs::rename(&tmp_path, &index_path)?;
+
+    Ok(())
+}
+
+/// Compact the cache directory and the version directories of its model directories, see
+/// `compact_dir`. Returns the number of entries in the new packs.
+pub fn compact(dir: &Path) -> anyhow::Result<usize> {
+    let mut compacted = compact_dir(dir)?;
+    for model_dir in sub_dirs(dir)? {
+        for version_dir in sub_dirs(&model_dir)? {
+            compacted += compact_dir(&version_dir)?;
+        }
+    }
+
+    Ok(compacted)
+}
+
+/// The directories in a directory, except for hidden directories.
+fn sub_dirs(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut sub_dirs: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .collect();
+    sub_dirs.sort();
+
+    Ok(sub_dirs)
+}
+
+/// Compact the loose entries and the existing packs of a directory into a single new pack file,
+/// and remove them. Loose entries replace packed entries with the same file name. Returns the
+/// number of entries in the new pack.
+fn compact_dir(dir: &Path) -> anyhow::Result<usize> {
+    let _lock = DirLock::exclusive(dir)?;
+
+    let mut loose: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            // Temporary files of writes in progress are hidden.
+            file_name.ends_with(ENTRY_EXTENSION) && !file_name.starts_with('.')
+        })
+        .map(|entry| entry.path())
+        .collect();
+    loose.sort();
+    let packed = entries(dir)?;
+    let old_packs = pack_paths(dir)?;
+
+    let mut contents: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for path in packed.into_iter().chain(loose.iter().cloned()) {
+        contents.insert(
+            path.file_name().unwrap().to_string_lossy().to_string(),
+            path,
+        );
+    }
+    if contents.is_empty() && old_packs.is_empty() {
+        return Ok(0);
+    }
+
+    // Packs are numbered, so a new pack never has the name of an old one.
+    let number = old_packs
+        .iter()
+        .filter_map(|path| {
+            path.file_stem()?
+                .to_str()?
+                .strip_prefix("pack-")?
+                .parse()
+                .ok()
+        })
+        .max()
+        .map_or(1, |number: u64| number + 1);
+    let pack_path = dir.join(format!("pack-{number}.{PACK_EXTENSION}"));
+    let mut index = PackIndex::new();
+    lock::write_new(&pack_path, |writer| {
+        let mut offset = 0;
+        for (file_name, path) in &contents {
+            let content = read(path)?;
+            writer.write_all(&content)?;
+            index.insert(file_name.clone(), (offset, content.len()));
+            offset += content.len();
+        }
+        Ok(())
+    })?;
+    // The pack is only read once its index exists, so readers never see a partial pack.
+    lock::write_new(index_path(&pack_path), |writer| {
+        Ok(serde_json::to_writer(writer, &index)?)
+    })?;
+
+    for old_pack in &old_packs {
+        fs::remove_file(index_path(old_pack))?;
+        fs::remove_file(old_pack)?;
+        OPEN_PACKS.lock().unwrap().remove(old_pack);
+    }
+    for path in &loose {
+        fs::remove_file(path)?;
+    }
+
+    info!(
+        "Compacted {} loose entries and {} packs into {}",
+        loose.len(),
+        old_packs.len(),
+        pack_path.display()
+    );
+
+    Ok(index.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn it_compacts_loose_entries_into_packs() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("a.inferstore"), "a").unwrap();
+        fs::write(dir.join("b.inferstore"), "bb").unwrap();
+        fs::write(dir.join("pinned.json"), "[]").unwrap();
+
+        assert_eq!(2, compact(dir).unwrap());
+        assert!(!dir.join("a.inferstore").exists());
+        assert!(dir.join("pinned.json").exists());
+
+        let packed = entries(dir).unwrap();
+        assert_eq!(2, packed.len());
+        assert!(is_packed(&packed[1]));
+        assert_eq!(b"bb"[..], read(&packed[1]).unwrap()[..]);
+        assert_eq!(2, size(&packed[1]).unwrap());
+
+        // New loose entries are compacted together with the existing pack.
+        fs::write(dir.join("c.inferstore"), "c").unwrap();
+        assert_eq!(1, remove(&[packed[0].clone()]).len());
+        assert_eq!(2, compact(dir).unwrap());
+
+        let packed = entries(dir).unwrap();
+        let file_names: Vec<String> = packed
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(vec!["b.inferstore", "c.inferstore"], file_names);
+        assert_eq!(b"c"[..], read(&packed[1]).unwrap()[..]);
+
+        // The version directories of model directories are compacted as well.
+        let version_dir = dir.join("simple").join("1");
+        fs::create_dir_all(&version_dir).unwrap();
+        fs::write(version_dir.join("d.inferstore"), "d").unwrap();
+        assert_eq!(3, compact(dir).unwrap());
+        assert!(!version_dir.join("d.inferstore").exists());
+        assert_eq!(1, entries(&version_dir).unwrap().len());
+    }
+
+    #[test]
+    fn it_rejects_packs_with_overflowing_bounds() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let pack_path = tmp_dir.path().join(format!("pack-1.{PACK_EXTENSION}"));
+        fs::write(&pack_path, "a").unwrap();
+        fs::write(
+            index_path(&pack_path),
+            format!(r#"{{"a.inferstore":[1,{}]}}"#, usize::MAX),
+        )
+        .unwrap();
+
+        let err = read(&pack_path.join("a.inferstore")).unwrap_err();
+        assert!(err.to_string().contains("truncated"));
+    }
+}
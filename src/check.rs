@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure};
+
+use crate::caching::cachable::Cachable;
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::parsing::input::ProcessedInput;
+use crate::parsing::output::ProcessedOutput;
+use crate::service::inference_protocol::grpc_inference_service_client::GrpcInferenceServiceClient;
+use crate::service::inference_protocol::model_infer_request::InferInputTensor;
+use crate::service::inference_protocol::model_infer_response::InferOutputTensor;
+use crate::service::inference_protocol::{
+    ModelInferRequest, ModelInferResponse, ServerReadyRequest,
+};
+use crate::settings::{ServerMode, Settings, TargetServer};
+use crate::verify;
+
+// How long the target server may take to connect and report it is ready.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Check that the store directory is usable and that entries survive a round trip through a
+/// file, and optionally that the target server is ready. Prints a report and returns whether any
+/// of the checks failed. The config itself is checked by loading the settings.
+pub async fn run(settings: &Settings, ping_target: bool) -> bool {
+    let dir = PathBuf::from(&settings.request_collection.path);
+    // Only Collect mode writes to the store directory, in Serve mode it can be read-only and the
+    // round trip is done in the temporary directory.
+    let collects = settings.mode == ServerMode::Collect;
+    let round_trip_dir = match collects {
+        true => dir.clone(),
+        false => std::env::temp_dir(),
+    };
+    let mut checks = vec![
+        ("store directory", check_dir(&dir, collects)),
+        ("entries", check_entries(&dir)),
+        ("round trip", round_trip(&round_trip_dir)),
+    ];
+    if ping_target {
+        checks.push(("target server", ping(&settings.target_server).await));
+    }
+
+    println!("config: ok");
+    let mut failed = false;
+    for (name, result) in checks {
+        match result {
+            Ok(detail) => println!("{name}: ok, {detail}"),
+            Err(err) => {
+                println!("{name}: failed, {err}");
+                failed = true;
+            }
+        }
+    }
+
+    failed
+}
+
+/// Check that the directory exists. When it has to be writable, it is created when missing, and
+/// checked that files can be written to it.
+fn check_dir(dir: &Path, writable: bool) -> anyhow::Result<String> {
+    if !writable {
+        ensure!(dir.is_dir(), "{} is not a directory", dir.display());
+        return Ok(format!("{} exists", dir.display()));
+    }
+
+    fs::create_dir_all(dir)?;
+    ensure!(dir.is_dir(), "{} is not a directory", dir.display());
+
+    let probe = dir.join(format!(".check-{}", std::process::id()));
+    fs::write(&probe, b"").map_err(|err| anyhow!("{} is not writable: {err}", dir.display()))?;
+    fs::remove_file(&probe)?;
+
+    Ok(format!("{} is writable", dir.display()))
+}
+
+fn check_entries(dir: &Path) -> anyhow::Result<String> {
+    let (verified, problems) = verify::verify_dir(dir)?;
+    ensure!(
+        problems.is_empty(),
+        "{} of {verified} entries are invalid, run the verify command for details",
+        problems.len()
+    );
+
+    Ok(format!("{verified} entries are valid"))
+}
+
+/// Write a synthetic entry to a hidden directory in the store directory and read it back.
+fn round_trip(dir: &Path) -> anyhow::Result<String> {
+    let check_dir = dir.join(format!(".check-{}", std::process::id()));
+    fs::create_dir_all(&check_dir)?;
+    let result = round_trip_in(&check_dir);
+    fs::remove_dir_all(&check_dir)?;
+
+    result
+}
+
+fn round_trip_in(dir: &Path) -> anyhow::Result<String> {
+    let request = ModelInferRequest {
+        model_name: "inferencestore-check".to_string(),
+        model_version: "1".to_string(),
+        inputs: vec![InferInputTensor {
+            name: "INPUT".to_string(),
+            datatype: "INT32".to_string(),
+            shape: vec![2],
+            ..Default::default()
+        }],
+        raw_input_contents: vec![[1i32.to_le_bytes(), 2i32.to_le_bytes()].concat()],
+        ..Default::default()
+    };
+    let response = ModelInferResponse {
+        model_name: request.model_name.clone(),
+        model_version: request.model_version.clone(),
+        outputs: vec![InferOutputTensor {
+            name: "OUTPUT".to_string(),
+            datatype: "INT32".to_string(),
+            shape: vec![1],
+            ..Default::default()
+        }],
+        raw_output_contents: vec![3i32.to_le_bytes().to_vec().into()],
+        ..Default::default()
+    };
+    let input = ProcessedInput::from_infer_request(request, &Default::default());
+    let output = ProcessedOutput::from_response(&response);
+
+    let (path, _) = <CachableModelInfer as Cachable>::new(
+        dir,
+        input.clone(),
+        output.clone(),
+        Default::default(),
+    )?;
+    let cachable = CachableModelInfer::from_file(&path)?;
+    ensure!(
+        *cachable.get_input()? == input,
+        "the input read back differs from the written input"
+    );
+    ensure!(
+        cachable.get_output()? == output,
+        "the output read back differs from the written output"
+    );
+    cachable.verify()?;
+
+    Ok("an entry was written and read back".to_string())
+}
+
+async fn ping(target: &TargetServer) -> anyhow::Result<String> {
+    let endpoint = target
+        .connection
+        .endpoint(&target.host)?
+        .connect_timeout(PING_TIMEOUT)
+        .timeout(PING_TIMEOUT);
+    let channel = endpoint.connect().await?;
+    let ready = GrpcInferenceServiceClient::new(channel)
+        .server_ready(ServerReadyRequest {})
+        .await?
+        .into_inner()
+        .ready;
+    ensure!(ready, "{} is not ready", target.host);
+
+    Ok(format!("{} is ready", target.host))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn it_checks_the_store_directory() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let dir = tmp_dir.path().join("cache");
+
+        assert!(check_dir(&dir, false).is_err());
+        assert!(check_dir(&dir, true).is_ok());
+        assert!(check_dir(&dir, false).is_ok());
+        assert!(round_trip(&dir).is_ok());
+        assert_eq!(1, fs::read_dir(tmp_dir.path()).unwrap().count());
+        assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+        assert!(check_entries(&dir).is_ok());
+
+        let file = tmp_dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+        assert!(check_dir(&file, true).is_err());
+        assert!(check_dir(&file, false).is_err());
+    }
+}
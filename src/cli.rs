@@ -0,0 +1,137 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Command-line arguments. Every provided argument overrides the value from the config file and
+/// the environment.
+#[derive(Parser, Default, Clone)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the config file, without the extension.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<String>,
+
+    /// The server mode.
+    #[arg(long, value_parser = ["collect", "serve", "dry_run"])]
+    pub mode: Option<String>,
+
+    /// The port the grpc server listens on.
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// The directory where the cache is stored.
+    #[arg(long, value_name = "PATH")]
+    pub store_path: Option<String>,
+
+    /// The target grpc inference service host.
+    #[arg(long, value_name = "HOST")]
+    pub target: Option<String>,
+
+    /// Remove all cache entries recorded more than this many seconds ago, except for the pinned
+    /// entries, and exit.
+    #[arg(long, value_name = "SECONDS")]
+    pub prune_older_than: Option<u64>,
+
+    /// Remove this many of the least frequently used infer entries, by the hits in the usage
+    /// file, except for the pinned entries, and exit.
+    #[arg(long, value_name = "COUNT")]
+    pub evict_least_used: Option<usize>,
+
+    /// Upgrade all cache entries to the current on-disk format and exit.
+    #[arg(long)]
+    pub migrate: bool,
+
+    /// Print the effective settings and exit.
+    #[arg(long)]
+    pub print_config: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    /// Compare the infer entries of two cache directories and report the added, removed and
+    /// changed entries. Exits with status 1 when the directories differ.
+    Diff {
+        dir_a: PathBuf,
+
+        dir_b: PathBuf,
+
+        /// The maximum difference between numeric output elements that are considered equal.
+        #[arg(long, default_value_t = 0.0)]
+        tolerance: f64,
+    },
+
+    /// Verify that every entry in a cache directory can be loaded, that the hashes in the file
+    /// names match the contents and that the tensor contents fit their shapes and datatypes.
+    /// Exits with status 1 when invalid entries are found.
+    Verify { dir: PathBuf },
+
+    /// Export the infer entries of a cache directory as NPZ files per model and tensor, for
+    /// offline evaluation and retraining.
+    Export {
+        dir: PathBuf,
+
+        out_dir: PathBuf,
+
+        /// Only export the entries recorded with a label, as key=value. Can be repeated.
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+
+    /// Compact the entry files and packs of a cache directory into a single pack file, to save
+    /// inodes and directory scans for caches with many small entries. Run it while no instance
+    /// writes to the directory.
+    Compact { dir: PathBuf },
+
+    /// Check the config, the store directory and a round trip of an entry through a file before
+    /// serving traffic, e.g. in a container init step. Exits with status 1 when a check fails.
+    Check {
+        /// Also check that the target server is ready.
+        #[arg(long)]
+        target: bool,
+    },
+
+    /// Import the requests of a perf_analyzer input data file or a KServe v2 JSON request, and
+    /// record them by sending them to the target server.
+    Import {
+        file: PathBuf,
+
+        /// The model the requests are sent to.
+        #[arg(long)]
+        model: String,
+
+        /// The model version, empty selects the version chosen by the target server.
+        #[arg(long, default_value = "")]
+        model_version: String,
+
+        /// Write the requests as JSON files to this directory instead, to record them later with
+        /// the `warmup.requests` setting.
+        #[arg(long, value_name = "PATH")]
+        out_dir: Option<PathBuf>,
+    },
+
+    /// Replay a request log, with a JSON encoded inference request per line, or the infer entries
+    /// of a cache directory through the match settings of the config, and report per model how
+    /// many entries would be recorded and the hit rate the requests would achieve.
+    Estimate {
+        /// A request log file, or a cache directory recorded with `store_inputs`.
+        source: PathBuf,
+    },
+
+    /// Measure the hashing throughput per datatype, and the startup load time and lookup latency
+    /// of synthetic caches, to catch performance regressions in the matching path.
+    Bench {
+        /// The numbers of entries of the synthetic caches.
+        #[arg(long, value_delimiter = ',', default_values_t = [1000, 10000])]
+        store_sizes: Vec<usize>,
+
+        /// The size of the input and output tensors in bytes.
+        #[arg(long, default_value_t = 4096)]
+        tensor_size: usize,
+
+        /// The number of measured hashes and lookups.
+        #[arg(long, default_value_t = 1000)]
+        iterations: usize,
+    },
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use log::{debug, warn};
+use tokio::task::JoinSet;
+use tonic::Request;
+
+use crate::deadline::call_upstream;
+use crate::parsing::output::ProcessedOutput;
+use crate::parsing::tensor_diff::diff_outputs;
+use crate::pool::ChannelPool;
+use crate::service::inference_protocol::ModelInferRequest;
+
+/// Other target servers that have to agree with the target server on the output of a request
+/// before it is recorded, so entries represent stable model behavior.
+pub struct Consensus {
+    // The hosts of the other target servers, with their channels.
+    backends: Vec<(String, ChannelPool)>,
+    // The number of target servers, including the target server itself, that have to agree.
+    quorum: usize,
+    // The maximum difference between numeric output elements that are considered equal.
+    tolerance: f64,
+}
+
+impl Consensus {
+    /// A quorum of 0 requires all target servers to agree.
+    pub fn new(backends: Vec<(String, ChannelPool)>, quorum: usize, tolerance: f64) -> Self {
+        let quorum = match quorum {
+            0 => backends.len() + 1,
+            quorum => quorum,
+        };
+
+        Self {
+            backends,
+            quorum,
+            tolerance,
+        }
+    }
+
+    /// Send the request to the other target servers, and check if enough of them agree with the
+    /// output of the target server. Resolves as soon as the quorum is reached or can no longer be
+    /// reached, the calls to the remaining target servers are cancelled. Target servers that fail
+    /// to respond do not agree.
+    pub async fn agrees(
+        &self,
+        request: &ModelInferRequest,
+        output: &ProcessedOutput,
+        timeout: Option<Duration>,
+    ) -> bool {
+        let mut calls = JoinSet::new();
+        for (host, pool) in &self.backends {
+            let (host, mut client, request) = (host.clone(), pool.client(), request.clone());
+            calls.spawn(async move {
+                let response =
+                    call_upstream(Request::new(request), timeout, |request| async move {
+                        client.model_infer(request).await
+                    })
+                    .await;
+                (host, response)
+            });
+        }
+
+        let mut agreeing = 1;
+        let mut pending = self.backends.len();
+        while agreeing < self.quorum && agreeing + pending >= self.quorum {
+            let Some(joined) = calls.join_next().await else {
+                break;
+            };
+            pending -= 1;
+            match joined {
+                Ok((_, Ok(response))) => {
+                    let other = ProcessedOutput::from_response(response.get_ref());
+                    if self.agrees_with(output, &other) {
+                        agreeing += 1;
+                    }
+                }
+                Ok((host, Err(status))) => {
+                    warn!(
+                        "consensus target server {host} failed: {}",
+                        status.message()
+                    )
+                }
+                Err(err) => warn!("consensus call failed: {err}"),
+            }
+        }
+
+        if agreeing < self.quorum {
+            debug!(
+                "{agreeing} of {} target servers agree on the output, {} required",
+                self.backends.len() + 1,
+                self.quorum
+            );
+        }
+
+        agreeing >= self.quorum
+    }
+
+    fn agrees_with(&self, output: &ProcessedOutput, other: &ProcessedOutput) -> bool {
+        diff_outputs(output, other, self.tolerance).is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::output::tests::BASE_INFER_OUTPUT;
+    use crate::parsing::tensor::encode_f32;
+    use tonic::transport::Endpoint;
+
+    fn output(value: f32) -> ProcessedOutput {
+        let mut output = BASE_INFER_OUTPUT.clone();
+        output.outputs[0].datatype = "FP32".to_string();
+        output.outputs[0].shape = vec![1];
+        output.raw_output_contents = vec![encode_f32("FP32", &[value]).unwrap().into()];
+        output
+    }
+
+    #[tokio::test]
+    async fn it_counts_the_agreeing_target_servers() {
+        let endpoint = Endpoint::from_static("http://localhost:8001");
+        let backends = (0..2)
+            .map(|_| {
+                let pool = ChannelPool::new(vec![endpoint.connect_lazy()]);
+                ("http://localhost:8001".to_string(), pool)
+            })
+            .collect();
+        let consensus = Consensus::new(backends, 0, 0.01);
+        assert_eq!(3, consensus.quorum);
+
+        assert!(consensus.agrees_with(&output(1.0), &output(1.005)));
+        assert!(!consensus.agrees_with(&output(1.0), &output(2.0)));
+    }
+}
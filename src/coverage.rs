@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::trace::{Outcome, TraceRecord};
+
+/// Counts the hits per cache entry and the requests that missed the cache, so a test run can
+/// verify that every fixture was used and no request went unserved.
+#[derive(Default)]
+pub struct Coverage {
+    // The hits per model name and entry file name.
+    hits: Mutex<BTreeMap<(String, String), u64>>,
+    // The misses per model name, version and request hash.
+    misses: Mutex<BTreeMap<(String, String, String), u64>>,
+}
+
+/// The entries that were never hit and the requests that missed the cache.
+#[derive(Serialize, PartialEq, Debug)]
+pub struct CoverageReport {
+    pub unused_entries: Vec<String>,
+    pub misses: Vec<MissedRequest>,
+}
+
+impl CoverageReport {
+    /// Write the report as JSON, e.g. for CI to assert full fixture coverage.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+pub struct MissedRequest {
+    pub model_name: String,
+    pub model_version: String,
+    pub request_hash: String,
+    // The number of times the request missed.
+    pub count: u64,
+}
+
+impl Coverage {
+    /// Count a handled request. Requests that were served from an entry are hits, requests that
+    /// were synthesized, forwarded or not served at all are misses.
+    pub fn record(&self, record: &TraceRecord) {
+        match (&record.outcome, &record.entry, &record.request_hash) {
+            (Outcome::Hit | Outcome::ApproximateHit, Some(entry), _) => {
+                *self
+                    .hits
+                    .lock()
+                    .unwrap()
+                    .entry((record.model_name.clone(), entry.clone()))
+                    .or_default() += 1;
+            }
+            (Outcome::Hit | Outcome::ApproximateHit, None, _) | (_, _, None) => {}
+            (_, _, Some(request_hash)) => {
+                *self
+                    .misses
+                    .lock()
+                    .unwrap()
+                    .entry((
+                        record.model_name.clone(),
+                        record.model_version.clone(),
+                        request_hash.clone(),
+                    ))
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    /// Report which of the entries with the file names were never hit, and the misses of a model,
+    /// or of all models when the name is empty.
+    pub fn report(&self, file_names: Vec<String>, model_name: &str) -> CoverageReport {
+        let hit: HashSet<String> = self
+            .hits
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|(_, file_name)| file_name.clone())
+            .collect();
+        let mut unused_entries: Vec<String> = file_names
+            .into_iter()
+            .filter(|file_name| !hit.contains(file_name))
+            .collect();
+        unused_entries.sort();
+
+        let misses = self
+            .misses
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((name, _, _), _)| model_name.is_empty() || name == model_name)
+            .map(
+                |((model_name, model_version, request_hash), count)| MissedRequest {
+                    model_name: model_name.clone(),
+                    model_version: model_version.clone(),
+                    request_hash: request_hash.clone(),
+                    count: *count,
+                },
+            )
+            .collect();
+
+        CoverageReport {
+            unused_entries,
+            misses,
+        }
+    }
+
+    /// The number of hits and misses per model name.
+    pub fn model_counts(&self) -> BTreeMap<String, (u64, u64)> {
+        let mut counts: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for ((model_name, _), count) in self.hits.lock().unwrap().iter() {
+            counts.entry(model_name.clone()).or_default().0 += count;
+        }
+        for ((model_name, _, _), count) in self.misses.lock().unwrap().iter() {
+            counts.entry(model_name.clone()).or_default().1 += count;
+        }
+
+        counts
+    }
+
+    /// Forget the hits and misses of a model, or of all models when the name is empty.
+    pub fn reset(&self, model_name: &str) {
+        let is_reset = |name: &String| model_name.is_empty() || name == model_name;
+        self.hits
+            .lock()
+            .unwrap()
+            .retain(|(name, _), _| !is_reset(name));
+        self.misses
+            .lock()
+            .unwrap()
+            .retain(|(name, _, _), _| !is_reset(name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(outcome: Outcome, entry: Option<&str>) -> TraceRecord {
+        let mut record = TraceRecord::new("test", "1");
+        record.outcome = outcome;
+        record.entry = entry.map(str::to_string);
+        record.request_hash = Some("abc".to_string());
+        record
+    }
+
+    #[test]
+    fn it_reports_unused_entries_and_misses() {
+        let coverage = Coverage::default();
+        coverage.record(&record(Outcome::Hit, Some("a.inferstore")));
+        coverage.record(&record(Outcome::Miss, None));
+        coverage.record(&record(Outcome::Synthesized, None));
+
+        let file_names = vec!["b.inferstore".to_string(), "a.inferstore".to_string()];
+        let report = coverage.report(file_names.clone(), "");
+        assert_eq!(vec!["b.inferstore".to_string()], report.unused_entries);
+        assert_eq!(
+            vec![MissedRequest {
+                model_name: "test".to_string(),
+                model_version: "1".to_string(),
+                request_hash: "abc".to_string(),
+                count: 2,
+            }],
+            report.misses
+        );
+        assert!(coverage
+            .report(file_names.clone(), "other")
+            .misses
+            .is_empty());
+        assert_eq!((1, 2), coverage.model_counts()["test"]);
+
+        coverage.reset("test");
+        let report = coverage.report(file_names, "");
+        assert_eq!(2, report.unused_entries.len());
+        assert!(report.misses.is_empty());
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, StatusCode};
+use log::info;
+use tokio::sync::Mutex;
+use urlencoding::{decode, encode};
+
+use crate::caching::cachable::Cachable;
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachestore::{CacheStore, Usage};
+use crate::coverage::Coverage;
+use crate::parsing::tensor::{decode_f64, element_size};
+
+// The number of elements shown in the preview of a tensor.
+const PREVIEW_ELEMENTS: usize = 8;
+
+// How long the usage shown on the models page is reused, as counting it visits every entry.
+const USAGE_TTL: Duration = Duration::from_secs(10);
+
+/// The usage per model name and version, see `CacheStore::usage`.
+type ModelUsage = BTreeMap<(String, String), Usage>;
+
+const TENSOR_HEADER: &str =
+    "<table><tr><th>Name</th><th>Datatype</th><th>Shape</th><th>Preview</th></tr>";
+
+/// A read-only web dashboard to browse the infer entries of the cache: the models with their entry
+/// counts and hit rates, the entries of a model, and the entries with previews of their tensors.
+pub struct Dashboard {
+    inference_store: Arc<CacheStore<CachableModelInfer>>,
+    coverage: Arc<Coverage>,
+    // The usage per model and the time it was counted, see `USAGE_TTL`.
+    usage: Mutex<Option<(Instant, ModelUsage)>>,
+}
+
+impl Dashboard {
+    pub fn new(inference_store: Arc<CacheStore<CachableModelInfer>>) -> Self {
+        Self {
+            inference_store,
+            coverage: Default::default(),
+            usage: Default::default(),
+        }
+    }
+
+    /// Report the hit rates counted by the inference service.
+    pub fn with_coverage(self, coverage: Arc<Coverage>) -> Self {
+        Self { coverage, ..self }
+    }
+
+    async fn handle(&self, method: &Method, path: &str) -> hyper::Response<Body> {
+        if method != Method::GET {
+            return text(StatusCode::METHOD_NOT_ALLOWED, "the dashboard is read-only");
+        }
+
+        let segments: Vec<String> = path
+            .trim_matches('/')
+            .split('/')
+            .map(|segment| decode(segment).map_or(segment.to_string(), |s| s.into_owned()))
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        match segments.as_slice() {
+            [""] => html(self.models_page().await),
+            ["models", name, version] => html(self.model_page(name, version).await),
+            ["entries", file_name] => match self.entry_page(file_name).await {
+                Some(page) => html(page),
+                None => text(StatusCode::NOT_FOUND, "entry not found"),
+            },
+            ["entries", file_name, "download"] => {
+                match self.inference_store.read_file(file_name).await {
+                    Some(contents) => hyper::Response::builder()
+                        .header(CONTENT_TYPE, "application/octet-stream")
+                        .header(
+                            CONTENT_DISPOSITION,
+                            format!("attachment; filename=\"{file_name}\""),
+                        )
+                        .body(Body::from(contents))
+                        .expect("download response is valid"),
+                    None => text(StatusCode::NOT_FOUND, "entry not found"),
+                }
+            }
+            _ => text(StatusCode::NOT_FOUND, "not found"),
+        }
+    }
+
+    /// The models with their entry counts, sizes and hits, and the hit rate since starting.
+    async fn models_page(&self) -> String {
+        let counts = self.coverage.model_counts();
+        let mut rows = String::new();
+        for ((name, version), usage) in self.usage().await {
+            let hit_rate = match counts.get(&name) {
+                Some((hits, misses)) if hits + misses > 0 => {
+                    format!("{:.1}%", *hits as f64 / (hits + misses) as f64 * 100.0)
+                }
+                _ => "-".to_string(),
+            };
+            rows.push_str(&format!(
+                "<tr><td><a href=\"/models/{}/{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td>\
+                 <td>{}</td><td>{hit_rate}</td></tr>",
+                encode(&name),
+                encode(&version),
+                escape(&name),
+                escape(&version),
+                usage.entries,
+                usage.bytes,
+                usage.hits,
+            ));
+        }
+
+        page(
+            "Models",
+            &format!(
+                "<table><tr><th>Model</th><th>Version</th><th>Entries</th><th>Bytes</th>\
+                 <th>Hits</th><th>Hit rate</th></tr>{rows}</table>"
+            ),
+        )
+    }
+
+    /// The usage per model, counted at most once per `USAGE_TTL`. Concurrent requests wait for
+    /// the same count.
+    async fn usage(&self) -> ModelUsage {
+        let mut cached = self.usage.lock().await;
+        if let Some((counted_at, usage)) = &*cached {
+            if counted_at.elapsed() < USAGE_TTL {
+                return usage.clone();
+            }
+        }
+
+        let usage = self.inference_store.usage().await;
+        *cached = Some((Instant::now(), usage.clone()));
+        usage
+    }
+
+    /// The entries of a model version with their hits.
+    async fn model_page(&self, name: &str, version: &str) -> String {
+        let mut file_names = self
+            .inference_store
+            .file_names_where(|cachable| cachable.model() == Some((name, version)))
+            .await;
+        file_names.sort();
+        let hits = self.inference_store.hits();
+
+        let mut rows = String::new();
+        for file_name in file_names {
+            let hits = hits.get(&file_name).copied().unwrap_or_default();
+            rows.push_str(&format!(
+                "<tr><td><a href=\"/entries/{}\">{}</a></td><td>{}</td></tr>",
+                encode(&file_name),
+                escape(&file_name),
+                hits.count,
+            ));
+        }
+
+        page(
+            &format!("{} {}", name, version),
+            &format!("<table><tr><th>Entry</th><th>Hits</th></tr>{rows}</table>"),
+        )
+    }
+
+    /// An entry with the previews of its input and output tensors, or None when it is not found.
+    async fn entry_page(&self, file_name: &str) -> Option<String> {
+        let (_, input, output) = self
+            .inference_store
+            .entries_by_file_name(|name| name == file_name)
+            .await
+            .pop()?;
+
+        let mut body = format!(
+            "<p>Model {} {}, request {}, id {}. <a href=\"/entries/{}/download\">Download</a></p>",
+            escape(&input.model_name),
+            escape(&input.model_version),
+            input.request_hash(),
+            escape(&input.id),
+            encode(file_name),
+        );
+
+        body.push_str("<h2>Inputs</h2>");
+        body.push_str(TENSOR_HEADER);
+        for (index, tensor) in input.inputs.iter().enumerate() {
+            let preview = match &input.raw_input_contents {
+                Some(contents) => contents
+                    .get(index)
+                    .map_or(String::new(), |content| preview(&tensor.datatype, content)),
+                None => "not stored".to_string(),
+            };
+            body.push_str(&tensor_row(
+                &tensor.name,
+                &tensor.datatype,
+                &tensor.shape,
+                &preview,
+            ));
+        }
+        body.push_str("</table>");
+
+        body.push_str("<h2>Outputs</h2>");
+        match &output.error {
+            Some(error) => body.push_str(&format!(
+                "<p>Error {}: {}</p>",
+                error.code,
+                escape(&error.message)
+            )),
+            None => {
+                body.push_str(TENSOR_HEADER);
+                for (tensor, content) in output.outputs.iter().zip(&output.raw_output_contents) {
+                    body.push_str(&tensor_row(
+                        &tensor.name,
+                        &tensor.datatype,
+                        &tensor.shape,
+                        &preview(&tensor.datatype, content),
+                    ));
+                }
+                body.push_str("</table>");
+            }
+        }
+
+        Some(page(file_name, &body))
+    }
+}
+
+/// Serve the dashboard over HTTP.
+pub async fn serve_dashboard(addr: SocketAddr, dashboard: Arc<Dashboard>) -> anyhow::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let dashboard = dashboard.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let dashboard = dashboard.clone();
+                async move {
+                    Ok::<_, Infallible>(
+                        dashboard
+                            .handle(request.method(), request.uri().path())
+                            .await,
+                    )
+                }
+            }))
+        }
+    });
+
+    info!("Starting the web dashboard on {}", addr);
+    hyper::Server::try_bind(&addr)?.serve(make_service).await?;
+
+    Ok(())
+}
+
+fn html(page: String) -> hyper::Response<Body> {
+    hyper::Response::builder()
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(page))
+        .expect("dashboard page is valid")
+}
+
+fn text(status: StatusCode, message: &'static str) -> hyper::Response<Body> {
+    hyper::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(message))
+        .expect("dashboard response is valid")
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>InferenceStore - {title}</title>\
+         <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #ccc;padding:4px 8px;text-align:left}}</style></head>\
+         <body><p><a href=\"/\">InferenceStore</a></p><h1>{title}</h1>{body}</body></html>",
+        title = escape(title),
+    )
+}
+
+fn tensor_row(name: &str, datatype: &str, shape: &[i64], preview: &str) -> String {
+    format!(
+        "<tr><td>{}</td><td>{}</td><td>{:?}</td><td>{}</td></tr>",
+        escape(name),
+        escape(datatype),
+        shape,
+        escape(preview),
+    )
+}
+
+/// The first elements of a tensor, or its size when the content can not be decoded.
+fn preview(datatype: &str, content: &[u8]) -> String {
+    match preview_elements(datatype, content) {
+        Some((elements, count)) if count > PREVIEW_ELEMENTS => {
+            format!("{}, ... ({count} elements)", elements.join(", "))
+        }
+        Some((elements, _)) => elements.join(", "),
+        None => format!("{} bytes", content.len()),
+    }
+}
+
+/// The first `PREVIEW_ELEMENTS` elements of a tensor and its number of elements. Only the
+/// previewed elements are decoded, so large tensors are cheap to preview.
+fn preview_elements(datatype: &str, content: &[u8]) -> Option<(Vec<String>, usize)> {
+    if datatype == "BYTES" {
+        let mut elements = vec![];
+        let mut count = 0;
+        let mut rest = content;
+        while !rest.is_empty() {
+            let length = u32::from_le_bytes(rest.get(0..4)?.try_into().unwrap()) as usize;
+            let element = rest.get(4..4usize.checked_add(length)?)?;
+            if elements.len() < PREVIEW_ELEMENTS {
+                elements.push(format!("{:?}", String::from_utf8_lossy(element)));
+            }
+            count += 1;
+            rest = &rest[4 + length..];
+        }
+
+        return Some((elements, count));
+    }
+
+    let size = element_size(datatype)?;
+    if !content.len().is_multiple_of(size) {
+        return None;
+    }
+    let previewed = &content[..content.len().min(PREVIEW_ELEMENTS * size)];
+    let elements = decode_f64(datatype, previewed)?;
+
+    Some((
+        elements.iter().map(f64::to_string).collect(),
+        content.len() / size,
+    ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::parsing::input::tests::BASE_INFER_INPUT;
+    use crate::parsing::output::tests::BASE_INFER_OUTPUT;
+    use crate::parsing::tensor::{encode_bytes_elements, encode_f32};
+
+    async fn get(dashboard: &Dashboard, path: &str) -> (StatusCode, String) {
+        let response = dashboard.handle(&Method::GET, path).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    #[tokio::test]
+    async fn it_browses_the_cache() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let inference_store = Arc::new(CacheStore::new(tmp_dir.path().to_path_buf()));
+        let mut output = BASE_INFER_OUTPUT.clone();
+        output.outputs[0].datatype = "FP32".to_string();
+        output.raw_output_contents = vec![encode_f32("FP32", &[0.5; 10]).unwrap().into()];
+        let (path, _) = inference_store
+            .store(BASE_INFER_INPUT.clone(), output, Default::default())
+            .await
+            .unwrap();
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let dashboard = Dashboard::new(inference_store);
+
+        let (status, models) = get(&dashboard, "/").await;
+        assert_eq!(StatusCode::OK, status);
+        assert!(models.contains("<a href=\"/models/test/1\">test</a>"));
+
+        let (_, entries) = get(&dashboard, "/models/test/1").await;
+        assert!(entries.contains(&file_name));
+
+        let (_, entry) = get(&dashboard, &format!("/entries/{file_name}")).await;
+        assert!(entry.contains("0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, ... (10 elements)"));
+        assert!(entry.contains("not stored"));
+
+        let (status, _) = get(&dashboard, &format!("/entries/{file_name}/download")).await;
+        assert_eq!(StatusCode::OK, status);
+        let (status, _) = get(&dashboard, "/entries/other").await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!(
+            StatusCode::METHOD_NOT_ALLOWED,
+            dashboard.handle(&Method::DELETE, "/").await.status()
+        );
+    }
+
+    #[test]
+    fn it_previews_the_first_elements() {
+        let content = encode_f32("FP32", &[0.5; 1000]).unwrap();
+        assert_eq!(
+            "0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, ... (1000 elements)",
+            preview("FP32", &content)
+        );
+        assert_eq!("3 bytes", preview("FP32", &content[..3]));
+
+        let content = encode_bytes_elements(&vec![b"a".to_vec(); 9]);
+        assert_eq!(
+            r#""a", "a", "a", "a", "a", "a", "a", "a", ... (9 elements)"#,
+            preview("BYTES", &content)
+        );
+        assert_eq!("4 bytes", preview("BYTES", &[5, 0, 0, 0]));
+    }
+}
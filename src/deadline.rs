@@ -0,0 +1,134 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tonic::{Request, Status};
+
+// The gRPC header in which clients send the time they are willing to wait for a response.
+const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// The moment by which the client expects a response to the request, from the `grpc-timeout`
+/// header. Requests without a (valid) header have no deadline.
+pub fn request_deadline<T>(request: &Request<T>) -> Option<Instant> {
+    let value = request.metadata().get(GRPC_TIMEOUT_HEADER)?.to_str().ok()?;
+    parse_grpc_timeout(value).map(|timeout| Instant::now() + timeout)
+}
+
+/// Parse a `grpc-timeout` value, which is at most 8 digits followed by a unit.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let (amount, unit) = value.split_at(value.len().checked_sub(1)?);
+    if amount.is_empty() || amount.len() > 8 {
+        return None;
+    }
+    let amount: u64 = amount.parse().ok()?;
+
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// The time an upstream call may take: the configured timeout, shortened to the time left until
+/// the client's deadline.
+pub fn upstream_timeout(timeout: Option<Duration>, deadline: Option<Instant>) -> Option<Duration> {
+    let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    match (timeout, remaining) {
+        (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+        (timeout, remaining) => timeout.or(remaining),
+    }
+}
+
+/// Call the target server with the timeout, which is also sent along so the target server can
+/// abandon the request. Fails with DEADLINE_EXCEEDED when the target server does not respond in
+/// time.
+pub async fn call_upstream<T, R, F, Fut>(
+    mut request: Request<T>,
+    timeout: Option<Duration>,
+    call: F,
+) -> Result<R, Status>
+where
+    F: FnOnce(Request<T>) -> Fut,
+    Fut: Future<Output = Result<R, Status>>,
+{
+    let Some(timeout) = timeout else {
+        return call(request).await;
+    };
+    if timeout.is_zero() {
+        return Err(Status::deadline_exceeded(
+            "deadline exceeded before calling the target server",
+        ));
+    }
+
+    request.set_timeout(timeout);
+    match tokio::time::timeout(timeout, call(request)).await {
+        Ok(result) => result,
+        Err(_) => Err(Status::deadline_exceeded(format!(
+            "target server did not respond within {timeout:?}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_grpc_timeouts() {
+        assert_eq!(Some(Duration::from_millis(250)), parse_grpc_timeout("250m"));
+        assert_eq!(Some(Duration::from_secs(120)), parse_grpc_timeout("2M"));
+        assert_eq!(None, parse_grpc_timeout("m"));
+        assert_eq!(None, parse_grpc_timeout("123456789S"));
+        assert_eq!(None, parse_grpc_timeout("10x"));
+        assert_eq!(None, parse_grpc_timeout(""));
+
+        let mut request = Request::new(());
+        request.set_timeout(Duration::from_secs(10));
+        let deadline = request_deadline(&request).unwrap();
+        assert!(deadline > Instant::now() + Duration::from_secs(9));
+    }
+
+    #[test]
+    fn it_shortens_timeouts_to_the_deadline() {
+        let deadline = Some(Instant::now() + Duration::from_secs(1));
+
+        assert_eq!(None, upstream_timeout(None, None));
+        assert_eq!(
+            Some(Duration::from_millis(10)),
+            upstream_timeout(Some(Duration::from_millis(10)), deadline)
+        );
+        assert!(
+            upstream_timeout(Some(Duration::from_secs(5)), deadline).unwrap()
+                <= Duration::from_secs(1)
+        );
+        assert!(upstream_timeout(None, deadline).unwrap() <= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn it_fails_stalled_calls() {
+        let stalled = call_upstream(Request::new(()), Some(Duration::from_millis(10)), |_| {
+            std::future::pending::<Result<(), Status>>()
+        })
+        .await;
+        assert_eq!(tonic::Code::DeadlineExceeded, stalled.unwrap_err().code());
+
+        let forwarded = call_upstream(
+            Request::new(()),
+            Some(Duration::from_secs(1)),
+            |request| async move {
+                Ok::<_, Status>(request.metadata().get(GRPC_TIMEOUT_HEADER).is_some())
+            },
+        )
+        .await;
+        assert!(forwarded.unwrap());
+
+        let expired = call_upstream(Request::new(()), Some(Duration::ZERO), |_| async {
+            Ok::<_, Status>(())
+        })
+        .await;
+        assert_eq!(tonic::Code::DeadlineExceeded, expired.unwrap_err().code());
+    }
+}
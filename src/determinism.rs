@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind::NotFound;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::caching::lock;
+use crate::caching::metadata::unix_millis;
+use crate::parsing::output::ProcessedOutput;
+
+/// The file in the cache directory the models found to be nondeterministic are persisted to.
+pub const DETERMINISM_FILE: &str = "determinism.json";
+
+/// How a probe found a model to be nondeterministic.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Nondeterministic {
+    // The hash of the request that got two different outputs.
+    pub request_hash: String,
+    // The time it was found, in milliseconds since the Unix epoch.
+    pub found_at: i64,
+}
+
+/// The models that determinism probes found to be nondeterministic. A model can only be proven
+/// nondeterministic, so every model is considered deterministic until a probe finds otherwise.
+#[derive(Default)]
+pub struct Determinism {
+    models: Mutex<BTreeMap<String, Nondeterministic>>,
+    file: Option<PathBuf>,
+}
+
+impl Determinism {
+    /// Persist the decisions to a file, and load the decisions already in it.
+    pub fn with_file(self, file: PathBuf) -> Self {
+        let models = match fs::read(&file) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|err| {
+                warn!("ignoring determinism file {}: {err}", file.display());
+                BTreeMap::new()
+            }),
+            Err(err) if err.kind() == NotFound => BTreeMap::new(),
+            Err(err) => {
+                warn!("could not read determinism file {}: {err}", file.display());
+                BTreeMap::new()
+            }
+        };
+
+        Self {
+            models: Mutex::new(models),
+            file: Some(file),
+        }
+    }
+
+    pub fn is_nondeterministic(&self, model_name: &str) -> bool {
+        self.models.lock().unwrap().contains_key(model_name)
+    }
+
+    /// The models found to be nondeterministic.
+    pub fn models(&self) -> BTreeMap<String, Nondeterministic> {
+        self.models.lock().unwrap().clone()
+    }
+
+    /// Compare the outputs of two calls with the same request. When they differ, the model is
+    /// marked nondeterministic and true is returned.
+    pub fn probe(
+        &self,
+        model_name: &str,
+        request_hash: &str,
+        output: &ProcessedOutput,
+        probe_output: &ProcessedOutput,
+    ) -> bool {
+        if output.hash() == probe_output.hash() {
+            return false;
+        }
+
+        warn!(
+            "Model {model_name} is nondeterministic, request {request_hash} got different outputs"
+        );
+        let mut models = self.models.lock().unwrap();
+        models.insert(
+            model_name.to_string(),
+            Nondeterministic {
+                request_hash: request_hash.to_string(),
+                found_at: unix_millis(SystemTime::now()),
+            },
+        );
+        if let Err(err) = self.save(&models) {
+            warn!("could not save the determinism of model {model_name}: {err}");
+        }
+
+        true
+    }
+
+    /// Write the decisions to the file, when set. The file is replaced atomically.
+    fn save(&self, models: &BTreeMap<String, Nondeterministic>) -> anyhow::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        lock::write_replace(file, |writer| {
+            Ok(serde_json::to_writer_pretty(writer, models)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::output::tests::BASE_INFER_OUTPUT;
+    use tempdir::TempDir;
+
+    #[test]
+    fn it_persists_nondeterministic_models() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let file = tmp_dir.path().join(DETERMINISM_FILE);
+        let determinism = Determinism::default().with_file(file.clone());
+
+        let output = BASE_INFER_OUTPUT.clone();
+        assert!(!determinism.probe("model", "hash", &output, &output));
+        assert!(!determinism.is_nondeterministic("model"));
+
+        let mut probe_output = output.clone();
+        probe_output.raw_output_contents = vec![vec![1, 2, 3].into()];
+        assert!(determinism.probe("model", "hash", &output, &probe_output));
+        assert!(determinism.is_nondeterministic("model"));
+
+        let determinism = Determinism::default().with_file(file);
+        assert!(determinism.is_nondeterministic("model"));
+        assert_eq!(determinism.models()["model"].request_hash, "hash");
+    }
+}
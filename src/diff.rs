@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachestore::CacheStore;
+use crate::parsing::input::ProcessedInput;
+use crate::parsing::output::ProcessedOutput;
+use crate::parsing::tensor_diff::{diff_outputs, OutputDiff};
+
+/// A difference between the infer entries of two cache directories.
+#[derive(PartialEq, Debug)]
+pub enum EntryDiff {
+    Added(String),
+    Removed(String),
+    Changed(String, OutputDiff),
+}
+
+/// Compare the infer entries of two cache directories, print the differences like a golden-file
+/// diff and return whether the directories differ.
+pub async fn run(dir_a: PathBuf, dir_b: PathBuf, tolerance: f64) -> anyhow::Result<bool> {
+    let store_a = CacheStore::<CachableModelInfer>::new_read_only(dir_a);
+    let store_b = CacheStore::<CachableModelInfer>::new_read_only(dir_b);
+    store_a.load().await?;
+    store_b.load().await?;
+
+    let diffs = diff_entries(
+        &store_a.entries(|_| true).await,
+        &store_b.entries(|_| true).await,
+        tolerance,
+    );
+
+    for diff in &diffs {
+        match diff {
+            EntryDiff::Added(entry) => println!("+ {entry}"),
+            EntryDiff::Removed(entry) => println!("- {entry}"),
+            EntryDiff::Changed(entry, diff) => {
+                println!("~ {entry}");
+                diff.lines()
+                    .iter()
+                    .for_each(|change| println!("    {change}"));
+            }
+        }
+    }
+
+    Ok(!diffs.is_empty())
+}
+
+/// Compare entries by the hashes of their requests. Changed outputs are compared tensor by tensor,
+/// numeric elements that differ by at most `tolerance` are considered equal.
+pub fn diff_entries(
+    entries_a: &[(ProcessedInput, ProcessedOutput)],
+    entries_b: &[(ProcessedInput, ProcessedOutput)],
+    tolerance: f64,
+) -> Vec<EntryDiff> {
+    let entries_a = by_request(entries_a);
+    let mut entries_b = by_request(entries_b);
+    let mut diffs = vec![];
+
+    for (entry, output_a) in entries_a {
+        match entries_b.remove(&entry) {
+            None => diffs.push(EntryDiff::Removed(entry)),
+            Some(output_b) => {
+                let diff = diff_outputs(output_a, output_b, tolerance);
+                if !diff.is_empty() {
+                    diffs.push(EntryDiff::Changed(entry, diff));
+                }
+            }
+        }
+    }
+
+    diffs.extend(entries_b.into_keys().map(EntryDiff::Added));
+    diffs
+}
+
+/// Index the outputs by a readable identifier of their request: the model and the hashes of the
+/// request.
+fn by_request(entries: &[(ProcessedInput, ProcessedOutput)]) -> BTreeMap<String, &ProcessedOutput> {
+    entries
+        .iter()
+        .map(|(input, output)| {
+            let entry = format!(
+                "{}#{} {}",
+                input.model_name,
+                input.model_version,
+                input.request_hash()
+            );
+
+            (entry, output)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::input::tests::raw_infer_request;
+    use crate::parsing::output::Output;
+
+    fn entry(input: u8, output: Vec<f32>) -> (ProcessedInput, ProcessedOutput) {
+        let request = raw_infer_request(vec![("INPUT0", "UINT8", vec![1], vec![input])]);
+
+        (
+            ProcessedInput::from_infer_request(request, &Default::default()),
+            ProcessedOutput {
+                parameters: Default::default(),
+                outputs: vec![Output {
+                    parameters: Default::default(),
+                    name: "OUTPUT0".to_string(),
+                    datatype: "FP32".to_string(),
+                    shape: vec![output.len() as i64],
+                }],
+                raw_output_contents: vec![output.iter().flat_map(|v| v.to_le_bytes()).collect()],
+                error: None,
+                metadata: Default::default(),
+                raw_response: None,
+            },
+        )
+    }
+
+    #[test]
+    fn it_reports_added_and_removed_entries() {
+        let diffs = diff_entries(&[entry(1, vec![1.0])], &[entry(2, vec![1.0])], 0.0);
+
+        assert_eq!(2, diffs.len());
+        assert!(matches!(diffs[0], EntryDiff::Removed(_)));
+        assert!(matches!(diffs[1], EntryDiff::Added(_)));
+    }
+
+    #[test]
+    fn it_reports_changed_outputs_within_tolerance() {
+        let entries_a = [entry(1, vec![1.0, 2.0, 3.0])];
+        let entries_b = [entry(1, vec![1.0, 2.01, 3.5])];
+
+        let diffs = diff_entries(&entries_a, &entries_b, 0.1);
+
+        assert_eq!(1, diffs.len());
+        match &diffs[0] {
+            EntryDiff::Changed(entry, diff) => {
+                assert!(entry.starts_with("test#1 "));
+                assert_eq!(
+                    vec!["OUTPUT0: 1 of 3 elements differ, max abs error 0.5, first at [2]"],
+                    diff.lines()
+                );
+            }
+            diff => panic!("unexpected diff {diff:?}"),
+        }
+        assert!(diff_entries(&entries_a, &entries_a, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, info};
+
+use crate::parsing::input::ProcessedInput;
+
+/// Counts what would be served and recorded in DryRun mode, where every request is forwarded to
+/// the target server and nothing is written to the cache.
+#[derive(Default)]
+pub struct DryRun {
+    // The model names, versions and request hashes of the requests that would have been recorded
+    // during this run.
+    recorded: Mutex<HashSet<(String, String, String)>>,
+    report: Mutex<DryRunReport>,
+}
+
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct DryRunReport {
+    pub requests: u64,
+    // The requests that would be served from an entry in the cache.
+    pub hits: u64,
+    // The requests that would be served from an entry recorded earlier during this run.
+    pub repeated: u64,
+    pub recorded_entries: u64,
+    // The encoded size of the requests and responses that would be recorded.
+    pub recorded_bytes: u64,
+}
+
+impl DryRunReport {
+    /// The fraction of the requests that would be served from the cache.
+    pub fn hit_rate(&self) -> f64 {
+        match self.requests {
+            0 => 0.0,
+            requests => (self.hits + self.repeated) as f64 / requests as f64,
+        }
+    }
+}
+
+impl DryRun {
+    /// Count a request that was looked up in the cache, with the entry it would be served from.
+    pub fn lookup(&self, input: &ProcessedInput, entry: Option<&Path>) {
+        let request_hash = input.request_hash();
+        let mut report = self.report.lock().unwrap();
+        report.requests += 1;
+        match entry {
+            Some(path) => {
+                report.hits += 1;
+                debug!(
+                    "Dry run: request {request_hash} of model {} would be served from {}",
+                    input.model_name,
+                    path.display()
+                );
+            }
+            None if self.recorded.lock().unwrap().contains(&recorded_key(input)) => {
+                report.repeated += 1;
+                debug!(
+                    "Dry run: request {request_hash} of model {} would be served from the entry \
+                     recorded during this run",
+                    input.model_name
+                );
+            }
+            None => debug!(
+                "Dry run: request {request_hash} of model {} would be forwarded",
+                input.model_name
+            ),
+        }
+    }
+
+    /// Count a request that would be recorded, with the encoded size of the request and response.
+    /// Requests that would already have been recorded during this run are not counted again.
+    pub fn record(&self, input: &ProcessedInput, bytes: usize) {
+        if !self.recorded.lock().unwrap().insert(recorded_key(input)) {
+            return;
+        }
+
+        let mut report = self.report.lock().unwrap();
+        report.recorded_entries += 1;
+        report.recorded_bytes += bytes as u64;
+        debug!(
+            "Dry run: request {} of model {} would be recorded ({bytes} bytes)",
+            input.request_hash(),
+            input.model_name
+        );
+    }
+
+    pub fn report(&self) -> DryRunReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Log what would have been served and recorded so far.
+    pub fn log_report(&self) {
+        let report = self.report();
+        info!(
+            "Dry run: {} requests, {:.1}% would be served from the cache, {} entries of {} bytes \
+             would be recorded",
+            report.requests,
+            report.hit_rate() * 100.0,
+            report.recorded_entries,
+            report.recorded_bytes
+        );
+    }
+
+    /// Log the report every interval.
+    pub async fn log_periodically(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately, before any request was handled.
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            self.log_report();
+        }
+    }
+}
+
+fn recorded_key(input: &ProcessedInput) -> (String, String, String) {
+    (
+        input.model_name.clone(),
+        input.model_version.clone(),
+        input.request_hash(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::input::MatchConfig;
+    use crate::service::inference_protocol::ModelInferRequest;
+
+    fn input(model_name: &str) -> ProcessedInput {
+        let request = ModelInferRequest {
+            model_name: model_name.to_string(),
+            model_version: "1".to_string(),
+            ..Default::default()
+        };
+        ProcessedInput::from_infer_request(request, &MatchConfig::default())
+    }
+
+    #[test]
+    fn it_counts_what_would_be_served_and_recorded() {
+        let dry_run = DryRun::default();
+        let (first, second) = (input("first"), input("second"));
+
+        dry_run.lookup(&first, None);
+        dry_run.record(&first, 100);
+        dry_run.lookup(&first, None);
+        dry_run.record(&first, 100);
+        dry_run.lookup(&second, Some(Path::new("infer-second.inferstore")));
+
+        assert_eq!(
+            dry_run.report(),
+            DryRunReport {
+                requests: 3,
+                hits: 1,
+                repeated: 1,
+                recorded_entries: 1,
+                recorded_bytes: 100,
+            }
+        );
+        assert_eq!(dry_run.report().hit_rate(), 2.0 / 3.0);
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+
+use prost::Message;
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Status};
+
+use error_details::ErrorInfo;
+
+pub mod error_details {
+    tonic::include_proto!("google.rpc");
+}
+
+/// The metadata key of the statuses of proxy failures, with the kind of the failure.
+pub const ERROR_KIND_KEY: &str = "inferencestore-error";
+
+/// The domain of the `google.rpc.ErrorInfo` in the details of the statuses of proxy failures.
+pub const ERROR_DOMAIN: &str = "inferencestore";
+
+const ERROR_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+
+/// A failure of InferenceStore itself, as opposed to an error of the model that the target server
+/// returned, which is passed on unchanged. Every kind maps to its own gRPC code, and the status
+/// carries the kind in the `inferencestore-error` metadata, and as the reason of a
+/// `google.rpc.ErrorInfo` in its details, so clients can tell them apart.
+#[derive(Debug)]
+pub enum ProxyError {
+    // A cache entry could not be read or processed.
+    CacheRead(anyhow::Error),
+    // A cache entry could not be written.
+    CacheWrite(anyhow::Error),
+    // The target server can not be reached, or is not used in Serve mode.
+    TargetUnavailable(String),
+    // No cache entry matches the request in Serve mode.
+    MatchNotFound(String),
+    // A request, response or entry could not be encoded or decoded.
+    Serialization(anyhow::Error),
+}
+
+impl ProxyError {
+    /// A failure to write an entry, which is a serialization error when it was caused by one.
+    pub fn from_write(err: anyhow::Error) -> Self {
+        let serialization = err.chain().any(|cause| {
+            cause.is::<serde_json::Error>()
+                || cause.is::<prost::EncodeError>()
+                || cause.is::<prost::DecodeError>()
+        });
+
+        match serialization {
+            true => ProxyError::Serialization(err),
+            false => ProxyError::CacheWrite(err),
+        }
+    }
+
+    /// The kind of the failure, as sent in the `inferencestore-error` metadata.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProxyError::CacheRead(_) => "cache_read",
+            ProxyError::CacheWrite(_) => "cache_write",
+            ProxyError::TargetUnavailable(_) => "target_unavailable",
+            ProxyError::MatchNotFound(_) => "match_not_found",
+            ProxyError::Serialization(_) => "serialization",
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            ProxyError::CacheRead(_) => Code::Internal,
+            ProxyError::CacheWrite(_) => Code::Internal,
+            ProxyError::TargetUnavailable(_) => Code::Unavailable,
+            ProxyError::MatchNotFound(_) => Code::NotFound,
+            ProxyError::Serialization(_) => Code::Internal,
+        }
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::CacheRead(err) => write!(f, "could not read the cache: {err:#}"),
+            ProxyError::CacheWrite(err) => write!(f, "could not write the cache: {err:#}"),
+            ProxyError::TargetUnavailable(message) => write!(f, "{message}"),
+            ProxyError::MatchNotFound(message) => write!(f, "{message}"),
+            ProxyError::Serialization(err) => write!(f, "could not serialize: {err:#}"),
+        }
+    }
+}
+
+impl From<ProxyError> for Status {
+    fn from(err: ProxyError) -> Self {
+        let error_info = ErrorInfo {
+            reason: err.kind().to_string(),
+            domain: ERROR_DOMAIN.to_string(),
+            metadata: Default::default(),
+        };
+        let details = error_details::Status {
+            code: err.code() as i32,
+            message: err.to_string(),
+            details: vec![prost_types::Any {
+                type_url: ERROR_INFO_TYPE_URL.to_string(),
+                value: error_info.encode_to_vec(),
+            }],
+        };
+
+        let mut status =
+            Status::with_details(err.code(), err.to_string(), details.encode_to_vec().into());
+        status
+            .metadata_mut()
+            .insert(ERROR_KIND_KEY, MetadataValue::from_static(err.kind()));
+        status
+    }
+}
+
+/// The kind of the proxy failure of a status, from its `google.rpc.ErrorInfo` details.
+pub fn error_kind(status: &Status) -> Option<String> {
+    error_details::Status::decode(status.details())
+        .ok()?
+        .details
+        .into_iter()
+        .filter(|detail| detail.type_url == ERROR_INFO_TYPE_URL)
+        .filter_map(|detail| ErrorInfo::decode(detail.value.as_slice()).ok())
+        .find(|error_info| error_info.domain == ERROR_DOMAIN)
+        .map(|error_info| error_info.reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn it_maps_proxy_errors_to_statuses() {
+        let status = Status::from(ProxyError::MatchNotFound(
+            "could not match request".to_string(),
+        ));
+        assert_eq!(Code::NotFound, status.code());
+        assert_eq!("could not match request", status.message());
+        assert_eq!(
+            "match_not_found",
+            status.metadata().get(ERROR_KIND_KEY).unwrap()
+        );
+        assert_eq!(Some("match_not_found".to_string()), error_kind(&status));
+        assert_eq!(None, error_kind(&Status::not_found("model error")));
+
+        let write_error = anyhow::anyhow!("disk full").context("could not write entry");
+        assert_eq!("cache_write", ProxyError::from_write(write_error).kind());
+
+        let serialization_error = serde_json::from_str::<u8>("x")
+            .context("could not write entry")
+            .unwrap_err();
+        let status = Status::from(ProxyError::from_write(serialization_error));
+        assert_eq!(Code::Internal, status.code());
+        assert_eq!(
+            "serialization",
+            status.metadata().get(ERROR_KIND_KEY).unwrap()
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachestore::CacheStore;
+use crate::parsing::input::{MatchConfig, ProcessedInput};
+use crate::parsing::normalization::NormalizeConfig;
+use crate::parsing::scrubbing::ScrubConfig;
+use crate::service::inference_protocol::ModelInferRequest;
+use crate::settings::Settings;
+
+/// The entries a traffic sample would create and the requests that would be served from them.
+#[derive(Default, PartialEq, Debug)]
+pub struct Estimate {
+    pub requests: u64,
+    pub entries: u64,
+}
+
+impl Estimate {
+    /// The requests that would be served from an entry created by an earlier request.
+    pub fn hits(&self) -> u64 {
+        self.requests - self.entries
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        match self.requests {
+            0 => 0.0,
+            requests => self.hits() as f64 / requests as f64,
+        }
+    }
+}
+
+/// Replays requests through the matching of a candidate match config, recording every request
+/// that matches none of the requests before it.
+pub struct Estimator {
+    match_config: MatchConfig,
+    normalization: NormalizeConfig,
+    scrubbing: ScrubConfig,
+    // The inputs that would be recorded, per model name and content hash. Inputs only match
+    // inputs with the same content hash, except for models with a match script, of which the
+    // inputs are all under None.
+    recorded: HashMap<String, HashMap<Option<[u8; 32]>, Vec<ProcessedInput>>>,
+    // The estimate per model name.
+    models: BTreeMap<String, Estimate>,
+}
+
+impl Estimator {
+    pub fn new(
+        match_config: MatchConfig,
+        normalization: NormalizeConfig,
+        scrubbing: ScrubConfig,
+    ) -> Self {
+        Self {
+            match_config,
+            normalization,
+            scrubbing,
+            recorded: Default::default(),
+            models: Default::default(),
+        }
+    }
+
+    /// Normalize, scrub and parse a request like the inference service does, and count whether it
+    /// would be served from an entry or recorded.
+    pub fn add(&mut self, mut request: ModelInferRequest) {
+        self.normalization.normalize(&mut request);
+        let request = self.scrubbing.scrub(request);
+        let input = ProcessedInput::from_infer_request(request, &self.match_config);
+
+        let estimate = self.models.entry(input.model_name.clone()).or_default();
+        estimate.requests += 1;
+        let content_hash = (!self
+            .match_config
+            .match_scripts
+            .contains_key(&input.model_name))
+        .then_some(input.content_hash);
+        let recorded = self
+            .recorded
+            .entry(input.model_name.clone())
+            .or_default()
+            .entry(content_hash)
+            .or_default();
+        if !recorded
+            .iter()
+            .any(|cached| cached.matches(&input, &self.match_config))
+        {
+            estimate.entries += 1;
+            recorded.push(input);
+        }
+    }
+
+    /// The estimate per model name.
+    pub fn models(&self) -> &BTreeMap<String, Estimate> {
+        &self.models
+    }
+
+    /// The estimate of all models together.
+    pub fn total(&self) -> Estimate {
+        self.models
+            .values()
+            .fold(Estimate::default(), |total, estimate| Estimate {
+                requests: total.requests + estimate.requests,
+                entries: total.entries + estimate.entries,
+            })
+    }
+}
+
+/// Replay a request log, with a JSON encoded inference request per line, or the infer entries of
+/// a cache directory through the matching of the settings, and print how many entries would be
+/// created and the hit rate the requests would achieve per model. Entries recorded without
+/// `store_inputs` can not be matched again, and are skipped.
+pub async fn run(settings: &Settings, source: PathBuf) -> anyhow::Result<()> {
+    let mut estimator = Estimator::new(
+        settings.get_match_config(),
+        settings.request_matching.normalization.clone(),
+        settings.request_collection.scrubbing.clone(),
+    );
+
+    let mut skipped = 0;
+    match source.is_dir() {
+        true => {
+            let (requests, without_inputs) = cached_requests(&source).await?;
+            requests
+                .into_iter()
+                .for_each(|request| estimator.add(request));
+            skipped = without_inputs;
+        }
+        false => {
+            for request in logged_requests(&source)? {
+                estimator.add(request?);
+            }
+        }
+    }
+
+    println!("model           requests    entries    hit rate");
+    for (model_name, estimate) in estimator.models() {
+        print_estimate(model_name, estimate);
+    }
+    print_estimate("total", &estimator.total());
+    if skipped > 0 {
+        println!("Skipped {skipped} entries recorded without their inputs");
+    }
+
+    Ok(())
+}
+
+fn print_estimate(name: &str, estimate: &Estimate) {
+    println!(
+        "{name:<15} {:>8} {:>10} {:>10.1}%",
+        estimate.requests,
+        estimate.entries,
+        estimate.hit_rate() * 100.0
+    );
+}
+
+/// The requests of a request log, with a JSON encoded inference request per line. The log is
+/// read line by line, so it does not have to fit in memory.
+fn logged_requests(
+    path: &Path,
+) -> anyhow::Result<impl Iterator<Item = anyhow::Result<ModelInferRequest>>> {
+    let lines = BufReader::new(File::open(path)?).lines();
+
+    Ok(lines
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(index, line)| {
+            serde_json::from_str(&line?)
+                .with_context(|| format!("invalid request on line {}", index + 1))
+        }))
+}
+
+/// The recorded requests of the infer entries of a cache directory, and the number of entries
+/// that were recorded without their inputs.
+async fn cached_requests(dir: &Path) -> anyhow::Result<(Vec<ModelInferRequest>, usize)> {
+    let store = CacheStore::<CachableModelInfer>::new_read_only(dir.to_path_buf());
+    store.load().await?;
+
+    let (with_inputs, without_inputs): (Vec<_>, Vec<_>) = store
+        .entries_where(|_| true)
+        .await
+        .into_iter()
+        .map(|(input, _)| input)
+        .partition(|input| input.raw_input_contents.is_some());
+
+    Ok((
+        with_inputs.iter().map(ProcessedInput::to_request).collect(),
+        without_inputs.len(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::parsing::input::tests::raw_infer_request;
+
+    fn request(model_name: &str, id: &str, content: u8) -> ModelInferRequest {
+        let mut request = raw_infer_request(vec![("INPUT0", "UINT8", vec![1], vec![content])]);
+        request.model_name = model_name.to_string();
+        request.id = id.to_string();
+        request
+    }
+
+    #[test]
+    fn it_estimates_the_entries_and_hit_rate_of_a_match_config() {
+        let requests = vec![
+            request("first", "1", 0),
+            request("first", "2", 0),
+            request("first", "3", 1),
+            request("second", "4", 0),
+        ];
+
+        let mut estimator = Estimator::new(
+            MatchConfig::default(),
+            Default::default(),
+            Default::default(),
+        );
+        requests.iter().cloned().for_each(|r| estimator.add(r));
+        assert_eq!(
+            estimator.models().get("first"),
+            Some(&Estimate {
+                requests: 3,
+                entries: 2
+            })
+        );
+        assert_eq!(estimator.total().hit_rate(), 0.25);
+
+        // Matching the ids creates an entry per request.
+        let mut estimator = Estimator::new(
+            MatchConfig {
+                match_id: true,
+                ..Default::default()
+            },
+            Default::default(),
+            Default::default(),
+        );
+        requests.into_iter().for_each(|r| estimator.add(r));
+        assert_eq!(
+            estimator.total(),
+            Estimate {
+                requests: 4,
+                entries: 4
+            }
+        );
+    }
+
+    #[test]
+    fn it_reads_request_logs_line_by_line() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("requests.jsonl");
+        let line = serde_json::to_string(&request("first", "1", 0)).unwrap();
+        std::fs::write(&path, format!("{line}\n\n{line}\nnot json\n")).unwrap();
+
+        let requests: Vec<_> = logged_requests(&path).unwrap().collect();
+        assert_eq!(3, requests.len());
+        assert_eq!(request("first", "1", 0), *requests[1].as_ref().unwrap());
+        let err = requests[2].as_ref().unwrap_err();
+        assert_eq!("invalid request on line 4", err.to_string());
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use urlencoding::encode;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::caching::cachable::Cachable;
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachestore::CacheStore;
+use crate::caching::metadata::has_labels;
+use crate::parsing::output::check_tensor;
+use crate::parsing::tensor::decode_bytes_elements;
+
+/// Export the infer entries of a cache directory as NPZ files, one per model and tensor:
+/// `<out_dir>/<model>#<version>/<inputs|outputs>/<tensor>.npz`. Every NPZ file contains an array
+/// per entry, named by the hashes of the request, so the inputs and outputs of an entry share
+/// the same array name. Inputs are only exported for entries recorded with `store_inputs`. When
+/// labels are provided, only the entries recorded with all of them are exported.
+pub async fn run(
+    dir: PathBuf,
+    out_dir: PathBuf,
+    labels: BTreeMap<String, String>,
+) -> anyhow::Result<()> {
+    let store = CacheStore::<CachableModelInfer>::new_read_only(dir);
+    store.load().await?;
+
+    // The arrays per NPZ file, by the path of the file.
+    let mut archives: BTreeMap<PathBuf, Vec<(String, Vec<u8>)>> = BTreeMap::new();
+    let mut exported = HashSet::new();
+    let mut without_inputs = 0;
+
+    let entries = store
+        .entries_where(|cachable| has_labels(cachable.labels().unwrap(), &labels))
+        .await;
+    for (input, output) in entries {
+        let request_hash = input.request_hash();
+        if !exported.insert((input.model_name.clone(), request_hash.clone())) {
+            warn!("skipping another recorded output of request {request_hash}");
+            continue;
+        }
+
+        let model_dir = out_dir.join(format!(
+            "{}#{}",
+            encode(&input.model_name),
+            encode(&input.model_version)
+        ));
+        let mut add =
+            |kind: &str, name: &str, datatype: &str, shape: &[i64], content: &[u8]| match to_npy(
+                datatype, shape, content,
+            ) {
+                Ok(npy) => archives
+                    .entry(model_dir.join(kind).join(format!("{}.npz", encode(name))))
+                    .or_default()
+                    .push((request_hash.clone(), npy)),
+                Err(err) => warn!("could not export tensor {name} of {request_hash}: {err}"),
+            };
+
+        match &input.raw_input_contents {
+            Some(contents) => {
+                for (tensor, content) in input.inputs.iter().zip(contents) {
+                    add(
+                        "inputs",
+                        &tensor.name,
+                        &tensor.datatype,
+                        &tensor.shape,
+                        content,
+                    );
+                }
+            }
+            None => without_inputs += 1,
+        }
+        for (tensor, content) in output.outputs.iter().zip(&output.raw_output_contents) {
+            add(
+                "outputs",
+                &tensor.name,
+                &tensor.datatype,
+                &tensor.shape,
+                content,
+            );
+        }
+    }
+
+    if without_inputs > 0 {
+        warn!("{without_inputs} entries were recorded without store_inputs, their inputs are not exported");
+    }
+
+    for (path, arrays) in &archives {
+        write_npz(path, arrays)?;
+    }
+    info!(
+        "Exported {} entries to {} NPZ files",
+        exported.len(),
+        archives.len()
+    );
+
+    Ok(())
+}
+
+fn write_npz(path: &Path, arrays: &[(String, Vec<u8>)]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    // NumPy reads uncompressed archives, like the ones written by numpy.savez.
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+
+    for (name, npy) in arrays {
+        zip.start_file(format!("{name}.npy"), options)?;
+        zip.write_all(npy)?;
+    }
+    zip.finish()?;
+
+    Ok(())
+}
+
+/// Encode raw tensor content in the NPY format. BYTES tensors become fixed-width byte strings,
+/// BF16 tensors are exported as their uint16 bit patterns as NumPy has no bfloat16 type. Content
+/// that does not fit the shape is rejected, as the array could not be loaded.
+fn to_npy(datatype: &str, shape: &[i64], content: &[u8]) -> anyhow::Result<Vec<u8>> {
+    check_tensor(datatype, shape, content)?;
+
+    let (descr, data) = match datatype {
+        "BOOL" => ("|b1".to_string(), content.to_vec()),
+        "UINT8" => ("|u1".to_string(), content.to_vec()),
+        "INT8" => ("|i1".to_string(), content.to_vec()),
+        "INT16" => ("<i2".to_string(), content.to_vec()),
+        "UINT16" | "BF16" => ("<u2".to_string(), content.to_vec()),
+        "FP16" => ("<f2".to_string(), content.to_vec()),
+        "INT32" => ("<i4".to_string(), content.to_vec()),
+        "UINT32" => ("<u4".to_string(), content.to_vec()),
+        "FP32" => ("<f4".to_string(), content.to_vec()),
+        "INT64" => ("<i8".to_string(), content.to_vec()),
+        "UINT64" => ("<u8".to_string(), content.to_vec()),
+        "FP64" => ("<f8".to_string(), content.to_vec()),
+        "BYTES" => {
+            let Some(elements) = decode_bytes_elements(content) else {
+                anyhow::bail!("invalid BYTES content");
+            };
+            let width = elements.iter().map(Vec::len).max().unwrap_or(0).max(1);
+            let data = elements
+                .into_iter()
+                .flat_map(|mut element| {
+                    element.resize(width, 0);
+                    element
+                })
+                .collect();
+
+            (format!("|S{width}"), data)
+        }
+        datatype => anyhow::bail!("{datatype} tensors can not be exported"),
+    };
+
+    let shape = shape
+        .iter()
+        .map(|dim| format!("{dim},"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut header =
+        format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': ({shape}), }}");
+    // The magic string, version and header length take 10 bytes, the header ends with a newline
+    // and is padded so the data is 64-byte aligned.
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+    npy.extend((header.len() as u16).to_le_bytes());
+    npy.extend(header.as_bytes());
+    npy.extend(data);
+
+    Ok(npy)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::caching::metadata::EntryMetadata;
+    use crate::parsing::input::tests::raw_infer_request;
+    use crate::parsing::input::{raw_input_contents, ProcessedInput};
+    use crate::parsing::output::ProcessedOutput;
+    use crate::service::inference_protocol::model_infer_response::InferOutputTensor;
+    use crate::service::inference_protocol::ModelInferResponse;
+
+    #[test]
+    fn it_encodes_npy() {
+        let npy = to_npy("INT32", &[1, 2], &[1, 0, 0, 0, 2, 0, 0, 0]).unwrap();
+
+        assert_eq!(b"\x93NUMPY\x01\x00", &npy[..8]);
+        let header_length = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        let header = std::str::from_utf8(&npy[10..10 + header_length]).unwrap();
+
+        assert_eq!(0, (10 + header_length) % 64);
+        assert!(header.starts_with("{'descr': '<i4', 'fortran_order': False, 'shape': (1, 2,), }"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(vec![1, 0, 0, 0, 2, 0, 0, 0], npy[10 + header_length..]);
+    }
+
+    #[test]
+    fn it_encodes_bytes_as_fixed_width_strings() {
+        let npy = to_npy("BYTES", &[2], &[1, 0, 0, 0, b'a', 2, 0, 0, 0, b'b', b'c']).unwrap();
+        let header_length = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+
+        assert!(std::str::from_utf8(&npy[10..10 + header_length])
+            .unwrap()
+            .contains("'descr': '|S2'"));
+        assert_eq!(b"a\0bc", &npy[10 + header_length..]);
+    }
+
+    #[test]
+    fn it_rejects_content_that_does_not_fit_the_shape() {
+        assert!(to_npy("INT32", &[1, 2], &[1, 0, 0, 0]).is_err());
+        assert!(to_npy("INT32", &[1], &[1, 0, 0, 0, 2]).is_err());
+        assert!(to_npy("BYTES", &[2], &[1, 0, 0, 0, b'a']).is_err());
+    }
+
+    #[tokio::test]
+    async fn it_exports_entries_per_model_and_tensor() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let cache_dir = tmp_dir.path().join("cache");
+        let out_dir = tmp_dir.path().join("export");
+        fs::create_dir_all(&cache_dir).unwrap();
+
+        let request = raw_infer_request(vec![("INPUT0", "UINT8", vec![2], vec![1, 2])]);
+        let mut input = ProcessedInput::from_infer_request(request.clone(), &Default::default());
+        input.raw_input_contents = Some(raw_input_contents(&request));
+        let output = ProcessedOutput::from_response(&ModelInferResponse {
+            outputs: vec![InferOutputTensor {
+                name: "OUTPUT0".to_string(),
+                datatype: "FP32".to_string(),
+                shape: vec![1],
+                ..Default::default()
+            }],
+            raw_output_contents: vec![1.5f32.to_le_bytes().to_vec().into()],
+            ..Default::default()
+        });
+        let request_hash = input.request_hash();
+        let metadata = EntryMetadata::default()
+            .with_labels(BTreeMap::from([("dataset".to_string(), "val".to_string())]));
+        CachableModelInfer::new(&cache_dir, input, output, metadata).unwrap();
+
+        let labels = |value: &str| BTreeMap::from([("dataset".to_string(), value.to_string())]);
+        run(cache_dir.clone(), out_dir.clone(), labels("train"))
+            .await
+            .unwrap();
+        assert!(!out_dir.join("test#1").exists());
+        run(cache_dir, out_dir.clone(), labels("val"))
+            .await
+            .unwrap();
+
+        for (path, content) in [
+            ("test#1/inputs/INPUT0.npz", vec![1, 2]),
+            ("test#1/outputs/OUTPUT0.npz", 1.5f32.to_le_bytes().to_vec()),
+        ] {
+            let mut archive =
+                zip::ZipArchive::new(File::open(out_dir.join(path)).unwrap()).unwrap();
+            let mut npy = vec![];
+            archive
+                .by_name(&format!("{request_hash}.npy"))
+                .unwrap()
+                .read_to_end(&mut npy)
+                .unwrap();
+
+            assert!(npy.ends_with(&content));
+        }
+    }
+}
@@ -0,0 +1,53 @@
+/// The protocol extensions InferenceStore supports, reported in the server metadata. The newer
+/// protocol of the `vendored-proto` feature adds request and tensor parameters, such as
+/// `binary_data_output`, and the response cache and metrics fields of model configs.
+pub const EXTENSIONS: &[&str] = &[
+    "classification",
+    "model_configuration",
+    "model_repository",
+    "statistics",
+    "system_shared_memory",
+    "cuda_shared_memory",
+    "logging",
+    #[cfg(feature = "vendored-proto")]
+    "parameters",
+];
+
+/// Negotiate the extensions to report with the extensions of the target server: only those both
+/// InferenceStore and the target server support, so clients do not use an extension of the
+/// protocol InferenceStore is built with that the target server does not know about.
+pub fn negotiate(target_extensions: &[String]) -> Vec<String> {
+    EXTENSIONS
+        .iter()
+        .filter(|extension| target_extensions.iter().any(|target| target == *extension))
+        .map(|extension| extension.to_string())
+        .collect()
+}
+
+/// The extensions to report when there is no target server to negotiate with.
+pub fn supported() -> Vec<String> {
+    EXTENSIONS
+        .iter()
+        .map(|extension| extension.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_negotiates_the_extensions_both_sides_support() {
+        let target = vec![
+            "statistics".to_string(),
+            "binary_tensor_data".to_string(),
+            "logging".to_string(),
+        ];
+
+        assert_eq!(
+            vec!["statistics".to_string(), "logging".to_string()],
+            negotiate(&target)
+        );
+        assert!(negotiate(&[]).is_empty());
+    }
+}
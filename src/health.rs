@@ -0,0 +1,262 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, StatusCode};
+use log::info;
+use tokio::sync::mpsc;
+use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::pool::ChannelPool;
+use crate::service::inference_protocol::ServerReadyRequest;
+use health_protocol::health_check_response::ServingStatus;
+use health_protocol::health_server::Health;
+use health_protocol::{HealthCheckRequest, HealthCheckResponse};
+
+pub mod health_protocol {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+// The services of which the status can be checked, besides the server itself.
+const SERVICES: [&str; 1] = ["inference.GRPCInferenceService"];
+
+// How long the target server may take to report it is ready.
+const TARGET_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How often the status is checked for changes while watching it.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks whether InferenceStore is ready to receive traffic. The process is live as soon as it
+/// runs, it is ready once the cache is loaded and, in Collect mode, the target server is ready.
+pub struct Readiness {
+    loaded: AtomicBool,
+    forwards: bool,
+    // The channels to the target server, once connected.
+    target: OnceLock<ChannelPool>,
+}
+
+impl Readiness {
+    /// Create the readiness of a server, which forwards requests to the target server when
+    /// `forwards` is true.
+    pub fn new(forwards: bool) -> Self {
+        Self {
+            loaded: AtomicBool::new(false),
+            forwards,
+            target: OnceLock::new(),
+        }
+    }
+
+    /// Check the readiness of the target server over the pooled channels, so probes do not open
+    /// a connection every time.
+    pub fn set_target(&self, target: ChannelPool) {
+        let _ = self.target.set(target);
+    }
+
+    /// Mark the cache as fully loaded.
+    pub fn set_loaded(&self) {
+        self.loaded.store(true, Ordering::Relaxed);
+    }
+
+    pub async fn is_ready(&self) -> bool {
+        if !self.loaded.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        if !self.forwards {
+            return true;
+        }
+        match self.target.get() {
+            None => false,
+            Some(target) => tokio::time::timeout(TARGET_TIMEOUT, target_ready(target))
+                .await
+                .unwrap_or(false),
+        }
+    }
+
+    async fn status(&self) -> ServingStatus {
+        match self.is_ready().await {
+            true => ServingStatus::Serving,
+            false => ServingStatus::NotServing,
+        }
+    }
+}
+
+async fn target_ready(target: &ChannelPool) -> bool {
+    target
+        .client()
+        .server_ready(ServerReadyRequest {})
+        .await
+        .is_ok_and(|response| response.into_inner().ready)
+}
+
+/// Implements the gRPC health checking protocol on top of the readiness.
+pub struct HealthService {
+    readiness: Arc<Readiness>,
+}
+
+impl HealthService {
+    pub fn new(readiness: Arc<Readiness>) -> Self {
+        Self { readiness }
+    }
+}
+
+fn check_service(request: Request<HealthCheckRequest>) -> Option<Status> {
+    let service = request.into_inner().service;
+    (!service.is_empty() && !SERVICES.contains(&service.as_str()))
+        .then(|| Status::not_found(format!("unknown service {service}")))
+}
+
+#[tonic::async_trait]
+impl Health for HealthService {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        if let Some(status) = check_service(request) {
+            return Err(status);
+        }
+
+        Ok(Response::new(HealthCheckResponse {
+            status: self.readiness.status().await.into(),
+        }))
+    }
+
+    type WatchStream = ReceiverStream<Result<HealthCheckResponse, Status>>;
+
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        if let Some(status) = check_service(request) {
+            return Err(status);
+        }
+
+        let (tx, rx) = mpsc::channel(1);
+        let readiness = self.readiness.clone();
+        tokio::spawn(async move {
+            let mut last_status = None;
+            loop {
+                let status = readiness.status().await;
+                if last_status != Some(status) {
+                    let response = HealthCheckResponse {
+                        status: status.into(),
+                    };
+                    if tx.send(Ok(response)).await.is_err() {
+                        break;
+                    }
+                    last_status = Some(status);
+                }
+
+                tokio::select! {
+                    _ = tx.closed() => break,
+                    _ = tokio::time::sleep(WATCH_INTERVAL) => {}
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Serve the HTTP liveness (`/livez`) and readiness (`/readyz`) probes. The probes are served
+/// while the cache is still loading, so slow starts are not mistaken for dead processes.
+pub async fn serve_probes(addr: SocketAddr, readiness: Arc<Readiness>) -> anyhow::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let readiness = readiness.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let readiness = readiness.clone();
+                async move {
+                    let status = probe(&readiness, request.method(), request.uri().path()).await;
+                    let body = status.canonical_reason().unwrap_or_default();
+                    Ok::<_, Infallible>(
+                        hyper::Response::builder()
+                            .status(status)
+                            .body(Body::from(body))
+                            .expect("probe response is valid"),
+                    )
+                }
+            }))
+        }
+    });
+
+    info!("Starting HTTP probes on {}", addr);
+    hyper::Server::try_bind(&addr)?.serve(make_service).await?;
+
+    Ok(())
+}
+
+async fn probe(readiness: &Readiness, method: &Method, path: &str) -> StatusCode {
+    match (method, path) {
+        (&Method::GET, "/livez") => StatusCode::OK,
+        (&Method::GET, "/readyz") if readiness.is_ready().await => StatusCode::OK,
+        (&Method::GET, "/readyz") => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::NOT_FOUND,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_is_ready_once_loaded() {
+        let readiness = Readiness::new(false);
+
+        assert_eq!(
+            StatusCode::OK,
+            probe(&readiness, &Method::GET, "/livez").await
+        );
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            probe(&readiness, &Method::GET, "/readyz").await
+        );
+
+        readiness.set_loaded();
+
+        assert_eq!(
+            StatusCode::OK,
+            probe(&readiness, &Method::GET, "/readyz").await
+        );
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            probe(&readiness, &Method::GET, "/other").await
+        );
+    }
+
+    #[tokio::test]
+    async fn it_is_not_ready_without_target() {
+        let readiness = Arc::new(Readiness::new(true));
+        readiness.set_loaded();
+        assert!(!readiness.is_ready().await);
+        let endpoint = tonic::transport::Endpoint::from_static("http://127.0.0.1:1");
+        readiness.set_target(ChannelPool::new(vec![endpoint.connect_lazy()]));
+
+        let service = HealthService::new(readiness);
+        let response = service
+            .check(Request::new(HealthCheckRequest {
+                service: "inference.GRPCInferenceService".to_string(),
+            }))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            ServingStatus::NotServing as i32,
+            response.into_inner().status
+        );
+        assert_eq!(
+            tonic::Code::NotFound,
+            service
+                .check(Request::new(HealthCheckRequest {
+                    service: "other".to_string(),
+                }))
+                .await
+                .unwrap_err()
+                .code()
+        );
+    }
+}
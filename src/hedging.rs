@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+use std::future::{pending, Future};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use tonic::{Request, Status};
+
+use crate::queue::UpstreamQueue;
+
+// The number of most recent latencies the hedging delay is computed from.
+const LATENCY_WINDOW: usize = 100;
+
+// The number of latencies observed before calls are hedged.
+const MIN_LATENCIES: usize = 10;
+
+/// Hedges upstream calls: when the target server has not responded within a percentile of the
+/// recent latencies, a duplicate call is sent and the first response is used. This smooths the
+/// latency of backends with cold replicas, e.g. while they are autoscaling.
+pub struct Hedging {
+    // The percentile of the recent latencies after which a duplicate call is sent, e.g. 95.
+    percentile: f64,
+    // The latencies of the most recent successful calls, oldest first.
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl Hedging {
+    pub fn new(percentile: f64) -> Self {
+        Self {
+            percentile: percentile.clamp(0.0, 100.0),
+            latencies: Default::default(),
+        }
+    }
+
+    /// The time after which a duplicate call is sent, or None until enough latencies were
+    /// observed.
+    pub fn delay(&self) -> Option<Duration> {
+        let latencies = self.latencies.lock().unwrap();
+        if latencies.len() < MIN_LATENCIES {
+            return None;
+        }
+
+        let mut sorted: Vec<Duration> = latencies.iter().copied().collect();
+        sorted.sort();
+        let index = ((sorted.len() - 1) as f64 * self.percentile / 100.0).round() as usize;
+        Some(sorted[index])
+    }
+
+    fn observe(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == LATENCY_WINDOW {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    /// Call the target server, and call it again with a duplicate of the request when it has not
+    /// responded within the delay. The first response is returned, the other call is dropped. The
+    /// duplicate call waits for a permit of the upstream queue, and is not sent when the queue is
+    /// full.
+    pub async fn call<T, R, F, Fut>(
+        &self,
+        request: Request<T>,
+        upstream_queue: Option<&UpstreamQueue>,
+        priority: i64,
+        call: F,
+    ) -> Result<R, Status>
+    where
+        T: Clone,
+        F: Fn(Request<T>) -> Fut,
+        Fut: Future<Output = Result<R, Status>>,
+    {
+        let (metadata, _, message) = request.into_parts();
+        let duplicate =
+            || Request::from_parts(metadata.clone(), Default::default(), message.clone());
+
+        let started_at = Instant::now();
+        let first = call(duplicate());
+        let result = match self.delay() {
+            Some(delay) => {
+                tokio::pin!(first);
+                tokio::select! {
+                    result = &mut first => result,
+                    _ = tokio::time::sleep(delay) => {
+                        let second = async {
+                            let _permit = match upstream_queue {
+                                Some(queue) => match queue.acquire(priority).await {
+                                    Ok(permit) => Some(permit),
+                                    Err(_) => return pending().await,
+                                },
+                                None => None,
+                            };
+                            debug!("No response within {delay:?}, hedging the request");
+                            call(duplicate()).await
+                        };
+                        tokio::pin!(second);
+                        tokio::select! {
+                            result = &mut first => result,
+                            result = &mut second => result,
+                        }
+                    }
+                }
+            }
+            None => first.await,
+        };
+        if result.is_ok() {
+            self.observe(started_at.elapsed());
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_hedges_slow_calls() {
+        let hedging = Hedging::new(90.0);
+        assert_eq!(None, hedging.delay());
+        for millis in 1..=20 {
+            hedging.observe(Duration::from_millis(millis));
+        }
+        assert_eq!(Some(Duration::from_millis(18)), hedging.delay());
+
+        let calls = AtomicUsize::new(0);
+        let started_at = Instant::now();
+        let result = hedging
+            .call(Request::new(()), None, 0, |_| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    // The first call hangs, like a cold replica.
+                    if call == 0 {
+                        tokio::time::sleep(Duration::from_secs(10)).await;
+                    }
+                    Ok::<_, Status>(call)
+                }
+            })
+            .await;
+
+        assert_eq!(1, result.unwrap());
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn it_does_not_hedge_when_the_queue_is_full() {
+        let hedging = Hedging::new(0.0);
+        for _ in 0..MIN_LATENCIES {
+            hedging.observe(Duration::from_millis(1));
+        }
+        let queue = UpstreamQueue::new(1, 0);
+        let _permit = queue.acquire(0).await.unwrap();
+
+        let calls = AtomicUsize::new(0);
+        let result = hedging
+            .call(Request::new(()), Some(&queue), 0, |_| {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok::<_, Status>(call)
+                }
+            })
+            .await;
+
+        assert_eq!(0, result.unwrap());
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+}
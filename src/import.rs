@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use log::{info, warn};
+use serde_json::Value;
+use tonic::Request;
+
+use crate::parsing::binary_data::{decode_request, flatten, json_to_raw};
+use crate::service::inference_protocol::grpc_inference_service_server::GrpcInferenceService;
+use crate::service::inference_protocol::model_infer_request::InferInputTensor;
+use crate::service::inference_protocol::{
+    ModelInferRequest, ModelMetadataRequest, ModelMetadataResponse,
+};
+use crate::service::InferenceStoreGrpcInferenceService;
+
+/// Import the requests of a perf_analyzer input data file or a KServe v2 JSON request. The
+/// requests are recorded by sending them to the target server, or written to `out_dir` as JSON
+/// requests that can be recorded later with the `warmup.requests` setting.
+pub async fn run(
+    service: &InferenceStoreGrpcInferenceService,
+    file: PathBuf,
+    model_name: &str,
+    model_version: &str,
+    out_dir: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let content = fs::read(&file).with_context(|| format!("could not read {}", file.display()))?;
+    let json: Value = serde_json::from_slice(&content)?;
+
+    let requests = if json.get("data").is_some() {
+        // perf_analyzer files don't contain datatypes, they are taken from the model metadata.
+        let metadata_request = ModelMetadataRequest {
+            name: model_name.to_string(),
+            version: model_version.to_string(),
+        };
+        let metadata = service
+            .model_metadata(Request::new(metadata_request))
+            .await
+            .map_err(|err| anyhow::anyhow!("could not get the metadata of {model_name}: {err}"))?
+            .into_inner();
+
+        parse_perf_analyzer(model_name, model_version, &json, &metadata)?
+    } else {
+        vec![decode_request(model_name, model_version, &content, None)?]
+    };
+
+    match out_dir {
+        Some(out_dir) => {
+            write_requests(&out_dir, model_name, &requests)?;
+            info!("Wrote {} requests to {}", requests.len(), out_dir.display());
+        }
+        None => {
+            let mut recorded = 0;
+            for request in &requests {
+                match service.model_infer(Request::new(request.clone())).await {
+                    Ok(_) => recorded += 1,
+                    Err(err) => warn!("could not record imported request: {err}"),
+                }
+            }
+            info!(
+                "Recorded {recorded} of {} imported requests",
+                requests.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse perf_analyzer input data, `{"data": [{"INPUT0": ...}, ...]}`. An input is a (nested) array
+/// of values, an object with `content` and `shape`, or an object with base64 encoded raw content
+/// in `b64`. Shapes that are not provided are taken from the model metadata, with the dynamic
+/// dimension derived from the number of values.
+pub fn parse_perf_analyzer(
+    model_name: &str,
+    model_version: &str,
+    json: &Value,
+    metadata: &ModelMetadataResponse,
+) -> anyhow::Result<Vec<ModelInferRequest>> {
+    let steps = json["data"]
+        .as_array()
+        .context("perf_analyzer data must be an array")?;
+
+    steps
+        .iter()
+        .map(|step| {
+            let mut request = ModelInferRequest {
+                model_name: model_name.to_string(),
+                model_version: model_version.to_string(),
+                ..Default::default()
+            };
+
+            for tensor in &metadata.inputs {
+                let value = match step.get(&tensor.name) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let (content, shape) = match value {
+                    Value::Object(object) if object.contains_key("b64") => (
+                        BASE64_STANDARD.decode(object["b64"].as_str().unwrap_or_default())?,
+                        object.get("shape").cloned(),
+                    ),
+                    Value::Object(object) => (
+                        tensor_content(&tensor.datatype, &object["content"])?,
+                        object.get("shape").cloned(),
+                    ),
+                    data => (tensor_content(&tensor.datatype, data)?, None),
+                };
+                let shape = match shape {
+                    Some(shape) => serde_json::from_value(shape)?,
+                    None => resolve_shape(&tensor.shape, value)
+                        .with_context(|| format!("the shape of {} is required", tensor.name))?,
+                };
+
+                request.inputs.push(InferInputTensor {
+                    name: tensor.name.clone(),
+                    datatype: tensor.datatype.clone(),
+                    shape,
+                    ..Default::default()
+                });
+                request.raw_input_contents.push(content);
+            }
+
+            Ok(request)
+        })
+        .collect()
+}
+
+fn tensor_content(datatype: &str, data: &Value) -> anyhow::Result<Vec<u8>> {
+    match data {
+        Value::Object(object) if object.contains_key("b64") => {
+            Ok(BASE64_STANDARD.decode(object["b64"].as_str().unwrap_or_default())?)
+        }
+        data => json_to_raw(datatype, data),
+    }
+}
+
+/// Replace the dynamic dimension of a model metadata shape, based on the number of JSON values.
+fn resolve_shape(shape: &[i64], value: &Value) -> Option<Vec<i64>> {
+    let dynamic = shape.iter().filter(|dim| **dim < 0).count();
+    if dynamic == 0 {
+        return Some(shape.to_vec());
+    }
+    if dynamic > 1 || !value.is_array() {
+        return None;
+    }
+
+    let mut values = vec![];
+    flatten(value, &mut values);
+    let known: i64 = shape.iter().filter(|dim| **dim >= 0).product();
+    if known == 0 {
+        return None;
+    }
+
+    let dim = values.len() as i64 / known;
+    Some(
+        shape
+            .iter()
+            .map(|d| if *d < 0 { dim } else { *d })
+            .collect(),
+    )
+}
+
+fn write_requests(
+    out_dir: &Path,
+    model_name: &str,
+    requests: &[ModelInferRequest],
+) -> anyhow::Result<()> {
+    fs::create_dir_all(out_dir)?;
+
+    for (index, request) in requests.iter().enumerate() {
+        let path = out_dir.join(format!("{}-{index}.json", urlencoding::encode(model_name)));
+        fs::write(path, serde_json::to_vec_pretty(request)?)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::parsing::input::tests::raw_infer_request;
+    use crate::service::inference_protocol::model_metadata_response::TensorMetadata;
+
+    fn metadata() -> ModelMetadataResponse {
+        ModelMetadataResponse {
+            inputs: vec![
+                TensorMetadata {
+                    name: "INPUT0".to_string(),
+                    datatype: "INT32".to_string(),
+                    shape: vec![-1, 2],
+                },
+                TensorMetadata {
+                    name: "INPUT1".to_string(),
+                    datatype: "UINT8".to_string(),
+                    shape: vec![2],
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_parses_perf_analyzer_data() {
+        let json = json!({"data": [
+            {"INPUT0": [1, 2, 3, 4], "INPUT1": {"b64": "AQI="}},
+            {"INPUT0": {"content": [5, 6], "shape": [1, 2]}, "INPUT1": [7, 8]},
+        ]});
+
+        let requests = parse_perf_analyzer("test", "1", &json, &metadata()).unwrap();
+
+        let int32 = |values: &[i32]| values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(
+            vec![
+                raw_infer_request(vec![
+                    ("INPUT0", "INT32", vec![2, 2], int32(&[1, 2, 3, 4])),
+                    ("INPUT1", "UINT8", vec![2], vec![1, 2]),
+                ]),
+                raw_infer_request(vec![
+                    ("INPUT0", "INT32", vec![1, 2], int32(&[5, 6])),
+                    ("INPUT1", "UINT8", vec![2], vec![7, 8]),
+                ]),
+            ],
+            requests
+        );
+    }
+
+    #[test]
+    fn it_requires_shapes_of_raw_dynamic_inputs() {
+        let json = json!({"data": [{"INPUT0": {"b64": "AQIDBA=="}}]});
+
+        assert!(parse_perf_analyzer("test", "1", &json, &metadata()).is_err());
+    }
+}
@@ -0,0 +1,38 @@
+pub mod admin;
+pub mod auth;
+pub mod bench;
+pub mod caching;
+pub mod check;
+pub mod cli;
+pub mod consensus;
+pub mod coverage;
+pub mod dashboard;
+pub mod deadline;
+pub mod determinism;
+pub mod diff;
+pub mod dry_run;
+pub mod error;
+pub mod estimate;
+pub mod export;
+pub mod extensions;
+pub mod health;
+pub mod hedging;
+pub mod import;
+pub mod logging;
+pub mod middleware;
+pub mod parsing;
+pub mod pool;
+pub mod queue;
+pub mod raw_response;
+pub mod remote_cache;
+pub mod replication;
+pub mod sampling;
+pub mod service;
+pub mod settings;
+pub mod statistics;
+pub mod trace;
+pub mod utils;
+pub mod verify;
+
+// The generated admin protocol refers to the inference protocol by its package name.
+use service::inference_protocol as inference;
@@ -0,0 +1,177 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use env_logger::{Builder, Env, DEFAULT_WRITE_STYLE_ENV};
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::service::inference_protocol::log_settings_request;
+use crate::service::inference_protocol::log_settings_response;
+use crate::service::inference_protocol::LogSettingsRequest;
+
+/// The log setting with the log level of InferenceStore itself, e.g. `debug`. It is handled by
+/// InferenceStore, and not forwarded to the target server.
+pub const LOG_LEVEL_SETTING: &str = "inferencestore_log_level";
+
+/// The log level of InferenceStore itself, as the index of a `LevelFilter`. It can be changed at
+/// runtime, also beyond `RUST_LOG`.
+static LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+/// The maximum level of the dependency logs `RUST_LOG` enables. The global maximum level stays at
+/// least this high when the InferenceStore level changes.
+static DEPENDENCIES_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
+/// A logger of which the level of the InferenceStore logs can be changed at runtime. The logs of
+/// dependencies keep following `RUST_LOG`.
+struct ReloadableLogger {
+    /// The logger configured by `RUST_LOG`, only used to filter the logs of dependencies.
+    filter: env_logger::Logger,
+    /// The logger that writes every record it is given.
+    writer: env_logger::Logger,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if is_inference_store(metadata.target()) {
+            metadata.level() <= level()
+        } else {
+            self.filter.enabled(metadata)
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.writer.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.writer.flush();
+    }
+}
+
+/// Whether a log target is InferenceStore itself, rather than a dependency.
+fn is_inference_store(target: &str) -> bool {
+    let crate_name = env!("CARGO_CRATE_NAME");
+    target
+        .strip_prefix(crate_name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// Install the logger of which the InferenceStore log level is set by `set_level`.
+pub fn init() -> Result<(), SetLoggerError> {
+    let logger = ReloadableLogger {
+        filter: Builder::from_default_env().build(),
+        writer: Builder::from_env(Env::new().write_style(DEFAULT_WRITE_STYLE_ENV))
+            .filter_level(LevelFilter::Trace)
+            .build(),
+    };
+    let dependencies = logger.filter.filter();
+    log::set_boxed_logger(Box::new(logger))?;
+    DEPENDENCIES_LEVEL.store(dependencies as usize, Ordering::Relaxed);
+    log::set_max_level(level().max(dependencies));
+
+    Ok(())
+}
+
+/// Set the log level of InferenceStore itself.
+pub fn set_level(level: LevelFilter) {
+    LEVEL.store(level as usize, Ordering::Relaxed);
+    let dependencies = level_filter(DEPENDENCIES_LEVEL.load(Ordering::Relaxed));
+    log::set_max_level(level.max(dependencies));
+}
+
+/// The log level of InferenceStore itself.
+pub fn level() -> LevelFilter {
+    level_filter(LEVEL.load(Ordering::Relaxed))
+}
+
+fn level_filter(index: usize) -> LevelFilter {
+    LevelFilter::iter().nth(index).unwrap_or(LevelFilter::Trace)
+}
+
+/// Remove the InferenceStore log level from the log settings, and return the level it sets.
+pub fn take_log_level(request: &mut LogSettingsRequest) -> anyhow::Result<Option<LevelFilter>> {
+    let Some(setting) = request.settings.remove(LOG_LEVEL_SETTING) else {
+        return Ok(None);
+    };
+
+    match setting.parameter_choice {
+        Some(log_settings_request::setting_value::ParameterChoice::StringParam(level)) => {
+            match LevelFilter::from_str(&level) {
+                Ok(level) => Ok(Some(level)),
+                Err(_) => anyhow::bail!(
+                    "invalid {LOG_LEVEL_SETTING} {level}, expected off, error, warn, info, debug or trace"
+                ),
+            }
+        }
+        _ => anyhow::bail!("{LOG_LEVEL_SETTING} must be a string"),
+    }
+}
+
+/// The current log level of InferenceStore, as a log setting.
+pub fn log_level_setting() -> (String, log_settings_response::SettingValue) {
+    let level = level().to_string().to_lowercase();
+
+    (
+        LOG_LEVEL_SETTING.to_string(),
+        log_settings_response::SettingValue {
+            parameter_choice: Some(
+                log_settings_response::setting_value::ParameterChoice::StringParam(level),
+            ),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use log_settings_request::setting_value::ParameterChoice;
+    use log_settings_request::SettingValue;
+
+    fn request(parameter_choice: ParameterChoice) -> LogSettingsRequest {
+        LogSettingsRequest {
+            settings: HashMap::from([
+                (
+                    LOG_LEVEL_SETTING.to_string(),
+                    SettingValue {
+                        parameter_choice: Some(parameter_choice),
+                    },
+                ),
+                (
+                    "log_verbose_level".to_string(),
+                    SettingValue {
+                        parameter_choice: Some(ParameterChoice::Uint32Param(1)),
+                    },
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn it_recognizes_its_own_targets() {
+        assert!(is_inference_store("inference_store"));
+        assert!(is_inference_store("inference_store::service"));
+        assert!(!is_inference_store("inference_store_client"));
+        assert!(!is_inference_store("h2::codec"));
+    }
+
+    #[test]
+    fn it_takes_the_log_level() {
+        let mut req = request(ParameterChoice::StringParam("DEBUG".to_string()));
+
+        assert_eq!(Some(LevelFilter::Debug), take_log_level(&mut req).unwrap());
+        assert_eq!(
+            vec!["log_verbose_level"],
+            req.settings.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(None, take_log_level(&mut req).unwrap());
+
+        assert!(take_log_level(&mut request(ParameterChoice::StringParam(
+            "loud".to_string()
+        )))
+        .is_err());
+        assert!(take_log_level(&mut request(ParameterChoice::BoolParam(true))).is_err());
+    }
+}
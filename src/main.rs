@@ -1,55 +1,357 @@
-mod caching;
-mod parsing;
-mod service;
-mod settings;
-mod utils;
-
-use crate::caching::cachestore::CacheStore;
-use crate::service::inference_protocol::grpc_inference_service_client::GrpcInferenceServiceClient;
-use crate::service::inference_protocol::grpc_inference_service_server::GrpcInferenceServiceServer;
-use crate::settings::ServerMode;
-use log::{error, info, LevelFilter};
-use settings::Settings;
+use clap::Parser;
+use inference_store::admin::admin_protocol::admin_server::AdminServer;
+use inference_store::admin::AdminService;
+use inference_store::auth::TokenInterceptor;
+use inference_store::caching::backend::{DirectoryBackend, StorageBackend};
+use inference_store::caching::cachable::Cachable;
+use inference_store::caching::cachable_modelconfig::CachableModelConfig;
+use inference_store::caching::cachable_modelinfer::CachableModelInfer;
+use inference_store::caching::cachable_modelmetadata::CachableModelMetadata;
+use inference_store::caching::cachestore::CacheStore;
+use inference_store::caching::flush::FLUSH_QUEUE;
+use inference_store::caching::metadata::{parse_labels, unix_millis};
+use inference_store::caching::migration;
+use inference_store::caching::pack;
+use inference_store::cli::{Cli, Command};
+use inference_store::consensus::Consensus;
+use inference_store::coverage::Coverage;
+use inference_store::dashboard::{self, Dashboard};
+use inference_store::determinism::{Determinism, DETERMINISM_FILE};
+use inference_store::dry_run::DryRun;
+use inference_store::health::health_protocol::health_server::HealthServer;
+use inference_store::health::{HealthService, Readiness};
+use inference_store::hedging::Hedging;
+use inference_store::pool::ChannelPool;
+use inference_store::queue::UpstreamQueue;
+use inference_store::raw_response::RawResponseServer;
+use inference_store::remote_cache::remote_cache_protocol::remote_cache_server::RemoteCacheServer;
+use inference_store::remote_cache::{RemoteCache, RemoteCacheService};
+use inference_store::replication::replication_protocol::replication_server::ReplicationServer;
+use inference_store::replication::{ReplicationService, Replicator};
+use inference_store::service::inference_protocol::ServerMetadataRequest;
+use inference_store::settings::Settings;
+use inference_store::settings::{FlushPolicy, IndexOverBudget, ListenerService, ServerMode};
+use inference_store::trace::TraceLog;
+use inference_store::{
+    bench, check, diff, estimate, export, health, import, logging, service, settings, verify,
+};
+use log::{error, info, warn, LevelFilter};
 use std::io::ErrorKind::NotFound;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use std::{fs, io};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Server;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::init();
+    logging::init()?;
 
-    let settings = match Settings::new() {
+    let cli = Cli::parse();
+
+    if let Some(Command::Diff {
+        dir_a,
+        dir_b,
+        tolerance,
+    }) = cli.command.clone()
+    {
+        let differs = diff::run(dir_a, dir_b, tolerance).await?;
+        std::process::exit(differs as i32);
+    }
+
+    if let Some(Command::Bench {
+        store_sizes,
+        tensor_size,
+        iterations,
+    }) = cli.command.clone()
+    {
+        return bench::run(store_sizes, tensor_size, iterations).await;
+    }
+
+    if let Some(Command::Verify { dir }) = cli.command.clone() {
+        let invalid = verify::run(dir)?;
+        std::process::exit(invalid as i32);
+    }
+
+    if let Some(Command::Export {
+        dir,
+        out_dir,
+        labels,
+    }) = cli.command.clone()
+    {
+        return export::run(dir, out_dir, parse_labels(&labels.join(","))).await;
+    }
+
+    if let Some(Command::Compact { dir }) = cli.command.clone() {
+        let compacted = pack::compact(&dir)?;
+        println!("Compacted {compacted} entries in {}", dir.display());
+        return Ok(());
+    }
+
+    let mut settings = match Settings::new(&cli) {
         Ok(settings) => settings,
         Err(err) => {
-            error!("Could not load config: {}", err.to_string());
+            error!("Could not load config: {}", err);
             std::process::exit(1)
         }
     };
 
-    log::set_max_level(if settings.debug {
+    if let Some(Command::Check { target }) = cli.command.clone() {
+        let failed = check::run(&settings, target).await;
+        std::process::exit(failed as i32);
+    }
+
+    // Imported requests are recorded by sending them through the service, which only records in
+    // Collect mode.
+    if let Some(Command::Import { out_dir: None, .. }) = &cli.command {
+        if settings.mode != ServerMode::Collect {
+            error!("Imported requests are only recorded in Collect mode, or pass --out-dir");
+            std::process::exit(1)
+        }
+    }
+
+    if let Some(Command::Estimate { source }) = cli.command.clone() {
+        return estimate::run(&settings, source).await;
+    }
+
+    if cli.print_config {
+        println!("{}", serde_json::to_string_pretty(&settings)?);
+        return Ok(());
+    }
+
+    logging::set_level(if settings.debug {
         LevelFilter::Debug
     } else {
         LevelFilter::Info
     });
 
-    let addr = format!("{}:{}", settings.server.host, settings.server.port).parse()?;
+    let listeners = settings.server.listeners();
+    let connection = settings.server.connection.clone();
 
-    let inference_client = match settings.mode {
+    // The probes are served while loading the cache, so the process is live but not yet ready.
+    let readiness = Arc::new(Readiness::new(settings.mode != ServerMode::Serve));
+    if !settings.server.probe_address.is_empty() {
+        let addr = settings.server.probe_address.parse()?;
+        let readiness = readiness.clone();
+        tokio::spawn(async move {
+            if let Err(err) = health::serve_probes(addr, readiness).await {
+                error!("Could not serve probes on {addr}: {err}");
+            }
+        });
+    }
+
+    match settings.request_collection.flush_policy {
+        FlushPolicy::Always => {}
+        FlushPolicy::Interval => {
+            FLUSH_QUEUE.defer();
+            let interval = Duration::from_secs(settings.request_collection.flush_interval.max(1));
+            tokio::spawn(FLUSH_QUEUE.sync_periodically(interval));
+        }
+        FlushPolicy::Shutdown => FLUSH_QUEUE.defer(),
+    }
+
+    if settings.request_collection.canonical_json {
+        migration::use_canonical_json();
+    }
+
+    let inference_store_path = PathBuf::from(&settings.request_collection.path);
+    let low_memory = settings.request_collection.low_memory;
+    let model_dirs = settings.request_collection.model_directories;
+    let single_use_models = settings.request_matching.single_use_models.clone();
+
+    // In Serve mode the stores are opened read-only, and corrupted files prevent starting. In
+    // DryRun mode they are opened read-only as well, so nothing is written to the cache.
+    let (inference_store, config_store, metadata_store): (
+        CacheStore<CachableModelInfer>,
+        CacheStore<CachableModelConfig>,
+        CacheStore<CachableModelMetadata>,
+    ) = match settings.mode {
         ServerMode::Collect => {
-            match GrpcInferenceServiceClient::connect(settings.target_server.host.clone()).await {
-                Ok(client) => {
+            let mut stores = (
+                CacheStore::new(inference_store_path.clone())
+                    .with_low_memory(low_memory)
+                    .with_model_dirs(model_dirs)
+                    .with_required_labels(settings.get_collect_labels())
+                    .with_single_use_models(single_use_models.clone()),
+                CacheStore::new(inference_store_path.clone()).with_model_dirs(model_dirs),
+                CacheStore::new(inference_store_path.clone()).with_model_dirs(model_dirs),
+            );
+            // The base directories are shared, so they are only read and never written.
+            for path in &settings.request_collection.base_paths {
+                let path = PathBuf::from(path);
+                stores = (
+                    stores.0.with_fallback(
+                        CacheStore::new_read_only(path.clone())
+                            .with_low_memory(low_memory)
+                            .with_model_dirs(model_dirs)
+                            .with_single_use_models(single_use_models.clone()),
+                    ),
+                    stores.1.with_fallback(
+                        CacheStore::new_read_only(path.clone()).with_model_dirs(model_dirs),
+                    ),
+                    stores
+                        .2
+                        .with_fallback(CacheStore::new_read_only(path).with_model_dirs(model_dirs)),
+                );
+            }
+            stores
+        }
+        ServerMode::Serve => {
+            let serve_store = |path: PathBuf| {
+                CacheStore::new_read_only(path)
+                    .with_low_memory(low_memory)
+                    .with_required_labels(settings.get_serve_labels())
+                    .with_recorded_before(settings.get_serve_as_of())
+                    .with_single_use_models(single_use_models.clone())
+            };
+            let mut stores = (
+                serve_store(inference_store_path.clone()),
+                CacheStore::new_read_only(inference_store_path.clone()),
+                CacheStore::new_read_only(inference_store_path.clone()),
+            );
+            for path in &settings.request_matching.serve_fallback_paths {
+                let path = PathBuf::from(path);
+                stores = (
+                    stores.0.with_fallback(serve_store(path.clone())),
+                    stores
+                        .1
+                        .with_fallback(CacheStore::new_read_only(path.clone())),
+                    stores.2.with_fallback(CacheStore::new_read_only(path)),
+                );
+            }
+            stores
+        }
+        ServerMode::DryRun => (
+            CacheStore::new_read_only(inference_store_path.clone())
+                .with_low_memory(low_memory)
+                .with_model_dirs(model_dirs)
+                .with_single_use_models(single_use_models),
+            CacheStore::new_read_only(inference_store_path.clone()).with_model_dirs(model_dirs),
+            CacheStore::new_read_only(inference_store_path.clone()).with_model_dirs(model_dirs),
+        ),
+    };
+    let backend: Option<Arc<dyn StorageBackend>> =
+        match settings.request_collection.backend_dir.as_str() {
+            "" => None,
+            dir => {
+                // The cache directory holds the working copy of the storage backend.
+                fs::create_dir_all(&inference_store_path)?;
+                Some(Arc::new(DirectoryBackend::new(PathBuf::from(dir))?))
+            }
+        };
+    let (inference_store, config_store, metadata_store) = match backend {
+        Some(backend) => (
+            inference_store.with_backend(backend.clone()),
+            config_store.with_backend(backend.clone()),
+            metadata_store.with_backend(backend),
+        ),
+        None => (inference_store, config_store, metadata_store),
+    };
+    let inference_store = match settings.request_collection.usage_path.as_str() {
+        "" => inference_store,
+        path => inference_store.with_usage_file(PathBuf::from(path)),
+    };
+    let inference_store = inference_store.with_index_budget(
+        settings.request_collection.max_index_bytes,
+        settings.request_collection.index_over_budget == IndexOverBudget::Refuse,
+    );
+
+    let is_not_found = |err: &anyhow::Error| {
+        err.downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == NotFound)
+    };
+    let cache_exists = match inference_store.load().await {
+        Err(err) if settings.mode == ServerMode::Collect && is_not_found(&err) => {
+            fs::create_dir_all(&inference_store_path)?;
+            info!(
+                "Created path {} to store inference files",
+                inference_store_path.display()
+            );
+            true
+        }
+        // A dry run does not create the cache directory, every request would be recorded.
+        Err(err) if settings.mode == ServerMode::DryRun && is_not_found(&err) => {
+            info!(
+                "Path {} does not exist, starting the dry run with an empty cache",
+                inference_store_path.display()
+            );
+            false
+        }
+        Err(err) => return Err(err),
+        _ => true,
+    };
+
+    if cache_exists {
+        config_store.load().await?;
+        metadata_store.load().await?;
+    }
+
+    if cli.migrate {
+        let migrated =
+            inference_store.migrate()? + config_store.migrate()? + metadata_store.migrate()?;
+        info!("Migrated {migrated} cache entries");
+
+        return Ok(());
+    }
+
+    if let Some(seconds) = cli.prune_older_than {
+        let cutoff = unix_millis(SystemTime::now() - Duration::from_secs(seconds));
+        let is_outdated =
+            |recorded_at: Option<i64>| recorded_at.is_some_and(|recorded_at| recorded_at < cutoff);
+
+        let removed = inference_store
+            .prune(|c| is_outdated(c.recorded_or_modified_at()))
+            .await?
+            + config_store
+                .prune(|c| is_outdated(c.recorded_or_modified_at()))
+                .await?
+            + metadata_store
+                .prune(|c| is_outdated(c.recorded_or_modified_at()))
+                .await?;
+        info!("Pruned {removed} cache entries");
+
+        return Ok(());
+    }
+
+    if let Some(count) = cli.evict_least_used {
+        let least_used = inference_store.least_used(count).await;
+        let removed = inference_store
+            .prune(|c| {
+                c.path()
+                    .file_name()
+                    .is_some_and(|file_name| least_used.contains(&*file_name.to_string_lossy()))
+            })
+            .await?;
+        inference_store.save_usage().await?;
+        info!("Evicted {removed} cache entries");
+
+        return Ok(());
+    }
+
+    let inference_client = match settings.mode {
+        ServerMode::Collect | ServerMode::DryRun => {
+            let endpoint = settings
+                .target_server
+                .connection
+                .endpoint(&settings.target_server.host)?;
+            match ChannelPool::connect(endpoint, settings.target_server.channels).await {
+                Ok(pool) => {
                     info!(
-                        "Connected to target grpc inference service {}",
-                        settings.target_server.host.clone()
+                        "Connected to target grpc inference service {} with {} channels",
+                        settings.target_server.host.clone(),
+                        pool.len()
                     );
-                    Some(client)
+                    Some(pool)
                 }
                 Err(err) => {
                     error!(
                         "Could not connect to grpc inference service {}: {}",
                         settings.target_server.host.clone(),
-                        err.to_string()
+                        err
                     );
                     std::process::exit(1)
                 }
@@ -60,58 +362,324 @@ async fn main() -> anyhow::Result<()> {
             None
         }
     };
+    if let Some(pool) = &inference_client {
+        readiness.set_target(pool.clone());
+    }
 
-    let inference_store_path = PathBuf::from(&settings.request_collection.path);
-    let inference_store = CacheStore::new(inference_store_path.clone());
-    let config_store = CacheStore::new(inference_store_path.clone());
-
-    match inference_store.load().await {
-        Err(err)
-            if err
-                .downcast_ref::<io::Error>()
-                .map_or(false, |e| e.kind() == NotFound) =>
-        {
-            fs::create_dir_all(&inference_store_path)?;
+    // The consensus target servers are connected lazily, target servers that can not be reached
+    // do not agree.
+    let consensus = match (
+        &inference_client,
+        &settings.target_server.consensus_hosts[..],
+    ) {
+        (Some(_), hosts) if !hosts.is_empty() => {
+            let mut backends = vec![];
+            for host in hosts {
+                let endpoint = settings.target_server.connection.endpoint(host)?;
+                backends.push((
+                    host.clone(),
+                    ChannelPool::new(vec![endpoint.connect_lazy()]),
+                ));
+            }
             info!(
-                "Created path {} to store inference files",
-                inference_store_path.display()
+                "Recording outputs {} target servers agree on",
+                backends.len() + 1
             );
+            Some(Consensus::new(
+                backends,
+                settings.target_server.consensus_quorum,
+                settings.target_server.consensus_tolerance,
+            ))
+        }
+        _ => None,
+    };
+
+    if settings.target_server.name.is_empty() {
+        let partition_by_target = settings.request_matching.partition_by_target;
+        match &inference_client {
+            Some(pool) => match pool
+                .client()
+                .server_metadata(ServerMetadataRequest {})
+                .await
+            {
+                Ok(response) => {
+                    let response = response.into_inner();
+                    settings.target_server.name = format!("{} {}", response.name, response.version);
+                    info!(
+                        "Identified target server as {}",
+                        settings.target_server.name
+                    );
+                }
+                Err(err) if partition_by_target => {
+                    anyhow::bail!("could not identify the target server: {}", err.message())
+                }
+                Err(err) => warn!("Could not identify the target server: {}", err.message()),
+            },
+            None if partition_by_target => anyhow::bail!(
+                "target_server.name is required to partition by target server in serving mode"
+            ),
+            None => {}
         }
-        Err(err) => return Err(err.into()),
-        _ => {}
     }
 
-    match config_store.load().await {
-        Err(err)
-            if err
-                .downcast_ref::<io::Error>()
-                .map_or(false, |e| e.kind() == NotFound) =>
-        {
-            fs::create_dir_all(&inference_store_path)?;
-            info!(
-                "Created path {} to store inference files",
-                inference_store_path.display()
-            );
+    let inference_store = Arc::new(inference_store);
+    let config_store = Arc::new(config_store);
+    let metadata_store = Arc::new(metadata_store);
+
+    if settings.request_collection.refresh_interval > 0 {
+        let interval = Duration::from_secs(settings.request_collection.refresh_interval);
+        tokio::spawn(inference_store.clone().refresh_periodically(interval));
+        tokio::spawn(config_store.clone().refresh_periodically(interval));
+        tokio::spawn(metadata_store.clone().refresh_periodically(interval));
+    }
+    let dry_run = (settings.mode == ServerMode::DryRun).then(|| Arc::new(DryRun::default()));
+    if let Some(dry_run) = &dry_run {
+        let interval = Duration::from_secs(settings.request_collection.dry_run_report_interval);
+        if !interval.is_zero() {
+            tokio::spawn(dry_run.clone().log_periodically(interval));
         }
-        Err(err) => return Err(err.into()),
-        _ => {}
+    }
+    // The usage of the entries is not written in DryRun mode.
+    let saves_usage = dry_run.is_none();
+    if saves_usage && settings.request_collection.usage_interval > 0 {
+        let interval = Duration::from_secs(settings.request_collection.usage_interval);
+        tokio::spawn(inference_store.clone().save_usage_periodically(interval));
     }
 
+    // The remote cache is connected lazily, misses are forwarded to the target server when it can
+    // not be reached. Nothing is imported from it in DryRun mode.
+    let remote_cache = match settings.cluster.remote_cache.as_str() {
+        "" => None,
+        _ if dry_run.is_some() => None,
+        address => {
+            let endpoint = settings.target_server.connection.endpoint(address)?;
+            info!("Consulting the remote cache at {address}");
+            Some(
+                RemoteCache::new(endpoint.connect_lazy(), inference_store.clone())
+                    .with_push(settings.cluster.remote_cache_push)
+                    .with_token(settings.cluster.remote_cache_token.clone())
+                    .with_timeout(settings.cluster.remote_cache_timeout()),
+            )
+        }
+    };
+    let replicator = Replicator::new(
+        settings.cluster.peers.clone(),
+        settings.cluster.advertise_address.clone(),
+        inference_store.clone(),
+        config_store.clone(),
+        metadata_store.clone(),
+    );
+    let replicator = Arc::new(match &remote_cache {
+        Some(remote_cache) => replicator.with_remote_cache(remote_cache.clone()),
+        None => replicator,
+    });
+    if dry_run.is_none() {
+        replicator.sync().await;
+    }
+    let cluster_peers = settings.cluster.peers.clone();
+    // The hits of the entries are persisted and reported at shutdown.
+    let coverage = Arc::new(Coverage::default());
+    let coverage_report = match settings.trace.coverage_report.as_str() {
+        "" => None,
+        path => Some(PathBuf::from(path)),
+    };
+    // The hits and misses are only counted when something reports them, as the misses grow with
+    // every distinct request.
+    let counts_coverage = coverage_report.is_some()
+        || settings.server.admin
+        || !settings.server.dashboard_address.is_empty();
+    // The models found nondeterministic are persisted in Collect mode only.
+    let determinism = match settings.mode {
+        ServerMode::Collect => {
+            Determinism::default().with_file(inference_store_path.join(DETERMINISM_FILE))
+        }
+        _ => Determinism::default(),
+    };
+    let shutdown_store = inference_store.clone();
+    if !settings.server.dashboard_address.is_empty() {
+        let addr = settings.server.dashboard_address.parse()?;
+        let dashboard =
+            Arc::new(Dashboard::new(inference_store.clone()).with_coverage(coverage.clone()));
+        tokio::spawn(async move {
+            if let Err(err) = dashboard::serve_dashboard(addr, dashboard).await {
+                error!("Could not serve the dashboard on {addr}: {err}");
+            }
+        });
+    }
+    // The admin service is only exposed when enabled.
+    let admin_token = settings.server.admin_token.clone();
+    let admin_service = settings.server.admin.then(|| {
+        Arc::new(
+            AdminService::new(
+                inference_store.clone(),
+                config_store.clone(),
+                metadata_store.clone(),
+            )
+            .with_coverage(coverage.clone()),
+        )
+    });
+    let warmup = (settings.mode == ServerMode::Collect).then(|| settings.warmup.clone());
+    let (max_concurrency, max_queued) = (
+        settings.target_server.max_concurrency,
+        settings.target_server.max_queued,
+    );
+    let command = cli.command.clone();
+    let trace = match settings.trace.path.as_str() {
+        "" => None,
+        path => Some(Arc::new(TraceLog::open(
+            PathBuf::from(path),
+            settings.trace.max_size,
+        )?)),
+    };
+
+    let serve_remote_cache = settings.cluster.serve_remote_cache;
+    // Requests are only hedged in Collect mode.
+    let hedge_percentile = (settings.mode == ServerMode::Collect
+        && settings.target_server.hedge_percentile > 0.0)
+        .then_some(settings.target_server.hedge_percentile);
+    let settings = Arc::new(RwLock::new(settings));
+    // The remote cache service is only exposed when enabled.
+    let remote_cache_service = serve_remote_cache.then(|| {
+        Arc::new(RemoteCacheService::new(
+            settings.clone(),
+            inference_store.clone(),
+            replicator.clone(),
+        ))
+    });
+
+    tokio::spawn(settings::reload_on_hangup(cli, settings.clone()));
+
     let service = service::InferenceStoreGrpcInferenceService::new(
         settings,
         inference_store,
         config_store,
+        metadata_store,
         inference_client,
-    );
-    let service_server =
-        GrpcInferenceServiceServer::new(service).max_decoding_message_size(1024 * 1024 * 128);
+        replicator.clone(),
+        trace,
+    )
+    .with_determinism(Arc::new(determinism));
+    let service = match counts_coverage {
+        true => service.with_coverage(coverage.clone()),
+        false => service,
+    };
+    let service = match consensus {
+        Some(consensus) => service.with_consensus(consensus),
+        None => service,
+    };
+    let service = match &dry_run {
+        Some(dry_run) => service.with_dry_run(dry_run.clone()),
+        None => service,
+    };
+    let service = match remote_cache {
+        Some(remote_cache) => service.with_remote_cache(remote_cache),
+        None => service,
+    };
+    let service = match hedge_percentile {
+        Some(percentile) => service.with_hedging(Hedging::new(percentile)),
+        None => service,
+    };
+    let service = match max_concurrency {
+        0 => service,
+        max_concurrency => {
+            service.with_upstream_queue(UpstreamQueue::new(max_concurrency, max_queued))
+        }
+    };
+    if let Some(warmup) = warmup {
+        service.warmup(&warmup).await;
+    }
+
+    if let Some(Command::Import {
+        file,
+        model,
+        model_version,
+        out_dir,
+    }) = command
+    {
+        let imported = import::run(&service, file, &model, &model_version, out_dir).await;
+        FLUSH_QUEUE.sync_on_shutdown();
+        return imported;
+    }
+
+    let service = Arc::new(service);
+    readiness.set_loaded();
+
+    // The replication service is only exposed in cluster mode.
+    let replication_service =
+        (!cluster_peers.is_empty()).then(|| Arc::new(ReplicationService::new(replicator)));
+
+    // Every listener serves its own selection of the shared services.
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        let addr = listener.address()?;
+
+        let service_server = listener.exposes(ListenerService::Inference).then(|| {
+            RawResponseServer::from_arc(service.clone())
+                .max_decoding_message_size(1024 * 1024 * 128)
+        });
+        let replication_server = replication_service
+            .clone()
+            .filter(|_| listener.exposes(ListenerService::Replication))
+            .map(ReplicationServer::from_arc);
+        let admin_server = admin_service
+            .clone()
+            .filter(|_| listener.exposes(ListenerService::Admin))
+            .map(|admin_service| {
+                InterceptedService::new(
+                    AdminServer::from_arc(admin_service),
+                    TokenInterceptor::new(admin_token.clone()),
+                )
+            });
+        let remote_cache_server = remote_cache_service
+            .clone()
+            .filter(|_| listener.exposes(ListenerService::RemoteCache))
+            .map(RemoteCacheServer::from_arc);
+
+        info!("Starting GRPC server on {}", addr);
 
-    info!("Starting GRPC server on {}", addr);
+        servers.spawn(
+            connection
+                .configure_server(Server::builder())
+                .add_service(HealthServer::new(HealthService::new(readiness.clone())))
+                .add_optional_service(service_server)
+                .add_optional_service(replication_server)
+                .add_optional_service(admin_server)
+                .add_optional_service(remote_cache_server)
+                .serve(addr),
+        );
+    }
 
-    Server::builder()
-        .add_service(service_server)
-        .serve(addr)
-        .await?;
+    // Stop as soon as any of the listeners fails, or on a shutdown signal. Deferred syncs of the
+    // cache files are done before exiting.
+    let mut terminate = signal(SignalKind::terminate())?;
+    let result = loop {
+        tokio::select! {
+            joined = servers.join_next() => match joined {
+                Some(Ok(Ok(()))) => continue,
+                Some(Ok(Err(err))) => break Err(err.into()),
+                Some(Err(err)) => break Err(err.into()),
+                None => break Ok(()),
+            },
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+            _ = terminate.recv() => break Ok(()),
+        }
+    };
+    FLUSH_QUEUE.sync_on_shutdown();
+    if saves_usage {
+        if let Err(err) = shutdown_store.save_usage().await {
+            error!("Could not save the usage of the cache entries: {err}");
+        }
+    }
+    if let Some(dry_run) = &dry_run {
+        dry_run.log_report();
+    }
+    if let Some(path) = coverage_report {
+        let report = coverage.report(shutdown_store.file_names().await, "");
+        match report.write(&path) {
+            Ok(()) => info!("Wrote the coverage report to {}", path.display()),
+            Err(err) => error!("Could not write the coverage report: {err}"),
+        }
+    }
 
-    Ok(())
+    result
 }
@@ -0,0 +1,78 @@
+use tonic::{Request, Status};
+
+use crate::service::inference_protocol::ModelInferRequest;
+use crate::trace::TraceRecord;
+
+/// Custom logic around the cache lookups and target server calls of inference requests, e.g.
+/// authentication, request mutation or bespoke metrics, plugged in with
+/// `InferenceStoreGrpcInferenceService::with_middleware` when InferenceStore is used as a library.
+/// All hooks do nothing by default, and are called for unary and streaming requests.
+#[tonic::async_trait]
+pub trait Middleware: Send + Sync {
+    /// Called before a request is looked up in the cache. Changes to the request are part of the
+    /// cache key, an error is returned to the client without handling the request. The requests
+    /// of a stream carry the metadata of the stream.
+    async fn on_request(&self, _request: &mut Request<ModelInferRequest>) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Called before a request that is not served from the cache is forwarded to the target
+    /// server, e.g. to add credentials. Changes to the request are not part of the cache key, an
+    /// error is returned to the client without forwarding the request. The requests of a stream
+    /// carry the metadata of the stream here as well.
+    async fn on_forward(&self, _request: &mut Request<ModelInferRequest>) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Called once a request is handled, with how it was handled and its latency.
+    fn on_complete(&self, _record: &TraceRecord) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::trace::{Outcome, TraceGuard};
+
+    #[derive(Default)]
+    struct Recorder {
+        completed: Mutex<Vec<TraceRecord>>,
+    }
+
+    #[tonic::async_trait]
+    impl Middleware for Recorder {
+        async fn on_request(&self, request: &mut Request<ModelInferRequest>) -> Result<(), Status> {
+            match request.metadata().get("authorization") {
+                Some(_) => Ok(()),
+                None => Err(Status::unauthenticated("no authorization")),
+            }
+        }
+
+        fn on_complete(&self, record: &TraceRecord) {
+            self.completed.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn it_calls_the_hooks() {
+        let recorder = Arc::new(Recorder::default());
+
+        let mut request = Request::new(ModelInferRequest::default());
+        assert!(recorder.on_request(&mut request).await.is_err());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer token".parse().unwrap());
+        assert!(recorder.on_request(&mut request).await.is_ok());
+        assert!(recorder.on_forward(&mut request).await.is_ok());
+
+        {
+            let mut guard =
+                TraceGuard::new(None, "test", "1").with_middleware(Some(recorder.clone()));
+            guard.record.outcome = Outcome::Hit;
+        }
+        let completed = recorder.completed.lock().unwrap();
+        assert_eq!(1, completed.len());
+        assert_eq!(Outcome::Hit, completed[0].outcome);
+    }
+}
@@ -1,2 +1,13 @@
+pub mod batch;
+pub mod binary_data;
+pub mod cache_control;
+pub mod classification;
 pub mod input;
+pub mod normalization;
 pub mod output;
+pub mod script;
+pub mod scrubbing;
+pub mod sequence;
+pub mod synthesize;
+pub mod tensor;
+pub mod tensor_diff;
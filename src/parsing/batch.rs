@@ -0,0 +1,359 @@
+use bytes::Bytes;
+use std::iter;
+
+use crate::parsing::cache_control::uses_shared_memory;
+use crate::parsing::input::raw_input_contents;
+use crate::parsing::sequence::SEQUENCE_ID;
+use crate::parsing::tensor::{decode_bytes_elements, element_size, encode_bytes_elements};
+use crate::service::inference_protocol::model_infer_request::InferInputTensor;
+use crate::service::inference_protocol::model_infer_response::InferOutputTensor;
+use crate::service::inference_protocol::{ModelInferRequest, ModelInferResponse};
+
+/// Split a batched request in a request per sample, so every sample can be looked up and recorded
+/// separately. Every input must have the same first (batch) dimension of at least 2. Returns None
+/// for requests that can not be split: sequence requests, requests using shared memory, and
+/// requests of which an input is not batched or its content does not divide by the batch size.
+pub fn split_request(request: &ModelInferRequest) -> Option<Vec<ModelInferRequest>> {
+    if request.inputs.is_empty()
+        || request.parameters.contains_key(SEQUENCE_ID)
+        || uses_shared_memory(request)
+    {
+        return None;
+    }
+    let batch_size = *request.inputs[0].shape.first()?;
+    if batch_size < 2
+        || request
+            .inputs
+            .iter()
+            .any(|input| input.shape.first() != Some(&batch_size))
+    {
+        return None;
+    }
+    let batch_size = batch_size as usize;
+
+    // The contents of every input, split in the contents of every sample.
+    let contents = request
+        .inputs
+        .iter()
+        .zip(raw_input_contents(request))
+        .map(|(input, content)| split_content(&input.datatype, &content, batch_size))
+        .collect::<Option<Vec<_>>>()?;
+
+    // The request without its contents, which are not copied for every sample.
+    let template = ModelInferRequest {
+        inputs: vec![],
+        raw_input_contents: vec![],
+        ..request.clone()
+    };
+    let samples = (0..batch_size)
+        .map(|sample| ModelInferRequest {
+            inputs: request
+                .inputs
+                .iter()
+                .map(|input| InferInputTensor {
+                    shape: iter::once(1)
+                        .chain(input.shape[1..].iter().copied())
+                        .collect(),
+                    contents: None,
+                    ..input.clone()
+                })
+                .collect(),
+            raw_input_contents: contents
+                .iter()
+                .map(|content| content[sample].clone())
+                .collect(),
+            ..template.clone()
+        })
+        .collect();
+
+    Some(samples)
+}
+
+/// Combine the samples of a split request in one batched request, the opposite of
+/// `split_request`, e.g. to forward the samples that are not cached at once. The parameters and
+/// id are taken from the first sample. Returns None when the samples have different inputs.
+pub fn combine_requests(samples: &[ModelInferRequest]) -> Option<ModelInferRequest> {
+    let (first, rest) = samples.split_first()?;
+    let mut inputs = first.inputs.clone();
+    let mut contents = raw_input_contents(first);
+    for sample in rest {
+        if sample.inputs.len() != inputs.len() {
+            return None;
+        }
+        for ((input, content), (sample_input, sample_content)) in inputs
+            .iter_mut()
+            .zip(contents.iter_mut())
+            .zip(sample.inputs.iter().zip(raw_input_contents(sample)))
+        {
+            if input.name != sample_input.name
+                || input.datatype != sample_input.datatype
+                || input.shape.is_empty()
+                || sample_input.shape.is_empty()
+                || input.shape[1..] != sample_input.shape[1..]
+            {
+                return None;
+            }
+            input.shape[0] = input.shape[0].checked_add(sample_input.shape[0])?;
+            content.extend_from_slice(&sample_content);
+        }
+    }
+
+    Some(ModelInferRequest {
+        inputs: inputs
+            .into_iter()
+            .map(|input| InferInputTensor {
+                contents: None,
+                ..input
+            })
+            .collect(),
+        raw_input_contents: contents,
+        ..first.clone()
+    })
+}
+
+/// Split the raw content of a tensor in the contents of its samples. Returns None when the batch
+/// size is larger than the content, as every sample has at least one element.
+fn split_content(datatype: &str, content: &[u8], batch_size: usize) -> Option<Vec<Vec<u8>>> {
+    if batch_size == 0 || batch_size > content.len() {
+        return None;
+    }
+    match element_size(datatype) {
+        Some(size) => {
+            let batch_bytes = size.checked_mul(batch_size)?;
+            if !content.len().is_multiple_of(batch_bytes) {
+                return None;
+            }
+            Some(
+                content
+                    .chunks_exact(content.len() / batch_size)
+                    .map(<[u8]>::to_vec)
+                    .collect(),
+            )
+        }
+        None if datatype == "BYTES" => {
+            let elements = decode_bytes_elements(content)?;
+            if elements.is_empty() || !elements.len().is_multiple_of(batch_size) {
+                return None;
+            }
+            Some(
+                elements
+                    .chunks_exact(elements.len() / batch_size)
+                    .map(encode_bytes_elements)
+                    .collect(),
+            )
+        }
+        None => None,
+    }
+}
+
+/// Split the response to a batched request in the responses to its samples, the opposite of
+/// `combine_responses`. Returns None when an output is not batched with the batch size, or has no
+/// raw content that divides by it.
+pub fn split_response(
+    response: &ModelInferResponse,
+    batch_size: usize,
+) -> Option<Vec<ModelInferResponse>> {
+    if response.outputs.is_empty() || response.raw_output_contents.len() != response.outputs.len() {
+        return None;
+    }
+
+    let contents = response
+        .outputs
+        .iter()
+        .zip(&response.raw_output_contents)
+        .map(|(output, content)| match output.shape.first() {
+            Some(&size) if size == batch_size as i64 => {
+                split_content(&output.datatype, content, batch_size)
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let samples = (0..batch_size)
+        .map(|sample| ModelInferResponse {
+            outputs: response
+                .outputs
+                .iter()
+                .map(|output| InferOutputTensor {
+                    shape: iter::once(1)
+                        .chain(output.shape[1..].iter().copied())
+                        .collect(),
+                    contents: None,
+                    ..output.clone()
+                })
+                .collect(),
+            raw_output_contents: contents
+                .iter()
+                .map(|content| Bytes::from(content[sample].clone()))
+                .collect(),
+            ..response.clone()
+        })
+        .collect();
+
+    Some(samples)
+}
+
+/// Combine the responses of the samples of a split request in one response, concatenating the
+/// outputs along the first (batch) dimension. The names, parameters and id are taken from the
+/// first response. Returns None when the responses have different outputs, or an output has no
+/// raw content.
+pub fn combine_responses(responses: Vec<ModelInferResponse>) -> Option<ModelInferResponse> {
+    let (first, rest) = responses.split_first()?;
+    if first.raw_output_contents.len() != first.outputs.len() {
+        return None;
+    }
+
+    let mut outputs = first.outputs.clone();
+    let mut contents: Vec<Vec<u8>> = first
+        .raw_output_contents
+        .iter()
+        .map(|content| content.to_vec())
+        .collect();
+    for response in rest {
+        if response.outputs.len() != outputs.len()
+            || response.raw_output_contents.len() != outputs.len()
+        {
+            return None;
+        }
+        for ((output, content), (sample_output, sample_content)) in outputs
+            .iter_mut()
+            .zip(contents.iter_mut())
+            .zip(response.outputs.iter().zip(&response.raw_output_contents))
+        {
+            if !is_same_output(output, sample_output) {
+                return None;
+            }
+            output.shape[0] = output.shape[0].checked_add(sample_output.shape[0])?;
+            content.extend_from_slice(sample_content);
+        }
+    }
+
+    Some(ModelInferResponse {
+        outputs,
+        raw_output_contents: contents.into_iter().map(Bytes::from).collect(),
+        ..first.clone()
+    })
+}
+
+/// Check if the output tensors of two samples only differ in their batch dimension. Both must have
+/// a batch dimension.
+fn is_same_output(output: &InferOutputTensor, other: &InferOutputTensor) -> bool {
+    output.name == other.name
+        && output.datatype == other.datatype
+        && !output.shape.is_empty()
+        && !other.shape.is_empty()
+        && output.shape[1..] == other.shape[1..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::input::tests::raw_infer_request;
+
+    fn response(outputs: Vec<(&str, Vec<i64>, Vec<u8>)>) -> ModelInferResponse {
+        ModelInferResponse {
+            model_name: "test".to_string(),
+            model_version: "1".to_string(),
+            outputs: outputs
+                .iter()
+                .map(|(name, shape, _)| InferOutputTensor {
+                    name: name.to_string(),
+                    datatype: "UINT8".to_string(),
+                    shape: shape.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+            raw_output_contents: outputs
+                .into_iter()
+                .map(|(_, _, content)| Bytes::from(content))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_splits_batched_requests() {
+        let request = raw_infer_request(vec![
+            ("INPUT0", "UINT8", vec![2, 2], vec![1, 2, 3, 4]),
+            (
+                "INPUT1",
+                "BYTES",
+                vec![2],
+                encode_bytes_elements(&[b"a".to_vec(), b"bc".to_vec()]),
+            ),
+        ]);
+
+        let samples = split_request(&request).unwrap();
+        assert_eq!(2, samples.len());
+        assert_eq!(vec![1, 2], samples[0].inputs[0].shape);
+        assert_eq!(vec![1], samples[1].inputs[1].shape);
+        assert_eq!(vec![3, 4], samples[1].raw_input_contents[0]);
+        assert_eq!(
+            encode_bytes_elements(&[b"bc".to_vec()]),
+            samples[1].raw_input_contents[1]
+        );
+        assert_eq!(request.model_name, samples[0].model_name);
+
+        // Combining the samples gives the batched request again.
+        assert_eq!(Some(request), combine_requests(&samples));
+        let first = combine_requests(&samples[..1]).unwrap();
+        assert_eq!(vec![1, 2], first.inputs[0].shape);
+    }
+
+    #[test]
+    fn it_does_not_split_unbatched_requests() {
+        // A batch of one.
+        let request = raw_infer_request(vec![("INPUT0", "UINT8", vec![1, 2], vec![1, 2])]);
+        assert_eq!(None, split_request(&request));
+
+        // Inputs with different batch sizes.
+        let request = raw_infer_request(vec![
+            ("INPUT0", "UINT8", vec![2], vec![1, 2]),
+            ("INPUT1", "UINT8", vec![3], vec![1, 2, 3]),
+        ]);
+        assert_eq!(None, split_request(&request));
+
+        // Content that does not divide by the batch size.
+        let request = raw_infer_request(vec![("INPUT0", "INT32", vec![2], vec![1, 2, 3, 4])]);
+        assert_eq!(None, split_request(&request));
+
+        // A batch size that overflows the size of the batch, or is larger than the content.
+        let request = raw_infer_request(vec![("INPUT0", "INT64", vec![(1 << 61) + 1], vec![0; 8])]);
+        assert_eq!(None, split_request(&request));
+        let request = raw_infer_request(vec![("INPUT0", "BYTES", vec![1 << 40], vec![0; 8])]);
+        assert_eq!(None, split_request(&request));
+    }
+
+    #[test]
+    fn it_combines_the_responses_of_samples() {
+        let combined = combine_responses(vec![
+            response(vec![("OUTPUT0", vec![1, 2], vec![1, 2])]),
+            response(vec![("OUTPUT0", vec![1, 2], vec![3, 4])]),
+        ])
+        .unwrap();
+        assert_eq!(vec![2, 2], combined.outputs[0].shape);
+        assert_eq!(
+            Bytes::from(vec![1, 2, 3, 4]),
+            combined.raw_output_contents[0]
+        );
+
+        // Splitting the combined response gives the responses of the samples again.
+        let samples = split_response(&combined, 2).unwrap();
+        assert_eq!(vec![1, 2], samples[1].outputs[0].shape);
+        assert_eq!(Bytes::from(vec![3, 4]), samples[1].raw_output_contents[0]);
+        assert_eq!(None, split_response(&combined, 4));
+
+        let different = combine_responses(vec![
+            response(vec![("OUTPUT0", vec![1, 2], vec![1, 2])]),
+            response(vec![("OUTPUT1", vec![1, 2], vec![3, 4])]),
+        ]);
+        assert_eq!(None, different);
+
+        // Outputs without a batch dimension.
+        let unbatched = combine_responses(vec![
+            response(vec![("OUTPUT0", vec![1, 2], vec![1, 2])]),
+            response(vec![("OUTPUT0", vec![], vec![3, 4])]),
+        ]);
+        assert_eq!(None, unbatched);
+    }
+}
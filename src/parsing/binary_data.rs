@@ -0,0 +1,240 @@
+// Triton's binary tensor data extension for the HTTP/REST protocol. There is no HTTP frontend
+// yet, this codec converts HTTP request bodies to their grpc counterparts, so requests imported
+// from HTTP bodies are byte-identical to requests made over grpc.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::parsing::tensor::encode_bytes_elements;
+use crate::service::inference_protocol::infer_parameter::ParameterChoice;
+use crate::service::inference_protocol::model_infer_request::{
+    InferInputTensor, InferRequestedOutputTensor,
+};
+use crate::service::inference_protocol::{InferParameter, ModelInferRequest};
+
+/// The HTTP header that contains the length of the JSON part of a body with binary data.
+pub const HEADER_CONTENT_LENGTH: &str = "Inference-Header-Content-Length";
+
+const BINARY_DATA_SIZE: &str = "binary_data_size";
+
+/// Decode an HTTP inference request body. The JSON header is followed by the binary data of the
+/// inputs with a `binary_data_size` parameter, in the order of the inputs. Inputs with JSON data
+/// are converted to the raw format, so every input ends up in `raw_input_contents`.
+///
+/// # Arguments
+///
+/// * `header_length`: The value of the `Inference-Header-Content-Length` header, or None when the
+///   body is only JSON.
+pub fn decode_request(
+    model_name: &str,
+    model_version: &str,
+    body: &[u8],
+    header_length: Option<usize>,
+) -> anyhow::Result<ModelInferRequest> {
+    let header_length = header_length.unwrap_or(body.len());
+    let (header, mut binary) = match (body.get(..header_length), body.get(header_length..)) {
+        (Some(header), Some(binary)) => (header, binary),
+        _ => anyhow::bail!("{HEADER_CONTENT_LENGTH} is larger than the body"),
+    };
+    let header: Value = serde_json::from_slice(header)?;
+
+    let mut inputs = vec![];
+    let mut raw_input_contents = vec![];
+
+    for input in header["inputs"].as_array().into_iter().flatten() {
+        let name = input["name"].as_str().unwrap_or_default().to_string();
+        let datatype = input["datatype"].as_str().unwrap_or_default().to_string();
+        let mut parameters = decode_parameters(&input["parameters"]);
+
+        let content = match parameters.remove(BINARY_DATA_SIZE) {
+            Some(InferParameter {
+                parameter_choice: Some(ParameterChoice::Int64Param(size)),
+            }) => {
+                let size = size as usize;
+                if binary.len() < size {
+                    anyhow::bail!("binary data of input {name} is missing");
+                }
+                let (content, rest) = binary.split_at(size);
+                binary = rest;
+                content.to_vec()
+            }
+            _ => json_to_raw(&datatype, &input["data"])?,
+        };
+
+        inputs.push(InferInputTensor {
+            name,
+            datatype,
+            shape: serde_json::from_value(input["shape"].clone()).unwrap_or_default(),
+            parameters,
+            contents: None,
+        });
+        raw_input_contents.push(content);
+    }
+
+    let outputs = header["outputs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|output| InferRequestedOutputTensor {
+            name: output["name"].as_str().unwrap_or_default().to_string(),
+            parameters: decode_parameters(&output["parameters"]),
+        })
+        .collect();
+
+    Ok(ModelInferRequest {
+        model_name: model_name.to_string(),
+        model_version: model_version.to_string(),
+        id: header["id"].as_str().unwrap_or_default().to_string(),
+        parameters: decode_parameters(&header["parameters"]),
+        inputs,
+        outputs,
+        raw_input_contents,
+    })
+}
+
+fn decode_parameters(parameters: &Value) -> HashMap<String, InferParameter> {
+    parameters
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| {
+            let parameter_choice = match value {
+                Value::Bool(v) => ParameterChoice::BoolParam(*v),
+                Value::String(v) => ParameterChoice::StringParam(v.clone()),
+                Value::Number(v) => match (v.as_i64(), v.as_u64(), v.as_f64()) {
+                    (Some(v), _, _) => ParameterChoice::Int64Param(v),
+                    (_, Some(v), _) => ParameterChoice::Uint64Param(v),
+                    (_, _, Some(v)) => ParameterChoice::DoubleParam(v),
+                    _ => return None,
+                },
+                _ => return None,
+            };
+
+            Some((
+                key.clone(),
+                InferParameter {
+                    parameter_choice: Some(parameter_choice),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Flatten JSON tensor data, which can be nested by shape.
+pub fn flatten(data: &Value, values: &mut Vec<Value>) {
+    match data {
+        Value::Array(elements) => elements.iter().for_each(|e| flatten(e, values)),
+        value => values.push(value.clone()),
+    }
+}
+
+/// Convert JSON tensor data to the little-endian raw format of grpc.
+pub fn json_to_raw(datatype: &str, data: &Value) -> anyhow::Result<Vec<u8>> {
+    let mut values = vec![];
+    flatten(data, &mut values);
+
+    if datatype == "BYTES" {
+        let elements: Option<Vec<_>> = values
+            .iter()
+            .map(|v| v.as_str().map(|s| s.as_bytes().to_vec()))
+            .collect();
+        return match elements {
+            Some(elements) => Ok(encode_bytes_elements(&elements)),
+            None => anyhow::bail!("BYTES data must contain strings"),
+        };
+    }
+
+    let mut raw = vec![];
+    for value in values {
+        let int = value.as_i64().or_else(|| value.as_bool().map(i64::from));
+        let float = value.as_f64();
+
+        let out_of_range = |_| anyhow::anyhow!("{datatype} data value {value} is out of range");
+        match (datatype, int, float) {
+            ("BOOL" | "UINT8", Some(v), _) => raw.push(u8::try_from(v).map_err(out_of_range)?),
+            ("INT8", Some(v), _) => {
+                raw.extend(i8::try_from(v).map_err(out_of_range)?.to_le_bytes())
+            }
+            ("INT16", Some(v), _) => {
+                raw.extend(i16::try_from(v).map_err(out_of_range)?.to_le_bytes())
+            }
+            ("UINT16", Some(v), _) => {
+                raw.extend(u16::try_from(v).map_err(out_of_range)?.to_le_bytes())
+            }
+            ("INT32", Some(v), _) => {
+                raw.extend(i32::try_from(v).map_err(out_of_range)?.to_le_bytes())
+            }
+            ("UINT32", Some(v), _) => {
+                raw.extend(u32::try_from(v).map_err(out_of_range)?.to_le_bytes())
+            }
+            ("INT64", Some(v), _) => raw.extend(v.to_le_bytes()),
+            ("UINT64", _, _) if value.is_u64() => raw.extend(value.as_u64().unwrap().to_le_bytes()),
+            ("FP32", _, Some(v)) => raw.extend((v as f32).to_le_bytes()),
+            ("FP64", _, Some(v)) => raw.extend(v.to_le_bytes()),
+            _ => anyhow::bail!("unsupported {datatype} data value {value}"),
+        }
+    }
+
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::parsing::input::tests::raw_infer_request;
+
+    #[test]
+    fn it_decodes_requests_identical_to_grpc() {
+        let header = br#"{"id":"1","inputs":[
+            {"name":"INPUT0","datatype":"FP32","shape":[1,2],"parameters":{"binary_data_size":8}},
+            {"name":"INPUT1","datatype":"INT32","shape":[1,2],"data":[[1,2]]},
+            {"name":"INPUT2","datatype":"BYTES","shape":[1],"data":["a"]}
+        ]}"#;
+        let mut body = header.to_vec();
+        body.extend(1.0f32.to_le_bytes());
+        body.extend(2.0f32.to_le_bytes());
+
+        let request = decode_request("test", "1", &body, Some(header.len())).unwrap();
+
+        let mut expected = raw_infer_request(vec![
+            (
+                "INPUT0",
+                "FP32",
+                vec![1, 2],
+                [1.0f32.to_le_bytes(), 2.0f32.to_le_bytes()].concat(),
+            ),
+            (
+                "INPUT1",
+                "INT32",
+                vec![1, 2],
+                [1i32.to_le_bytes(), 2i32.to_le_bytes()].concat(),
+            ),
+            ("INPUT2", "BYTES", vec![1], vec![1, 0, 0, 0, b'a']),
+        ]);
+        expected.id = "1".to_string();
+
+        assert_eq!(expected, request);
+    }
+
+    #[test]
+    fn it_rejects_missing_binary_data() {
+        let header = br#"{"inputs":[
+            {"name":"INPUT0","datatype":"FP32","shape":[2],"parameters":{"binary_data_size":8}}
+        ]}"#;
+
+        assert!(decode_request("test", "1", header, Some(header.len())).is_err());
+        assert!(decode_request("test", "1", header, Some(header.len() + 1)).is_err());
+    }
+
+    #[test]
+    fn it_rejects_data_out_of_range() {
+        assert_eq!(vec![255], json_to_raw("UINT8", &json!([255])).unwrap());
+        assert_eq!(vec![255], json_to_raw("INT8", &json!([-1])).unwrap());
+        assert!(json_to_raw("UINT8", &json!([256])).is_err());
+        assert!(json_to_raw("INT8", &json!([128])).is_err());
+        assert!(json_to_raw("UINT32", &json!([-1])).is_err());
+    }
+}
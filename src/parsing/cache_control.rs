@@ -0,0 +1,169 @@
+use crate::service::inference_protocol::infer_parameter::ParameterChoice;
+use crate::service::inference_protocol::{InferParameter, ModelInferRequest};
+
+/// When true, the request is forwarded to the target server without using or updating the cache.
+pub const SKIP_CACHE: &str = "inferencestore.skip_cache";
+
+/// When true, the request is forwarded to the target server and recorded, without serving it
+/// from the cache.
+pub const RECORD_ONLY: &str = "inferencestore.record_only";
+
+/// Entries are only matched with requests of the same namespace.
+pub const NAMESPACE: &str = "inferencestore.namespace";
+
+/// The tensor parameter with the shared memory region a tensor is read from or written to.
+pub const SHARED_MEMORY_REGION: &str = "shared_memory_region";
+
+/// Requests with a higher priority are forwarded first when the target server is busy.
+pub const PRIORITY: &str = "inferencestore.priority";
+
+/// The caching behavior of a single request, controlled with reserved request parameters.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct CacheControl {
+    pub skip_cache: bool,
+    pub record_only: bool,
+    pub namespace: Option<String>,
+    pub priority: i64,
+}
+
+impl CacheControl {
+    /// Remove the reserved parameters from the request, so they are neither hashed nor forwarded
+    /// to the target server, and return the caching behavior they describe.
+    pub fn take(req: &mut ModelInferRequest) -> CacheControl {
+        CacheControl {
+            // The contents of shared memory tensors are not part of the request, so such requests
+            // can not be matched or replayed.
+            skip_cache: is_true(req.parameters.remove(SKIP_CACHE)) || uses_shared_memory(req),
+            record_only: is_true(req.parameters.remove(RECORD_ONLY)),
+            namespace: match req.parameters.remove(NAMESPACE) {
+                Some(InferParameter {
+                    parameter_choice: Some(ParameterChoice::StringParam(namespace)),
+                }) if !namespace.is_empty() => Some(namespace),
+                _ => None,
+            },
+            priority: match req.parameters.remove(PRIORITY) {
+                Some(InferParameter {
+                    parameter_choice: Some(ParameterChoice::Int64Param(priority)),
+                }) => priority,
+                Some(InferParameter {
+                    parameter_choice: Some(ParameterChoice::StringParam(priority)),
+                }) => priority.parse().unwrap_or_default(),
+                _ => 0,
+            },
+        }
+    }
+
+    /// Should the cache be searched for the request?
+    pub fn uses_cache(&self) -> bool {
+        !self.skip_cache && !self.record_only
+    }
+
+    /// Should the response of the target server be recorded?
+    pub fn records(&self) -> bool {
+        !self.skip_cache
+    }
+}
+
+/// Check if an input or output tensor of the request is in a shared memory region.
+pub fn uses_shared_memory(req: &ModelInferRequest) -> bool {
+    req.inputs
+        .iter()
+        .any(|input| input.parameters.contains_key(SHARED_MEMORY_REGION))
+        || req
+            .outputs
+            .iter()
+            .any(|output| output.parameters.contains_key(SHARED_MEMORY_REGION))
+}
+
+fn is_true(parameter: Option<InferParameter>) -> bool {
+    match parameter.and_then(|parameter| parameter.parameter_choice) {
+        Some(ParameterChoice::BoolParam(value)) => value,
+        Some(ParameterChoice::StringParam(value)) => value == "true",
+        Some(ParameterChoice::Int64Param(value)) => value != 0,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::service::inference_protocol::model_infer_request::InferInputTensor;
+
+    fn parameter(parameter_choice: ParameterChoice) -> InferParameter {
+        InferParameter {
+            parameter_choice: Some(parameter_choice),
+        }
+    }
+
+    #[test]
+    fn it_takes_reserved_parameters() {
+        let mut req = ModelInferRequest {
+            parameters: HashMap::from([
+                (
+                    RECORD_ONLY.to_string(),
+                    parameter(ParameterChoice::BoolParam(true)),
+                ),
+                (
+                    NAMESPACE.to_string(),
+                    parameter(ParameterChoice::StringParam("suite-a".to_string())),
+                ),
+                (
+                    "priority".to_string(),
+                    parameter(ParameterChoice::Int64Param(1)),
+                ),
+                (
+                    PRIORITY.to_string(),
+                    parameter(ParameterChoice::Int64Param(3)),
+                ),
+            ]),
+            ..Default::default()
+        };
+
+        let cache_control = CacheControl::take(&mut req);
+
+        assert_eq!(
+            CacheControl {
+                skip_cache: false,
+                record_only: true,
+                namespace: Some("suite-a".to_string()),
+                priority: 3,
+            },
+            cache_control
+        );
+        assert!(!cache_control.uses_cache());
+        assert!(cache_control.records());
+        assert_eq!(vec!["priority"], req.parameters.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn it_skips_the_cache_for_shared_memory() {
+        let mut req = ModelInferRequest {
+            inputs: vec![InferInputTensor {
+                name: "input".to_string(),
+                parameters: HashMap::from([(
+                    SHARED_MEMORY_REGION.to_string(),
+                    parameter(ParameterChoice::StringParam("input_region".to_string())),
+                )]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        assert!(uses_shared_memory(&req));
+        assert!(!CacheControl::take(&mut req).uses_cache());
+        assert!(!CacheControl::take(&mut ModelInferRequest::default()).skip_cache);
+    }
+
+    #[test]
+    fn it_defaults_to_caching() {
+        let mut req = ModelInferRequest::default();
+
+        let cache_control = CacheControl::take(&mut req);
+
+        assert_eq!(CacheControl::default(), cache_control);
+        assert!(cache_control.uses_cache());
+        assert!(cache_control.records());
+    }
+}
@@ -0,0 +1,254 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::parsing::input::{Parameter, ProcessedInput};
+use crate::parsing::output::ProcessedOutput;
+use crate::parsing::tensor::{decode_bytes_elements, decode_f64, encode_bytes_elements};
+
+/// The output parameter with which a client requests the top classes of an output instead of the
+/// output tensor, see the classification extension of Triton.
+pub const CLASSIFICATION_PARAMETER: &str = "classification";
+
+/// The number of classes requested with the classification parameter of an output.
+pub fn requested_classes(parameters: &BTreeMap<String, Option<Parameter>>) -> Option<usize> {
+    match parameters.get(CLASSIFICATION_PARAMETER)? {
+        Some(Parameter::Int64Param(classes)) => usize::try_from(*classes).ok(),
+        Some(Parameter::Uint64Param(classes)) => usize::try_from(*classes).ok(),
+        _ => None,
+    }
+}
+
+/// The output parameters without the classification parameter, which is matched separately.
+pub fn without_classification(
+    parameters: &BTreeMap<String, Option<Parameter>>,
+) -> Cow<'_, BTreeMap<String, Option<Parameter>>> {
+    match parameters.contains_key(CLASSIFICATION_PARAMETER) {
+        true => Cow::Owned(
+            parameters
+                .iter()
+                .filter(|(key, _)| *key != CLASSIFICATION_PARAMETER)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        ),
+        false => Cow::Borrowed(parameters),
+    }
+}
+
+/// Check if a cached output can be served for the requested classes. The classes can be computed
+/// from a cached output tensor, but cached classes can only be cut down to fewer classes.
+pub fn is_servable(cached: Option<usize>, requested: Option<usize>) -> bool {
+    match (cached, requested) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(cached), Some(requested)) => requested <= cached,
+    }
+}
+
+/// Convert the outputs of a cached response to the classes requested by the input. Classes are
+/// computed from cached output tensors as `value:index` elements, labels are only known when the
+/// target server returned classes. Cached classes are cut down to the requested number.
+pub fn classify(
+    output: &mut ProcessedOutput,
+    cached_input: &ProcessedInput,
+    input: &ProcessedInput,
+) -> anyhow::Result<()> {
+    if output.error.is_some() {
+        return Ok(());
+    }
+
+    for requested in &input.outputs {
+        let Some(classes) = requested_classes(&requested.parameters) else {
+            continue;
+        };
+        let Some(index) = output.outputs.iter().position(|o| o.name == requested.name) else {
+            continue;
+        };
+        let cached_classes = cached_input
+            .outputs
+            .iter()
+            .find(|cached| cached.name == requested.name)
+            .and_then(|cached| requested_classes(&cached.parameters));
+        let tensor = &mut output.outputs[index];
+        let Some(content) = output.raw_output_contents.get_mut(index) else {
+            anyhow::bail!("no content for output {}", tensor.name);
+        };
+
+        let (shape, classified) = match cached_classes {
+            Some(cached) if cached == classes => continue,
+            Some(_) => cut_classes(&tensor.shape, content, classes),
+            None => top_classes(&tensor.datatype, &tensor.shape, content, classes),
+        }
+        .ok_or_else(|| anyhow::anyhow!("can not classify output {}", tensor.name))?;
+
+        tensor.datatype = "BYTES".to_string();
+        tensor.shape = shape;
+        *content = classified.into();
+        // The classified output is served from its fields instead of the recorded response.
+        output.raw_response = None;
+    }
+
+    Ok(())
+}
+
+/// The shape of classes of a tensor: the last dimension holds the values of the classes.
+fn classes_shape(shape: &[i64], classes: usize) -> Vec<i64> {
+    let mut shape = shape.to_vec();
+    match shape.last_mut() {
+        Some(last) => *last = classes as i64,
+        None => shape.push(classes as i64),
+    }
+
+    shape
+}
+
+/// Compute the top classes of every row of a numeric tensor, sorted by descending value.
+fn top_classes(
+    datatype: &str,
+    shape: &[i64],
+    content: &[u8],
+    classes: usize,
+) -> Option<(Vec<i64>, Vec<u8>)> {
+    let values = decode_f64(datatype, content)?;
+    let row_length = shape.last().map_or(values.len(), |&last| last as usize);
+    let classes = classes.min(row_length);
+    if row_length == 0 {
+        return Some((classes_shape(shape, 0), vec![]));
+    }
+    let is_float = matches!(datatype, "FP16" | "BF16" | "FP32" | "FP64");
+
+    let mut elements = vec![];
+    for row in values.chunks(row_length) {
+        let mut indices: Vec<usize> = (0..row.len()).collect();
+        // The sort is stable, so equal values keep the order of their indices.
+        indices.sort_by(|&a, &b| row[b].total_cmp(&row[a]));
+
+        elements.extend(indices.into_iter().take(classes).map(|index| {
+            match is_float {
+                true => format!("{:.6}:{index}", row[index]),
+                false => format!("{}:{index}", row[index] as i64),
+            }
+            .into_bytes()
+        }));
+    }
+
+    Some((
+        classes_shape(shape, classes),
+        encode_bytes_elements(&elements),
+    ))
+}
+
+/// Keep the first classes of every row of recorded classes, which are sorted by descending value.
+fn cut_classes(shape: &[i64], content: &[u8], classes: usize) -> Option<(Vec<i64>, Vec<u8>)> {
+    let elements = decode_bytes_elements(content)?;
+    let row_length = shape.last().map_or(elements.len(), |&last| last as usize);
+    let classes = classes.min(row_length);
+    if row_length == 0 {
+        return Some((classes_shape(shape, 0), vec![]));
+    }
+
+    let elements: Vec<Vec<u8>> = elements
+        .chunks(row_length)
+        .flat_map(|row| row.iter().take(classes).cloned())
+        .collect();
+
+    Some((
+        classes_shape(shape, classes),
+        encode_bytes_elements(&elements),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::input::tests::BASE_INFER_INPUT;
+    use crate::parsing::input::Output as RequestedOutput;
+    use crate::parsing::output::Output;
+    use crate::parsing::tensor::{decode_strings, encode_f32, encode_strings};
+
+    fn input(classes: Option<i64>) -> ProcessedInput {
+        let mut input = BASE_INFER_INPUT.clone();
+        input.outputs = vec![RequestedOutput {
+            name: "PROBS".to_string(),
+            parameters: classes
+                .map(|classes| {
+                    BTreeMap::from([(
+                        CLASSIFICATION_PARAMETER.to_string(),
+                        Some(Parameter::Int64Param(classes)),
+                    )])
+                })
+                .unwrap_or_default(),
+        }];
+        input
+    }
+
+    fn output(datatype: &str, shape: Vec<i64>, content: Vec<u8>) -> ProcessedOutput {
+        ProcessedOutput {
+            parameters: Default::default(),
+            outputs: vec![Output {
+                parameters: Default::default(),
+                name: "PROBS".to_string(),
+                datatype: datatype.to_string(),
+                shape,
+            }],
+            raw_output_contents: vec![content.into()],
+            error: None,
+            metadata: Default::default(),
+            raw_response: None,
+        }
+    }
+
+    #[test]
+    fn it_matches_classes() {
+        assert!(is_servable(None, None));
+        assert!(is_servable(None, Some(3)));
+        assert!(is_servable(Some(3), Some(2)));
+        assert!(!is_servable(Some(2), Some(3)));
+        assert!(!is_servable(Some(2), None));
+
+        let parameters = &input(Some(2)).outputs[0].parameters;
+        assert_eq!(Some(2), requested_classes(parameters));
+        assert!(without_classification(parameters).is_empty());
+    }
+
+    #[test]
+    fn it_computes_classes_from_tensors() {
+        let mut output = output(
+            "FP32",
+            vec![2, 3],
+            encode_f32("FP32", &[0.1, 0.7, 0.2, 0.5, 0.25, 0.25]).unwrap(),
+        );
+        classify(&mut output, &input(None), &input(Some(2))).unwrap();
+
+        assert_eq!("BYTES", output.outputs[0].datatype);
+        assert_eq!(vec![2, 2], output.outputs[0].shape);
+        assert_eq!(
+            Some(vec![
+                "0.700000:1".to_string(),
+                "0.200000:2".to_string(),
+                "0.500000:0".to_string(),
+                "0.250000:1".to_string()
+            ]),
+            decode_strings(&output.raw_output_contents[0])
+        );
+    }
+
+    #[test]
+    fn it_cuts_recorded_classes() {
+        let recorded = encode_strings(&["0.7:1:cat", "0.2:2:dog", "0.1:0:fish"]);
+        let mut output = output("BYTES", vec![3], recorded.clone());
+        classify(&mut output, &input(Some(3)), &input(Some(1))).unwrap();
+
+        assert_eq!(vec![1], output.outputs[0].shape);
+        assert_eq!(
+            Some(vec!["0.7:1:cat".to_string()]),
+            decode_strings(&output.raw_output_contents[0])
+        );
+
+        let mut unchanged = self::output("BYTES", vec![3], recorded.clone());
+        classify(&mut unchanged, &input(Some(3)), &input(Some(3))).unwrap();
+        assert_eq!(recorded, unchanged.raw_output_contents[0].to_vec());
+
+        let mut unclassified = self::output("BYTES", vec![3], recorded);
+        assert!(classify(&mut unclassified, &input(None), &input(Some(1))).is_err());
+    }
+}
@@ -1,18 +1,26 @@
 use blake2::{Blake2b, Blake2s256, Digest};
 use digest::consts::U8;
+use log::warn;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use xxhash_rust::xxh3::Xxh3;
 
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
 use serde_with::base64::Base64;
 
+use crate::parsing::classification::{is_servable, requested_classes, without_classification};
+use crate::parsing::script::MatchScript;
+use crate::parsing::sequence::{without_sequence_parameters, SequenceStep};
+use crate::parsing::tensor::{decode_bytes_elements, decode_f64, encode_bytes_elements};
 use crate::service::inference_protocol::infer_parameter::ParameterChoice;
 use crate::service::inference_protocol::model_infer_request::{
     InferInputTensor, InferRequestedOutputTensor,
 };
 use crate::service::inference_protocol::{InferParameter, ModelInferRequest};
-use crate::utils::btreemap_compare;
+use crate::utils::{btreemap_compare, shape_compare};
 
 type Blake2b64 = Blake2b<U8>;
 
@@ -29,6 +37,106 @@ pub struct ProcessedInput {
     pub outputs: Vec<Output>,
     #[serde_as(as = "Base64")]
     pub content_hash: [u8; 32],
+    // The algorithm the content hash was computed with, entries without it used blake2.
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_default")]
+    pub hash_algorithm: HashAlgorithm,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<SequenceStep>,
+    // The namespace of the request, set with the `inferencestore.namespace` parameter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    // The raw contents of the inputs, only stored when `request_collection.store_inputs` is set.
+    #[serde_as(as = "Option<Vec<Base64>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_input_contents: Option<Vec<Vec<u8>>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum CandidateRanking {
+    // Prefer cached inputs with the same model version as the incoming request.
+    #[serde(alias = "exact_version")]
+    ExactVersion,
+
+    // Prefer the most recently recorded cached inputs.
+    #[serde(alias = "most_recent")]
+    MostRecent,
+
+    // Prefer cached inputs with the highest priority stored in the cache file.
+    #[serde(alias = "priority")]
+    Priority,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum HashAlgorithm {
+    // Blake2s, compatible with caches recorded before the algorithm was configurable.
+    #[default]
+    #[serde(alias = "blake2")]
+    Blake2,
+
+    // Blake3, a cryptographic hash that is considerably faster for large tensors.
+    #[serde(alias = "blake3")]
+    Blake3,
+
+    // The 128-bit XXH3 hash, the fastest, but not cryptographic.
+    #[serde(alias = "xxh3")]
+    Xxh3,
+}
+
+impl HashAlgorithm {
+    fn is_default(&self) -> bool {
+        *self == HashAlgorithm::Blake2
+    }
+
+    fn hasher(self) -> ContentHasher {
+        match self {
+            HashAlgorithm::Blake2 => ContentHasher::Blake2(Blake2s256::new()),
+            HashAlgorithm::Blake3 => ContentHasher::Blake3(Box::default()),
+            HashAlgorithm::Xxh3 => ContentHasher::Xxh3(Box::default()),
+        }
+    }
+}
+
+/// Hashes the contents of input tensors with the configured algorithm.
+enum ContentHasher {
+    Blake2(Blake2s256),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+}
+
+impl ContentHasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            ContentHasher::Blake2(hasher) => Digest::update(hasher, data),
+            ContentHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            ContentHasher::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The hash, where the 128-bit XXH3 hash is padded with zeros.
+    fn finalize(self) -> [u8; 32] {
+        match self {
+            ContentHasher::Blake2(hasher) => hasher.finalize().into(),
+            ContentHasher::Blake3(hasher) => hasher.finalize().into(),
+            ContentHasher::Xxh3(hasher) => {
+                let mut hash = [0; 32];
+                hash[..16].copy_from_slice(&hasher.digest128().to_le_bytes());
+                hash
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct NearestNeighborConfig {
+    // The name of the float (FP16, BF16, FP32 or FP64) input tensor that contains the embedding.
+    pub input: String,
+
+    // The maximum euclidean distance between two embeddings to be considered an approximate match.
+    pub max_distance: f64,
 }
 
 #[derive(Clone)]
@@ -41,6 +149,19 @@ pub struct MatchConfig {
     pub output_parameter_keys: HashMap<String, Vec<String>>,
     pub exclude_output_parameters: bool,
     pub match_pruned_output: bool,
+    pub ignored_input_tensors: HashMap<String, Vec<String>>,
+    pub input_shape_patterns: HashMap<String, Vec<i64>>,
+    pub ignore_batch_dimension: bool,
+    pub ignore_model_version: bool,
+    pub ignore_model_version_models: Vec<String>,
+    pub float_significant_digits: Option<u32>,
+    pub json_input_tensors: HashMap<String, Vec<String>>,
+    pub match_scripts: HashMap<String, Arc<MatchScript>>,
+    pub nearest_neighbor: HashMap<String, NearestNeighborConfig>,
+    pub candidate_ranking: Vec<CandidateRanking>,
+    pub sequence_matching: bool,
+    pub hash_algorithm: HashAlgorithm,
+    pub target_name: Option<String>,
 }
 
 impl Default for MatchConfig {
@@ -54,24 +175,84 @@ impl Default for MatchConfig {
             output_parameter_keys: Default::default(),
             exclude_output_parameters: true,
             match_pruned_output: true,
+            ignored_input_tensors: Default::default(),
+            input_shape_patterns: Default::default(),
+            ignore_batch_dimension: false,
+            ignore_model_version: false,
+            ignore_model_version_models: vec![],
+            float_significant_digits: None,
+            json_input_tensors: Default::default(),
+            match_scripts: Default::default(),
+            nearest_neighbor: Default::default(),
+            candidate_ranking: vec![],
+            sequence_matching: false,
+            hash_algorithm: HashAlgorithm::Blake2,
+            target_name: None,
         }
     }
 }
 
+impl MatchConfig {
+    /// Check if the input tensor of the provided model should be left out of matching.
+    pub fn is_ignored_input(&self, model_name: &str, input_name: &str) -> bool {
+        self.ignored_input_tensors
+            .get(model_name)
+            .is_some_and(|tensors| tensors.iter().any(|t| t == input_name))
+    }
+
+    /// Check if the elements of the input tensor of the provided model contain JSON documents.
+    pub fn is_json_input(&self, model_name: &str, input_name: &str) -> bool {
+        self.json_input_tensors
+            .get(model_name)
+            .is_some_and(|tensors| tensors.iter().any(|t| t == input_name))
+    }
+
+    /// Check if the model version should be left out of matching for the provided model.
+    pub fn is_model_version_ignored(&self, model_name: &str) -> bool {
+        self.ignore_model_version
+            || self
+                .ignore_model_version_models
+                .iter()
+                .any(|m| m == model_name)
+    }
+}
+
 impl ProcessedInput {
     /// Parse a ModelInfer request in a format that makes matching it with future requests easier.
-    pub fn from_infer_request(req: ModelInferRequest) -> ProcessedInput {
-        let mut hasher = Blake2s256::new();
+    /// The contents of the input tensors that are ignored by the config are not hashed.
+    pub fn from_infer_request(req: ModelInferRequest, config: &MatchConfig) -> ProcessedInput {
+        let mut hasher = config.hash_algorithm.hasher();
+        let mut embedding = None;
+        let embedding_input = config
+            .nearest_neighbor
+            .get(&req.model_name)
+            .map(|nearest_neighbor| &nearest_neighbor.input);
+
+        for (index, input) in req.inputs.iter().enumerate() {
+            // Typed contents are converted to the raw format, so both encodings hash equally.
+            let content = match req.raw_input_contents.get(index) {
+                Some(content) => content.clone(),
+                None => typed_content_as_raw(input),
+            };
+
+            if embedding_input == Some(&input.name) {
+                embedding = decode_floats(&input.datatype, &content)
+                    .map(|values| values.into_iter().map(|v| v as f32).collect());
+            }
 
-        // TODO parse inputs if there are not raw_input_contents.
-        for content in req.raw_input_contents {
-            Digest::update(&mut hasher, content);
-        }
+            if config.is_ignored_input(&req.model_name, &input.name) {
+                continue;
+            }
 
-        let hash = hasher.finalize();
-        let hash: &[u8; 32] = hash.as_slice().try_into().unwrap();
+            hasher.update(&hashable_content(
+                &input.datatype,
+                content,
+                config.is_json_input(&req.model_name, &input.name),
+                config,
+            ));
+        }
 
-        return ProcessedInput {
+        ProcessedInput {
             model_name: req.model_name,
             model_version: req.model_version,
             id: req.id,
@@ -121,8 +302,47 @@ impl ProcessedInput {
                         .collect(),
                 })
                 .collect(),
-            content_hash: *hash,
-        };
+            content_hash: hasher.finalize(),
+            hash_algorithm: config.hash_algorithm,
+            embedding,
+            sequence: None,
+            namespace: None,
+            raw_input_contents: None,
+        }
+    }
+
+    /// Get the euclidean distance between the embeddings of this input and the provided input.
+    /// Returns None when nearest neighbor matching is not configured for the model, or when one of
+    /// the inputs has no embedding.
+    pub fn embedding_distance(
+        &self,
+        other_input: &ProcessedInput,
+        config: &MatchConfig,
+    ) -> Option<f64> {
+        // Only the contents are compared by their embedding, the rest of the request has to match.
+        if self.model_name != other_input.model_name
+            || self.namespace != other_input.namespace
+            || !self.matches_apart_from_contents(other_input, config)
+        {
+            return None;
+        }
+
+        let nearest_neighbor = config.nearest_neighbor.get(&self.model_name)?;
+        let (embedding, other_embedding) =
+            (self.embedding.as_ref()?, other_input.embedding.as_ref()?);
+
+        if embedding.len() != other_embedding.len() {
+            return None;
+        }
+
+        let distance = embedding
+            .iter()
+            .zip(other_embedding)
+            .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+            .sum::<f64>()
+            .sqrt();
+
+        (distance <= nearest_neighbor.max_distance).then_some(distance)
     }
 
     /// Check if the provided input is compatible with this input.
@@ -131,103 +351,145 @@ impl ProcessedInput {
     ///
     /// * `other_input` - The input to compare this input to.
     /// * `match_id` - Should the `id` be compared?
-    pub fn matches(&self, other_input: &ProcessedInput, config: MatchConfig) -> bool {
-        if self.model_name != other_input.model_name
-            || self.model_version != other_input.model_version
+    pub fn matches(&self, other_input: &ProcessedInput, config: &MatchConfig) -> bool {
+        if self.model_name != other_input.model_name || self.namespace != other_input.namespace {
+            return false;
+        }
+
+        // A match script replaces all other matching rules of a model.
+        if let Some(script) = config.match_scripts.get(&self.model_name) {
+            return script.matches(other_input, self).unwrap_or_else(|err| {
+                warn!("{err}");
+                false
+            });
+        }
+
+        // Contents hashed with different algorithms can not be compared.
+        if self.hash_algorithm != other_input.hash_algorithm
             || self.content_hash != other_input.content_hash
         {
             return false;
         }
 
+        self.matches_apart_from_contents(other_input, config)
+    }
+
+    /// Check if the provided input matches this input by everything but the contents of the
+    /// tensors: the model version, id, parameters, and the inputs and requested outputs.
+    fn matches_apart_from_contents(
+        &self,
+        other_input: &ProcessedInput,
+        config: &MatchConfig,
+    ) -> bool {
+        if !config.is_model_version_ignored(&self.model_name)
+            && self.model_version != other_input.model_version
+        {
+            return false;
+        }
+
         if config.match_id && self.id != other_input.id {
             return false;
         }
 
+        // The sequence parameters differ between runs, the position in the sequence is matched.
+        let (parameters, other_parameters) = if config.sequence_matching {
+            match (&self.sequence, &other_input.sequence) {
+                (Some(step), Some(other_step)) if !step.matches(other_step) => return false,
+                (Some(_), None) | (None, Some(_)) => return false,
+                _ => {}
+            }
+
+            (
+                Cow::Owned(without_sequence_parameters(&self.parameters)),
+                Cow::Owned(without_sequence_parameters(&other_input.parameters)),
+            )
+        } else {
+            (
+                Cow::Borrowed(&self.parameters),
+                Cow::Borrowed(&other_input.parameters),
+            )
+        };
+
         if !btreemap_compare(
-            self.parameters.clone(),
-            other_input.parameters.clone(),
-            config.parameter_keys,
+            &parameters,
+            &other_parameters,
+            &config.parameter_keys,
             config.exclude_parameters,
         ) {
             return false;
         }
 
-        let self_inputs: HashMap<_, _> = self
-            .inputs
-            .iter()
-            .map(|input| (input.name.clone(), input.clone()))
-            .collect();
+        for self_value in &self.inputs {
+            if config.is_ignored_input(&self.model_name, &self_value.name) {
+                continue;
+            }
 
-        let other_inputs: HashMap<_, _> = other_input
-            .inputs
-            .iter()
-            .map(|input| (input.name.clone(), input.clone()))
-            .collect();
-
-        for (key, self_value) in self_inputs {
-            if let Some(other_value) = other_inputs.get(&key) {
-                if self_value.name != other_value.name
-                    || self_value.datatype != other_value.datatype
-                    || self_value.shape != other_value.shape
-                {
-                    return false;
-                }
+            let Some(other_value) = other_input
+                .inputs
+                .iter()
+                .find(|input| input.name == self_value.name)
+            else {
+                return false;
+            };
 
-                if !btreemap_compare(
-                    self_value.parameters,
-                    other_value.parameters.clone(),
+            if self_value.datatype != other_value.datatype
+                || !shape_compare(
+                    &self_value.shape,
+                    &other_value.shape,
                     config
-                        .input_parameter_keys
-                        .clone()
-                        .entry(key)
-                        .or_insert(Vec::new())
-                        .clone(),
-                    config.exclude_input_parameters,
-                ) {
-                    return false;
-                }
-            } else {
+                        .input_shape_patterns
+                        .get(&self_value.name)
+                        .map(Vec::as_slice),
+                    config.ignore_batch_dimension,
+                )
+            {
                 return false;
             }
-        }
 
-        let self_outputs: HashMap<_, _> = self
-            .outputs
-            .iter()
-            .map(|output| (output.name.clone(), output.clone()))
-            .collect();
+            if !btreemap_compare(
+                &self_value.parameters,
+                &other_value.parameters,
+                config
+                    .input_parameter_keys
+                    .get(&self_value.name)
+                    .map_or(&[], Vec::as_slice),
+                config.exclude_input_parameters,
+            ) {
+                return false;
+            }
+        }
 
-        let other_outputs: HashMap<_, _> = other_input
-            .outputs
-            .iter()
-            .map(|output| (output.name.clone(), output.clone()))
-            .collect();
+        for self_value in &self.outputs {
+            let Some(other_value) = other_input
+                .outputs
+                .iter()
+                .find(|output| output.name == self_value.name)
+            else {
+                return false;
+            };
 
-        for (key, self_value) in self_outputs {
-            if let Some(other_value) = other_outputs.get(&key) {
-                if self_value.name != other_value.name {
-                    return false;
-                }
+            // Outputs with other requested classes match when the classes can be converted.
+            if !is_servable(
+                requested_classes(&self_value.parameters),
+                requested_classes(&other_value.parameters),
+            ) {
+                return false;
+            }
 
-                if !btreemap_compare(
-                    self_value.parameters,
-                    other_value.parameters.clone(),
-                    config
-                        .output_parameter_keys
-                        .clone()
-                        .entry(key)
-                        .or_insert(Vec::new())
-                        .clone(),
-                    config.exclude_output_parameters,
-                ) {
-                    return false;
-                }
-            } else {
+            if !btreemap_compare(
+                &without_classification(&self_value.parameters),
+                &without_classification(&other_value.parameters),
+                config
+                    .output_parameter_keys
+                    .get(&self_value.name)
+                    .map_or(&[], Vec::as_slice),
+                config.exclude_output_parameters,
+            ) {
                 return false;
             }
         }
 
-        return true;
+        true
     }
 
     // Produces a hash based on the model that's used, and the inputs.
@@ -235,23 +497,125 @@ impl ProcessedInput {
     pub fn inputs_hash(&self) -> [u8; 8] {
         let mut hasher = Blake2b64::new();
 
-        Digest::update(&mut hasher, &self.model_name.as_bytes());
-        Digest::update(&mut hasher, &self.model_version.as_bytes());
-        Digest::update(&mut hasher, &self.content_hash);
+        Digest::update(&mut hasher, self.model_name.as_bytes());
+        Digest::update(&mut hasher, self.model_version.as_bytes());
+        Digest::update(&mut hasher, self.content_hash);
+
+        // Entries without a namespace keep the file names of earlier versions.
+        if let Some(namespace) = &self.namespace {
+            Digest::update(&mut hasher, namespace.as_bytes());
+        }
 
         for input in &self.inputs {
-            Digest::update(&mut hasher, &input.datatype.as_bytes());
-            Digest::update(&mut hasher, &input.name.as_bytes());
+            Digest::update(&mut hasher, input.datatype.as_bytes());
+            Digest::update(&mut hasher, input.name.as_bytes());
 
             for shape in &input.shape {
-                Digest::update(&mut hasher, &shape.to_le_bytes());
+                Digest::update(&mut hasher, shape.to_le_bytes());
             }
         }
 
         let hash = hasher.finalize();
         let hash: &[u8; 8] = hash.as_slice().try_into().unwrap();
 
-        return *hash;
+        *hash
+    }
+
+    /// Reconstruct the recorded request, so it can be sent again. The input contents are only
+    /// included when they were stored, see `request_collection.store_inputs`.
+    pub fn to_request(&self) -> ModelInferRequest {
+        let to_parameters = |parameters: &BTreeMap<String, Option<Parameter>>| {
+            parameters
+                .iter()
+                .map(|(key, parameter)| {
+                    let parameter = match parameter {
+                        Some(parameter) => parameter.clone().to_infer_parameter(),
+                        None => InferParameter::default(),
+                    };
+                    (key.clone(), parameter)
+                })
+                .collect()
+        };
+
+        ModelInferRequest {
+            model_name: self.model_name.clone(),
+            model_version: self.model_version.clone(),
+            id: self.id.clone(),
+            parameters: to_parameters(&self.parameters),
+            inputs: self
+                .inputs
+                .iter()
+                .map(|input| InferInputTensor {
+                    name: input.name.clone(),
+                    datatype: input.datatype.clone(),
+                    shape: input.shape.clone(),
+                    parameters: to_parameters(&input.parameters),
+                    contents: None,
+                })
+                .collect(),
+            outputs: self
+                .outputs
+                .iter()
+                .map(|output| InferRequestedOutputTensor {
+                    name: output.name.clone(),
+                    parameters: to_parameters(&output.parameters),
+                })
+                .collect(),
+            raw_input_contents: self.raw_input_contents.clone().unwrap_or_default(),
+        }
+    }
+
+    /// The hashes of the request in hex, which are also the first part of the entry file name.
+    pub fn request_hash(&self) -> String {
+        format!(
+            "{}#{}#{}",
+            hex::encode(self.inputs_hash()),
+            hex::encode(self.outputs_hash()),
+            hex::encode(self.metadata_hash()),
+        )
+    }
+
+    /// An estimate of the memory this input uses on the heap, in bytes. The parameters are
+    /// counted by their names only.
+    pub fn memory_size(&self) -> usize {
+        let parameters = |parameters: &BTreeMap<String, Option<Parameter>>| {
+            parameters
+                .keys()
+                .map(|key| key.len() + size_of::<Parameter>())
+                .sum::<usize>()
+        };
+
+        self.model_name.len()
+            + self.model_version.len()
+            + self.id.len()
+            + parameters(&self.parameters)
+            + self
+                .inputs
+                .iter()
+                .map(|input| {
+                    size_of::<Input>()
+                        + input.name.len()
+                        + input.datatype.len()
+                        + input.shape.len() * size_of::<i64>()
+                        + parameters(&input.parameters)
+                })
+                .sum::<usize>()
+            + self
+                .outputs
+                .iter()
+                .map(|output| {
+                    size_of::<Output>() + output.name.len() + parameters(&output.parameters)
+                })
+                .sum::<usize>()
+            + self
+                .embedding
+                .as_ref()
+                .map_or(0, |embedding| embedding.len() * size_of::<f32>())
+            + self.namespace.as_ref().map_or(0, String::len)
+            + self
+                .raw_input_contents
+                .as_ref()
+                .map_or(0, |contents| contents.iter().map(Vec::len).sum())
     }
 
     pub fn outputs_hash(&self) -> [u8; 8] {
@@ -264,16 +628,16 @@ impl ProcessedInput {
         let hash = hasher.finalize();
         let hash: &[u8; 8] = hash.as_slice().try_into().unwrap();
 
-        return *hash;
+        *hash
     }
 
     pub fn metadata_hash(&self) -> [u8; 8] {
         let mut hasher = Blake2b64::new();
 
-        Digest::update(&mut hasher, &self.id.as_bytes());
+        Digest::update(&mut hasher, self.id.as_bytes());
 
         for (key, value) in &self.parameters {
-            Digest::update(&mut hasher, &key.as_bytes());
+            Digest::update(&mut hasher, key.as_bytes());
             if value.is_some() {
                 Digest::update(&mut hasher, value.as_ref().unwrap().as_bytes());
             }
@@ -281,7 +645,7 @@ impl ProcessedInput {
 
         for input in &self.inputs {
             for (key, value) in &input.parameters {
-                Digest::update(&mut hasher, &key.as_bytes());
+                Digest::update(&mut hasher, key.as_bytes());
                 if value.is_some() {
                     Digest::update(&mut hasher, value.as_ref().unwrap().as_bytes());
                 }
@@ -290,7 +654,7 @@ impl ProcessedInput {
 
         for output in &self.outputs {
             for (key, value) in &output.parameters {
-                Digest::update(&mut hasher, &key.as_bytes());
+                Digest::update(&mut hasher, key.as_bytes());
                 if value.is_some() {
                     Digest::update(&mut hasher, value.as_ref().unwrap().as_bytes());
                 }
@@ -300,10 +664,148 @@ impl ProcessedInput {
         let hash = hasher.finalize();
         let hash: &[u8; 8] = hash.as_slice().try_into().unwrap();
 
-        return *hash;
+        *hash
+    }
+}
+
+/// The contents of all inputs of a request in the raw format, typed contents are converted.
+pub fn raw_input_contents(req: &ModelInferRequest) -> Vec<Vec<u8>> {
+    req.inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| match req.raw_input_contents.get(index) {
+            Some(content) => content.clone(),
+            None => typed_content_as_raw(input),
+        })
+        .collect()
+}
+
+/// Convert the typed contents of an input tensor to the raw (little-endian) format.
+fn typed_content_as_raw(input: &InferInputTensor) -> Vec<u8> {
+    let contents = match &input.contents {
+        Some(contents) => contents,
+        None => return vec![],
+    };
+
+    match input.datatype.as_str() {
+        "BOOL" => contents.bool_contents.iter().map(|v| *v as u8).collect(),
+        "INT8" => contents.int_contents.iter().map(|v| *v as u8).collect(),
+        "INT16" => contents
+            .int_contents
+            .iter()
+            .flat_map(|v| (*v as i16).to_le_bytes())
+            .collect(),
+        "INT32" => contents
+            .int_contents
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect(),
+        "INT64" => contents
+            .int64_contents
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect(),
+        "UINT8" => contents.uint_contents.iter().map(|v| *v as u8).collect(),
+        "UINT16" => contents
+            .uint_contents
+            .iter()
+            .flat_map(|v| (*v as u16).to_le_bytes())
+            .collect(),
+        "UINT32" => contents
+            .uint_contents
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect(),
+        "UINT64" => contents
+            .uint64_contents
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect(),
+        "FP32" => contents
+            .fp32_contents
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect(),
+        "FP64" => contents
+            .fp64_contents
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect(),
+        "BYTES" => encode_bytes_elements(&contents.bytes_contents),
+        _ => vec![],
+    }
+}
+
+/// Re-serialize a JSON document without whitespace and with sorted keys. Returns the element
+/// unchanged when it is not valid JSON.
+fn canonicalize_json(element: Vec<u8>) -> Vec<u8> {
+    match serde_json::from_slice::<serde_json::Value>(&element) {
+        Ok(value) => serde_json::to_vec(&value).unwrap_or(element),
+        Err(_) => element,
     }
 }
 
+/// Convert the raw content of a tensor to the form that is hashed. BYTES tensors are decoded into
+/// their elements and encoded again, and when configured, float tensors are rounded to a number of
+/// significant digits, so slightly jittery inputs get the same hash.
+///
+/// # Arguments
+///
+/// * `datatype` - The datatype of the tensor.
+/// * `content` - The raw content of the tensor.
+/// * `json` - When true, the elements of a BYTES tensor are canonicalized as JSON documents.
+/// * `config` - The match config.
+fn hashable_content(datatype: &str, content: Vec<u8>, json: bool, config: &MatchConfig) -> Vec<u8> {
+    if datatype == "BYTES" {
+        return match decode_bytes_elements(&content) {
+            Some(elements) if json => encode_bytes_elements(
+                &elements
+                    .into_iter()
+                    .map(canonicalize_json)
+                    .collect::<Vec<_>>(),
+            ),
+            Some(elements) => encode_bytes_elements(&elements),
+            None => content,
+        };
+    }
+
+    let digits = match config.float_significant_digits {
+        Some(digits) => digits,
+        None => return content,
+    };
+
+    let values = match decode_floats(datatype, &content) {
+        Some(values) => values,
+        None => return content,
+    };
+
+    values
+        .into_iter()
+        .flat_map(|value| round_significant(value, digits).to_le_bytes())
+        .collect()
+}
+
+/// Decode the raw content of a float tensor, half precision included. Returns None for other
+/// datatypes.
+fn decode_floats(datatype: &str, content: &[u8]) -> Option<Vec<f64>> {
+    match datatype {
+        "FP16" | "BF16" | "FP32" | "FP64" => decode_f64(datatype, content),
+        _ => None,
+    }
+}
+
+/// Round a value to the provided number of significant digits.
+fn round_significant(value: f64, digits: u32) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+
+    let magnitude = value.abs().log10().floor() as i32;
+    let factor = 10f64.powi(digits.max(1) as i32 - 1 - magnitude);
+
+    (value * factor).round() / factor
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Input {
     pub name: String,
@@ -383,6 +885,10 @@ pub mod tests {
     use once_cell::sync::Lazy;
 
     use super::*;
+    use crate::parsing::classification::CLASSIFICATION_PARAMETER;
+    use crate::parsing::sequence::SEQUENCE_ID;
+    use crate::parsing::tensor::encode_f32;
+    use crate::service::inference_protocol::InferTensorContents;
 
     pub static BASE_INFER_INPUT: Lazy<ProcessedInput> = Lazy::new(|| ProcessedInput {
         model_name: "test".to_string(),
@@ -431,43 +937,94 @@ pub mod tests {
             .collect::<Vec<u8>>()
             .try_into()
             .unwrap(),
+        hash_algorithm: HashAlgorithm::Blake2,
+        embedding: None,
+        sequence: None,
+        namespace: None,
+        raw_input_contents: None,
     });
 
+    /// Build a request for the `test` model with the provided raw inputs (name, datatype, shape,
+    /// raw content).
+    pub fn raw_infer_request(inputs: Vec<(&str, &str, Vec<i64>, Vec<u8>)>) -> ModelInferRequest {
+        ModelInferRequest {
+            model_name: "test".to_string(),
+            model_version: "1".to_string(),
+            id: "".to_string(),
+            parameters: Default::default(),
+            inputs: inputs
+                .iter()
+                .map(|(name, datatype, shape, _)| InferInputTensor {
+                    name: name.to_string(),
+                    datatype: datatype.to_string(),
+                    shape: shape.clone(),
+                    parameters: Default::default(),
+                    contents: None,
+                })
+                .collect(),
+            outputs: vec![],
+            raw_input_contents: inputs
+                .into_iter()
+                .map(|(_, _, _, content)| content)
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn it_reconstructs_the_request() {
+        let request = raw_infer_request(vec![("input1", "INT64", vec![1], vec![1; 8])]);
+        let mut input = ProcessedInput::from_infer_request(request.clone(), &Default::default());
+
+        assert!(input.to_request().raw_input_contents.is_empty());
+
+        input.raw_input_contents = Some(raw_input_contents(&request));
+        let reconstructed = input.to_request();
+
+        assert_eq!(request, reconstructed);
+        assert_eq!(
+            input.request_hash(),
+            ProcessedInput::from_infer_request(reconstructed, &Default::default()).request_hash()
+        );
+    }
+
     #[test]
     fn it_parsed_a_model_infer_request() {
-        let input = ProcessedInput::from_infer_request(ModelInferRequest {
-            model_name: "test".to_string(),
-            model_version: "v1".to_string(),
-            id: "999".to_string(),
-            parameters: HashMap::from([(
-                "param1".to_string(),
-                InferParameter {
-                    parameter_choice: Some(ParameterChoice::StringParam("hoi".to_string())),
-                },
-            )]),
-            inputs: vec![InferInputTensor {
-                name: "img".to_string(),
-                datatype: "FP32".to_string(),
-                shape: vec![1, 2, 3],
-                parameters: HashMap::from([(
-                    "input_param1".to_string(),
-                    InferParameter {
-                        parameter_choice: Some(ParameterChoice::StringParam("hoi".to_string())),
-                    },
-                )]),
-                contents: None,
-            }],
-            outputs: vec![InferRequestedOutputTensor {
-                name: "output1".to_string(),
+        let input = ProcessedInput::from_infer_request(
+            ModelInferRequest {
+                model_name: "test".to_string(),
+                model_version: "v1".to_string(),
+                id: "999".to_string(),
                 parameters: HashMap::from([(
-                    "output_param1".to_string(),
+                    "param1".to_string(),
                     InferParameter {
                         parameter_choice: Some(ParameterChoice::StringParam("hoi".to_string())),
                     },
                 )]),
-            }],
-            raw_input_contents: vec![vec![255, 128, 1]],
-        });
+                inputs: vec![InferInputTensor {
+                    name: "img".to_string(),
+                    datatype: "FP32".to_string(),
+                    shape: vec![1, 2, 3],
+                    parameters: HashMap::from([(
+                        "input_param1".to_string(),
+                        InferParameter {
+                            parameter_choice: Some(ParameterChoice::StringParam("hoi".to_string())),
+                        },
+                    )]),
+                    contents: None,
+                }],
+                outputs: vec![InferRequestedOutputTensor {
+                    name: "output1".to_string(),
+                    parameters: HashMap::from([(
+                        "output_param1".to_string(),
+                        InferParameter {
+                            parameter_choice: Some(ParameterChoice::StringParam("hoi".to_string())),
+                        },
+                    )]),
+                }],
+                raw_input_contents: vec![vec![255, 128, 1]],
+            },
+            &Default::default(),
+        );
 
         assert_eq!(input.model_name, "test");
         assert_eq!(input.model_version, "v1");
@@ -476,12 +1033,254 @@ pub mod tests {
         // TODO add more asserts
     }
 
+    #[test]
+    fn it_ignores_configured_input_tensors() {
+        let config = MatchConfig {
+            ignored_input_tensors: HashMap::from([(
+                "test".to_string(),
+                vec!["timestamp".to_string()],
+            )]),
+            ..Default::default()
+        };
+
+        let input1 = ProcessedInput::from_infer_request(
+            raw_infer_request(vec![
+                ("img", "UINT8", vec![3], vec![1, 2, 3]),
+                ("timestamp", "INT64", vec![1], 1u64.to_le_bytes().to_vec()),
+            ]),
+            &config,
+        );
+        let input2 = ProcessedInput::from_infer_request(
+            raw_infer_request(vec![
+                ("img", "UINT8", vec![3], vec![1, 2, 3]),
+                ("timestamp", "INT64", vec![1], 2u64.to_le_bytes().to_vec()),
+            ]),
+            &config,
+        );
+        let input3 = ProcessedInput::from_infer_request(
+            raw_infer_request(vec![
+                ("img", "UINT8", vec![3], vec![3, 2, 1]),
+                ("timestamp", "INT64", vec![1], 1u64.to_le_bytes().to_vec()),
+            ]),
+            &config,
+        );
+
+        assert_eq!(input1.content_hash, input2.content_hash);
+        assert!(input1.matches(&input2, &config));
+        assert!(!input1.matches(&input3, &config));
+    }
+
+    #[test]
+    fn it_rounds_float_inputs_before_hashing() {
+        let config = MatchConfig {
+            float_significant_digits: Some(3),
+            ..Default::default()
+        };
+        let as_bytes =
+            |values: &[f32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+
+        let input1 = ProcessedInput::from_infer_request(
+            raw_infer_request(vec![("x", "FP32", vec![2], as_bytes(&[0.123401, 1520.02]))]),
+            &config,
+        );
+        let input2 = ProcessedInput::from_infer_request(
+            raw_infer_request(vec![("x", "FP32", vec![2], as_bytes(&[0.123399, 1519.98]))]),
+            &config,
+        );
+        let input3 = ProcessedInput::from_infer_request(
+            raw_infer_request(vec![("x", "FP32", vec![2], as_bytes(&[0.125, 1520.02]))]),
+            &config,
+        );
+
+        assert_eq!(input1.content_hash, input2.content_hash);
+        assert_ne!(input1.content_hash, input3.content_hash);
+    }
+
+    #[test]
+    fn it_rounds_half_precision_inputs_before_hashing() {
+        let config = MatchConfig {
+            float_significant_digits: Some(2),
+            ..Default::default()
+        };
+        let hash = |datatype: &str, values: &[f32]| {
+            let content = encode_f32(datatype, values).unwrap();
+            ProcessedInput::from_infer_request(
+                raw_infer_request(vec![("x", datatype, vec![2], content)]),
+                &config,
+            )
+            .content_hash
+        };
+
+        for datatype in ["FP16", "BF16"] {
+            assert_eq!(
+                hash(datatype, &[0.5, 12.0]),
+                hash(datatype, &[0.501, 12.02])
+            );
+            assert_ne!(hash(datatype, &[0.5, 12.0]), hash(datatype, &[0.55, 12.0]));
+        }
+    }
+
+    #[test]
+    fn it_hashes_typed_and_raw_contents_equally() {
+        let raw_request = raw_infer_request(vec![
+            (
+                "text",
+                "BYTES",
+                vec![2],
+                encode_bytes_elements(&[b"ab".to_vec(), b"c".to_vec()]),
+            ),
+            ("x", "FP32", vec![1], 1.5f32.to_le_bytes().to_vec()),
+        ]);
+
+        let mut typed_request = raw_request.clone();
+        typed_request.raw_input_contents = vec![];
+        typed_request.inputs[0].contents = Some(InferTensorContents {
+            bytes_contents: vec![b"ab".to_vec(), b"c".to_vec()],
+            ..Default::default()
+        });
+        typed_request.inputs[1].contents = Some(InferTensorContents {
+            fp32_contents: vec![1.5],
+            ..Default::default()
+        });
+
+        let raw_input = ProcessedInput::from_infer_request(raw_request, &Default::default());
+        let typed_input = ProcessedInput::from_infer_request(typed_request, &Default::default());
+
+        assert_eq!(raw_input.content_hash, typed_input.content_hash);
+    }
+
+    #[test]
+    fn it_hashes_with_the_configured_algorithm() {
+        let request = raw_infer_request(vec![("x", "UINT8", vec![3], vec![1, 2, 3])]);
+        let inputs: Vec<_> = [
+            HashAlgorithm::Blake2,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Xxh3,
+        ]
+        .into_iter()
+        .map(|hash_algorithm| {
+            let config = MatchConfig {
+                hash_algorithm,
+                ..Default::default()
+            };
+            (
+                config.clone(),
+                ProcessedInput::from_infer_request(request.clone(), &config),
+            )
+        })
+        .collect();
+
+        for (config, input) in &inputs {
+            assert!(input.matches(input, config));
+        }
+        assert_ne!(inputs[0].1.content_hash, inputs[1].1.content_hash);
+        assert!(!inputs[1].1.matches(&inputs[2].1, &inputs[2].0));
+        assert_eq!([0; 16], inputs[2].1.content_hash[16..]);
+
+        // Entries recorded before the algorithm was configurable were hashed with blake2.
+        let mut entry = serde_json::to_value(&inputs[0].1).unwrap();
+        assert_eq!(None, entry.get("hash_algorithm"));
+        entry["hash_algorithm"] = "Blake3".into();
+        let entry: ProcessedInput = serde_json::from_value(entry).unwrap();
+        assert_eq!(HashAlgorithm::Blake3, entry.hash_algorithm);
+    }
+
+    #[test]
+    fn it_canonicalizes_json_inputs() {
+        let config = MatchConfig {
+            json_input_tensors: HashMap::from([("test".to_string(), vec!["doc".to_string()])]),
+            ..Default::default()
+        };
+        let json_request = |doc: &str| {
+            raw_infer_request(vec![(
+                "doc",
+                "BYTES",
+                vec![1],
+                encode_bytes_elements(&[doc.as_bytes().to_vec()]),
+            )])
+        };
+
+        let input1 =
+            ProcessedInput::from_infer_request(json_request(r#"{"a": 1, "b": [1, 2]}"#), &config);
+        let input2 =
+            ProcessedInput::from_infer_request(json_request(r#"{"b":[1,2],"a":1}"#), &config);
+        let input3 =
+            ProcessedInput::from_infer_request(json_request(r#"{"b":[2,1],"a":1}"#), &config);
+        let input4 = ProcessedInput::from_infer_request(
+            json_request(r#"{"b":[1,2],"a":1}"#),
+            &Default::default(),
+        );
+
+        assert_eq!(input1.content_hash, input2.content_hash);
+        assert_ne!(input1.content_hash, input3.content_hash);
+        assert_ne!(input1.content_hash, input4.content_hash);
+    }
+
+    #[test]
+    fn it_gets_the_embedding_distance() {
+        let config = MatchConfig {
+            nearest_neighbor: HashMap::from([(
+                "test".to_string(),
+                NearestNeighborConfig {
+                    input: "embedding".to_string(),
+                    max_distance: 0.6,
+                },
+            )]),
+            ..Default::default()
+        };
+        let embedding_request = |values: [f32; 2]| {
+            raw_infer_request(vec![(
+                "embedding",
+                "FP32",
+                vec![2],
+                values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            )])
+        };
+
+        let input1 = ProcessedInput::from_infer_request(embedding_request([0.0, 0.0]), &config);
+        let input2 = ProcessedInput::from_infer_request(embedding_request([0.3, 0.4]), &config);
+        let input3 = ProcessedInput::from_infer_request(embedding_request([1.0, 0.0]), &config);
+
+        assert_eq!(input1.embedding, Some(vec![0.0, 0.0]));
+        assert!((input1.embedding_distance(&input2, &config).unwrap() - 0.5).abs() < 1e-6);
+        assert_eq!(input1.embedding_distance(&input3, &config), None);
+        assert_eq!(
+            input1.embedding_distance(&input2, &Default::default()),
+            None
+        );
+
+        // Requests that differ apart from their contents are not candidates.
+        let mut other_version = embedding_request([0.3, 0.4]);
+        other_version.model_version = "2".to_string();
+        let other_version = ProcessedInput::from_infer_request(other_version, &config);
+        assert_eq!(input1.embedding_distance(&other_version, &config), None);
+
+        let mut other_output = embedding_request([0.3, 0.4]);
+        other_output.outputs = vec![InferRequestedOutputTensor {
+            name: "OUTPUT1".to_string(),
+            parameters: Default::default(),
+        }];
+        let other_output = ProcessedInput::from_infer_request(other_output, &config);
+        assert_eq!(other_output.embedding_distance(&input2, &config), None);
+    }
+
+    #[test]
+    fn it_decodes_bytes_elements() {
+        let content = encode_bytes_elements(&[b"hello".to_vec(), vec![]]);
+
+        assert_eq!(
+            decode_bytes_elements(&content),
+            Some(vec![b"hello".to_vec(), vec![]])
+        );
+        assert_eq!(decode_bytes_elements(&[5, 0, 0, 0, 1]), None);
+    }
+
     #[test]
     fn it_matches_equal_inputs() {
         let input1 = BASE_INFER_INPUT.clone();
         let input2 = BASE_INFER_INPUT.clone();
 
-        assert!(input1.matches(&input2, Default::default()));
+        assert!(input1.matches(&input2, &Default::default()));
     }
 
     #[test]
@@ -491,7 +1290,7 @@ pub mod tests {
 
         input2.model_name = "hoi".to_string();
 
-        assert!(!input1.matches(&input2, Default::default()));
+        assert!(!input1.matches(&input2, &Default::default()));
     }
 
     #[test]
@@ -501,7 +1300,37 @@ pub mod tests {
 
         input2.model_version = "19".to_string();
 
-        assert!(!input1.matches(&input2, Default::default()));
+        assert!(!input1.matches(&input2, &Default::default()));
+    }
+
+    #[test]
+    fn it_ignores_model_version() {
+        let input1 = BASE_INFER_INPUT.clone();
+        let mut input2 = BASE_INFER_INPUT.clone();
+
+        input2.model_version = "".to_string();
+
+        assert!(input1.matches(
+            &input2,
+            &MatchConfig {
+                ignore_model_version: true,
+                ..Default::default()
+            }
+        ));
+        assert!(input1.matches(
+            &input2,
+            &MatchConfig {
+                ignore_model_version_models: vec!["test".to_string()],
+                ..Default::default()
+            }
+        ));
+        assert!(!input1.matches(
+            &input2,
+            &MatchConfig {
+                ignore_model_version_models: vec!["other".to_string()],
+                ..Default::default()
+            }
+        ));
     }
 
     #[test]
@@ -514,7 +1343,7 @@ pub mod tests {
             Some(Parameter::StringParam("test2".to_string())),
         );
 
-        assert!(!input1.matches(&input2, Default::default()));
+        assert!(!input1.matches(&input2, &Default::default()));
     }
 
     #[test]
@@ -533,7 +1362,7 @@ pub mod tests {
 
         assert!(input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 parameter_keys: vec!["ignore_me".to_string()],
                 ..Default::default()
             }
@@ -556,7 +1385,7 @@ pub mod tests {
 
         assert!(input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 parameter_keys: vec!["test".to_string()],
                 exclude_parameters: false,
                 ..Default::default()
@@ -574,7 +1403,7 @@ pub mod tests {
             Some(Parameter::StringParam("test2".to_string())),
         );
 
-        assert!(!input1.matches(&input2, Default::default()));
+        assert!(!input1.matches(&input2, &Default::default()));
     }
 
     #[test]
@@ -593,7 +1422,7 @@ pub mod tests {
 
         assert!(input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 input_parameter_keys: HashMap::from([(
                     "input1".to_string(),
                     vec!["ignore_me".to_string()]
@@ -619,7 +1448,7 @@ pub mod tests {
 
         assert!(input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 input_parameter_keys: HashMap::from([(
                     "input1".to_string(),
                     vec!["test".to_string()]
@@ -640,7 +1469,24 @@ pub mod tests {
             Some(Parameter::StringParam("test2".to_string())),
         );
 
-        assert!(!input1.matches(&input2, Default::default()));
+        assert!(!input1.matches(&input2, &Default::default()));
+    }
+
+    #[test]
+    fn it_matches_convertible_classification_outputs() {
+        let with_classes = |classes: i64| {
+            let mut input = BASE_INFER_INPUT.clone();
+            input.outputs[0].parameters.insert(
+                CLASSIFICATION_PARAMETER.to_string(),
+                Some(Parameter::Int64Param(classes)),
+            );
+            input
+        };
+
+        assert!(BASE_INFER_INPUT.matches(&with_classes(3), &Default::default()));
+        assert!(with_classes(3).matches(&with_classes(2), &Default::default()));
+        assert!(!with_classes(2).matches(&with_classes(3), &Default::default()));
+        assert!(!with_classes(2).matches(&BASE_INFER_INPUT, &Default::default()));
     }
 
     #[test]
@@ -659,7 +1505,7 @@ pub mod tests {
 
         assert!(input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 output_parameter_keys: HashMap::from([(
                     "output1".to_string(),
                     vec!["ignore_me".to_string()]
@@ -685,7 +1531,7 @@ pub mod tests {
 
         assert!(input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 output_parameter_keys: HashMap::from([(
                     "input1".to_string(),
                     vec!["test".to_string()]
@@ -705,7 +1551,7 @@ pub mod tests {
 
         assert!(!input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 ..Default::default()
             }
         ));
@@ -720,7 +1566,30 @@ pub mod tests {
 
         assert!(!input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn it_matches_input_shape_patterns() {
+        let input1 = BASE_INFER_INPUT.clone();
+        let mut input2 = BASE_INFER_INPUT.clone();
+
+        input2.inputs[0].shape = vec![8, 2, 3];
+
+        assert!(input1.matches(
+            &input2,
+            &MatchConfig {
+                input_shape_patterns: HashMap::from([("input1".to_string(), vec![-1, 2, 3])]),
+                ..Default::default()
+            }
+        ));
+        assert!(input1.matches(
+            &input2,
+            &MatchConfig {
+                ignore_batch_dimension: true,
                 ..Default::default()
             }
         ));
@@ -735,7 +1604,7 @@ pub mod tests {
 
         assert!(!input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 ..Default::default()
             }
         ));
@@ -750,9 +1619,58 @@ pub mod tests {
 
         assert!(!input1.matches(
             &input2,
-            MatchConfig {
+            &MatchConfig {
                 ..Default::default()
             }
         ));
     }
+
+    #[test]
+    fn it_matches_sequence_positions() {
+        let mut input1 = BASE_INFER_INPUT.clone();
+        let mut input2 = BASE_INFER_INPUT.clone();
+
+        input1
+            .parameters
+            .insert(SEQUENCE_ID.to_string(), Some(Parameter::Int64Param(7)));
+        input1.sequence = Some(SequenceStep {
+            id: "7".to_string(),
+            position: 1,
+        });
+        input2
+            .parameters
+            .insert(SEQUENCE_ID.to_string(), Some(Parameter::Int64Param(42)));
+        input2.sequence = Some(SequenceStep {
+            id: "".to_string(),
+            position: 1,
+        });
+
+        let config = MatchConfig {
+            exclude_parameters: true,
+            sequence_matching: true,
+            ..Default::default()
+        };
+
+        assert!(input1.matches(&input2, &config));
+
+        input2.sequence.as_mut().unwrap().position = 2;
+
+        assert!(!input1.matches(&input2, &config));
+    }
+
+    #[test]
+    fn it_matches_namespaces() {
+        let input1 = BASE_INFER_INPUT.clone();
+        let mut input2 = BASE_INFER_INPUT.clone();
+        input2.namespace = Some("suite-a".to_string());
+
+        assert!(!input1.matches(&input2, &Default::default()));
+        assert_ne!(input1.inputs_hash(), input2.inputs_hash());
+
+        let mut input1 = input1;
+        input1.namespace = Some("suite-a".to_string());
+
+        assert!(input1.matches(&input2, &Default::default()));
+        assert_eq!(input1.inputs_hash(), input2.inputs_hash());
+    }
 }
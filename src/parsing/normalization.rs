@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::parsing::input::Parameter;
+use crate::service::inference_protocol::{ModelInferRequest, ModelInferResponse};
+
+/// Rules that normalize incoming requests before they are handled, so existing caches keep
+/// matching when the naming conventions of clients drift. The normalized request is matched,
+/// recorded and forwarded to the target server, and renamed outputs are renamed back in the
+/// responses.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct NormalizeConfig {
+    // The request parameters that are removed.
+    pub strip_parameters: Vec<String>,
+
+    // The model version per model that overwrites the version of the requests.
+    pub model_versions: HashMap<String, String>,
+
+    // The input and output tensors per model that are renamed, from the name used by the clients
+    // to the name in the cache. Every name in the cache is used once, see `validate`.
+    pub rename_tensors: HashMap<String, HashMap<String, String>>,
+
+    // When true, the request id is removed.
+    pub strip_id: bool,
+}
+
+impl NormalizeConfig {
+    /// Check that no two tensors of a model are renamed to the same name, as the outputs could
+    /// not be renamed back to the name the client used.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (model_name, renamed) in &self.rename_tensors {
+            let mut client_names: HashMap<&String, &String> = HashMap::new();
+            for (client_name, name) in renamed {
+                if let Some(other) = client_names.insert(name, client_name) {
+                    anyhow::bail!(
+                        "tensors {other} and {client_name} of model {model_name} are both renamed \
+                         to {name}"
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the rules to an incoming request.
+    pub fn normalize(&self, req: &mut ModelInferRequest) {
+        if self.strip_id {
+            req.id = String::new();
+        }
+
+        req.parameters
+            .retain(|key, _| !self.strip_parameters.contains(key));
+
+        if let Some(renamed) = self.rename_tensors.get(&req.model_name) {
+            for input in req.inputs.iter_mut() {
+                if let Some(name) = renamed.get(&input.name) {
+                    input.name = name.clone();
+                }
+            }
+            for output in req.outputs.iter_mut() {
+                if let Some(name) = renamed.get(&output.name) {
+                    output.name = name.clone();
+                }
+            }
+        }
+
+        if let Some(version) = self.model_versions.get(&req.model_name) {
+            req.model_version = version.clone();
+        }
+    }
+
+    /// Rename the outputs of a response to a normalized request back to the names used by the
+    /// clients.
+    pub fn restore(&self, res: &mut ModelInferResponse) {
+        let Some(renamed) = self.rename_tensors.get(&res.model_name) else {
+            return;
+        };
+
+        for output in res.outputs.iter_mut() {
+            if let Some((client_name, _)) = renamed.iter().find(|(_, name)| **name == output.name) {
+                output.name = client_name.clone();
+            }
+        }
+    }
+}
+
+/// Rules that rewrite cached responses before they are served, so they can be adapted to newer
+/// client expectations without re-recording them.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct RewriteConfig {
+    // The model version per model that overwrites the version of the cached responses.
+    pub model_versions: HashMap<String, String>,
+
+    // The response parameters per model that are added to the cached responses, replacing
+    // recorded parameters with the same key.
+    pub parameters: HashMap<String, HashMap<String, Parameter>>,
+
+    // The output tensors per model that are renamed, from the name in the cache to the name
+    // expected by the clients.
+    pub rename_outputs: HashMap<String, HashMap<String, String>>,
+}
+
+impl RewriteConfig {
+    /// Apply the rules to a cached response.
+    pub fn rewrite(&self, res: &mut ModelInferResponse) {
+        if let Some(renamed) = self.rename_outputs.get(&res.model_name) {
+            for output in res.outputs.iter_mut() {
+                if let Some(name) = renamed.get(&output.name) {
+                    output.name = name.clone();
+                }
+            }
+        }
+
+        if let Some(parameters) = self.parameters.get(&res.model_name) {
+            for (key, parameter) in parameters {
+                res.parameters
+                    .insert(key.clone(), parameter.clone().to_infer_parameter());
+            }
+        }
+
+        if let Some(version) = self.model_versions.get(&res.model_name) {
+            res.model_version = version.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::input::tests::raw_infer_request;
+    use crate::service::inference_protocol::model_infer_request::InferRequestedOutputTensor;
+    use crate::service::inference_protocol::model_infer_response::InferOutputTensor;
+    use crate::service::inference_protocol::InferParameter;
+
+    #[test]
+    fn it_rejects_renaming_tensors_to_the_same_name() {
+        let rename = |renamed: &[(&str, &str)]| NormalizeConfig {
+            rename_tensors: HashMap::from([(
+                "test".to_string(),
+                renamed
+                    .iter()
+                    .map(|(from, to)| (from.to_string(), to.to_string()))
+                    .collect(),
+            )]),
+            ..Default::default()
+        };
+
+        assert!(rename(&[("probs", "OUTPUT0"), ("input", "INPUT0")])
+            .validate()
+            .is_ok());
+        let err = rename(&[("probs", "OUTPUT0"), ("scores", "OUTPUT0")])
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("renamed to OUTPUT0"));
+    }
+
+    #[test]
+    fn it_normalizes_requests() {
+        let mut req = raw_infer_request(vec![("input", "UINT8", vec![2], vec![1, 2])]);
+        req.id = "request-1".to_string();
+        req.model_version = "2".to_string();
+        req.parameters
+            .insert("client".to_string(), InferParameter::default());
+        req.outputs = vec![InferRequestedOutputTensor {
+            name: "probs".to_string(),
+            parameters: Default::default(),
+        }];
+
+        let config = NormalizeConfig {
+            strip_parameters: vec!["client".to_string()],
+            model_versions: HashMap::from([(req.model_name.clone(), "1".to_string())]),
+            rename_tensors: HashMap::from([(
+                req.model_name.clone(),
+                HashMap::from([
+                    ("input".to_string(), "INPUT0".to_string()),
+                    ("probs".to_string(), "OUTPUT0".to_string()),
+                ]),
+            )]),
+            strip_id: true,
+        };
+        config.normalize(&mut req);
+
+        assert_eq!("", req.id);
+        assert_eq!("1", req.model_version);
+        assert!(req.parameters.is_empty());
+        assert_eq!("INPUT0", req.inputs[0].name);
+        assert_eq!("OUTPUT0", req.outputs[0].name);
+
+        let mut res = ModelInferResponse {
+            model_name: req.model_name.clone(),
+            outputs: vec![InferOutputTensor {
+                name: "OUTPUT0".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        config.restore(&mut res);
+
+        assert_eq!("probs", res.outputs[0].name);
+    }
+
+    #[test]
+    fn it_rewrites_responses() {
+        let mut res = ModelInferResponse {
+            model_name: "simple".to_string(),
+            model_version: "1".to_string(),
+            outputs: vec![InferOutputTensor {
+                name: "OUTPUT0".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let config = RewriteConfig {
+            model_versions: HashMap::from([("simple".to_string(), "2".to_string())]),
+            parameters: HashMap::from([(
+                "simple".to_string(),
+                HashMap::from([("schema".to_string(), Parameter::Int64Param(2))]),
+            )]),
+            rename_outputs: HashMap::from([(
+                "simple".to_string(),
+                HashMap::from([("OUTPUT0".to_string(), "probabilities".to_string())]),
+            )]),
+        };
+        config.rewrite(&mut res);
+
+        assert_eq!("2", res.model_version);
+        assert_eq!("probabilities", res.outputs[0].name);
+        assert_eq!(
+            Some(Parameter::Int64Param(2).to_infer_parameter()),
+            res.parameters.get("schema").cloned()
+        );
+
+        let config: RewriteConfig = serde_json::from_str(
+            r#"{"model_versions": {}, "rename_outputs": {}, "parameters": {"simple": {"flag": true, "name": "a"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(&Parameter::BoolParam(true)),
+            config.parameters["simple"].get("flag")
+        );
+    }
+}
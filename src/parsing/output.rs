@@ -1,17 +1,62 @@
 use crate::parsing::input::Parameter;
+use crate::parsing::tensor::{decode_bytes_elements, element_size};
 use crate::service::inference_protocol::model_infer_response::InferOutputTensor;
-use crate::service::inference_protocol::{
-    InferParameter, ModelInferRequest, ModelInferResponse, ModelStreamInferResponse,
-};
+use crate::service::inference_protocol::{InferParameter, ModelInferRequest, ModelInferResponse};
 use blake2::{Blake2b, Digest};
+use bytes::Bytes;
 use digest::consts::U8;
+use log::warn;
+use prost::Message;
 use serde::{Deserialize, Serialize};
 use serde_with::base64::Base64;
 use serde_with::serde_as;
 use std::collections::BTreeMap;
+use std::path::Path;
+use tonic::codegen::http::header::{HeaderMap, HeaderName, HeaderValue};
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Status};
 
 type Blake2b64 = Blake2b<U8>;
 
+/// Response metadata that is set by the transport, which is not recorded.
+const TRANSPORT_METADATA: &[&str] = &[
+    "content-type",
+    "content-length",
+    "date",
+    "te",
+    "trailer",
+    "grpc-status",
+    "grpc-message",
+    "grpc-status-details-bin",
+    "grpc-encoding",
+    "grpc-accept-encoding",
+];
+
+/// The response parameter that flags a response as an approximate (nearest neighbor) match.
+pub const APPROXIMATE_MATCH_PARAMETER: &str = "inferencestore_approximate_match";
+
+/// The response parameter that tells whether a response was served from the cache.
+pub const HIT_PARAMETER: &str = "inferencestore.hit";
+
+/// The response parameter that contains the file name of the cache entry a response was served from.
+pub const ENTRY_PARAMETER: &str = "inferencestore.entry";
+
+/// Annotate a response with its provenance: whether it was served from the cache entry at `entry`,
+/// or from the live target server (or synthesized) when `entry` is None.
+pub fn annotate_provenance(response: &mut ModelInferResponse, entry: Option<&Path>) {
+    response.parameters.insert(
+        HIT_PARAMETER.to_string(),
+        Parameter::BoolParam(entry.is_some()).to_infer_parameter(),
+    );
+
+    if let Some(file_name) = entry.and_then(Path::file_name) {
+        response.parameters.insert(
+            ENTRY_PARAMETER.to_string(),
+            Parameter::StringParam(file_name.to_string_lossy().to_string()).to_infer_parameter(),
+        );
+    }
+}
+
 // Represents a parsed form of ModelInferRequest that is less heavy to process as the full request.
 // It basically contains the same information, but the content has been hashed to reduce the size.
 #[serde_as]
@@ -19,8 +64,108 @@ type Blake2b64 = Blake2b<U8>;
 pub struct ProcessedOutput {
     pub parameters: BTreeMap<String, Option<Parameter>>,
     pub outputs: Vec<Output>,
+    // Shared bytes, so contents read from a memory-mapped entry are not copied into responses.
     #[serde_as(as = "Vec<Base64>")]
-    pub raw_output_contents: Vec<Vec<u8>>,
+    pub raw_output_contents: Vec<Bytes>,
+    // The error status the target server responded with, instead of outputs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RecordedError>,
+    // The response headers and trailers, which are not part of the hash as they may contain
+    // values that differ per response.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    // The response exactly as the target server encoded it, when raw responses are recorded. It
+    // is replayed instead of a response built from the fields above.
+    #[serde_as(as = "Option<Base64>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_response: Option<Bytes>,
+}
+
+/// A recorded error status of the target server.
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RecordedError {
+    pub code: i32,
+    pub message: String,
+    // The rich error details, e.g. an encoded google.rpc.Status.
+    #[serde_as(as = "Base64")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<u8>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Convert response metadata to a map that can be recorded, without the transport metadata.
+/// Binary (`-bin`) values are kept in their base64 encoded form.
+pub fn metadata_to_map(metadata: &MetadataMap) -> BTreeMap<String, String> {
+    metadata
+        .clone()
+        .into_headers()
+        .iter()
+        .filter(|(name, _)| !TRANSPORT_METADATA.contains(&name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Convert recorded metadata back to response metadata.
+pub fn map_to_metadata(map: &BTreeMap<String, String>) -> MetadataMap {
+    let headers: HeaderMap = map
+        .iter()
+        .filter_map(|(name, value)| {
+            Some((
+                HeaderName::from_bytes(name.as_bytes()).ok()?,
+                HeaderValue::from_str(value).ok()?,
+            ))
+        })
+        .collect();
+
+    MetadataMap::from_headers(headers)
+}
+
+/// Check if errors with the code should be recorded. The codes are names like `InvalidArgument`
+/// or `invalid_argument`.
+pub fn is_recorded_error(codes: &[String], code: Code) -> bool {
+    let normalize = |name: &str| name.replace('_', "").to_lowercase();
+    let name = normalize(&format!("{code:?}"));
+
+    codes.iter().any(|c| normalize(c) == name)
+}
+
+/// Check that the raw content of a tensor holds exactly the number of elements of its shape.
+pub fn check_tensor(datatype: &str, shape: &[i64], content: &[u8]) -> anyhow::Result<()> {
+    if let Some(dim) = shape.iter().find(|&&dim| dim < 0) {
+        anyhow::bail!("invalid dimension {dim} in shape {shape:?}");
+    }
+    let elements = shape.iter().try_fold(1usize, |elements, &dim| {
+        elements.checked_mul(usize::try_from(dim).ok()?)
+    });
+    let Some(elements) = elements else {
+        anyhow::bail!("the number of elements of shape {shape:?} overflows");
+    };
+
+    let stored = match element_size(datatype) {
+        _ if datatype == "BYTES" => match decode_bytes_elements(content) {
+            Some(elements) => elements.len(),
+            None => anyhow::bail!("invalid BYTES content"),
+        },
+        Some(size) => {
+            let stored = content.len() / size;
+            if stored * size != content.len() {
+                anyhow::bail!(
+                    "{} bytes is not a multiple of the {datatype} size",
+                    content.len()
+                );
+            }
+            stored
+        }
+        None => anyhow::bail!("unknown datatype {datatype}"),
+    };
+
+    if stored != elements {
+        anyhow::bail!("{stored} {datatype} elements stored for shape {shape:?}");
+    }
+
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -32,26 +177,28 @@ pub struct Output {
 }
 
 impl ProcessedOutput {
+    /// The hash of the decoded output. The raw response is not hashed, as responses that only
+    /// differ in their encoding, e.g. the order of the parameters, are the same output.
     pub fn hash(&self) -> [u8; 8] {
         let mut hasher = Blake2b64::new();
 
         for (key, value) in &self.parameters {
-            blake2::Digest::update(&mut hasher, &key.as_bytes());
+            blake2::Digest::update(&mut hasher, key.as_bytes());
             if value.is_some() {
                 blake2::Digest::update(&mut hasher, value.as_ref().unwrap().as_bytes());
             }
         }
 
         for output in &self.outputs {
-            blake2::Digest::update(&mut hasher, &output.datatype.as_bytes());
-            blake2::Digest::update(&mut hasher, &output.name.as_bytes());
+            blake2::Digest::update(&mut hasher, output.datatype.as_bytes());
+            blake2::Digest::update(&mut hasher, output.name.as_bytes());
 
             for shape in &output.shape {
-                blake2::Digest::update(&mut hasher, &shape.to_le_bytes());
+                blake2::Digest::update(&mut hasher, shape.to_le_bytes());
             }
 
             for (key, value) in &output.parameters {
-                blake2::Digest::update(&mut hasher, &key.as_bytes());
+                blake2::Digest::update(&mut hasher, key.as_bytes());
                 if value.is_some() {
                     blake2::Digest::update(&mut hasher, value.as_ref().unwrap().as_bytes());
                 }
@@ -62,13 +209,18 @@ impl ProcessedOutput {
             blake2::Digest::update(&mut hasher, output_content);
         }
 
+        if let Some(error) = &self.error {
+            blake2::Digest::update(&mut hasher, error.code.to_le_bytes());
+            blake2::Digest::update(&mut hasher, error.message.as_bytes());
+        }
+
         let hash = hasher.finalize();
         let hash: &[u8; 8] = hash.as_slice().try_into().unwrap();
 
-        return *hash;
+        *hash
     }
     pub fn from_response(response: &ModelInferResponse) -> ProcessedOutput {
-        return ProcessedOutput {
+        ProcessedOutput {
             parameters: response
                 .parameters
                 .iter()
@@ -108,12 +260,81 @@ impl ProcessedOutput {
                 )
                 .collect(),
             raw_output_contents: response.raw_output_contents.clone(),
-        };
+            error: None,
+            metadata: Default::default(),
+            raw_response: None,
+        }
+    }
+
+    /// Record an error status of the target server.
+    pub fn from_status(status: &Status) -> ProcessedOutput {
+        ProcessedOutput {
+            parameters: Default::default(),
+            outputs: vec![],
+            raw_output_contents: vec![],
+            error: Some(RecordedError {
+                code: status.code() as i32,
+                message: status.message().to_string(),
+                details: status.details().to_vec(),
+                metadata: metadata_to_map(status.metadata()),
+            }),
+            metadata: Default::default(),
+            raw_response: None,
+        }
+    }
+
+    /// Check that there is a raw content for every output, that holds the number of elements of
+    /// its shape. Recorded errors have no outputs.
+    pub fn check_contents(&self) -> anyhow::Result<()> {
+        if self.error.is_some() {
+            return Ok(());
+        }
+        if self.raw_output_contents.len() != self.outputs.len() {
+            anyhow::bail!(
+                "{} output contents for {} outputs",
+                self.raw_output_contents.len(),
+                self.outputs.len()
+            );
+        }
+        for (tensor, content) in self.outputs.iter().zip(&self.raw_output_contents) {
+            check_tensor(&tensor.datatype, &tensor.shape, content)
+                .map_err(|err| err.context(format!("output {}", tensor.name)))?;
+        }
+
+        Ok(())
+    }
+
+    /// The recorded error status, which is replayed instead of a response.
+    pub fn to_status(&self) -> Option<Status> {
+        self.error.as_ref().map(|error| {
+            Status::with_details_and_metadata(
+                Code::from(error.code),
+                error.message.clone(),
+                error.details.clone().into(),
+                map_to_metadata(&error.metadata),
+            )
+        })
     }
 
-    /// Convert the processed output to an actual ModelInferResponse based on the request.
+    /// Convert the processed output to an actual ModelInferResponse based on the request. A
+    /// recorded raw response is decoded, and only gets the id and model of the request. The gRPC
+    /// server sends the raw response itself, see `raw_response::RawResponseServer`.
     pub fn to_response(&self, request: ModelInferRequest) -> ModelInferResponse {
-        return ModelInferResponse {
+        if let Some(raw_response) = &self.raw_response {
+            match ModelInferResponse::decode(raw_response.clone()) {
+                Ok(response) => {
+                    return ModelInferResponse {
+                        model_name: request.model_name,
+                        model_version: request.model_version,
+                        id: request.id,
+                        ..response
+                    }
+                }
+                Err(err) => warn!("could not decode the recorded raw response: {err}"),
+            }
+        }
+
+        ModelInferResponse {
             model_name: request.model_name,
             model_version: request.model_version,
             id: request.id,
@@ -121,13 +342,13 @@ impl ProcessedOutput {
                 .parameters
                 .iter()
                 .map(|(name, parameter)| {
-                    return (
+                    (
                         name.clone(),
                         match parameter {
                             None => InferParameter::default(),
                             Some(parameter) => parameter.clone().to_infer_parameter(),
                         },
-                    );
+                    )
                 })
                 .collect(),
             outputs: self
@@ -140,14 +361,14 @@ impl ProcessedOutput {
                          shape,
                          parameters,
                      }| {
-                        return InferOutputTensor {
+                        InferOutputTensor {
                             name: name.clone(),
                             datatype: datatype.clone(),
                             shape: shape.clone(),
                             parameters: parameters
                                 .iter()
                                 .map(|(name, parameter)| {
-                                    return (
+                                    (
                                         name.clone(),
                                         match parameter {
                                             None => InferParameter::default(),
@@ -155,23 +376,32 @@ impl ProcessedOutput {
                                                 parameter.clone().to_infer_parameter()
                                             }
                                         },
-                                    );
+                                    )
                                 })
                                 .collect(),
                             contents: None, // TODO add contents.
-                        };
+                        }
                     },
                 )
                 .collect(),
             raw_output_contents: self.raw_output_contents.clone(),
-        };
+        }
     }
 
-    pub fn to_stream_response(&self, request: ModelInferRequest) -> ModelStreamInferResponse {
-        return ModelStreamInferResponse {
-            error_message: "".to_string(),
-            infer_response: Some(self.to_response(request)),
-        };
+    /// Convert the processed output to a response for an approximate match, which is flagged by the
+    /// `inferencestore_approximate_match` parameter containing the distance to the cached input.
+    pub fn to_approximate_response(
+        &self,
+        request: ModelInferRequest,
+        distance: f64,
+    ) -> ModelInferResponse {
+        let mut response = self.to_response(request);
+        response.parameters.insert(
+            APPROXIMATE_MATCH_PARAMETER.to_string(),
+            Parameter::DoubleParam(distance).to_infer_parameter(),
+        );
+
+        response
     }
 }
 
@@ -180,6 +410,7 @@ pub mod tests {
     use once_cell::sync::Lazy;
 
     use super::*;
+    use crate::service::inference_protocol::InferTensorContents;
 
     pub static BASE_INFER_OUTPUT: Lazy<ProcessedOutput> = Lazy::new(|| ProcessedOutput {
         parameters: BTreeMap::from([(
@@ -195,9 +426,39 @@ pub mod tests {
             datatype: "INT64".to_string(),
             shape: vec![1, 2, 3],
         }],
-        raw_output_contents: vec![vec![69]],
+        raw_output_contents: vec![vec![69].into()],
+        error: None,
+        metadata: Default::default(),
+        raw_response: None,
     });
 
+    #[test]
+    fn it_checks_tensor_contents() {
+        assert!(check_tensor("FP32", &[1, 2], &[0; 8]).is_ok());
+        assert!(check_tensor("FP32", &[1, 2], &[0; 4]).is_err());
+        assert!(check_tensor("FP32", &[1, 2], &[0; 7]).is_err());
+        assert!(check_tensor("FP32", &[-1], &[]).is_err());
+        assert!(check_tensor("FP32", &[i64::MAX, i64::MAX, 0], &[]).is_err());
+        assert!(check_tensor("BYTES", &[2], &[1, 0, 0, 0, b'a', 0, 0, 0, 0]).is_ok());
+        assert!(check_tensor("BYTES", &[1], &[2, 0, 0, 0, b'a']).is_err());
+        assert!(check_tensor("FP8", &[1], &[0]).is_err());
+    }
+
+    #[test]
+    fn it_checks_output_contents() {
+        let mut output = BASE_INFER_OUTPUT.clone();
+        assert!(output.check_contents().is_err());
+
+        output.raw_output_contents = vec![vec![0; 48].into()];
+        assert!(output.check_contents().is_ok());
+
+        output.raw_output_contents = vec![];
+        assert!(output.check_contents().is_err());
+        assert!(ProcessedOutput::from_status(&Status::internal("failed"))
+            .check_contents()
+            .is_ok());
+    }
+
     #[test]
     fn it_converts_output_to_infer_response() {
         let response = BASE_INFER_OUTPUT.clone().to_response(ModelInferRequest {
@@ -215,6 +476,144 @@ pub mod tests {
         assert_eq!(response.id, "asdf");
     }
 
+    #[test]
+    fn it_replays_raw_responses() {
+        let recorded = ModelInferResponse {
+            model_name: "recorded".to_string(),
+            id: "recorded".to_string(),
+            outputs: vec![InferOutputTensor {
+                name: "OUTPUT".to_string(),
+                datatype: "INT32".to_string(),
+                shape: vec![1],
+                contents: Some(InferTensorContents {
+                    int_contents: vec![7],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut output = ProcessedOutput::from_response(&recorded);
+        let hash = output.hash();
+        // The raw response is a serving detail, outputs that only differ in their encoding are
+        // the same output.
+        output.raw_response = Some(recorded.encode_to_vec().into());
+        assert_eq!(hash, output.hash());
+
+        let output: ProcessedOutput =
+            serde_json::from_str(&serde_json::to_string(&output).unwrap()).unwrap();
+        let response = output.to_response(ModelInferRequest {
+            model_name: "test".to_string(),
+            model_version: "1".to_string(),
+            id: "asdf".to_string(),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            ModelInferResponse {
+                model_name: "test".to_string(),
+                model_version: "1".to_string(),
+                id: "asdf".to_string(),
+                ..recorded
+            },
+            response
+        );
+    }
+
+    #[test]
+    fn it_flags_approximate_responses() {
+        let response = BASE_INFER_OUTPUT
+            .clone()
+            .to_approximate_response(Default::default(), 0.5);
+
+        assert_eq!(
+            Parameter::from_infer_parameter(
+                response.parameters[APPROXIMATE_MATCH_PARAMETER].clone()
+            ),
+            Some(Parameter::DoubleParam(0.5))
+        );
+    }
+
+    #[test]
+    fn it_records_error_statuses() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("triton-error", "shape".parse().unwrap());
+        let status = Status::with_details_and_metadata(
+            Code::InvalidArgument,
+            "unexpected shape for input 'INPUT0'",
+            vec![1, 2, 3].into(),
+            metadata,
+        );
+        let output = ProcessedOutput::from_status(&status);
+
+        let replayed = output.to_status().unwrap();
+        assert_eq!(Code::InvalidArgument, replayed.code());
+        assert_eq!(status.message(), replayed.message());
+        assert_eq!(&[1, 2, 3], replayed.details());
+        assert_eq!("shape", replayed.metadata().get("triton-error").unwrap());
+        assert_ne!(BASE_INFER_OUTPUT.hash(), output.hash());
+        assert_eq!(None, BASE_INFER_OUTPUT.to_status().map(|s| s.code()));
+
+        let codes = vec!["invalid_argument".to_string(), "NotFound".to_string()];
+        assert!(is_recorded_error(&codes, Code::InvalidArgument));
+        assert!(is_recorded_error(&codes, Code::NotFound));
+        assert!(!is_recorded_error(&codes, Code::Unavailable));
+    }
+
+    #[test]
+    fn it_records_metadata_without_transport_metadata() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("content-type", "application/grpc".parse().unwrap());
+        metadata.insert("x-model-revision", "7".parse().unwrap());
+        metadata.insert_bin(
+            "trace-bin",
+            tonic::metadata::MetadataValue::from_bytes(&[1, 2]),
+        );
+
+        let map = metadata_to_map(&metadata);
+
+        assert_eq!(
+            BTreeMap::from([
+                ("trace-bin".to_string(), "AQI".to_string()),
+                ("x-model-revision".to_string(), "7".to_string()),
+            ]),
+            map
+        );
+        assert_eq!(
+            &[1, 2],
+            map_to_metadata(&map)
+                .get_bin("trace-bin")
+                .unwrap()
+                .to_bytes()
+                .unwrap()
+                .as_ref()
+        );
+    }
+
+    #[test]
+    fn it_annotates_provenance() {
+        let mut hit = BASE_INFER_OUTPUT.clone().to_response(Default::default());
+        annotate_provenance(&mut hit, Some(Path::new("/cache/abc-def.inferstore")));
+
+        assert_eq!(
+            Parameter::from_infer_parameter(hit.parameters[HIT_PARAMETER].clone()),
+            Some(Parameter::BoolParam(true))
+        );
+        assert_eq!(
+            Parameter::from_infer_parameter(hit.parameters[ENTRY_PARAMETER].clone()),
+            Some(Parameter::StringParam("abc-def.inferstore".to_string()))
+        );
+
+        let mut miss = ModelInferResponse::default();
+        annotate_provenance(&mut miss, None);
+
+        assert_eq!(
+            Parameter::from_infer_parameter(miss.parameters[HIT_PARAMETER].clone()),
+            Some(Parameter::BoolParam(false))
+        );
+        assert!(!miss.parameters.contains_key(ENTRY_PARAMETER));
+    }
+
     #[test]
     fn it_converts_infer_response_to_output() {
         let response = BASE_INFER_OUTPUT.clone().to_response(ModelInferRequest {
@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use crate::parsing::input::ProcessedInput;
+
+/// A user supplied Rhai script that decides if a cached input matches an incoming input. The
+/// script needs to define a `matches(input, candidate)` function that returns a bool, where
+/// `input` is the incoming input and `candidate` is the cached input, both as a ProcessedInput map.
+pub struct MatchScript {
+    #[cfg(feature = "scripting")]
+    engine: rhai::Engine,
+    #[cfg(feature = "scripting")]
+    ast: rhai::AST,
+}
+
+#[cfg(feature = "scripting")]
+impl MatchScript {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let engine = rhai::Engine::new();
+        let ast = engine
+            .compile_file(path.as_ref().to_path_buf())
+            .map_err(|err| anyhow::anyhow!("could not compile match script: {err}"))?;
+
+        Ok(MatchScript { engine, ast })
+    }
+
+    pub fn matches(
+        &self,
+        input: &ProcessedInput,
+        candidate: &ProcessedInput,
+    ) -> anyhow::Result<bool> {
+        let result = self.engine.call_fn::<bool>(
+            &mut rhai::Scope::new(),
+            &self.ast,
+            "matches",
+            (
+                rhai::serde::to_dynamic(input)?,
+                rhai::serde::to_dynamic(candidate)?,
+            ),
+        );
+
+        result.map_err(|err| anyhow::anyhow!("match script failed: {err}"))
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+impl MatchScript {
+    pub fn from_file<P: AsRef<Path>>(_path: P) -> anyhow::Result<Self> {
+        anyhow::bail!("match scripts require InferenceStore to be built with the scripting feature")
+    }
+
+    pub fn matches(
+        &self,
+        _input: &ProcessedInput,
+        _candidate: &ProcessedInput,
+    ) -> anyhow::Result<bool> {
+        unreachable!("match scripts can not be created without the scripting feature")
+    }
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::parsing::input::tests::BASE_INFER_INPUT;
+
+    #[test]
+    fn it_matches_with_a_script() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("match.rhai");
+        fs::write(
+            &path,
+            "fn matches(input, candidate) { input.model_name == candidate.model_name }",
+        )
+        .unwrap();
+
+        let script = MatchScript::from_file(&path).unwrap();
+
+        let input1 = BASE_INFER_INPUT.clone();
+        let mut input2 = BASE_INFER_INPUT.clone();
+        input2.content_hash = [0; 32];
+
+        assert!(script.matches(&input2, &input1).unwrap());
+
+        input2.model_name = "other".to_string();
+
+        assert!(!script.matches(&input2, &input1).unwrap());
+    }
+}
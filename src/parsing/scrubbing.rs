@@ -0,0 +1,125 @@
+use blake2::{Blake2s256, Digest};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::service::inference_protocol::infer_parameter::ParameterChoice;
+use crate::service::inference_protocol::{InferParameter, ModelInferRequest};
+
+/// Rules to remove sensitive data from requests before they are recorded, so recorded fixtures
+/// are safe to commit to a repository. The same rules are applied to incoming requests before
+/// matching, so scrubbed entries keep matching the original requests.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct ScrubConfig {
+    // The request parameters that are removed.
+    pub drop_parameters: Vec<String>,
+
+    // The request parameters of which the value is replaced by its hash.
+    pub hash_parameters: Vec<String>,
+
+    // The input tensors per model of which the content is replaced by zeros.
+    pub zero_input_tensors: HashMap<String, Vec<String>>,
+
+    // When true, the request id is removed.
+    pub strip_request_id: bool,
+}
+
+impl ScrubConfig {
+    /// Remove the configured sensitive data from a request.
+    pub fn scrub(&self, mut req: ModelInferRequest) -> ModelInferRequest {
+        if self.strip_request_id {
+            req.id = String::new();
+        }
+
+        req.parameters
+            .retain(|key, _| !self.drop_parameters.contains(key));
+
+        for (key, parameter) in req.parameters.iter_mut() {
+            if self.hash_parameters.contains(key) {
+                *parameter = hash_parameter(parameter);
+            }
+        }
+
+        let zeroed_tensors = match self.zero_input_tensors.get(&req.model_name) {
+            Some(zeroed_tensors) => zeroed_tensors,
+            None => return req,
+        };
+
+        for (index, input) in req.inputs.iter_mut().enumerate() {
+            if !zeroed_tensors.contains(&input.name) {
+                continue;
+            }
+
+            if let Some(raw) = req.raw_input_contents.get_mut(index) {
+                raw.fill(0);
+            }
+
+            if let Some(contents) = input.contents.as_mut() {
+                contents.bool_contents.fill(false);
+                contents.int_contents.fill(0);
+                contents.int64_contents.fill(0);
+                contents.uint_contents.fill(0);
+                contents.uint64_contents.fill(0);
+                contents.fp32_contents.fill(0.0);
+                contents.fp64_contents.fill(0.0);
+                contents.bytes_contents.iter_mut().for_each(|b| b.fill(0));
+            }
+        }
+
+        req
+    }
+}
+
+/// Replace a parameter value with the hex encoded hash of its protobuf encoding.
+fn hash_parameter(parameter: &InferParameter) -> InferParameter {
+    let hash = Blake2s256::digest(parameter.encode_to_vec());
+
+    InferParameter {
+        parameter_choice: Some(ParameterChoice::StringParam(hex::encode(hash))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::input::tests::raw_infer_request;
+
+    #[test]
+    fn it_scrubs_requests() {
+        let mut req = raw_infer_request(vec![
+            ("INPUT0", "UINT8", vec![2], vec![1, 2]),
+            ("INPUT1", "UINT8", vec![2], vec![3, 4]),
+        ]);
+        req.id = "request-1".to_string();
+        let user_id = InferParameter {
+            parameter_choice: Some(ParameterChoice::StringParam("alice".to_string())),
+        };
+        req.parameters
+            .insert("user_id".to_string(), user_id.clone());
+        req.parameters
+            .insert("session".to_string(), user_id.clone());
+        req.parameters.insert("kept".to_string(), user_id.clone());
+
+        let config = ScrubConfig {
+            drop_parameters: vec!["session".to_string()],
+            hash_parameters: vec!["user_id".to_string()],
+            zero_input_tensors: HashMap::from([(
+                req.model_name.clone(),
+                vec!["INPUT1".to_string()],
+            )]),
+            strip_request_id: true,
+        };
+
+        let scrubbed = config.scrub(req);
+
+        assert_eq!("", scrubbed.id);
+        assert!(!scrubbed.parameters.contains_key("session"));
+        assert_eq!(Some(&user_id), scrubbed.parameters.get("kept"));
+        assert_eq!(
+            Some(&hash_parameter(&user_id)),
+            scrubbed.parameters.get("user_id")
+        );
+        assert_ne!(Some(&user_id), scrubbed.parameters.get("user_id"));
+        assert_eq!(vec![vec![1, 2], vec![0, 0]], scrubbed.raw_input_contents);
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::parsing::input::{Parameter, ProcessedInput};
+use crate::service::inference_protocol::infer_parameter::ParameterChoice;
+use crate::service::inference_protocol::{InferParameter, ModelInferResponse};
+
+/// The request parameters Triton uses for sequence batching.
+pub const SEQUENCE_ID: &str = "sequence_id";
+pub const SEQUENCE_START: &str = "sequence_start";
+pub const SEQUENCE_END: &str = "sequence_end";
+const SEQUENCE_PARAMETERS: [&str; 3] = [SEQUENCE_ID, SEQUENCE_START, SEQUENCE_END];
+
+/// The position of a request within a sequence of a stateful model.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SequenceStep {
+    // The correlation id of the sequence. Empty for incoming requests of a sequence that is not
+    // bound to a recorded sequence yet.
+    pub id: String,
+
+    // The index of the request within the sequence.
+    pub position: u64,
+}
+
+impl SequenceStep {
+    /// Check if a cached step matches an incoming step. An incoming step without an id matches
+    /// the steps of every recorded sequence.
+    pub fn matches(&self, incoming: &SequenceStep) -> bool {
+        self.position == incoming.position && (incoming.id.is_empty() || incoming.id == self.id)
+    }
+}
+
+/// Remove the sequence parameters, so they are left out of parameter matching.
+pub fn without_sequence_parameters(
+    parameters: &BTreeMap<String, Option<Parameter>>,
+) -> BTreeMap<String, Option<Parameter>> {
+    parameters
+        .iter()
+        .filter(|(key, _)| !SEQUENCE_PARAMETERS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Replace the recorded sequence id in a response parameter with the id of the incoming request.
+pub fn rewrite_sequence_id(
+    response: &mut ModelInferResponse,
+    request_parameters: &HashMap<String, InferParameter>,
+) {
+    if let (Some(parameter), Some(request_parameter)) = (
+        response.parameters.get_mut(SEQUENCE_ID),
+        request_parameters.get(SEQUENCE_ID),
+    ) {
+        *parameter = request_parameter.clone();
+    }
+}
+
+#[derive(Default)]
+struct SequenceState {
+    // The index of the last request of the sequence.
+    position: u64,
+
+    // The recorded sequence the incoming sequence replays, once its first request matched.
+    recorded_id: Option<String>,
+}
+
+/// Tracks the position of the requests within the running sequences, and in Serve mode the
+/// recorded sequence every incoming sequence replays, so recorded sequences are replayed in order.
+#[derive(Default)]
+pub struct SequenceTracker {
+    sequences: Mutex<HashMap<String, SequenceState>>,
+}
+
+impl SequenceTracker {
+    /// Set the sequence step of a processed input based on the sequence parameters of its request.
+    /// When recording, the step holds the id of the request. When serving, it holds the id of the
+    /// recorded sequence the incoming sequence is bound to, if any.
+    pub fn track(
+        &self,
+        input: &mut ProcessedInput,
+        request_parameters: &HashMap<String, InferParameter>,
+        recording: bool,
+    ) {
+        let id = match request_parameters.get(SEQUENCE_ID).and_then(sequence_id) {
+            Some(id) => id,
+            None => return,
+        };
+        let is_start = is_set(request_parameters, SEQUENCE_START);
+        let is_end = is_set(request_parameters, SEQUENCE_END);
+
+        let mut sequences = self.sequences.lock().unwrap();
+        let step = match sequences.get_mut(&id) {
+            Some(state) if !is_start => {
+                state.position += 1;
+                SequenceStep {
+                    id: state.recorded_id.clone().unwrap_or_default(),
+                    position: state.position,
+                }
+            }
+            _ => {
+                sequences.insert(id.clone(), SequenceState::default());
+                SequenceStep {
+                    id: String::new(),
+                    position: 0,
+                }
+            }
+        };
+
+        if is_end {
+            sequences.remove(&id);
+        }
+
+        input.sequence = Some(match recording {
+            true => SequenceStep { id, ..step },
+            false => step,
+        });
+    }
+
+    /// Bind an incoming sequence to the recorded sequence of the cached input it matched, so the
+    /// next requests of the sequence only match requests of the same recorded sequence.
+    pub fn bind(
+        &self,
+        request_parameters: &HashMap<String, InferParameter>,
+        cached_input: &ProcessedInput,
+    ) {
+        let (id, recorded_step) = match (
+            request_parameters.get(SEQUENCE_ID).and_then(sequence_id),
+            &cached_input.sequence,
+        ) {
+            (Some(id), Some(recorded_step)) => (id, recorded_step),
+            _ => return,
+        };
+
+        if let Some(state) = self.sequences.lock().unwrap().get_mut(&id) {
+            state.recorded_id = Some(recorded_step.id.clone());
+        }
+    }
+}
+
+/// Triton accepts both integer and string correlation ids.
+fn sequence_id(parameter: &InferParameter) -> Option<String> {
+    match &parameter.parameter_choice {
+        Some(ParameterChoice::Int64Param(id)) => Some(id.to_string()),
+        Some(ParameterChoice::Uint64Param(id)) => Some(id.to_string()),
+        Some(ParameterChoice::StringParam(id)) => Some(id.clone()),
+        _ => None,
+    }
+}
+
+fn is_set(parameters: &HashMap<String, InferParameter>, key: &str) -> bool {
+    parameters.get(key).is_some_and(|parameter| {
+        parameter.parameter_choice == Some(ParameterChoice::BoolParam(true))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::input::tests::BASE_INFER_INPUT;
+
+    fn parameters(id: i64, start: bool, end: bool) -> HashMap<String, InferParameter> {
+        HashMap::from([
+            (
+                SEQUENCE_ID.to_string(),
+                Parameter::Int64Param(id).to_infer_parameter(),
+            ),
+            (
+                SEQUENCE_START.to_string(),
+                Parameter::BoolParam(start).to_infer_parameter(),
+            ),
+            (
+                SEQUENCE_END.to_string(),
+                Parameter::BoolParam(end).to_infer_parameter(),
+            ),
+        ])
+    }
+
+    fn step(id: &str, position: u64) -> Option<SequenceStep> {
+        Some(SequenceStep {
+            id: id.to_string(),
+            position,
+        })
+    }
+
+    #[test]
+    fn it_tracks_recorded_sequences() {
+        let tracker = SequenceTracker::default();
+        let mut input = BASE_INFER_INPUT.clone();
+
+        tracker.track(&mut input, &parameters(7, true, false), true);
+        assert_eq!(step("7", 0), input.sequence);
+
+        tracker.track(&mut input, &parameters(7, false, false), true);
+        assert_eq!(step("7", 1), input.sequence);
+
+        tracker.track(&mut input, &parameters(7, false, true), true);
+        assert_eq!(step("7", 2), input.sequence);
+
+        tracker.track(&mut input, &parameters(7, true, false), true);
+        assert_eq!(step("7", 0), input.sequence);
+    }
+
+    #[test]
+    fn it_binds_served_sequences() {
+        let tracker = SequenceTracker::default();
+        let mut input = BASE_INFER_INPUT.clone();
+        let mut cached_input = BASE_INFER_INPUT.clone();
+        cached_input.sequence = step("7", 0);
+
+        tracker.track(&mut input, &parameters(42, true, false), false);
+        assert_eq!(step("", 0), input.sequence);
+        assert!(cached_input
+            .sequence
+            .as_ref()
+            .unwrap()
+            .matches(input.sequence.as_ref().unwrap()));
+
+        tracker.bind(&parameters(42, true, false), &cached_input);
+
+        tracker.track(&mut input, &parameters(42, false, false), false);
+        assert_eq!(step("7", 1), input.sequence);
+        assert!(!step("8", 1)
+            .unwrap()
+            .matches(input.sequence.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn it_rewrites_sequence_ids() {
+        let mut response = ModelInferResponse {
+            parameters: parameters(7, false, false),
+            ..Default::default()
+        };
+
+        rewrite_sequence_id(&mut response, &parameters(42, false, false));
+
+        assert_eq!(
+            Some(Parameter::Int64Param(42)),
+            Parameter::from_infer_parameter(response.parameters[SEQUENCE_ID].clone())
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use bytes::Bytes;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::parsing::input::{Parameter, ProcessedInput};
+use crate::parsing::output::ProcessedOutput;
+use crate::parsing::tensor;
+use crate::service::inference_protocol::model_infer_response::InferOutputTensor;
+use crate::service::inference_protocol::{
+    DataType, ModelConfig, ModelConfigResponse, ModelInferRequest, ModelInferResponse, ModelInput,
+    ModelMetadataResponse, ModelOutput, ModelParameter,
+};
+
+/// The response parameter that flags a response as synthesized instead of recorded.
+pub const SYNTHETIC_RESPONSE_PARAMETER: &str = "inferencestore_synthetic";
+
+/// The maximum number of elements of a synthesized output tensor. The batch dimension is taken
+/// from the request, so this bounds the memory a single request can make InferenceStore allocate.
+pub const MAX_SYNTHESIZED_ELEMENTS: usize = 1 << 24;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub enum SynthesizeResponses {
+    // Do not synthesize responses, a cache miss returns an error.
+    #[serde(alias = "disable")]
+    Disable,
+
+    // Synthesize responses with tensors that only contain zeros.
+    #[serde(alias = "zeros")]
+    Zeros,
+
+    // Synthesize responses with tensors that contain random values.
+    #[serde(alias = "random")]
+    Random,
+
+    // Synthesize responses with tensors that contain pseudo-random values seeded from the inputs,
+    // so the same input always produces the same output.
+    #[serde(alias = "deterministic")]
+    Deterministic,
+}
+
+/// The name, datatype and shape of an output tensor, as described by the model. Dynamic
+/// dimensions are -1.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OutputSpec {
+    pub name: String,
+    pub datatype: String,
+    pub shape: Vec<i64>,
+}
+
+impl OutputSpec {
+    pub fn from_metadata(metadata: &ModelMetadataResponse) -> Vec<OutputSpec> {
+        metadata
+            .outputs
+            .iter()
+            .map(|output| OutputSpec {
+                name: output.name.clone(),
+                datatype: output.datatype.clone(),
+                shape: output.shape.clone(),
+            })
+            .collect()
+    }
+
+    pub fn from_config(config: &ModelConfigResponse) -> Vec<OutputSpec> {
+        let config = match &config.config {
+            Some(config) => config,
+            None => return vec![],
+        };
+
+        config
+            .output
+            .iter()
+            .map(|output| {
+                // Models that support batching leave the batch dimension out of their dims.
+                let mut shape = if config.max_batch_size > 0 {
+                    vec![-1]
+                } else {
+                    vec![]
+                };
+                shape.extend(&output.dims);
+
+                OutputSpec {
+                    name: output.name.clone(),
+                    datatype: config_datatype(output.data_type),
+                    shape,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Build a response with correctly shaped zero or random output tensors, flagged by the
+/// `inferencestore_synthetic` parameter. Only the requested outputs are included, or all outputs
+/// when no outputs are requested. The batch dimension is taken from the first input. The seed is
+/// only used by the deterministic mode. Fails when an output tensor would have more than
+/// `MAX_SYNTHESIZED_ELEMENTS` elements.
+pub fn synthesize_response(
+    request: ModelInferRequest,
+    specs: &[OutputSpec],
+    mode: SynthesizeResponses,
+    seed: u64,
+) -> anyhow::Result<ModelInferResponse> {
+    let batch_size = request
+        .inputs
+        .first()
+        .and_then(|input| input.shape.first())
+        .copied()
+        .filter(|size| *size > 0)
+        .unwrap_or(1);
+
+    let mut random = match mode {
+        SynthesizeResponses::Deterministic => Random::from_seed(seed),
+        _ => Random::from_seed(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos() as u64),
+        ),
+    };
+    let (outputs, raw_output_contents) = specs
+        .iter()
+        .filter(|spec| {
+            request.outputs.is_empty() || request.outputs.iter().any(|o| o.name == spec.name)
+        })
+        .map(|spec| {
+            let shape: Vec<i64> = spec
+                .shape
+                .iter()
+                .enumerate()
+                .map(|(index, dim)| match (index, *dim) {
+                    (0, dim) if dim < 0 => batch_size,
+                    (_, dim) if dim < 0 => 1,
+                    (_, dim) => dim,
+                })
+                .collect();
+            let elements = shape
+                .iter()
+                .try_fold(1usize, |elements, dim| {
+                    elements.checked_mul(usize::try_from(*dim).ok()?)
+                })
+                .filter(|elements| *elements <= MAX_SYNTHESIZED_ELEMENTS)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "can not synthesize output {} of shape {shape:?}, it has more than {MAX_SYNTHESIZED_ELEMENTS} elements",
+                        spec.name
+                    )
+                })?;
+            let content = match mode {
+                SynthesizeResponses::Random | SynthesizeResponses::Deterministic => {
+                    random.content(&spec.datatype, elements)
+                }
+                _ => vec![0; elements * element_size(&spec.datatype)],
+            };
+
+            let tensor = InferOutputTensor {
+                name: spec.name.clone(),
+                datatype: spec.datatype.clone(),
+                shape,
+                parameters: Default::default(),
+                contents: None,
+            };
+
+            Ok((tensor, Bytes::from(content)))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+
+    Ok(ModelInferResponse {
+        model_name: request.model_name,
+        model_version: request.model_version,
+        id: request.id,
+        parameters: [(
+            SYNTHETIC_RESPONSE_PARAMETER.to_string(),
+            Parameter::BoolParam(true).to_infer_parameter(),
+        )]
+        .into(),
+        outputs,
+        raw_output_contents,
+    })
+}
+
+/// Build a minimal model config from the recorded entries of a model, flagged by the
+/// `inferencestore_synthetic` config parameter. The datatypes and shapes of the inputs and outputs
+/// are taken from the entries, dimensions that differ between entries are dynamic (-1). Returns
+/// None when there are no entries.
+pub fn synthesize_config(
+    model_name: &str,
+    entries: &[(ProcessedInput, ProcessedOutput)],
+) -> Option<ModelConfigResponse> {
+    if entries.is_empty() {
+        return None;
+    }
+
+    let mut inputs: Vec<(String, String, Vec<i64>)> = vec![];
+    let mut outputs: Vec<(String, String, Vec<i64>)> = vec![];
+
+    for (input, output) in entries {
+        for tensor in &input.inputs {
+            merge_tensor(&mut inputs, &tensor.name, &tensor.datatype, &tensor.shape);
+        }
+        for tensor in &output.outputs {
+            merge_tensor(&mut outputs, &tensor.name, &tensor.datatype, &tensor.shape);
+        }
+    }
+
+    Some(ModelConfigResponse {
+        config: Some(ModelConfig {
+            name: model_name.to_string(),
+            input: inputs
+                .into_iter()
+                .map(|(name, datatype, dims)| ModelInput {
+                    name,
+                    data_type: protocol_data_type(&datatype),
+                    dims,
+                    ..Default::default()
+                })
+                .collect(),
+            output: outputs
+                .into_iter()
+                .map(|(name, datatype, dims)| ModelOutput {
+                    name,
+                    data_type: protocol_data_type(&datatype),
+                    dims,
+                    ..Default::default()
+                })
+                .collect(),
+            parameters: [(
+                SYNTHETIC_RESPONSE_PARAMETER.to_string(),
+                ModelParameter {
+                    string_value: "true".to_string(),
+                },
+            )]
+            .into(),
+            ..Default::default()
+        }),
+    })
+}
+
+/// Add a tensor to the list of tensors, or make the dimensions that differ from the listed tensor
+/// with the same name dynamic.
+fn merge_tensor(
+    tensors: &mut Vec<(String, String, Vec<i64>)>,
+    name: &str,
+    datatype: &str,
+    shape: &[i64],
+) {
+    match tensors.iter_mut().find(|(n, _, _)| n == name) {
+        Some((_, _, dims)) if dims.len() != shape.len() => *dims = vec![-1; shape.len()],
+        Some((_, _, dims)) => dims
+            .iter_mut()
+            .zip(shape)
+            .filter(|(dim, other)| dim != other)
+            .for_each(|(dim, _)| *dim = -1),
+        None => tensors.push((name.to_string(), datatype.to_string(), shape.to_vec())),
+    }
+}
+
+/// Convert an inference protocol datatype, e.g. FP32, to a model config data type.
+fn protocol_data_type(datatype: &str) -> i32 {
+    let data_type = match datatype {
+        "BYTES" => Some(DataType::TypeString),
+        datatype => DataType::from_str_name(&format!("TYPE_{datatype}")),
+    };
+
+    data_type.unwrap_or(DataType::TypeInvalid) as i32
+}
+
+/// Convert a model config data type, e.g. TYPE_FP32, to an inference protocol datatype.
+fn config_datatype(data_type: i32) -> String {
+    match DataType::try_from(data_type) {
+        Ok(DataType::TypeString) => "BYTES".to_string(),
+        Ok(data_type) => data_type
+            .as_str_name()
+            .trim_start_matches("TYPE_")
+            .to_string(),
+        Err(_) => "BYTES".to_string(),
+    }
+}
+
+/// The size of an element in bytes. BYTES elements are synthesized as empty strings, which only
+/// consist of their 4 byte length prefix.
+pub(crate) fn element_size(datatype: &str) -> usize {
+    tensor::element_size(datatype).unwrap_or(4)
+}
+
+/// A xorshift generator, random enough for smoke tests.
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn from_seed(seed: u64) -> Self {
+        // A xorshift generator never leaves a zero state.
+        Random(seed | 1)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A float in [0, 1).
+    fn next_float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Random content for a tensor. Floats are in [0, 1) and BYTES elements are empty.
+    pub(crate) fn content(&mut self, datatype: &str, elements: usize) -> Vec<u8> {
+        let mut content = Vec::with_capacity(elements * element_size(datatype));
+
+        for _ in 0..elements {
+            match datatype {
+                "BOOL" => content.push((self.next() & 1) as u8),
+                "FP32" => content.extend((self.next_float() as f32).to_le_bytes()),
+                "FP64" => content.extend(self.next_float().to_le_bytes()),
+                // The upper half of an FP32.
+                "BF16" => content.extend(&(self.next_float() as f32).to_le_bytes()[2..4]),
+                // An exponent of -1 with a random mantissa, in [0.5, 1).
+                "FP16" => content.extend((0x3800 | (self.next() & 0x3ff) as u16).to_le_bytes()),
+                "BYTES" => content.extend(0u32.to_le_bytes()),
+                datatype => content.extend(&self.next().to_le_bytes()[..element_size(datatype)]),
+            }
+        }
+
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::input::tests::raw_infer_request;
+    use crate::service::inference_protocol::model_infer_request::InferRequestedOutputTensor;
+
+    fn specs() -> Vec<OutputSpec> {
+        vec![
+            OutputSpec {
+                name: "OUTPUT0".to_string(),
+                datatype: "FP32".to_string(),
+                shape: vec![-1, 3],
+            },
+            OutputSpec {
+                name: "OUTPUT1".to_string(),
+                datatype: "INT64".to_string(),
+                shape: vec![-1, -1],
+            },
+        ]
+    }
+
+    #[test]
+    fn it_synthesizes_zero_responses() {
+        let request = raw_infer_request(vec![("INPUT0", "FP32", vec![2, 3], vec![0; 24])]);
+
+        let response =
+            synthesize_response(request, &specs(), SynthesizeResponses::Zeros, 0).unwrap();
+
+        assert_eq!(vec![2, 3], response.outputs[0].shape);
+        assert_eq!(vec![2, 1], response.outputs[1].shape);
+        assert_eq!(vec![vec![0; 24], vec![0; 16]], response.raw_output_contents);
+        assert_eq!(
+            Some(Parameter::BoolParam(true)),
+            Parameter::from_infer_parameter(
+                response.parameters[SYNTHETIC_RESPONSE_PARAMETER].clone()
+            )
+        );
+    }
+
+    #[test]
+    fn it_synthesizes_requested_random_outputs() {
+        let mut request = raw_infer_request(vec![("INPUT0", "FP32", vec![2, 3], vec![0; 24])]);
+        request.outputs = vec![InferRequestedOutputTensor {
+            name: "OUTPUT0".to_string(),
+            parameters: Default::default(),
+        }];
+
+        let response =
+            synthesize_response(request, &specs(), SynthesizeResponses::Random, 0).unwrap();
+
+        assert_eq!(1, response.outputs.len());
+        assert_eq!(24, response.raw_output_contents[0].len());
+        assert!(response.raw_output_contents[0]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .all(|value| (0.0..1.0).contains(&value)));
+    }
+
+    #[test]
+    fn it_synthesizes_deterministic_responses() {
+        let request = raw_infer_request(vec![("INPUT0", "FP32", vec![2, 3], vec![0; 24])]);
+        let synthesize = |seed| {
+            synthesize_response(
+                request.clone(),
+                &specs(),
+                SynthesizeResponses::Deterministic,
+                seed,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(synthesize(1234), synthesize(1234));
+        assert_ne!(
+            synthesize(1234).raw_output_contents,
+            synthesize(4321).raw_output_contents
+        );
+    }
+
+    #[test]
+    fn it_refuses_to_synthesize_oversized_outputs() {
+        let request = raw_infer_request(vec![("INPUT0", "FP32", vec![1 << 62, 3], vec![])]);
+
+        assert!(synthesize_response(request, &specs(), SynthesizeResponses::Zeros, 0).is_err());
+    }
+
+    #[test]
+    fn it_converts_config_datatypes() {
+        assert_eq!("FP32", config_datatype(DataType::TypeFp32 as i32));
+        assert_eq!("BYTES", config_datatype(DataType::TypeString as i32));
+        assert_eq!(DataType::TypeFp32 as i32, protocol_data_type("FP32"));
+        assert_eq!(DataType::TypeString as i32, protocol_data_type("BYTES"));
+    }
+
+    #[test]
+    fn it_synthesizes_configs_from_entries() {
+        let entry = |batch_size| {
+            let request = raw_infer_request(vec![(
+                "INPUT0",
+                "FP32",
+                vec![batch_size, 3],
+                vec![0; batch_size as usize * 12],
+            )]);
+            let response =
+                synthesize_response(request.clone(), &specs(), SynthesizeResponses::Zeros, 0)
+                    .unwrap();
+
+            (
+                ProcessedInput::from_infer_request(request, &Default::default()),
+                ProcessedOutput::from_response(&response),
+            )
+        };
+
+        assert_eq!(None, synthesize_config("test", &[]));
+
+        let config = synthesize_config("test", &[entry(1), entry(2)])
+            .unwrap()
+            .config
+            .unwrap();
+
+        assert_eq!("test", config.name);
+        assert_eq!(vec![-1, 3], config.input[0].dims);
+        assert_eq!(DataType::TypeFp32 as i32, config.input[0].data_type);
+        assert_eq!(
+            vec![("OUTPUT0", vec![-1, 3]), ("OUTPUT1", vec![-1, 1])],
+            config
+                .output
+                .iter()
+                .map(|output| (output.name.as_str(), output.dims.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert!(config.parameters.contains_key(SYNTHETIC_RESPONSE_PARAMETER));
+    }
+}
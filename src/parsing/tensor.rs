@@ -0,0 +1,244 @@
+use half::{bf16, f16};
+
+/// The size of an element of a fixed-size datatype in bytes. Returns None for BYTES, of which the
+/// elements vary in size, and for unknown datatypes.
+pub fn element_size(datatype: &str) -> Option<usize> {
+    match datatype {
+        "BOOL" | "INT8" | "UINT8" => Some(1),
+        "INT16" | "UINT16" | "FP16" | "BF16" => Some(2),
+        "INT32" | "UINT32" | "FP32" => Some(4),
+        "INT64" | "UINT64" | "FP64" => Some(8),
+        _ => None,
+    }
+}
+
+/// Split raw content in elements of N bytes, or None when it is not a whole number of elements.
+fn chunks<const N: usize>(content: &[u8]) -> Option<impl Iterator<Item = [u8; N]> + '_> {
+    let chunks = content.chunks_exact(N);
+
+    chunks
+        .remainder()
+        .is_empty()
+        .then(|| chunks.map(|chunk| chunk.try_into().unwrap()))
+}
+
+/// Decode the raw little-endian content of a numeric or BOOL tensor to floats. Returns None for
+/// BYTES and unknown datatypes, and for content that is not a whole number of elements.
+pub fn decode_f64(datatype: &str, content: &[u8]) -> Option<Vec<f64>> {
+    Some(match datatype {
+        "BOOL" | "UINT8" => content.iter().map(|&b| b as f64).collect(),
+        "INT8" => content.iter().map(|&b| b as i8 as f64).collect(),
+        "INT16" => chunks(content)?
+            .map(|b| i16::from_le_bytes(b) as f64)
+            .collect(),
+        "UINT16" => chunks(content)?
+            .map(|b| u16::from_le_bytes(b) as f64)
+            .collect(),
+        "INT32" => chunks(content)?
+            .map(|b| i32::from_le_bytes(b) as f64)
+            .collect(),
+        "UINT32" => chunks(content)?
+            .map(|b| u32::from_le_bytes(b) as f64)
+            .collect(),
+        "INT64" => chunks(content)?
+            .map(|b| i64::from_le_bytes(b) as f64)
+            .collect(),
+        "UINT64" => chunks(content)?
+            .map(|b| u64::from_le_bytes(b) as f64)
+            .collect(),
+        "FP16" => chunks(content)?
+            .map(|b| f16::from_le_bytes(b).to_f64())
+            .collect(),
+        "BF16" => chunks(content)?
+            .map(|b| bf16::from_le_bytes(b).to_f64())
+            .collect(),
+        "FP32" => chunks(content)?
+            .map(|b| f32::from_le_bytes(b) as f64)
+            .collect(),
+        "FP64" => chunks(content)?.map(f64::from_le_bytes).collect(),
+        _ => return None,
+    })
+}
+
+/// Decode the raw content of a numeric or BOOL tensor to single precision floats, see
+/// `decode_f64`.
+pub fn decode_f32(datatype: &str, content: &[u8]) -> Option<Vec<f32>> {
+    decode_f64(datatype, content).map(|values| values.into_iter().map(|v| v as f32).collect())
+}
+
+/// Decode the raw little-endian content of an integer or BOOL tensor. UINT64 values above the
+/// maximum of i64 wrap around. Returns None for float, BYTES and unknown datatypes.
+pub fn decode_i64(datatype: &str, content: &[u8]) -> Option<Vec<i64>> {
+    Some(match datatype {
+        "BOOL" | "UINT8" => content.iter().map(|&b| b as i64).collect(),
+        "INT8" => content.iter().map(|&b| b as i8 as i64).collect(),
+        "INT16" => chunks(content)?
+            .map(|b| i16::from_le_bytes(b) as i64)
+            .collect(),
+        "UINT16" => chunks(content)?
+            .map(|b| u16::from_le_bytes(b) as i64)
+            .collect(),
+        "INT32" => chunks(content)?
+            .map(|b| i32::from_le_bytes(b) as i64)
+            .collect(),
+        "UINT32" => chunks(content)?
+            .map(|b| u32::from_le_bytes(b) as i64)
+            .collect(),
+        "INT64" => chunks(content)?.map(i64::from_le_bytes).collect(),
+        "UINT64" => chunks(content)?
+            .map(|b| u64::from_le_bytes(b) as i64)
+            .collect(),
+        _ => return None,
+    })
+}
+
+/// Encode floats as the raw content of a numeric or BOOL tensor. Values are converted like `as`
+/// casts, e.g. floats are truncated and saturated for integer datatypes, and BOOL elements are
+/// true for values other than zero. Returns None for BYTES and unknown datatypes.
+pub fn encode_f64(datatype: &str, values: &[f64]) -> Option<Vec<u8>> {
+    let values = values.iter().copied();
+
+    Some(match datatype {
+        "BOOL" => values.map(|v| (v != 0.0) as u8).collect(),
+        "UINT8" => values.map(|v| v as u8).collect(),
+        "INT8" => values.map(|v| v as i8 as u8).collect(),
+        "INT16" => values.flat_map(|v| (v as i16).to_le_bytes()).collect(),
+        "UINT16" => values.flat_map(|v| (v as u16).to_le_bytes()).collect(),
+        "INT32" => values.flat_map(|v| (v as i32).to_le_bytes()).collect(),
+        "UINT32" => values.flat_map(|v| (v as u32).to_le_bytes()).collect(),
+        "INT64" => values.flat_map(|v| (v as i64).to_le_bytes()).collect(),
+        "UINT64" => values.flat_map(|v| (v as u64).to_le_bytes()).collect(),
+        "FP16" => values
+            .flat_map(|v| f16::from_f64(v).to_le_bytes())
+            .collect(),
+        "BF16" => values
+            .flat_map(|v| bf16::from_f64(v).to_le_bytes())
+            .collect(),
+        "FP32" => values.flat_map(|v| (v as f32).to_le_bytes()).collect(),
+        "FP64" => values.flat_map(f64::to_le_bytes).collect(),
+        _ => return None,
+    })
+}
+
+/// Encode single precision floats as the raw content of a numeric or BOOL tensor, see
+/// `encode_f64`.
+pub fn encode_f32(datatype: &str, values: &[f32]) -> Option<Vec<u8>> {
+    encode_f64(
+        datatype,
+        &values.iter().map(|&v| v as f64).collect::<Vec<_>>(),
+    )
+}
+
+/// Encode integers as the raw content of a numeric or BOOL tensor. Values that do not fit the
+/// datatype wrap around. Returns None for BYTES and unknown datatypes.
+pub fn encode_i64(datatype: &str, values: &[i64]) -> Option<Vec<u8>> {
+    let values = values.iter().copied();
+
+    Some(match datatype {
+        "BOOL" => values.map(|v| (v != 0) as u8).collect(),
+        "UINT8" | "INT8" => values.map(|v| v as u8).collect(),
+        "INT16" | "UINT16" => values.flat_map(|v| (v as u16).to_le_bytes()).collect(),
+        "INT32" | "UINT32" => values.flat_map(|v| (v as u32).to_le_bytes()).collect(),
+        "INT64" | "UINT64" => values.flat_map(i64::to_le_bytes).collect(),
+        "FP16" | "BF16" | "FP32" | "FP64" => {
+            return encode_f64(datatype, &values.map(|v| v as f64).collect::<Vec<_>>())
+        }
+        _ => return None,
+    })
+}
+
+/// Decode a raw BYTES tensor, where every element is prefixed with its 4-byte little-endian length.
+/// Returns None when the content is not a valid BYTES tensor.
+pub fn decode_bytes_elements(content: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut elements = vec![];
+    let mut rest = content;
+
+    while !rest.is_empty() {
+        let length = u32::from_le_bytes(rest.get(0..4)?.try_into().unwrap()) as usize;
+        elements.push(rest.get(4..4 + length)?.to_vec());
+        rest = &rest[4 + length..];
+    }
+
+    Some(elements)
+}
+
+/// Encode BYTES elements in the raw format, prefixing every element with its length.
+pub fn encode_bytes_elements(elements: &[Vec<u8>]) -> Vec<u8> {
+    elements
+        .iter()
+        .flat_map(|element| {
+            let mut encoded = (element.len() as u32).to_le_bytes().to_vec();
+            encoded.extend_from_slice(element);
+            encoded
+        })
+        .collect()
+}
+
+/// Decode a raw BYTES tensor of which the elements are UTF-8 strings. Returns None when the
+/// content is not a valid BYTES tensor, or an element is not valid UTF-8.
+pub fn decode_strings(content: &[u8]) -> Option<Vec<String>> {
+    decode_bytes_elements(content)?
+        .into_iter()
+        .map(|element| String::from_utf8(element).ok())
+        .collect()
+}
+
+/// Encode strings as a raw BYTES tensor.
+pub fn encode_strings<S: AsRef<str>>(strings: &[S]) -> Vec<u8> {
+    encode_bytes_elements(
+        &strings
+            .iter()
+            .map(|s| s.as_ref().as_bytes().to_vec())
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_and_encodes_numbers() {
+        for datatype in [
+            "BOOL", "INT8", "UINT8", "INT16", "UINT16", "INT32", "UINT32", "INT64", "UINT64",
+            "FP16", "BF16", "FP32", "FP64",
+        ] {
+            let content = encode_f64(datatype, &[0.0, 1.0, 0.0]).unwrap();
+            assert_eq!(3 * element_size(datatype).unwrap(), content.len());
+            assert_eq!(
+                Some(vec![0.0, 1.0, 0.0]),
+                decode_f64(datatype, &content),
+                "{datatype}"
+            );
+        }
+
+        assert_eq!(
+            Some(vec![-2, 3]),
+            decode_i64("INT16", &encode_i64("INT16", &[-2, 3]).unwrap())
+        );
+        assert_eq!(Some(vec![255, 1]), decode_i64("UINT8", &[255, 1]));
+        assert_eq!(Some(vec![-1, 1]), decode_i64("INT8", &[255, 1]));
+        assert_eq!(None, decode_i64("FP32", &[0; 4]));
+
+        assert_eq!(
+            Some(vec![1.5, -0.25]),
+            decode_f32("FP16", &encode_f32("FP16", &[1.5, -0.25]).unwrap())
+        );
+        assert_eq!(Some(vec![0x80, 0x3f]), encode_f32("BF16", &[1.0]));
+        assert_eq!(Some(vec![2.0]), decode_f64("FP32", &2f32.to_le_bytes()));
+        assert_eq!(None, decode_f64("FP32", &[0; 3]));
+        assert_eq!(None, decode_f64("BYTES", &[]));
+        assert_eq!(None, encode_f64("FP8", &[1.0]));
+    }
+
+    #[test]
+    fn it_decodes_and_encodes_strings() {
+        let content = encode_bytes_elements(&[b"hello".to_vec(), vec![]]);
+        assert_eq!(content, encode_strings(&["hello", ""]));
+        assert_eq!(
+            Some(vec!["hello".to_string(), "".to_string()]),
+            decode_strings(&content)
+        );
+        assert_eq!(None, decode_strings(&encode_bytes_elements(&[vec![0xff]])));
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt;
+
+use crate::parsing::output::ProcessedOutput;
+use crate::parsing::tensor::decode_f64;
+
+/// The number of differing element indices that are reported per tensor.
+pub const FIRST_MISMATCHES: usize = 5;
+
+/// The differences between two recorded outputs of the same request.
+#[derive(PartialEq, Debug, Default)]
+pub struct OutputDiff {
+    // Whether the response parameters differ.
+    pub parameters: bool,
+
+    // Whether the recorded error statuses differ.
+    pub error: bool,
+
+    pub tensors: Vec<TensorDiff>,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct TensorDiff {
+    pub name: String,
+    pub change: TensorChange,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum TensorChange {
+    Added,
+    Removed,
+    Datatype(String, String),
+    Shape(Vec<i64>, Vec<i64>),
+    Parameters,
+    // Numeric elements that differ by more than the tolerance.
+    Values {
+        mismatches: usize,
+        elements: usize,
+        max_abs_error: f64,
+        first_mismatches: Vec<usize>,
+    },
+    // The content of a non-numeric tensor, e.g. BYTES, differs.
+    Content,
+}
+
+impl OutputDiff {
+    pub fn is_empty(&self) -> bool {
+        !self.parameters && !self.error && self.tensors.is_empty()
+    }
+
+    /// A human-readable summary, one line per change.
+    pub fn lines(&self) -> Vec<String> {
+        let parameters = self.parameters.then(|| "parameters changed".to_string());
+        let error = self.error.then(|| "error status changed".to_string());
+
+        parameters
+            .into_iter()
+            .chain(error)
+            .chain(self.tensors.iter().map(TensorDiff::to_string))
+            .collect()
+    }
+}
+
+impl fmt::Display for TensorDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = &self.name;
+
+        match &self.change {
+            TensorChange::Added => write!(f, "{name}: added"),
+            TensorChange::Removed => write!(f, "{name}: removed"),
+            TensorChange::Datatype(a, b) => write!(f, "{name}: datatype {a} -> {b}"),
+            TensorChange::Shape(a, b) => write!(f, "{name}: shape {a:?} -> {b:?}"),
+            TensorChange::Parameters => write!(f, "{name}: parameters changed"),
+            TensorChange::Values {
+                mismatches,
+                elements,
+                max_abs_error,
+                first_mismatches,
+            } => write!(
+                f,
+                "{name}: {mismatches} of {elements} elements differ, max abs error {max_abs_error}, first at {first_mismatches:?}"
+            ),
+            TensorChange::Content => write!(f, "{name}: content changed"),
+        }
+    }
+}
+
+/// Compare two outputs tensor by tensor. Numeric elements that differ by at most `tolerance` are
+/// considered equal, other tensors are compared byte by byte.
+pub fn diff_outputs(a: &ProcessedOutput, b: &ProcessedOutput, tolerance: f64) -> OutputDiff {
+    let mut diff = OutputDiff {
+        parameters: a.parameters != b.parameters,
+        error: a.error != b.error,
+        tensors: vec![],
+    };
+    let mut push = |name: &str, change| {
+        diff.tensors.push(TensorDiff {
+            name: name.to_string(),
+            change,
+        })
+    };
+
+    for (index, output_a) in a.outputs.iter().enumerate() {
+        let name = &output_a.name;
+        let (index_b, output_b) = match b.outputs.iter().enumerate().find(|(_, o)| o.name == *name)
+        {
+            Some(output_b) => output_b,
+            None => {
+                push(name, TensorChange::Removed);
+                continue;
+            }
+        };
+
+        if output_a.datatype != output_b.datatype {
+            let datatypes = (output_a.datatype.clone(), output_b.datatype.clone());
+            push(name, TensorChange::Datatype(datatypes.0, datatypes.1));
+            continue;
+        }
+        if output_a.shape != output_b.shape {
+            let shapes = (output_a.shape.clone(), output_b.shape.clone());
+            push(name, TensorChange::Shape(shapes.0, shapes.1));
+            continue;
+        }
+        if output_a.parameters != output_b.parameters {
+            push(name, TensorChange::Parameters);
+        }
+
+        let content_a = a.raw_output_contents.get(index).map(|c| &c[..]);
+        let content_b = b.raw_output_contents.get(index_b).map(|c| &c[..]);
+        let (content_a, content_b) = (content_a.unwrap_or_default(), content_b.unwrap_or_default());
+
+        match (
+            decode_f64(&output_a.datatype, content_a),
+            decode_f64(&output_b.datatype, content_b),
+        ) {
+            (Some(values_a), Some(values_b)) if values_a.len() == values_b.len() => {
+                if let Some(change) = diff_values(&values_a, &values_b, tolerance) {
+                    push(name, change);
+                }
+            }
+            _ if content_a != content_b => push(name, TensorChange::Content),
+            _ => {}
+        }
+    }
+
+    for output_b in &b.outputs {
+        if !a.outputs.iter().any(|o| o.name == output_b.name) {
+            push(&output_b.name, TensorChange::Added);
+        }
+    }
+
+    diff
+}
+
+/// Compare numeric elements, returns None when all elements are within the tolerance.
+pub fn diff_values(values_a: &[f64], values_b: &[f64], tolerance: f64) -> Option<TensorChange> {
+    let mut mismatches = 0;
+    let mut max_abs_error = 0f64;
+    let mut first_mismatches = vec![];
+
+    for (index, (a, b)) in values_a.iter().zip(values_b).enumerate() {
+        // Elements that are NaN in both tensors, or the same infinity, are equal.
+        if a == b || (a.is_nan() && b.is_nan()) {
+            continue;
+        }
+        let error = (a - b).abs();
+        // NaN errors, e.g. a NaN element that became a number, are mismatches as well.
+        if error.is_nan() || error > tolerance {
+            mismatches += 1;
+            max_abs_error = max_abs_error.max(error);
+            if first_mismatches.len() < FIRST_MISMATCHES {
+                first_mismatches.push(index);
+            }
+        }
+    }
+
+    (mismatches > 0).then_some(TensorChange::Values {
+        mismatches,
+        elements: values_a.len(),
+        max_abs_error,
+        first_mismatches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::output::Output;
+    use crate::parsing::tensor::encode_f32;
+
+    fn output(name: &str, datatype: &str, shape: Vec<i64>, content: Vec<u8>) -> ProcessedOutput {
+        ProcessedOutput {
+            parameters: Default::default(),
+            outputs: vec![Output {
+                parameters: Default::default(),
+                name: name.to_string(),
+                datatype: datatype.to_string(),
+                shape,
+            }],
+            raw_output_contents: vec![content.into()],
+            error: None,
+            metadata: Default::default(),
+            raw_response: None,
+        }
+    }
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn it_summarizes_value_differences() {
+        let a = output("OUTPUT0", "FP32", vec![4], floats(&[1.0, 2.0, 3.0, 4.0]));
+        let b = output("OUTPUT0", "FP32", vec![4], floats(&[1.0, 2.01, 3.5, 5.0]));
+
+        let diff = diff_outputs(&a, &b, 0.1);
+
+        assert_eq!(
+            vec![TensorDiff {
+                name: "OUTPUT0".to_string(),
+                change: TensorChange::Values {
+                    mismatches: 2,
+                    elements: 4,
+                    max_abs_error: 1.0,
+                    first_mismatches: vec![2, 3],
+                },
+            }],
+            diff.tensors
+        );
+        assert_eq!(
+            vec!["OUTPUT0: 2 of 4 elements differ, max abs error 1, first at [2, 3]"],
+            diff.lines()
+        );
+        assert!(diff_outputs(&a, &a, 0.0).is_empty());
+    }
+
+    #[test]
+    fn it_treats_nan_in_both_tensors_as_equal() {
+        assert!(diff_values(&[f64::NAN, f64::INFINITY], &[f64::NAN, f64::INFINITY], 0.0).is_none());
+        assert!(diff_values(&[f64::NAN], &[1.0], 0.0).is_some());
+        assert!(diff_values(&[f64::INFINITY], &[f64::NEG_INFINITY], 0.0).is_some());
+    }
+
+    #[test]
+    fn it_compares_half_precision_outputs() {
+        for datatype in ["FP16", "BF16"] {
+            let half = |values: &[f32]| encode_f32(datatype, values).unwrap();
+            let a = output("OUTPUT0", datatype, vec![2], half(&[1.0, 2.0]));
+            let b = output("OUTPUT0", datatype, vec![2], half(&[1.0, 2.5]));
+
+            assert_eq!(
+                vec!["OUTPUT0: 1 of 2 elements differ, max abs error 0.5, first at [1]"],
+                diff_outputs(&a, &b, 0.1).lines()
+            );
+            assert!(diff_outputs(&a, &b, 0.5).is_empty());
+        }
+    }
+
+    #[test]
+    fn it_reports_structural_changes() {
+        let a = output("OUTPUT0", "FP32", vec![1], floats(&[1.0]));
+
+        assert_eq!(
+            vec!["OUTPUT0: shape [1] -> [2]"],
+            diff_outputs(
+                &a,
+                &output("OUTPUT0", "FP32", vec![2], floats(&[1.0, 1.0])),
+                0.0
+            )
+            .lines()
+        );
+        assert_eq!(
+            vec!["OUTPUT0: removed", "OUTPUT1: added"],
+            diff_outputs(&a, &output("OUTPUT1", "FP32", vec![1], floats(&[1.0])), 0.0).lines()
+        );
+        assert_eq!(
+            vec!["OUTPUT0: content changed"],
+            diff_outputs(
+                &output("OUTPUT0", "BYTES", vec![1], vec![1, 0, 0, 0, b'a']),
+                &output("OUTPUT0", "BYTES", vec![1], vec![1, 0, 0, 0, b'b']),
+                0.0
+            )
+            .lines()
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tonic::transport::{Channel, Endpoint};
+
+use crate::service::inference_protocol::grpc_inference_service_client::GrpcInferenceServiceClient;
+
+/// A pool of channels to the target server, each with its own HTTP/2 connection. Calls are spread
+/// over the channels round-robin, so a single connection does not limit the throughput.
+#[derive(Clone)]
+pub struct ChannelPool {
+    channels: Arc<Vec<Channel>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl ChannelPool {
+    /// Open `size` channels to the endpoint. All connections are established before returning,
+    /// so the first requests do not wait for them.
+    pub async fn connect(endpoint: Endpoint, size: usize) -> Result<Self, tonic::transport::Error> {
+        let mut channels = Vec::with_capacity(size.max(1));
+        for _ in 0..size.max(1) {
+            channels.push(endpoint.connect().await?);
+        }
+
+        Ok(Self::new(channels))
+    }
+
+    pub fn new(channels: Vec<Channel>) -> Self {
+        assert!(
+            !channels.is_empty(),
+            "a channel pool needs at least one channel"
+        );
+        ChannelPool {
+            channels: Arc::new(channels),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// A client on the next channel of the pool.
+    pub fn client(&self) -> GrpcInferenceServiceClient<Channel> {
+        GrpcInferenceServiceClient::new(self.channel())
+    }
+
+    /// The next channel of the pool, for calls that are not made through the generated client.
+    pub fn channel(&self) -> Channel {
+        self.channels[self.next_index()].clone()
+    }
+
+    fn next_index(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % self.channels.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_uses_the_channels_round_robin() {
+        let endpoint = Endpoint::from_static("http://localhost:8001");
+        let pool = ChannelPool::new((0..3).map(|_| endpoint.connect_lazy()).collect());
+
+        assert_eq!(3, pool.len());
+        let indices: Vec<usize> = (0..5).map(|_| pool.next_index()).collect();
+        assert_eq!(vec![0, 1, 2, 0, 1], indices);
+    }
+}
@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+use tonic::Status;
+
+/// Limits the number of concurrent calls to the target server. Calls beyond the limit wait in a
+/// bounded queue, where calls with a higher priority go first, and calls with the same priority
+/// in arrival order. Calls that do not fit in the queue are rejected with RESOURCE_EXHAUSTED.
+pub struct UpstreamQueue {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    concurrency: usize,
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    arrivals: u64,
+    waiting: BinaryHeap<Waiter>,
+}
+
+struct Waiter {
+    priority: i64,
+    arrival: u64,
+    sender: oneshot::Sender<Permit>,
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.arrival.cmp(&self.arrival))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+/// Allows a single call to the target server, the next call is let through when it is dropped.
+pub struct Permit {
+    inner: Option<Arc<Inner>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            inner.release();
+        }
+    }
+}
+
+impl UpstreamQueue {
+    /// Allow `concurrency` concurrent calls, with at most `capacity` calls waiting.
+    pub fn new(concurrency: usize, capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                concurrency: concurrency.max(1),
+                capacity,
+                state: Default::default(),
+            }),
+        }
+    }
+
+    /// Wait until a call with the priority may be made, or fail when the queue is full.
+    pub async fn acquire(&self, priority: i64) -> Result<Permit, Status> {
+        let receiver = {
+            let mut state = self.inner.state.lock().expect("queue lock is not poisoned");
+            if state.running < self.inner.concurrency {
+                state.running += 1;
+                return Ok(Permit {
+                    inner: Some(self.inner.clone()),
+                });
+            }
+
+            // Calls of which the client went away do not take up space.
+            state.waiting.retain(|waiter| !waiter.sender.is_closed());
+            if state.waiting.len() >= self.inner.capacity {
+                return Err(Status::resource_exhausted(
+                    "too many requests are waiting for the target server",
+                ));
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            state.arrivals += 1;
+            let arrival = state.arrivals;
+            state.waiting.push(Waiter {
+                priority,
+                arrival,
+                sender,
+            });
+            receiver
+        };
+
+        receiver
+            .await
+            .map_err(|_| Status::unavailable("the upstream queue was dropped"))
+    }
+}
+
+impl Inner {
+    /// Hand the slot of a finished call to the next waiting call, or free it.
+    fn release(self: Arc<Self>) {
+        loop {
+            let waiter = {
+                let mut state = self.state.lock().expect("queue lock is not poisoned");
+                match state.waiting.pop() {
+                    Some(waiter) => waiter,
+                    None => {
+                        state.running -= 1;
+                        return;
+                    }
+                }
+            };
+
+            let permit = Permit {
+                inner: Some(self.clone()),
+            };
+            match waiter.sender.send(permit) {
+                Ok(()) => return,
+                // The waiting call was cancelled, try the next one without releasing again.
+                Err(mut permit) => {
+                    permit.inner = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_lets_higher_priorities_go_first() {
+        let queue = Arc::new(UpstreamQueue::new(1, 2));
+        let running = queue.acquire(0).await.unwrap();
+
+        let (order_sender, mut order) = tokio::sync::mpsc::unbounded_channel();
+        for priority in [0, 5] {
+            let queue = queue.clone();
+            let order_sender = order_sender.clone();
+            tokio::spawn(async move {
+                let _permit = queue.acquire(priority).await.unwrap();
+                order_sender.send(priority).unwrap();
+            });
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(
+            tonic::Code::ResourceExhausted,
+            queue.acquire(10).await.err().unwrap().code()
+        );
+
+        drop(running);
+        assert_eq!(Some(5), order.recv().await);
+        assert_eq!(Some(0), order.recv().await);
+        assert!(queue.acquire(0).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_skips_cancelled_calls() {
+        let queue = UpstreamQueue::new(1, 1);
+        let running = queue.acquire(0).await.unwrap();
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), queue.acquire(0)).await;
+        assert!(cancelled.is_err());
+
+        // The slot is not handed to the cancelled call, and the call does not take up space.
+        drop(running);
+        assert_eq!(0, queue.inner.state.lock().unwrap().running);
+        let _running = queue.acquire(0).await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_millis(10), queue.acquire(0)).await;
+        assert!(waiting.is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use std::convert::Infallible;
+
+use bytes::{Buf, BufMut, Bytes};
+use prost::encoding::{hash_map, message, string};
+use prost::Message;
+use tonic::body::BoxBody;
+use tonic::client::Grpc;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder, ProstCodec};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::codegen::{http, Arc, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{NamedService, UnaryService};
+use tonic::transport::Channel;
+use tonic::{Request, Response, Status};
+
+use crate::error::ProxyError;
+use crate::service::inference_protocol::grpc_inference_service_server::{
+    GrpcInferenceService, GrpcInferenceServiceServer,
+};
+use crate::service::inference_protocol::{ModelInferRequest, ModelInferResponse};
+
+const MODEL_INFER_PATH: &str = "/inference.GRPCInferenceService/ModelInfer";
+
+/// A codec that encodes requests like the generated client, but keeps responses as the bytes they
+/// were encoded in.
+#[derive(Default)]
+struct RawResponseCodec;
+
+impl Codec for RawResponseCodec {
+    type Encode = ModelInferRequest;
+    type Decode = Bytes;
+    type Encoder = <ProstCodec<ModelInferRequest, ModelInferResponse> as Codec>::Encoder;
+    type Decoder = RawDecoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        ProstCodec::<ModelInferRequest, ModelInferResponse>::default().encoder()
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        RawDecoder
+    }
+}
+
+struct RawDecoder;
+
+impl Decoder for RawDecoder {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Bytes>, Status> {
+        Ok(Some(src.copy_to_bytes(src.remaining())))
+    }
+}
+
+/// Call ModelInfer on the target server, and return the response together with the exact bytes
+/// the target server encoded it in, including fields that are unknown to this version.
+pub async fn model_infer(
+    channel: Channel,
+    request: Request<ModelInferRequest>,
+) -> Result<(Response<ModelInferResponse>, Bytes), Status> {
+    let mut grpc = Grpc::new(channel);
+    grpc.ready()
+        .await
+        .map_err(|err| ProxyError::TargetUnavailable(format!("Service was not ready: {err}")))?;
+
+    let response = grpc
+        .unary(
+            request,
+            PathAndQuery::from_static(MODEL_INFER_PATH),
+            RawResponseCodec,
+        )
+        .await?;
+    let (metadata, raw_response, extensions) = response.into_parts();
+    let message = ModelInferResponse::decode(raw_response.clone())
+        .map_err(|err| ProxyError::Serialization(anyhow::Error::new(err)))?;
+
+    Ok((
+        Response::from_parts(metadata, message, extensions),
+        raw_response,
+    ))
+}
+
+/// The bytes a `ModelInfer` response was decoded from, attached to its extensions so
+/// `RawResponseServer` sends them as the target server encoded them, including fields that are
+/// unknown to this version.
+#[derive(Clone)]
+pub struct RawResponse(pub Bytes);
+
+/// A `ModelInfer` response as it is sent to the client: the raw response it was decoded from when
+/// attached, otherwise the message itself.
+pub struct ServedResponse {
+    message: ModelInferResponse,
+    raw_response: Option<Bytes>,
+}
+
+/// A codec that decodes requests like the generated server, and encodes responses as their raw
+/// response when they have one.
+struct PassThroughCodec;
+
+impl Codec for PassThroughCodec {
+    type Encode = ServedResponse;
+    type Decode = ModelInferRequest;
+    type Encoder = PassThroughEncoder;
+    type Decoder = <ProstCodec<ModelInferResponse, ModelInferRequest> as Codec>::Decoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        PassThroughEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        ProstCodec::<ModelInferResponse, ModelInferRequest>::default().decoder()
+    }
+}
+
+struct PassThroughEncoder;
+
+impl Encoder for PassThroughEncoder {
+    type Item = ServedResponse;
+    type Error = Status;
+
+    fn encode(&mut self, item: ServedResponse, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        let ServedResponse {
+            message,
+            raw_response,
+        } = item;
+        match raw_response {
+            Some(raw_response) => {
+                dst.put(raw_response);
+                encode_overrides(&message, dst);
+                Ok(())
+            }
+            None => message
+                .encode(dst)
+                .map_err(|err| Status::internal(format!("could not encode the response: {err}"))),
+        }
+    }
+}
+
+/// Encode the fields InferenceStore sets on a served response after its raw response. A later
+/// field replaces an earlier one, and a map entry the entry with the same key, so the raw response
+/// gets the id and model of the request, and e.g. the provenance parameters.
+fn encode_overrides(message: &ModelInferResponse, dst: &mut impl BufMut) {
+    // Empty strings are encoded as well, as they have to replace the recorded values.
+    string::encode(1, &message.model_name, dst);
+    string::encode(2, &message.model_version, dst);
+    string::encode(3, &message.id, dst);
+    hash_map::encode(
+        string::encode,
+        string::encoded_len,
+        message::encode,
+        message::encoded_len,
+        4,
+        &message.parameters,
+        dst,
+    );
+}
+
+struct ModelInferSvc<T>(Arc<T>);
+
+impl<T: GrpcInferenceService> UnaryService<ModelInferRequest> for ModelInferSvc<T> {
+    type Response = ServedResponse;
+    type Future = BoxFuture<Response<ServedResponse>, Status>;
+
+    fn call(&mut self, request: Request<ModelInferRequest>) -> Self::Future {
+        let inner = self.0.clone();
+        Box::pin(async move {
+            let (metadata, message, mut extensions) =
+                inner.model_infer(request).await?.into_parts();
+            let raw_response = extensions
+                .remove::<RawResponse>()
+                .map(|RawResponse(raw_response)| raw_response);
+            Ok(Response::from_parts(
+                metadata,
+                ServedResponse {
+                    message,
+                    raw_response,
+                },
+                extensions,
+            ))
+        })
+    }
+}
+
+/// The gRPC server of an inference service, of which `ModelInfer` sends the attached raw
+/// responses as they were recorded, see `RawResponse`. The generated server would decode and
+/// encode them again, dropping the fields that are unknown to this version. The other methods
+/// are served by the generated server.
+pub struct RawResponseServer<T: GrpcInferenceService> {
+    inner: Arc<T>,
+    server: GrpcInferenceServiceServer<T>,
+    max_decoding_message_size: Option<usize>,
+}
+
+impl<T: GrpcInferenceService> RawResponseServer<T> {
+    pub fn from_arc(inner: Arc<T>) -> Self {
+        Self {
+            server: GrpcInferenceServiceServer::from_arc(inner.clone()),
+            inner,
+            max_decoding_message_size: None,
+        }
+    }
+
+    /// Limit the maximum size of a decoded request, like the generated server.
+    pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+        self.server = self.server.max_decoding_message_size(limit);
+        self.max_decoding_message_size = Some(limit);
+        self
+    }
+}
+
+impl<T: GrpcInferenceService> Clone for RawResponseServer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            server: self.server.clone(),
+            max_decoding_message_size: self.max_decoding_message_size,
+        }
+    }
+}
+
+impl<T, B> Service<http::Request<B>> for RawResponseServer<T>
+where
+    T: GrpcInferenceService,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != MODEL_INFER_PATH {
+            return self.server.call(request);
+        }
+
+        let method = ModelInferSvc(self.inner.clone());
+        let mut grpc = tonic::server::Grpc::new(PassThroughCodec)
+            .apply_max_message_size_config(self.max_decoding_message_size, None);
+        Box::pin(async move { Ok(grpc.unary(method, request).await) })
+    }
+}
+
+impl<T: GrpcInferenceService> NamedService for RawResponseServer<T> {
+    const NAME: &'static str = <GrpcInferenceServiceServer<T> as NamedService>::NAME;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::service::inference_protocol::InferParameter;
+
+    #[test]
+    fn it_overrides_the_fields_of_raw_responses() {
+        let recorded = ModelInferResponse {
+            model_name: "recorded".to_string(),
+            id: "recorded".to_string(),
+            parameters: HashMap::from([("kept".to_string(), InferParameter::default())]),
+            ..Default::default()
+        };
+        // A field that is unknown to this version, with tag 31 and varint 7.
+        let mut raw_response = recorded.encode_to_vec();
+        raw_response.extend([0xf8, 0x01, 0x07]);
+
+        let mut served = ModelInferResponse {
+            model_name: "test".to_string(),
+            model_version: "1".to_string(),
+            id: String::new(),
+            ..recorded.clone()
+        };
+        served
+            .parameters
+            .insert("inferencestore.hit".to_string(), InferParameter::default());
+        let mut encoded = raw_response.clone();
+        encode_overrides(&served, &mut encoded);
+
+        assert!(encoded.starts_with(&raw_response));
+        assert_eq!(
+            served,
+            ModelInferResponse::decode(encoded.as_slice()).unwrap()
+        );
+    }
+}
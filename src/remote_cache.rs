@@ -0,0 +1,398 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, warn};
+use prost::Message;
+use tokio::sync::RwLock;
+use tonic::transport::Channel;
+use tonic::{Request, Response, Status};
+
+use crate::auth::{token_error, with_token};
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachestore::{CacheStore, Source};
+use crate::parsing::input::{MatchConfig, ProcessedInput};
+use crate::parsing::output::ProcessedOutput;
+use crate::replication::replication_protocol::Entry;
+use crate::replication::Replicator;
+use crate::service::inference_protocol::ModelInferRequest;
+use crate::settings::{ServerMode, Settings};
+use remote_cache_protocol::remote_cache_client::RemoteCacheClient;
+use remote_cache_protocol::remote_cache_server::RemoteCache as RemoteCacheApi;
+use remote_cache_protocol::{
+    FetchRequest, FetchResponse, LookupRequest, LookupResponse, PushRequest, PushResponse,
+};
+
+pub mod remote_cache_protocol {
+    tonic::include_proto!("inferencestore.remote_cache");
+}
+
+/// A central InferenceStore instance that is consulted for the inference requests that miss the
+/// local cache, before they are forwarded to the target server. Matching entries are imported
+/// into the local cache, and entries recorded locally can be pushed to the central instance.
+#[derive(Clone)]
+pub struct RemoteCache {
+    client: RemoteCacheClient<Channel>,
+    inference_store: Arc<CacheStore<CachableModelInfer>>,
+    // When true, the entries recorded by this instance are pushed to the central instance.
+    push: bool,
+    // The token presented to the central instance, see `auth::with_token`.
+    token: String,
+    // The time a lookup may take, after which the request is handled as a miss.
+    timeout: Option<Duration>,
+}
+
+impl RemoteCache {
+    pub fn new(channel: Channel, inference_store: Arc<CacheStore<CachableModelInfer>>) -> Self {
+        Self {
+            client: RemoteCacheClient::new(channel),
+            inference_store,
+            push: false,
+            token: String::new(),
+            timeout: None,
+        }
+    }
+
+    /// Push the entries recorded by this instance to the central instance.
+    pub fn with_push(self, push: bool) -> Self {
+        Self { push, ..self }
+    }
+
+    /// Present the token to the central instance.
+    pub fn with_token(self, token: String) -> Self {
+        Self { token, ..self }
+    }
+
+    /// Handle lookups that take longer than the timeout as misses, so an unresponsive central
+    /// instance does not delay the requests that are forwarded to the target server.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Look up a request that missed the local cache in the central instance, and import the
+    /// matching entry into the local cache. Returns the local match of the input afterwards. The
+    /// request is sent normalized but unscrubbed, the central instance applies its own scrubbing.
+    pub async fn find(
+        &self,
+        request: &ModelInferRequest,
+        input: &ProcessedInput,
+        config: &MatchConfig,
+    ) -> Option<(ProcessedInput, ProcessedOutput, Source)> {
+        let fetched = self.fetch(request, input.namespace.clone());
+        let fetched = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, fetched).await {
+                Ok(fetched) => fetched,
+                Err(_) => Err(Status::deadline_exceeded("the lookup timed out")),
+            },
+            None => fetched.await,
+        };
+        let (file_name, content) = match fetched {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return None,
+            Err(status) => {
+                warn!(
+                    "could not look up request in the remote cache: {}",
+                    status.message()
+                );
+                return None;
+            }
+        };
+        if let Err(err) = self.inference_store.import(&file_name, &content).await {
+            warn!("could not import cache entry {file_name} from the remote cache: {err}");
+            return None;
+        }
+
+        self.inference_store.find(input, config).await
+    }
+
+    /// The file name and content of the entry matching the request, or None when none matches.
+    async fn fetch(
+        &self,
+        request: &ModelInferRequest,
+        namespace: Option<String>,
+    ) -> Result<Option<(String, Vec<u8>)>, Status> {
+        let mut client = self.client.clone();
+        let file_name = client
+            .lookup(with_token(
+                LookupRequest {
+                    request: request.encode_to_vec(),
+                    namespace: namespace.unwrap_or_default(),
+                },
+                &self.token,
+            ))
+            .await?
+            .into_inner()
+            .file_name;
+        if file_name.is_empty() {
+            return Ok(None);
+        }
+
+        let content = client
+            .fetch(with_token(
+                FetchRequest {
+                    file_name: file_name.clone(),
+                },
+                &self.token,
+            ))
+            .await?
+            .into_inner()
+            .content;
+        debug!("Fetched cache entry {file_name} from the remote cache");
+
+        Ok(Some((file_name, content)))
+    }
+
+    /// Push a newly recorded entry to the central instance when pushing is enabled, without
+    /// waiting for it.
+    pub fn push(&self, path: &Path) {
+        if !self.push {
+            return;
+        }
+
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let inference_store = self.inference_store.clone();
+        let token = self.token.clone();
+        let mut client = self.client.clone();
+        tokio::spawn(async move {
+            // The entry is pushed as a single file that holds its output as well, like it is
+            // replicated.
+            let result = match inference_store.read_file(&file_name).await {
+                Some(content) => client
+                    .push(with_token(
+                        PushRequest {
+                            file_name: file_name.clone(),
+                            content,
+                        },
+                        &token,
+                    ))
+                    .await
+                    .map(|_| ()),
+                None => Err(Status::not_found("the entry can not be read")),
+            };
+
+            if let Err(status) = result {
+                warn!(
+                    "could not push cache entry {file_name} to the remote cache: {}",
+                    status.message()
+                );
+            }
+        });
+    }
+}
+
+/// The grpc service other instances use to consult this instance as their remote cache.
+pub struct RemoteCacheService {
+    settings: Arc<RwLock<Settings>>,
+    inference_store: Arc<CacheStore<CachableModelInfer>>,
+    replicator: Arc<Replicator>,
+}
+
+impl RemoteCacheService {
+    pub fn new(
+        settings: Arc<RwLock<Settings>>,
+        inference_store: Arc<CacheStore<CachableModelInfer>>,
+        replicator: Arc<Replicator>,
+    ) -> Self {
+        Self {
+            settings,
+            inference_store,
+            replicator,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl RemoteCacheApi for RemoteCacheService {
+    async fn lookup(
+        &self,
+        request: Request<LookupRequest>,
+    ) -> Result<Response<LookupResponse>, Status> {
+        let token = self
+            .settings
+            .read()
+            .await
+            .cluster
+            .remote_cache_token
+            .clone();
+        if let Some(status) = token_error(request.metadata(), &token) {
+            return Err(status);
+        }
+        let LookupRequest { request, namespace } = request.into_inner();
+        let mut request = ModelInferRequest::decode(request.as_slice())
+            .map_err(|err| Status::invalid_argument(format!("invalid request: {err}")))?;
+        let (match_config, normalization, scrubbing) = {
+            let settings = self.settings.read().await;
+            (
+                settings.get_match_config(),
+                settings.request_matching.normalization.clone(),
+                settings.request_collection.scrubbing.clone(),
+            )
+        };
+
+        // The request is matched like the inference service matches it.
+        normalization.normalize(&mut request);
+        let mut input = ProcessedInput::from_infer_request(scrubbing.scrub(request), &match_config);
+        input.namespace = (!namespace.is_empty()).then_some(namespace);
+        let file_name = match self.inference_store.find(&input, &match_config).await {
+            Some((_, _, source)) => source
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            None => String::new(),
+        };
+        debug!(
+            "Remote cache lookup of request {} of model {}: {}",
+            input.request_hash(),
+            input.model_name,
+            match file_name.as_str() {
+                "" => "miss",
+                file_name => file_name,
+            }
+        );
+
+        Ok(Response::new(LookupResponse { file_name }))
+    }
+
+    async fn fetch(
+        &self,
+        request: Request<FetchRequest>,
+    ) -> Result<Response<FetchResponse>, Status> {
+        let token = self
+            .settings
+            .read()
+            .await
+            .cluster
+            .remote_cache_token
+            .clone();
+        if let Some(status) = token_error(request.metadata(), &token) {
+            return Err(status);
+        }
+        let file_name = request.into_inner().file_name;
+        match self.replicator.read_file(&file_name).await {
+            Some(content) => Ok(Response::new(FetchResponse { content })),
+            None => Err(Status::not_found(format!("no cache entry {file_name}"))),
+        }
+    }
+
+    async fn push(&self, request: Request<PushRequest>) -> Result<Response<PushResponse>, Status> {
+        {
+            let settings = self.settings.read().await;
+            if settings.mode != ServerMode::Collect {
+                return Err(Status::failed_precondition(
+                    "only instances in Collect mode accept pushed entries",
+                ));
+            }
+            // Pushed entries are served to every instance, so only known instances may push.
+            if settings.cluster.remote_cache_token.is_empty() {
+                return Err(Status::failed_precondition(
+                    "only instances with a cluster.remote_cache_token accept pushed entries",
+                ));
+            }
+            if let Some(status) =
+                token_error(request.metadata(), &settings.cluster.remote_cache_token)
+            {
+                return Err(status);
+            }
+        }
+
+        let PushRequest { file_name, content } = request.into_inner();
+        let imported = self
+            .replicator
+            .import(&Entry { file_name, content })
+            .await
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        Ok(Response::new(PushResponse { imported }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::cli::Cli;
+    use crate::parsing::input::tests::raw_infer_request;
+    use crate::parsing::output::tests::BASE_INFER_OUTPUT;
+
+    #[tokio::test]
+    async fn it_serves_lookups_fetches_and_pushes() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        let mut settings = Settings::new(&Cli::default()).unwrap();
+        settings.cluster.remote_cache_token = "secret".to_string();
+        let match_config = settings.get_match_config();
+        let inference_store = Arc::new(CacheStore::new(dir.clone()));
+        let replicator = Arc::new(Replicator::new(
+            vec![],
+            String::new(),
+            inference_store.clone(),
+            Arc::new(CacheStore::new(dir.clone())),
+            Arc::new(CacheStore::new(dir.clone())),
+        ));
+        let service = RemoteCacheService::new(
+            Arc::new(RwLock::new(settings)),
+            inference_store.clone(),
+            replicator,
+        );
+
+        let request = raw_infer_request(vec![("INPUT0", "UINT8", vec![1], vec![1])]);
+        let lookup = |request: &ModelInferRequest| {
+            with_token(
+                LookupRequest {
+                    request: request.encode_to_vec(),
+                    namespace: String::new(),
+                },
+                "secret",
+            )
+        };
+        let unauthenticated = service.lookup(Request::new(LookupRequest::default())).await;
+        assert_eq!(
+            tonic::Code::Unauthenticated,
+            unauthenticated.unwrap_err().code()
+        );
+        let response = service.lookup(lookup(&request)).await.unwrap();
+        assert_eq!("", response.get_ref().file_name);
+
+        let input = ProcessedInput::from_infer_request(request.clone(), &match_config);
+        let (path, _) = inference_store
+            .store(input, BASE_INFER_OUTPUT.clone(), Default::default())
+            .await
+            .unwrap();
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let response = service.lookup(lookup(&request)).await.unwrap();
+        assert_eq!(file_name, response.get_ref().file_name);
+
+        let content = service
+            .fetch(with_token(
+                FetchRequest {
+                    file_name: file_name.clone(),
+                },
+                "secret",
+            ))
+            .await
+            .unwrap()
+            .into_inner()
+            .content;
+        assert_eq!(
+            inference_store.read_file(&file_name).await,
+            Some(content.clone())
+        );
+
+        // An entry that is already available is not imported again.
+        let push = |token| {
+            with_token(
+                PushRequest {
+                    file_name: file_name.clone(),
+                    content: content.clone(),
+                },
+                token,
+            )
+        };
+        assert!(service.push(push("other")).await.is_err());
+        let response = service.push(push("secret")).await.unwrap();
+        assert!(!response.get_ref().imported);
+    }
+}
@@ -0,0 +1,281 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use log::{debug, info, warn};
+use tonic::{Request, Response, Status};
+
+use crate::caching::cachable_modelconfig::CachableModelConfig;
+use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachable_modelmetadata::CachableModelMetadata;
+use crate::caching::cachestore::CacheStore;
+use crate::remote_cache::RemoteCache;
+use replication_protocol::replication_client::ReplicationClient;
+use replication_protocol::replication_server::Replication;
+use replication_protocol::{
+    AnnounceEntriesRequest, AnnounceEntriesResponse, Entry, FetchEntriesRequest,
+    FetchEntriesResponse, ListEntriesRequest, ListEntriesResponse,
+};
+
+pub mod replication_protocol {
+    tonic::include_proto!("inferencestore.replication");
+}
+
+/// Replicates cache entries between InferenceStore instances. New entries are announced to all
+/// peers, which fetch the entries they are missing from the announcing instance.
+pub struct Replicator {
+    // The addresses of the peer instances.
+    peers: Vec<String>,
+
+    // The address peers use to reach this instance.
+    advertise_address: String,
+
+    inference_store: Arc<CacheStore<CachableModelInfer>>,
+    config_store: Arc<CacheStore<CachableModelConfig>>,
+    metadata_store: Arc<CacheStore<CachableModelMetadata>>,
+
+    // The remote cache newly recorded entries are pushed to.
+    remote_cache: Option<RemoteCache>,
+}
+
+impl Replicator {
+    pub fn new(
+        peers: Vec<String>,
+        advertise_address: String,
+        inference_store: Arc<CacheStore<CachableModelInfer>>,
+        config_store: Arc<CacheStore<CachableModelConfig>>,
+        metadata_store: Arc<CacheStore<CachableModelMetadata>>,
+    ) -> Self {
+        Self {
+            peers,
+            advertise_address,
+            inference_store,
+            config_store,
+            metadata_store,
+            remote_cache: None,
+        }
+    }
+
+    /// Also push newly recorded entries to a remote cache.
+    pub fn with_remote_cache(self, remote_cache: RemoteCache) -> Self {
+        Self {
+            remote_cache: Some(remote_cache),
+            ..self
+        }
+    }
+
+    /// Announce a newly recorded entry to all peers and the remote cache, without waiting for
+    /// them.
+    pub fn announce(&self, path: &Path) {
+        if let Some(remote_cache) = &self.remote_cache {
+            remote_cache.push(path);
+        }
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+
+        for peer in &self.peers {
+            let peer = peer.clone();
+            let request = AnnounceEntriesRequest {
+                peer: self.advertise_address.clone(),
+                file_names: vec![file_name.clone()],
+            };
+
+            tokio::spawn(async move {
+                let result = match ReplicationClient::connect(peer.clone()).await {
+                    Ok(mut client) => client.announce_entries(request).await.map(|_| ()),
+                    Err(err) => Err(Status::unavailable(err.to_string())),
+                };
+
+                if let Err(err) = result {
+                    warn!("could not announce cache entry to peer {peer}: {err}");
+                }
+            });
+        }
+    }
+
+    /// Fetch all entries this instance is missing from every peer.
+    pub async fn sync(&self) {
+        for peer in &self.peers {
+            let result = async {
+                let mut client = ReplicationClient::connect(peer.clone()).await?;
+                let file_names = client
+                    .list_entries(ListEntriesRequest {})
+                    .await?
+                    .into_inner()
+                    .file_names;
+
+                self.pull(peer, file_names).await
+            };
+
+            match result.await {
+                Ok(pulled) => info!("Pulled {pulled} cache entries from peer {peer}"),
+                Err(err) => warn!("could not sync cache entries with peer {peer}: {err}"),
+            }
+        }
+    }
+
+    /// Whether an address is one of the configured peers.
+    pub fn is_peer(&self, address: &str) -> bool {
+        self.peers.iter().any(|peer| peer == address)
+    }
+
+    /// Fetch the entries that are not available locally from a peer. Returns the number of
+    /// imported entries.
+    pub async fn pull(&self, peer: &str, file_names: Vec<String>) -> anyhow::Result<usize> {
+        let known = self.file_names().await;
+        let missing: Vec<_> = file_names
+            .into_iter()
+            .filter(|file_name| !known.contains(file_name))
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(0);
+        }
+
+        let mut client = ReplicationClient::connect(peer.to_string()).await?;
+        let entries = client
+            .fetch_entries(FetchEntriesRequest {
+                file_names: missing,
+            })
+            .await?
+            .into_inner()
+            .entries;
+
+        let mut imported = 0;
+        for entry in entries {
+            match self.import(&entry).await {
+                Ok(true) => imported += 1,
+                Ok(false) => {}
+                Err(err) => warn!("could not import cache entry from peer {peer}: {err}"),
+            }
+        }
+
+        Ok(imported)
+    }
+
+    async fn file_names(&self) -> Vec<String> {
+        let mut file_names = self.inference_store.file_names().await;
+        file_names.extend(self.config_store.file_names().await);
+        file_names.extend(self.metadata_store.file_names().await);
+
+        file_names
+    }
+
+    pub async fn read_file(&self, file_name: &str) -> Option<Vec<u8>> {
+        if let Some(content) = self.inference_store.read_file(file_name).await {
+            return Some(content);
+        }
+        if let Some(content) = self.config_store.read_file(file_name).await {
+            return Some(content);
+        }
+
+        self.metadata_store.read_file(file_name).await
+    }
+
+    /// Import an entry into the store of its kind. Returns false when it was already available.
+    pub async fn import(&self, entry: &Entry) -> anyhow::Result<bool> {
+        let Entry { file_name, content } = entry;
+
+        if CacheStore::<CachableModelInfer>::is_entry_file_name(file_name) {
+            self.inference_store.import(file_name, content).await
+        } else if CacheStore::<CachableModelMetadata>::is_entry_file_name(file_name) {
+            self.metadata_store.import(file_name, content).await
+        } else {
+            self.config_store.import(file_name, content).await
+        }
+    }
+}
+
+/// The grpc service peers use to announce and fetch entries.
+pub struct ReplicationService {
+    replicator: Arc<Replicator>,
+}
+
+impl ReplicationService {
+    pub fn new(replicator: Arc<Replicator>) -> Self {
+        Self { replicator }
+    }
+}
+
+#[tonic::async_trait]
+impl Replication for ReplicationService {
+    async fn announce_entries(
+        &self,
+        request: Request<AnnounceEntriesRequest>,
+    ) -> Result<Response<AnnounceEntriesResponse>, Status> {
+        let AnnounceEntriesRequest { peer, file_names } = request.into_inner();
+        // Entries are only pulled from configured peers, otherwise any client could make this
+        // instance connect to an arbitrary address and import entries from it.
+        if !self.replicator.is_peer(&peer) {
+            warn!("Rejected announcement of cache entries from unknown peer {peer}");
+            return Err(Status::permission_denied(format!(
+                "{peer} is not a configured peer"
+            )));
+        }
+        debug!(
+            "Received announcement of {} entries from {peer}",
+            file_names.len()
+        );
+
+        let replicator = self.replicator.clone();
+        tokio::spawn(async move {
+            if let Err(err) = replicator.pull(&peer, file_names).await {
+                warn!("could not pull announced cache entries from peer {peer}: {err}");
+            }
+        });
+
+        Ok(Response::new(AnnounceEntriesResponse {}))
+    }
+
+    async fn list_entries(
+        &self,
+        _request: Request<ListEntriesRequest>,
+    ) -> Result<Response<ListEntriesResponse>, Status> {
+        Ok(Response::new(ListEntriesResponse {
+            file_names: self.replicator.file_names().await,
+        }))
+    }
+
+    async fn fetch_entries(
+        &self,
+        request: Request<FetchEntriesRequest>,
+    ) -> Result<Response<FetchEntriesResponse>, Status> {
+        let mut entries = vec![];
+
+        for file_name in request.into_inner().file_names {
+            if let Some(content) = self.replicator.read_file(&file_name).await {
+                entries.push(Entry { file_name, content });
+            }
+        }
+
+        Ok(Response::new(FetchEntriesResponse { entries }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_rejects_announcements_of_unknown_peers() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        let replicator = Replicator::new(
+            vec!["http://inferencestore-2:50051".to_string()],
+            "http://inferencestore-1:50051".to_string(),
+            Arc::new(CacheStore::new(dir.clone())),
+            Arc::new(CacheStore::new(dir.clone())),
+            Arc::new(CacheStore::new(dir)),
+        );
+        let service = ReplicationService::new(Arc::new(replicator));
+
+        let status = service
+            .announce_entries(Request::new(AnnounceEntriesRequest {
+                peer: "http://attacker:50051".to_string(),
+                file_names: vec!["infer-test.inferstore".to_string()],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(tonic::Code::PermissionDenied, status.code());
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Counts how often inputs were forwarded to record a sample of their output. Identical outputs
+/// are recorded as a single entry, so the recorded entries do not tell how often an input was
+/// sampled. The count of an input starts at its number of recorded entries.
+#[derive(Default)]
+pub struct SampleAttempts {
+    // The number of samples per request hash, see `ProcessedInput::request_hash`.
+    attempts: Mutex<HashMap<String, usize>>,
+}
+
+impl SampleAttempts {
+    /// The number of samples of the input with the request hash, if it is counted already.
+    pub fn get(&self, request_hash: &str) -> Option<usize> {
+        self.attempts.lock().unwrap().get(request_hash).copied()
+    }
+
+    /// Start counting the samples of an input at its number of recorded entries, unless it is
+    /// counted already. Returns the number of samples.
+    pub fn init(&self, request_hash: String, recorded: usize) -> usize {
+        *self
+            .attempts
+            .lock()
+            .unwrap()
+            .entry(request_hash)
+            .or_insert(recorded)
+    }
+
+    /// Count a sample of the input, and return its number among the samples of the input.
+    pub fn next(&self, request_hash: String) -> usize {
+        let mut attempts = self.attempts.lock().unwrap();
+        let attempts = attempts.entry(request_hash).or_default();
+        *attempts += 1;
+
+        *attempts - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_attempts() {
+        let attempts = SampleAttempts::default();
+        assert_eq!(None, attempts.get("a"));
+
+        assert_eq!(1, attempts.init("a".to_string(), 1));
+        assert_eq!(1, attempts.next("a".to_string()));
+        assert_eq!(2, attempts.next("a".to_string()));
+        // Attempts are not reset by the number of recorded entries, which stays the same when
+        // the outputs are identical.
+        assert_eq!(3, attempts.init("a".to_string(), 1));
+        assert_eq!(Some(3), attempts.get("a"));
+
+        assert_eq!(0, attempts.next("b".to_string()));
+    }
+}
@@ -1,16 +1,56 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, RwLock};
 use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
 use tonic::codegen::tokio_stream::StreamExt;
+use tonic::metadata::MetadataMap;
+use tonic::transport::server::TcpConnectInfo;
 use tonic::transport::Channel;
-use tonic::{Request, Response, Status, Streaming};
+use tonic::{Extensions, Request, Response, Status, Streaming};
 
-use crate::caching::cachable_modelconfig::CachableModelConfig;
+use crate::auth::token_error;
+use crate::caching::cachable::Cachable;
+use crate::caching::cachable_modelconfig::{composing_models, CachableModelConfig};
 use crate::caching::cachable_modelinfer::CachableModelInfer;
+use crate::caching::cachable_modelmetadata::CachableModelMetadata;
 use crate::caching::cachestore::CacheStore;
-use crate::parsing::input::ProcessedInput;
-use crate::parsing::output::ProcessedOutput;
+use crate::caching::coalesce::{wait_for_leader, Flight, InFlight};
+use crate::caching::metadata::{parse_labels, EntryMetadata, SESSION_LABEL, TRACEPARENT_HEADER};
+use crate::consensus::Consensus;
+use crate::coverage::Coverage;
+use crate::deadline::{call_upstream, request_deadline, upstream_timeout};
+use crate::determinism::Determinism;
+use crate::dry_run::DryRun;
+use crate::error::{error_kind, ProxyError, ERROR_KIND_KEY};
+use crate::extensions;
+use crate::hedging::Hedging;
+use crate::logging::{self, log_level_setting, take_log_level, LOG_LEVEL_SETTING};
+use crate::middleware::Middleware;
+use crate::parsing::batch::{combine_requests, combine_responses, split_request, split_response};
+use crate::parsing::cache_control::{uses_shared_memory, CacheControl, SKIP_CACHE};
+use crate::parsing::classification::classify;
+use crate::parsing::input::{raw_input_contents, MatchConfig, Parameter, ProcessedInput};
+use crate::parsing::normalization::NormalizeConfig;
+use crate::parsing::output::{
+    annotate_provenance, is_recorded_error, map_to_metadata, metadata_to_map, ProcessedOutput,
+};
+use crate::parsing::scrubbing::ScrubConfig;
+use crate::parsing::sequence::{rewrite_sequence_id, SequenceTracker, SEQUENCE_ID};
+use crate::parsing::synthesize::{
+    synthesize_config, synthesize_response, OutputSpec, SynthesizeResponses,
+};
+use crate::pool::ChannelPool;
+use crate::queue::UpstreamQueue;
+use crate::raw_response::{self, RawResponse};
+use crate::remote_cache::RemoteCache;
+use crate::replication::Replicator;
+use crate::sampling::SampleAttempts;
 use crate::service::inference_protocol::{
     CudaSharedMemoryRegisterRequest, CudaSharedMemoryRegisterResponse,
     CudaSharedMemoryStatusRequest, CudaSharedMemoryStatusResponse,
@@ -23,7 +63,12 @@ use crate::service::inference_protocol::{
     SystemSharedMemoryStatusResponse, SystemSharedMemoryUnregisterRequest,
     SystemSharedMemoryUnregisterResponse, TraceSettingRequest, TraceSettingResponse,
 };
-use crate::settings::Settings;
+use crate::settings::{
+    MalformedResponses, NondeterministicModels, PersistFailures, RequestCollection, Settings,
+    SharedMemory, TargetServer, Warmup,
+};
+use crate::statistics::{self, Statistics};
+use crate::trace::{Outcome, TraceGuard, TraceLog, TraceRecord};
 use inference_protocol::grpc_inference_service_client::GrpcInferenceServiceClient;
 use inference_protocol::grpc_inference_service_server::GrpcInferenceService;
 use inference_protocol::{
@@ -31,33 +76,951 @@ use inference_protocol::{
     ModelReadyRequest, ModelReadyResponse, ServerLiveRequest, ServerLiveResponse,
     ServerMetadataRequest, ServerMetadataResponse, ServerReadyRequest, ServerReadyResponse,
 };
-use log::{debug, warn};
+use log::{debug, error, info, warn};
+use prost::Message;
 
 pub mod inference_protocol {
     tonic::include_proto!("inference");
 }
 
+#[derive(Clone)]
 pub struct InferenceStoreGrpcInferenceService {
-    settings: Settings,
-    inference_service_client: Option<GrpcInferenceServiceClient<Channel>>,
+    settings: Arc<RwLock<Settings>>,
+    inference_service_client: Option<ChannelPool>,
     inference_store: Arc<CacheStore<CachableModelInfer>>,
     config_store: Arc<CacheStore<CachableModelConfig>>,
+    metadata_store: Arc<CacheStore<CachableModelMetadata>>,
+    replicator: Arc<Replicator>,
+    sequences: Arc<SequenceTracker>,
+    trace: Option<Arc<TraceLog>>,
+    upstream_queue: Option<Arc<UpstreamQueue>>,
+    in_flight: Arc<InFlight<LeaderResult>>,
+    sample_attempts: Arc<SampleAttempts>,
+    statistics: Arc<Statistics>,
+    coverage: Option<Arc<Coverage>>,
+    middleware: Option<Arc<dyn Middleware>>,
+    dry_run: Option<Arc<DryRun>>,
+    determinism: Arc<Determinism>,
+    consensus: Option<Arc<Consensus>>,
+    remote_cache: Option<RemoteCache>,
+    hedging: Option<Arc<Hedging>>,
 }
 
 impl InferenceStoreGrpcInferenceService {
     pub fn new(
-        settings: Settings,
-        inference_store: CacheStore<CachableModelInfer>,
-        config_store: CacheStore<CachableModelConfig>,
-        inference_service_client: Option<GrpcInferenceServiceClient<Channel>>,
+        settings: Arc<RwLock<Settings>>,
+        inference_store: Arc<CacheStore<CachableModelInfer>>,
+        config_store: Arc<CacheStore<CachableModelConfig>>,
+        metadata_store: Arc<CacheStore<CachableModelMetadata>>,
+        inference_service_client: Option<ChannelPool>,
+        replicator: Arc<Replicator>,
+        trace: Option<Arc<TraceLog>>,
     ) -> Self {
         Self {
-            inference_store: Arc::new(inference_store),
-            config_store: Arc::new(config_store),
+            inference_store,
+            config_store,
+            metadata_store,
+            replicator,
             settings,
             inference_service_client,
+            sequences: Default::default(),
+            trace,
+            upstream_queue: None,
+            in_flight: Default::default(),
+            sample_attempts: Default::default(),
+            statistics: Default::default(),
+            coverage: None,
+            middleware: None,
+            dry_run: None,
+            determinism: Default::default(),
+            consensus: None,
+            remote_cache: None,
+            hedging: None,
         }
     }
+
+    /// Queue inference requests to the target server beyond the concurrency of the queue.
+    pub fn with_upstream_queue(mut self, upstream_queue: UpstreamQueue) -> Self {
+        self.upstream_queue = Some(Arc::new(upstream_queue));
+        self
+    }
+
+    /// Count the hits and misses in a coverage shared with e.g. the admin service. Without it
+    /// they are not counted, as the misses would grow with every distinct request.
+    pub fn with_coverage(mut self, coverage: Arc<Coverage>) -> Self {
+        self.coverage = Some(coverage);
+        self
+    }
+
+    /// Run custom logic around the cache lookups and target server calls of inference requests.
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middleware = Some(middleware);
+        self
+    }
+
+    /// Mark the models found nondeterministic by determinism probes in a shared determinism, e.g.
+    /// one persisted to the cache directory.
+    pub fn with_determinism(mut self, determinism: Arc<Determinism>) -> Self {
+        self.determinism = determinism;
+        self
+    }
+
+    /// Only record outputs that enough of the consensus target servers agree on.
+    pub fn with_consensus(mut self, consensus: Consensus) -> Self {
+        self.consensus = Some(Arc::new(consensus));
+        self
+    }
+
+    /// Look up the inference requests that miss the cache in a remote cache, before forwarding
+    /// them to the target server.
+    pub fn with_remote_cache(mut self, remote_cache: RemoteCache) -> Self {
+        self.remote_cache = Some(remote_cache);
+        self
+    }
+
+    /// Send a duplicate of the inference requests the target server is slow to respond to, and use
+    /// the first response.
+    pub fn with_hedging(mut self, hedging: Hedging) -> Self {
+        self.hedging = Some(Arc::new(hedging));
+        self
+    }
+
+    /// Forward every inference request and count what would be served and recorded, instead of
+    /// serving from and writing to the cache.
+    pub fn with_dry_run(mut self, dry_run: Arc<DryRun>) -> Self {
+        self.dry_run = Some(dry_run);
+        self
+    }
+
+    /// Serve an inference request from the cache, or forward it to the target server and record
+    /// it. The trace record is updated with how the request was handled. With `lookup_only`, a
+    /// miss in Collect mode returns None instead of forwarding the request.
+    async fn infer(
+        &self,
+        mut request: Request<ModelInferRequest>,
+        normalization: &NormalizeConfig,
+        trace: &mut TraceRecord,
+        lookup_only: bool,
+    ) -> Result<Option<Response<ModelInferResponse>>, Status> {
+        if let Some(middleware) = &self.middleware {
+            middleware.on_request(&mut request).await?;
+        }
+        normalization.normalize(request.get_mut());
+        let deadline = request_deadline(&request);
+        if self.settings.read().await.request_collection.shared_memory == SharedMemory::Reject {
+            if let Some(status) = shared_memory_error(request.get_ref()) {
+                return Err(status);
+            }
+        }
+        let mut cache_control = CacheControl::take(request.get_mut());
+        let (
+            match_config,
+            scrubbing,
+            rewrite_sequence_ids,
+            annotate_responses,
+            store_inputs,
+            record_errors,
+            infer_timeout,
+            samples,
+            response_rewrites,
+            labels,
+            determinism_probes,
+            nondeterministic_models,
+        ) = {
+            let settings = self.settings.read().await;
+            (
+                settings.get_match_config(),
+                settings.request_collection.scrubbing.clone(),
+                settings.request_matching.rewrite_sequence_ids,
+                settings.server.annotate_responses,
+                settings.request_collection.store_inputs,
+                settings.request_collection.record_errors.clone(),
+                settings.target_server.model_infer_timeout(),
+                settings.request_collection.samples,
+                settings.request_matching.response_rewrites.clone(),
+                entry_labels(&settings.request_collection, request.metadata()),
+                settings.request_collection.determinism_probes,
+                settings.request_collection.nondeterministic_models,
+            )
+        };
+        let scrubbed_request = scrubbing.scrub(request.get_ref().clone());
+        let stored_inputs = store_inputs.then(|| raw_input_contents(&scrubbed_request));
+        let mut parsed_input = ProcessedInput::from_infer_request(scrubbed_request, &match_config);
+        parsed_input.namespace = cache_control.namespace.clone();
+        parsed_input.raw_input_contents = stored_inputs;
+        trace.request_hash = Some(parsed_input.request_hash());
+        if match_config.sequence_matching {
+            self.sequences.track(
+                &mut parsed_input,
+                &request.get_ref().parameters,
+                self.inference_service_client.is_some(),
+            );
+        }
+
+        // In Collect mode, requests to nondeterministic models are bypassed or sampled.
+        let nondeterministic = self.inference_service_client.is_some()
+            && self
+                .determinism
+                .is_nondeterministic(&parsed_input.model_name);
+        if nondeterministic && nondeterministic_models == NondeterministicModels::Bypass {
+            cache_control.skip_cache = true;
+        }
+
+        // Identical misses wait for the first one to be forwarded, and are served its response.
+        let coalesces = cache_control.uses_cache()
+            && parsed_input.sequence.is_none()
+            && self.inference_service_client.is_some()
+            && self.dry_run.is_none()
+            && !lookup_only;
+        // In Collect mode, inputs are forwarded until enough distinct outputs are recorded.
+        let samples = match self.inference_service_client {
+            Some(_) if nondeterministic => samples.max(2),
+            Some(_) => samples,
+            None => 1,
+        };
+        let mut _flight = None;
+        let cached = loop {
+            let cached = match cache_control.uses_cache() {
+                true if samples > 1
+                    && sampled(
+                        &self.sample_attempts,
+                        &self.inference_store,
+                        &parsed_input,
+                        &match_config,
+                    )
+                    .await
+                        < samples =>
+                {
+                    None
+                }
+                true => {
+                    self.inference_store
+                        .find(&parsed_input, &match_config)
+                        .await
+                }
+                false => None,
+            };
+            // A new leader looks up the request once more, as the previous leader may have
+            // recorded it since the lookup above.
+            if cached.is_some() || !coalesces || _flight.is_some() {
+                break cached;
+            }
+            match self.in_flight.join(parsed_input.request_hash()) {
+                Flight::Leader(guard) => _flight = Some(guard),
+                Flight::Follower(leader) => match wait_for_leader(leader).await {
+                    // Sampled requests are forwarded until enough distinct outputs are recorded.
+                    Some(result) if samples == 1 => {
+                        trace.outcome = Outcome::Forwarded;
+                        let mut response = result.map_err(|status| *status)?;
+                        response.id = request.get_ref().id.clone();
+                        if annotate_responses {
+                            annotate_provenance(&mut response, None);
+                        }
+                        return Ok(Some(Response::new(response)));
+                    }
+                    _ => {}
+                },
+            }
+        };
+        // Misses are looked up in the remote cache, except for sampled and sequence requests.
+        let cached = match (cached, &self.remote_cache) {
+            (None, Some(remote_cache))
+                if cache_control.uses_cache()
+                    && samples == 1
+                    && parsed_input.sequence.is_none() =>
+            {
+                remote_cache
+                    .find(request.get_ref(), &parsed_input, &match_config)
+                    .await
+            }
+            (cached, _) => cached,
+        };
+        // In DryRun mode the request is forwarded, after counting whether it would be served.
+        let cached = match &self.dry_run {
+            Some(dry_run) => {
+                dry_run.lookup(
+                    &parsed_input,
+                    cached.as_ref().map(|(_, _, source)| source.path.as_path()),
+                );
+                None
+            }
+            None => cached,
+        };
+        // The outputs of raw responses can not be renamed, those are served from the outputs.
+        let renames_outputs = normalization
+            .rename_tensors
+            .contains_key(&request.get_ref().model_name)
+            || response_rewrites
+                .rename_outputs
+                .contains_key(&request.get_ref().model_name);
+        if let Some((cached_input, mut cached_output, source)) = cached {
+            self.sequences
+                .bind(&request.get_ref().parameters, &cached_input);
+            trace.outcome = Outcome::Hit;
+            trace.set_source(&source);
+            if let Some(status) = cached_output.to_status() {
+                return Err(status);
+            }
+            classify(&mut cached_output, &cached_input, &parsed_input)
+                .map_err(ProxyError::CacheRead)?;
+            let mut response = cached_output.to_response(request.get_ref().clone());
+            if rewrite_sequence_ids {
+                rewrite_sequence_id(&mut response, &request.get_ref().parameters);
+            }
+            response_rewrites.rewrite(&mut response);
+            if annotate_responses {
+                annotate_provenance(&mut response, Some(&source.path));
+            }
+            let mut response = Response::new(response);
+            *response.metadata_mut() = map_to_metadata(&cached_output.metadata);
+            if let (Some(raw_response), false) = (cached_output.raw_response, renames_outputs) {
+                response.extensions_mut().insert(RawResponse(raw_response));
+            }
+            return Ok(Some(response));
+        }
+        if lookup_only && self.inference_service_client.is_some() {
+            return Ok(None);
+        }
+
+        // When self.inference_service_client is None, Serve mode is enabled.
+        // In Serve mode only requests from cache will be served.
+        let inference_service_client = match &self.inference_service_client {
+            Some(client) => client,
+            None if !cache_control.uses_cache() => {
+                return Err(Status::failed_precondition(
+                    "bypassing the cache is not possible in Serve mode",
+                ));
+            }
+            None => {
+                if let Some((cached_output, distance, source)) = self
+                    .inference_store
+                    .find_nearest_output(&parsed_input, &match_config)
+                    .await
+                {
+                    trace.outcome = Outcome::ApproximateHit;
+                    trace.set_source(&source);
+                    if let Some(status) = cached_output.to_status() {
+                        return Err(status);
+                    }
+                    let mut response =
+                        cached_output.to_approximate_response(request.into_inner(), distance);
+                    response_rewrites.rewrite(&mut response);
+                    if annotate_responses {
+                        annotate_provenance(&mut response, Some(&source.path));
+                    }
+                    let mut response = Response::new(response);
+                    *response.metadata_mut() = map_to_metadata(&cached_output.metadata);
+                    return Ok(Some(response));
+                }
+
+                let mode = self
+                    .settings
+                    .read()
+                    .await
+                    .request_matching
+                    .synthesize_responses;
+                return match synthesize(
+                    &self.config_store,
+                    &self.metadata_store,
+                    mode,
+                    request.into_inner(),
+                    &parsed_input,
+                )
+                .await?
+                {
+                    Some(mut response) => {
+                        if annotate_responses {
+                            annotate_provenance(&mut response, None);
+                        }
+                        trace.outcome = Outcome::Synthesized;
+                        Ok(Some(Response::new(response)))
+                    }
+                    None => {
+                        Err(ProxyError::MatchNotFound("could not match request".to_string()).into())
+                    }
+                };
+            }
+        };
+
+        if let Some(middleware) = &self.middleware {
+            middleware.on_forward(&mut request).await?;
+        }
+        let _permit = match &self.upstream_queue {
+            Some(queue) => Some(queue.acquire(cache_control.priority).await?),
+            None => None,
+        };
+        let client_peer = request.remote_addr();
+        let traceparent = traceparent(request.metadata());
+        let request_bytes = request.get_ref().encoded_len();
+        let started_at = Instant::now();
+        let channel = inference_service_client.channel();
+        let timeout = upstream_timeout(infer_timeout, deadline);
+        let probe_request = (determinism_probes && cache_control.records() && !nondeterministic)
+            .then(|| request.get_ref().clone());
+        let consensus_request = (cache_control.records() && self.consensus.is_some())
+            .then(|| request.get_ref().clone());
+        let record_batch_samples = self
+            .settings
+            .read()
+            .await
+            .request_collection
+            .record_batch_samples;
+        let batch_samples = (record_batch_samples && cache_control.records() && samples == 1)
+            .then(|| split_request(request.get_ref()))
+            .flatten();
+        let raw_responses =
+            cache_control.records() && self.settings.read().await.request_collection.raw_responses;
+        // Duplicates of sequence requests would advance the state of the sequence twice.
+        let hedging = self
+            .hedging
+            .clone()
+            .filter(|_| !is_sequence_request(&parsed_input, request.get_ref()));
+        let upstream_queue = self.upstream_queue.clone();
+        let priority = cache_control.priority;
+        let response = call_upstream(request, timeout, move |request| async move {
+            let call = |request| {
+                let channel = channel.clone();
+                async move {
+                    match raw_responses {
+                        true => raw_response::model_infer(channel, request)
+                            .await
+                            .map(|(response, raw_response)| (response, Some(raw_response))),
+                        false => GrpcInferenceServiceClient::new(channel)
+                            .model_infer(request)
+                            .await
+                            .map(|response| (response, None)),
+                    }
+                }
+            };
+            match hedging {
+                Some(hedging) => {
+                    hedging
+                        .call(request, upstream_queue.as_deref(), priority, call)
+                        .await
+                }
+                None => call(request).await,
+            }
+        })
+        .await;
+        if let Some(flight) = &_flight {
+            flight.finish(|| {
+                response
+                    .as_ref()
+                    .map(|(response, _)| response.get_ref().clone())
+                    .map_err(|status| Box::new(status.clone()))
+            });
+        }
+        let mut metadata = entry_metadata(
+            &self.settings.read().await.target_server,
+            started_at.elapsed(),
+            client_peer,
+        )
+        .with_traceparent(traceparent.as_deref())
+        .with_labels(labels);
+        if samples > 1 {
+            let sample = self.sample_attempts.next(parsed_input.request_hash());
+            metadata.sample = Some(sample as u64);
+        }
+
+        let (response, raw_response) = match response {
+            Ok(response) => response,
+            // Deterministic errors are recorded, so they can be replayed in Serve mode.
+            Err(status)
+                if cache_control.records() && is_recorded_error(&record_errors, status.code()) =>
+            {
+                if let Some(dry_run) = &self.dry_run {
+                    dry_run.record(&parsed_input, request_bytes);
+                    return Err(status);
+                }
+                let processed_response = ProcessedOutput::from_status(&status);
+                match self
+                    .inference_store
+                    .store(parsed_input, processed_response, metadata)
+                    .await
+                {
+                    Ok((path, _)) => {
+                        self.replicator.announce(&path);
+                        trace.outcome = Outcome::Recorded;
+                    }
+                    Err(err) => warn!("could not record error status: {err}"),
+                }
+                return Err(status);
+            }
+            Err(status) => return Err(status),
+        };
+
+        if let Some(probe_request) = probe_request {
+            spawn_determinism_probe(
+                inference_service_client.clone(),
+                self.upstream_queue.clone(),
+                self.determinism.clone(),
+                probe_request,
+                response.get_ref().clone(),
+                parsed_input.request_hash(),
+                infer_timeout,
+            );
+        }
+
+        let mut recorded = false;
+        let max_entry_bytes = self
+            .settings
+            .read()
+            .await
+            .request_collection
+            .max_entry_bytes;
+        if cache_control.records()
+            && fits_entry(
+                &parsed_input.model_name,
+                request_bytes + response.get_ref().encoded_len(),
+                max_entry_bytes,
+            )
+        {
+            let mut processed_response = ProcessedOutput::from_response(response.get_ref());
+            processed_response.metadata = metadata_to_map(response.metadata());
+            processed_response.raw_response = raw_response.clone();
+            let malformed_responses = self
+                .settings
+                .read()
+                .await
+                .request_collection
+                .malformed_responses;
+
+            let is_recordable = is_recordable(
+                &processed_response,
+                &parsed_input.model_name,
+                malformed_responses,
+            );
+            if let (true, Some(dry_run)) = (is_recordable, &self.dry_run) {
+                dry_run.record(
+                    &parsed_input,
+                    request_bytes + response.get_ref().encoded_len(),
+                );
+            } else if let (true, Some(consensus), Some(consensus_request)) =
+                (is_recordable, &self.consensus, consensus_request)
+            {
+                let (inference_store, replicator) =
+                    (self.inference_store.clone(), self.replicator.clone());
+                let metadata = metadata.clone();
+                spawn_consensus_recording(
+                    consensus.clone(),
+                    consensus_request,
+                    ProcessedOutput::from_response(response.get_ref()),
+                    infer_timeout,
+                    async move {
+                        match inference_store
+                            .store(parsed_input, processed_response, metadata)
+                            .await
+                        {
+                            Ok((path, _)) => replicator.announce(&path),
+                            Err(err) => warn!("could not record an agreed output: {err:#}"),
+                        }
+                    },
+                );
+            } else if is_recordable {
+                match self
+                    .inference_store
+                    .store(parsed_input, processed_response, metadata.clone())
+                    .await
+                {
+                    Ok((path, _)) => {
+                        self.replicator.announce(&path);
+                        recorded = true;
+                    }
+                    Err(err) => {
+                        let persist_failures = self
+                            .settings
+                            .read()
+                            .await
+                            .request_collection
+                            .persist_failures;
+                        if let Some(status) = persist_failure(persist_failures, err) {
+                            return Err(status);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let (true, Some(batch_samples)) = (recorded, batch_samples) {
+            let sample_inputs: Vec<_> = batch_samples
+                .into_iter()
+                .map(|sample| {
+                    sample_input(
+                        sample,
+                        &cache_control.namespace,
+                        &scrubbing,
+                        store_inputs,
+                        &match_config,
+                    )
+                })
+                .collect();
+            match split_response(response.get_ref(), sample_inputs.len()) {
+                Some(outputs) => self.record_batch_samples(
+                    sample_inputs,
+                    outputs,
+                    metadata_to_map(response.metadata()),
+                    match_config.clone(),
+                    metadata,
+                ),
+                None => debug!(
+                    "Not recording the samples of a batch of which the outputs can not be split"
+                ),
+            }
+        }
+
+        let mut response = response.into_inner();
+        if annotate_responses {
+            annotate_provenance(&mut response, None);
+        }
+        trace.outcome = match recorded {
+            true => Outcome::Recorded,
+            false => Outcome::Forwarded,
+        };
+
+        let mut response = Response::new(response);
+        if let (Some(raw_response), false) = (raw_response, renames_outputs) {
+            response.extensions_mut().insert(RawResponse(raw_response));
+        }
+        Ok(Some(response))
+    }
+
+    /// Record the samples of a batched request as entries of their own, with the outputs of the
+    /// response sliced along the batch dimension. Runs in the background, so the batch is
+    /// responded to without waiting for the writes. Samples that are cached already are skipped,
+    /// and failures are only logged.
+    fn record_batch_samples(
+        &self,
+        inputs: Vec<ProcessedInput>,
+        outputs: Vec<ModelInferResponse>,
+        response_metadata: BTreeMap<String, String>,
+        match_config: MatchConfig,
+        metadata: EntryMetadata,
+    ) {
+        let (inference_store, replicator) = (self.inference_store.clone(), self.replicator.clone());
+        tokio::spawn(async move {
+            let mut recorded = 0;
+            for (input, output) in inputs.into_iter().zip(outputs) {
+                if inference_store.find(&input, &match_config).await.is_some() {
+                    continue;
+                }
+                let mut processed_output = ProcessedOutput::from_response(&output);
+                processed_output.metadata = response_metadata.clone();
+                match inference_store
+                    .store(input, processed_output, metadata.clone())
+                    .await
+                {
+                    Ok((path, _)) => {
+                        replicator.announce(&path);
+                        recorded += 1;
+                    }
+                    Err(err) => warn!("could not record a sample of a batched request: {err:#}"),
+                }
+            }
+            debug!("Recorded {recorded} samples of a batched request");
+        });
+    }
+
+    /// Serve the samples of a split batched request. Every sample is looked up first, the cached
+    /// samples are served from the cache and the missing samples are forwarded to the target
+    /// server together in one batch, of which the outputs are recorded per sample. The responses
+    /// of the samples are combined in one response. The trace record gets the outcome of the
+    /// first sample that is not a hit.
+    async fn infer_samples(
+        &self,
+        request: Request<ModelInferRequest>,
+        samples: Vec<ModelInferRequest>,
+        normalization: &NormalizeConfig,
+        trace: &mut TraceRecord,
+    ) -> Result<Response<ModelInferResponse>, Status> {
+        let connect_info = request.extensions().get::<TcpConnectInfo>().cloned();
+        let client_peer = request.remote_addr();
+        let metadata = request.into_parts().0;
+        let batch_size = samples.len();
+        let sample_request = |sample| {
+            let mut extensions = tonic::Extensions::default();
+            if let Some(connect_info) = connect_info.clone() {
+                extensions.insert(connect_info);
+            }
+            Request::from_parts(metadata.clone(), extensions, sample)
+        };
+
+        let mut responses = Vec::with_capacity(batch_size);
+        let mut missing = vec![];
+        trace.outcome = Outcome::Hit;
+        for sample in samples {
+            let mut record = TraceRecord::new(&sample.model_name, &sample.model_version);
+            let response = self
+                .infer(
+                    sample_request(sample.clone()),
+                    normalization,
+                    &mut record,
+                    true,
+                )
+                .await?;
+            if response.is_none() {
+                missing.push(sample);
+            }
+            responses.push(response.map(Response::into_inner));
+            match record.outcome {
+                Outcome::Hit => {}
+                outcome if trace.outcome == Outcome::Hit => trace.outcome = outcome,
+                _ => {}
+            }
+        }
+        debug!(
+            "Served {} of {batch_size} samples of a batched request from the cache",
+            batch_size - missing.len()
+        );
+
+        if !missing.is_empty() {
+            let mut outputs = self
+                .infer_missing_samples(sample_request, client_peer, missing, normalization, trace)
+                .await?
+                .into_iter();
+            for response in responses.iter_mut().filter(|response| response.is_none()) {
+                *response = outputs.next();
+            }
+        }
+
+        match combine_responses(responses.into_iter().flatten().collect()) {
+            Some(response) => Ok(Response::new(response)),
+            None => Err(ProxyError::Serialization(anyhow::anyhow!(
+                "could not combine the responses of the samples of a batched request"
+            ))
+            .into()),
+        }
+    }
+
+    /// Forward the samples of a split batched request that are not cached to the target server
+    /// in one batch, and record its outputs per sample. The batch itself is not recorded. Returns
+    /// the responses of the samples.
+    async fn infer_missing_samples<F>(
+        &self,
+        sample_request: F,
+        client_peer: Option<SocketAddr>,
+        missing: Vec<ModelInferRequest>,
+        normalization: &NormalizeConfig,
+        trace: &mut TraceRecord,
+    ) -> Result<Vec<ModelInferResponse>, Status>
+    where
+        F: Fn(ModelInferRequest) -> Request<ModelInferRequest>,
+    {
+        let missing_samples = missing.len();
+        let Some(mut batch) = combine_requests(&missing) else {
+            return Err(ProxyError::Serialization(anyhow::anyhow!(
+                "could not combine the samples of a batched request that are not cached"
+            ))
+            .into());
+        };
+        batch.parameters.insert(
+            SKIP_CACHE.to_string(),
+            Parameter::BoolParam(true).to_infer_parameter(),
+        );
+        let batch = sample_request(batch);
+        let (match_config, scrubbing, store_inputs, malformed_responses, labels, traceparent) = {
+            let settings = self.settings.read().await;
+            (
+                settings.get_match_config(),
+                settings.request_collection.scrubbing.clone(),
+                settings.request_collection.store_inputs,
+                settings.request_collection.malformed_responses,
+                entry_labels(&settings.request_collection, batch.metadata()),
+                traceparent(batch.metadata()),
+            )
+        };
+
+        let started_at = Instant::now();
+        let mut record = TraceRecord::new(&trace.model_name, &trace.model_version);
+        let response = self
+            .infer(batch, normalization, &mut record, false)
+            .await?
+            .ok_or_else(|| {
+                Status::from(ProxyError::MatchNotFound(
+                    "could not match request".to_string(),
+                ))
+            })?;
+        let metadata = entry_metadata(
+            &self.settings.read().await.target_server,
+            started_at.elapsed(),
+            client_peer,
+        )
+        .with_traceparent(traceparent.as_deref())
+        .with_labels(labels);
+        let Some(outputs) = split_response(response.get_ref(), missing_samples) else {
+            return Err(ProxyError::Serialization(anyhow::anyhow!(
+                "could not split the response to the samples of a batched request"
+            ))
+            .into());
+        };
+
+        let processed_response = ProcessedOutput::from_response(response.get_ref());
+        if self.dry_run.is_none()
+            && is_recordable(&processed_response, &trace.model_name, malformed_responses)
+        {
+            let inputs = missing
+                .into_iter()
+                .map(|mut sample| {
+                    normalization.normalize(&mut sample);
+                    let cache_control = CacheControl::take(&mut sample);
+                    sample_input(
+                        sample,
+                        &cache_control.namespace,
+                        &scrubbing,
+                        store_inputs,
+                        &match_config,
+                    )
+                })
+                .collect();
+            self.record_batch_samples(
+                inputs,
+                outputs.clone(),
+                metadata_to_map(response.metadata()),
+                match_config,
+                metadata,
+            );
+            trace.outcome = Outcome::Recorded;
+        } else {
+            trace.outcome = Outcome::Forwarded;
+        }
+
+        Ok(outputs)
+    }
+
+    /// Forward a request that is not cached, e.g. to control the target server, to the target
+    /// server. Such requests are only possible in Collect mode. The configured timeouts do not
+    /// apply, e.g. loading a model may take long, only the deadline of the client does.
+    async fn forward<Req, Res, F, Fut>(
+        &self,
+        request: Request<Req>,
+        call: F,
+    ) -> Result<Response<Res>, Status>
+    where
+        F: FnOnce(GrpcInferenceServiceClient<Channel>, Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Res>, Status>>,
+    {
+        let Some(client) = self
+            .inference_service_client
+            .as_ref()
+            .map(ChannelPool::client)
+        else {
+            return Err(ProxyError::TargetUnavailable(
+                "the target server can not be controlled during serving mode".to_string(),
+            )
+            .into());
+        };
+        let timeout = upstream_timeout(None, request_deadline(&request));
+
+        call_upstream(request, timeout, move |request| call(client, request)).await
+    }
+
+    /// Remove the cached config and metadata of a model after it is (re)loaded or unloaded, as
+    /// they may have changed, and its infer entries when `invalidate_infer_on_load` is set.
+    /// Pinned entries are kept.
+    async fn invalidate_model(&self, model_name: &str) {
+        let invalidate_infer = self
+            .settings
+            .read()
+            .await
+            .request_collection
+            .invalidate_infer_on_load;
+        if self.dry_run.is_some() {
+            debug!("Dry run: the cache entries of model {model_name} would be invalidated");
+            return;
+        }
+        let is_model =
+            |model: Option<(&str, &str)>| model.is_some_and(|(name, _)| name == model_name);
+
+        let removed = async {
+            let mut removed = self.config_store.prune(|c| is_model(c.model())).await?
+                + self.metadata_store.prune(|c| is_model(c.model())).await?;
+            if invalidate_infer {
+                removed += self.inference_store.prune(|c| is_model(c.model())).await?;
+            }
+            anyhow::Ok(removed)
+        }
+        .await;
+
+        match removed {
+            Ok(removed) => info!("Invalidated {removed} cache entries of model {model_name}"),
+            Err(err) => {
+                warn!("could not invalidate the cache entries of model {model_name}: {err}")
+            }
+        }
+    }
+
+    /// Record the configs and metadata of the models composing an ensemble config. Composing models
+    /// that are ensembles themselves record their composing models when their config is recorded.
+    /// Failures are logged, the ensemble config itself was recorded.
+    async fn record_composing_models(&self, config: &ModelConfigResponse) {
+        for model in composing_models(config) {
+            let metadata_request = ModelMetadataRequest {
+                name: model.name.clone(),
+                version: model.version.clone(),
+            };
+            if let Err(err) = self.model_config(Request::new(model.clone())).await {
+                warn!(
+                    "could not record the config of ensemble model {}: {err}",
+                    model.name
+                );
+            }
+            if let Err(err) = self.model_metadata(Request::new(metadata_request)).await {
+                warn!(
+                    "could not record the metadata of ensemble model {}: {err}",
+                    model.name
+                );
+            }
+        }
+    }
+
+    /// Cache the configs and metadata of the warmup models, and the responses to the warmup
+    /// requests, so they are available in Serve mode even when no client requested them during
+    /// recording. Warmup failures are logged, and do not prevent starting.
+    pub async fn warmup(&self, warmup: &Warmup) {
+        for model in &warmup.models {
+            let config_request = ModelConfigRequest {
+                name: model.name.clone(),
+                version: model.version.clone(),
+            };
+            if let Err(err) = self.model_config(Request::new(config_request)).await {
+                warn!(
+                    "could not warm up the config of model {}: {err}",
+                    model.name
+                );
+            }
+
+            let metadata_request = ModelMetadataRequest {
+                name: model.name.clone(),
+                version: model.version.clone(),
+            };
+            if let Err(err) = self.model_metadata(Request::new(metadata_request)).await {
+                warn!(
+                    "could not warm up the metadata of model {}: {err}",
+                    model.name
+                );
+            }
+        }
+
+        for path in &warmup.requests {
+            let request = fs::read(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_slice::<ModelInferRequest>(&content)?));
+
+            match request {
+                Ok(request) => {
+                    if let Err(err) = self.model_infer(Request::new(request)).await {
+                        warn!("warmup request {path} failed: {err}");
+                    }
+                }
+                Err(err) => warn!("could not read warmup request {path}: {err}"),
+            }
+        }
+
+        info!(
+            "Warmed up {} models and {} requests",
+            warmup.models.len(),
+            warmup.requests.len()
+        );
+    }
 }
 
 #[tonic::async_trait]
@@ -85,65 +1048,159 @@ impl GrpcInferenceService for InferenceStoreGrpcInferenceService {
 
     async fn server_metadata(
         &self,
-        _request: Request<ServerMetadataRequest>,
+        request: Request<ServerMetadataRequest>,
     ) -> Result<Response<ServerMetadataResponse>, Status> {
+        // In Collect mode the extensions are negotiated with the target server. When it can not
+        // be reached, or in Serve mode, all supported extensions are reported.
+        let extensions = match self.inference_service_client {
+            Some(_) => match self
+                .forward(request, |mut client, request| async move {
+                    client.server_metadata(request).await
+                })
+                .await
+            {
+                Ok(response) => extensions::negotiate(&response.get_ref().extensions),
+                Err(status) => {
+                    warn!("Could not negotiate extensions with the target server: {status}");
+                    extensions::supported()
+                }
+            },
+            None => extensions::supported(),
+        };
+
         Ok(Response::new(ServerMetadataResponse {
             name: String::from("Inference Store Server"),
             version: String::from("0.0.0"),
-            extensions: Vec::new(),
+            extensions,
         }))
     }
+
     async fn model_metadata(
         &self,
-        _request: Request<ModelMetadataRequest>,
+        request: Request<ModelMetadataRequest>,
     ) -> Result<Response<ModelMetadataResponse>, Status> {
-        Ok(Response::new(ModelMetadataResponse {
-            name: String::from("test"),
-            platform: String::from("test"),
-            inputs: Vec::new(),
-            outputs: Vec::new(),
-            versions: Vec::new(),
-        }))
-    }
-
-    async fn model_infer(
-        &self,
-        request: Request<ModelInferRequest>,
-    ) -> Result<Response<ModelInferResponse>, Status> {
-        let parsed_input = ProcessedInput::from_infer_request(request.get_ref().clone());
-
         if let Some(cached_output) = self
-            .inference_store
-            .find_output(&parsed_input, &self.settings.get_match_config())
+            .metadata_store
+            .find_output(request.get_ref(), &Default::default())
             .await
         {
-            let response = cached_output.to_response(request.get_ref().clone());
-            return Ok(Response::new(response));
+            return Ok(Response::new(cached_output));
         }
 
-        // When self.inference_service_client is None, Serve mode is enabled.
-        // In Serve mode only requests from cache will be served.
         let inference_service_client = match &self.inference_service_client {
             Some(client) => client,
-            None => return Err(Status::not_found("could not match request")),
+            None => {
+                return Err(ProxyError::TargetUnavailable(
+                    "uncached model metadata not available during serving mode".to_string(),
+                )
+                .into())
+            }
         };
 
-        let response = inference_service_client
-            .clone()
-            .model_infer(request)
-            .await?;
-
-        let processed_response = ProcessedOutput::from_response(response.get_ref());
-
-        if let Err(err) = self
-            .inference_store
-            .store(parsed_input, processed_response)
-            .await
+        let client_peer = request.remote_addr();
+        let started_at = Instant::now();
+        let mut client = inference_service_client.client();
+        let timeout = upstream_timeout(
+            self.settings
+                .read()
+                .await
+                .target_server
+                .model_config_timeout(),
+            request_deadline(&request),
+        );
+        let upstream_request = Request::new(request.get_ref().clone());
+        match call_upstream(upstream_request, timeout, move |request| async move {
+            client.model_metadata(request).await
+        })
+        .await
         {
-            return Err(Status::unknown(err.to_string()));
+            Ok(res) => {
+                let metadata = entry_metadata(
+                    &self.settings.read().await.target_server,
+                    started_at.elapsed(),
+                    client_peer,
+                );
+                if self.dry_run.is_some() {
+                    debug!(
+                        "Dry run: the metadata of model {} would be recorded",
+                        request.get_ref().name
+                    );
+                    return Ok(Response::new(res.into_inner()));
+                }
+                match self
+                    .metadata_store
+                    .store(request.into_inner(), res.get_ref().clone(), metadata)
+                    .await
+                {
+                    Ok((path, _)) => self.replicator.announce(&path),
+                    Err(err) => {
+                        let persist_failures = self
+                            .settings
+                            .read()
+                            .await
+                            .request_collection
+                            .persist_failures;
+                        if let Some(status) = persist_failure(persist_failures, err) {
+                            return Err(status);
+                        }
+                    }
+                }
+                Ok(Response::new(res.into_inner()))
+            }
+            // Errors of the target server are passed on unchanged.
+            Err(status) => Err(status),
         }
+    }
 
-        Ok(Response::new(response.into_inner()))
+    async fn model_infer(
+        &self,
+        request: Request<ModelInferRequest>,
+    ) -> Result<Response<ModelInferResponse>, Status> {
+        let mut trace = TraceGuard::new(
+            self.trace.clone(),
+            &request.get_ref().model_name,
+            &request.get_ref().model_version,
+        )
+        .with_statistics(self.statistics.clone())
+        .with_coverage(self.coverage.clone())
+        .with_middleware(self.middleware.clone());
+        trace.record.set_request_id(&request.get_ref().id);
+        let (normalization, split_batches) = {
+            let settings = self.settings.read().await;
+            (
+                settings.request_matching.normalization.clone(),
+                settings.request_matching.split_batches,
+            )
+        };
+        let samples = split_batches
+            .then(|| split_request(request.get_ref()))
+            .flatten();
+        let result = match samples {
+            Some(samples) => {
+                self.infer_samples(request, samples, &normalization, &mut trace.record)
+                    .await
+            }
+            None => match self
+                .infer(request, &normalization, &mut trace.record, false)
+                .await
+            {
+                Ok(Some(response)) => Ok(response),
+                // Only lookups return no response.
+                Ok(None) => {
+                    Err(ProxyError::MatchNotFound("could not match request".to_string()).into())
+                }
+                Err(status) => Err(status),
+            },
+        }
+        .map(|mut response| {
+            normalization.restore(response.get_mut());
+            response
+        });
+        if let Err(status) = &result {
+            trace.record.set_status(status.code());
+        }
+
+        result
     }
 
     type ModelStreamInferStream = ReceiverStream<Result<ModelStreamInferResponse, Status>>;
@@ -154,12 +1211,12 @@ impl GrpcInferenceService for InferenceStoreGrpcInferenceService {
     ) -> Result<Response<Self::ModelStreamInferStream>, Status> {
         debug!("Received model_stream_infer request");
 
+        let connect_info = request.extensions().get::<TcpConnectInfo>().cloned();
+        let deadline = request_deadline(&request);
+        let stream_metadata = request.metadata().clone();
         let mut stream = request.into_inner();
         let (tx, rx) = mpsc::channel(4);
-
-        let inference_service_client = self.inference_service_client.clone();
-        let inference_store = self.inference_store.clone();
-        let settings = self.settings.clone();
+        let service = self.clone();
 
         tokio::spawn(async move {
             while let Some(infer_request) = stream.next().await {
@@ -176,85 +1233,29 @@ impl GrpcInferenceService for InferenceStoreGrpcInferenceService {
                         return;
                     }
                 };
-                let parsed_input = ProcessedInput::from_infer_request(infer_request.clone());
-
-                if let Some(cached_output) = inference_store
-                    .find_output(&parsed_input, &settings.get_match_config())
-                    .await
-                {
-                    debug!("Found input in cache, return the cached output");
 
-                    let response = cached_output.to_stream_response(infer_request);
-                    if let Err(err) = tx.send(Ok(response)).await {
-                        warn!("sending cached response failed: {err}")
-                    }
-                    return;
+                // Every request of the stream is handled like a unary request, with the metadata,
+                // peer and remaining deadline of the stream.
+                let mut extensions = Extensions::default();
+                if let Some(connect_info) = connect_info.clone() {
+                    extensions.insert(connect_info);
                 }
-
-                // When self.inference_service_client is None, Serve mode is enabled.
-                // In Serve mode only requests from cache will be served.
-                let inference_service_client = match &inference_service_client {
-                    Some(client) => client,
-                    None => {
-                        if let Err(err) = tx
-                            .send(Err(Status::not_found("could not match request")))
-                            .await
-                        {
-                            warn!("sending inference error response failed: {err}")
-                        }
-
-                        return;
-                    }
-                };
-
-                debug!("Input not found in cache, calling the target grpc server");
-
-                let response = inference_service_client
-                    .clone()
-                    .model_infer(infer_request)
-                    .await;
-
-                let response = match response {
-                    Ok(response) => response,
-                    Err(err) => {
-                        debug!("Target GRPC server returned error: {err}");
-                        if let Err(err) = tx
-                            .send(Ok(ModelStreamInferResponse {
-                                error_message: err.to_string(),
-                                infer_response: None,
-                            }))
-                            .await
-                        {
-                            warn!("sending inference error response failed: {err}")
-                        }
-                        return;
-                    }
-                };
-
-                let processed_response = ProcessedOutput::from_response(response.get_ref());
-
-                debug!("Writing target GRPC server response to disk");
-
-                if let Err(err) = inference_store
-                    .store(parsed_input, processed_response)
-                    .await
-                {
-                    let _ = tx
-                        .send(Ok(ModelStreamInferResponse {
-                            error_message: format!("{err}"),
-                            infer_response: None,
-                        }))
-                        .await;
-                    return;
+                let mut request =
+                    Request::from_parts(stream_metadata.clone(), extensions, infer_request);
+                if let Some(deadline) = deadline {
+                    request.set_timeout(deadline.saturating_duration_since(Instant::now()));
                 }
 
-                if let Err(err) = tx
-                    .send(Ok(ModelStreamInferResponse {
+                // Like the target server, only the message of an error is streamed, and an error
+                // does not end the stream.
+                let response = match service.model_infer(request).await {
+                    Ok(response) => ModelStreamInferResponse {
                         error_message: "".to_string(),
                         infer_response: Some(response.into_inner()),
-                    }))
-                    .await
-                {
+                    },
+                    Err(status) => stream_error(&status),
+                };
+                if let Err(err) = tx.send(Ok(response)).await {
                     warn!("sending inference response failed: {err}")
                 }
             }
@@ -278,33 +1279,138 @@ impl GrpcInferenceService for InferenceStoreGrpcInferenceService {
         let inference_service_client = match &self.inference_service_client {
             Some(client) => client,
             None => {
-                return Err(Status::unavailable(
-                    "uncached model config not available during serving mode",
-                ))
+                // Clients that request the config before inferring still work when the model
+                // has recorded infer entries.
+                let ModelConfigRequest { name, version } = request.get_ref();
+                let entries = self
+                    .inference_store
+                    .entries(|input| {
+                        input.model_name == *name
+                            && (version.is_empty() || input.model_version == *version)
+                    })
+                    .await;
+
+                return match synthesize_config(name, &entries) {
+                    Some(config) => Ok(Response::new(config)),
+                    None => Err(ProxyError::TargetUnavailable(
+                        "uncached model config not available during serving mode".to_string(),
+                    )
+                    .into()),
+                };
             }
         };
 
-        match inference_service_client
-            .clone()
-            .model_config(request.get_ref().clone())
-            .await
+        let client_peer = request.remote_addr();
+        let started_at = Instant::now();
+        let mut client = inference_service_client.client();
+        let timeout = upstream_timeout(
+            self.settings
+                .read()
+                .await
+                .target_server
+                .model_config_timeout(),
+            request_deadline(&request),
+        );
+        let upstream_request = Request::new(request.get_ref().clone());
+        match call_upstream(upstream_request, timeout, move |request| async move {
+            client.model_config(request).await
+        })
+        .await
         {
             Ok(res) => {
-                self.config_store
-                    .store(request.into_inner(), res.get_ref().clone())
+                let metadata = entry_metadata(
+                    &self.settings.read().await.target_server,
+                    started_at.elapsed(),
+                    client_peer,
+                );
+                if self.dry_run.is_some() {
+                    debug!(
+                        "Dry run: the config of model {} would be recorded",
+                        request.get_ref().name
+                    );
+                    return Ok(Response::new(res.into_inner()));
+                }
+                match self
+                    .config_store
+                    .store(request.into_inner(), res.get_ref().clone(), metadata)
+                    .await
+                {
+                    Ok((path, _)) => self.replicator.announce(&path),
+                    Err(err) => {
+                        let persist_failures = self
+                            .settings
+                            .read()
+                            .await
+                            .request_collection
+                            .persist_failures;
+                        if let Some(status) = persist_failure(persist_failures, err) {
+                            return Err(status);
+                        }
+                    }
+                }
+                if self
+                    .settings
+                    .read()
                     .await
-                    .unwrap();
+                    .request_collection
+                    .record_ensemble_models
+                {
+                    // Recorded in the background, the client does not wait for the composing
+                    // models.
+                    let service = self.clone();
+                    let config = res.get_ref().clone();
+                    tokio::spawn(async move { service.record_composing_models(&config).await });
+                }
                 Ok(Response::new(res.get_ref().clone()))
             }
-            Err(err) => Err(Status::unknown(err.to_string())),
+            // Errors of the target server are passed on unchanged.
+            Err(status) => Err(status),
         }
     }
 
     async fn model_statistics(
         &self,
-        _request: Request<ModelStatisticsRequest>,
+        request: Request<ModelStatisticsRequest>,
     ) -> Result<Response<ModelStatisticsResponse>, Status> {
-        todo!()
+        let (dir, config_timeout) = {
+            let settings = self.settings.read().await;
+            (
+                PathBuf::from(&settings.request_collection.path),
+                settings.target_server.model_config_timeout(),
+            )
+        };
+
+        // The statistics of the target server are stored, so they can be served in Serve mode. A
+        // dry run does not write to the cache directory.
+        if let Some(pool) = &self.inference_service_client {
+            let mut client = pool.client();
+            let timeout = upstream_timeout(config_timeout, request_deadline(&request));
+            let response = call_upstream(request, timeout, move |request| async move {
+                client.model_statistics(request).await
+            })
+            .await?;
+            if self.dry_run.is_none() {
+                let model_stats = response.get_ref().model_stats.clone();
+                tokio::task::spawn_blocking(move || {
+                    if let Err(err) = statistics::write_cached(&dir, &model_stats) {
+                        warn!("could not store the model statistics: {err}");
+                    }
+                });
+            }
+            return Ok(response);
+        }
+
+        // In Serve mode the requests served from the cache are added to the stored statistics.
+        // Every version is merged, as requests without a version count for the served version.
+        let ModelStatisticsRequest { name, version } = request.get_ref();
+        let cached = statistics::read_cached(&dir, name, "").map_err(ProxyError::CacheRead)?;
+        let mut model_stats = statistics::merge(cached, self.statistics.model_statistics(name, ""));
+        model_stats.retain(|statistics| version.is_empty() || &statistics.version == version);
+        if model_stats.is_empty() && !name.is_empty() {
+            return Err(Status::not_found(format!("no statistics of model {name}")));
+        }
+
+        Ok(Response::new(ModelStatisticsResponse { model_stats }))
     }
 
     async fn repository_index(
@@ -316,71 +1422,686 @@ impl GrpcInferenceService for InferenceStoreGrpcInferenceService {
 
     async fn repository_model_load(
         &self,
-        _request: Request<RepositoryModelLoadRequest>,
+        request: Request<RepositoryModelLoadRequest>,
     ) -> Result<Response<RepositoryModelLoadResponse>, Status> {
-        todo!()
+        let model_name = request.get_ref().model_name.clone();
+        let response = self
+            .forward(request, |mut client, request| async move {
+                client.repository_model_load(request).await
+            })
+            .await?;
+
+        self.invalidate_model(&model_name).await;
+        Ok(response)
     }
 
     async fn repository_model_unload(
         &self,
-        _request: Request<RepositoryModelUnloadRequest>,
+        request: Request<RepositoryModelUnloadRequest>,
     ) -> Result<Response<RepositoryModelUnloadResponse>, Status> {
-        todo!()
+        let model_name = request.get_ref().model_name.clone();
+        let response = self
+            .forward(request, |mut client, request| async move {
+                client.repository_model_unload(request).await
+            })
+            .await?;
+
+        self.invalidate_model(&model_name).await;
+        Ok(response)
     }
 
     async fn system_shared_memory_status(
         &self,
-        _request: Request<SystemSharedMemoryStatusRequest>,
+        request: Request<SystemSharedMemoryStatusRequest>,
     ) -> Result<Response<SystemSharedMemoryStatusResponse>, Status> {
-        todo!()
+        self.forward(request, |mut client, request| async move {
+            client.system_shared_memory_status(request).await
+        })
+        .await
     }
 
     async fn system_shared_memory_register(
         &self,
-        _request: Request<SystemSharedMemoryRegisterRequest>,
+        request: Request<SystemSharedMemoryRegisterRequest>,
     ) -> Result<Response<SystemSharedMemoryRegisterResponse>, Status> {
-        todo!()
+        self.forward(request, |mut client, request| async move {
+            client.system_shared_memory_register(request).await
+        })
+        .await
     }
 
     async fn system_shared_memory_unregister(
         &self,
-        _request: Request<SystemSharedMemoryUnregisterRequest>,
+        request: Request<SystemSharedMemoryUnregisterRequest>,
     ) -> Result<Response<SystemSharedMemoryUnregisterResponse>, Status> {
-        todo!()
+        self.forward(request, |mut client, request| async move {
+            client.system_shared_memory_unregister(request).await
+        })
+        .await
     }
 
     async fn cuda_shared_memory_status(
         &self,
-        _request: Request<CudaSharedMemoryStatusRequest>,
+        request: Request<CudaSharedMemoryStatusRequest>,
     ) -> Result<Response<CudaSharedMemoryStatusResponse>, Status> {
-        todo!()
+        self.forward(request, |mut client, request| async move {
+            client.cuda_shared_memory_status(request).await
+        })
+        .await
     }
 
     async fn cuda_shared_memory_register(
         &self,
-        _request: Request<CudaSharedMemoryRegisterRequest>,
+        request: Request<CudaSharedMemoryRegisterRequest>,
     ) -> Result<Response<CudaSharedMemoryRegisterResponse>, Status> {
-        todo!()
+        self.forward(request, |mut client, request| async move {
+            client.cuda_shared_memory_register(request).await
+        })
+        .await
     }
 
     async fn cuda_shared_memory_unregister(
         &self,
-        _request: Request<CudaSharedMemoryUnregisterRequest>,
+        request: Request<CudaSharedMemoryUnregisterRequest>,
     ) -> Result<Response<CudaSharedMemoryUnregisterResponse>, Status> {
-        todo!()
+        self.forward(request, |mut client, request| async move {
+            client.cuda_shared_memory_unregister(request).await
+        })
+        .await
     }
 
     async fn trace_setting(
         &self,
-        _request: Request<TraceSettingRequest>,
+        request: Request<TraceSettingRequest>,
     ) -> Result<Response<TraceSettingResponse>, Status> {
-        todo!()
+        self.forward(request, |mut client, request| async move {
+            client.trace_setting(request).await
+        })
+        .await
     }
 
     async fn log_settings(
         &self,
-        _request: Request<LogSettingsRequest>,
+        mut request: Request<LogSettingsRequest>,
     ) -> Result<Response<LogSettingsResponse>, Status> {
-        todo!()
+        let level = take_log_level(request.get_mut())
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        if level.is_some() {
+            // Setting the log level of InferenceStore itself is an admin request.
+            let settings = self.settings.read().await;
+            if !settings.server.admin {
+                return Err(Status::permission_denied(format!(
+                    "{LOG_LEVEL_SETTING} can only be set with server.admin enabled"
+                )));
+            }
+            if let Some(status) = token_error(request.metadata(), &settings.server.admin_token) {
+                return Err(status);
+            }
+        }
+
+        // In Serve mode only the log level of InferenceStore itself can be queried and set.
+        let mut response = match self.inference_service_client {
+            None if request.get_ref().settings.is_empty() => LogSettingsResponse::default(),
+            _ => self
+                .forward(request, |mut client, request| async move {
+                    client.log_settings(request).await
+                })
+                .await?
+                .into_inner(),
+        };
+
+        if let Some(level) = level {
+            logging::set_level(level);
+            info!("Set the log level to {level}");
+        }
+        let (name, value) = log_level_setting();
+        response.settings.insert(name, value);
+
+        Ok(Response::new(response))
+    }
+}
+
+/// The number of times an input was sampled, counted from its recorded entries the first time.
+async fn sampled(
+    sample_attempts: &SampleAttempts,
+    inference_store: &CacheStore<CachableModelInfer>,
+    input: &ProcessedInput,
+    config: &MatchConfig,
+) -> usize {
+    let request_hash = input.request_hash();
+    if let Some(sampled) = sample_attempts.get(&request_hash) {
+        return sampled;
+    }
+
+    let recorded = inference_store.count(input, config).await;
+    sample_attempts.init(request_hash, recorded)
+}
+
+/// The result of a forwarded inference request, shared with the identical requests that waited
+/// for it, see `InFlight`.
+type LeaderResult = Result<ModelInferResponse, Box<Status>>;
+
+/// The metadata of an entry received from the target server.
+fn entry_metadata(
+    target_server: &TargetServer,
+    latency: Duration,
+    client_peer: Option<SocketAddr>,
+) -> EntryMetadata {
+    EntryMetadata::new(&target_server.host, latency, client_peer)
+        .with_target_name(&target_server.name)
+}
+
+/// The processed input of a sample of a batched request, of which the reserved parameters are
+/// taken already, to record it as an entry of its own.
+fn sample_input(
+    sample: ModelInferRequest,
+    namespace: &Option<String>,
+    scrubbing: &ScrubConfig,
+    store_inputs: bool,
+    match_config: &MatchConfig,
+) -> ProcessedInput {
+    let scrubbed_sample = scrubbing.scrub(sample);
+    let stored_inputs = store_inputs.then(|| raw_input_contents(&scrubbed_sample));
+    let mut input = ProcessedInput::from_infer_request(scrubbed_sample, match_config);
+    input.namespace = namespace.clone();
+    input.raw_input_contents = stored_inputs;
+    input
+}
+
+/// The W3C traceparent header of a request, if any.
+fn traceparent(metadata: &MetadataMap) -> Option<String> {
+    metadata
+        .get(TRACEPARENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// The labels of an entry recorded for a request: the configured labels, and the labels in the
+/// label header of the request.
+fn entry_labels(
+    request_collection: &RequestCollection,
+    metadata: &MetadataMap,
+) -> BTreeMap<String, String> {
+    let mut labels = request_collection.labels.clone();
+    if !request_collection.session.is_empty() {
+        labels.insert(
+            SESSION_LABEL.to_string(),
+            request_collection.session.clone(),
+        );
+    }
+    if request_collection.label_header.is_empty() {
+        return labels;
+    }
+
+    if let Some(value) = metadata
+        .get(request_collection.label_header.as_str())
+        .and_then(|value| value.to_str().ok())
+    {
+        labels.extend(parse_labels(value));
+    }
+
+    labels
+}
+
+/// Check the output contents of a response before it is recorded. Malformed responses are
+/// recorded with a warning, or not at all when `request_collection.malformed_responses` is refuse.
+fn is_recordable(
+    output: &ProcessedOutput,
+    model_name: &str,
+    malformed_responses: MalformedResponses,
+) -> bool {
+    let Err(err) = output.check_contents() else {
+        return true;
+    };
+
+    match malformed_responses {
+        MalformedResponses::Warn => {
+            warn!("recording malformed response of model {model_name}: {err:#}");
+            true
+        }
+        MalformedResponses::Refuse => {
+            warn!("not recording malformed response of model {model_name}: {err:#}");
+            false
+        }
+    }
+}
+
+/// The streamed response of a failed request. Streams can not carry the details of a status, so
+/// the kind of a proxy failure is sent as the `inferencestore-error` parameter of the response.
+fn stream_error(status: &Status) -> ModelStreamInferResponse {
+    let infer_response = error_kind(status).map(|kind| ModelInferResponse {
+        parameters: HashMap::from([(
+            ERROR_KIND_KEY.to_string(),
+            Parameter::StringParam(kind).to_infer_parameter(),
+        )]),
+        ..Default::default()
+    });
+
+    ModelStreamInferResponse {
+        error_message: status.message().to_string(),
+        infer_response,
+    }
+}
+
+/// Whether a request is part of a sequence, of which the requests must be sent once and in order.
+fn is_sequence_request(input: &ProcessedInput, request: &ModelInferRequest) -> bool {
+    input.sequence.is_some() || request.parameters.contains_key(SEQUENCE_ID)
+}
+
+/// Record an output in the background once enough of the consensus target servers agree on it,
+/// so the client does not wait for the other target servers.
+fn spawn_consensus_recording(
+    consensus: Arc<Consensus>,
+    request: ModelInferRequest,
+    output: ProcessedOutput,
+    timeout: Option<Duration>,
+    record: impl Future<Output = ()> + Send + 'static,
+) {
+    tokio::spawn(async move {
+        match consensus.agrees(&request, &output, timeout).await {
+            true => record.await,
+            false => info!("Not recording an output the target servers do not agree on"),
+        }
+    });
+}
+
+/// Send a request that is recorded to the target server again in the background, and compare the
+/// outputs to find nondeterministic models. The probe waits for the upstream queue behind the
+/// requests of clients, and is only bound by the configured timeout. A failed probe is logged.
+fn spawn_determinism_probe(
+    client: ChannelPool,
+    upstream_queue: Option<Arc<UpstreamQueue>>,
+    determinism: Arc<Determinism>,
+    request: ModelInferRequest,
+    response: ModelInferResponse,
+    request_hash: String,
+    timeout: Option<Duration>,
+) {
+    tokio::spawn(async move {
+        let model_name = request.model_name.clone();
+        let _permit = match &upstream_queue {
+            Some(queue) => match queue.acquire(i64::MIN).await {
+                Ok(permit) => Some(permit),
+                Err(status) => {
+                    warn!(
+                        "could not probe the determinism of model {model_name}: {}",
+                        status.message()
+                    );
+                    return;
+                }
+            },
+            None => None,
+        };
+        let mut client = client.client();
+        let probe_response =
+            call_upstream(Request::new(request), timeout, move |request| async move {
+                client.model_infer(request).await
+            })
+            .await;
+
+        match probe_response {
+            Ok(probe_response) => {
+                determinism.probe(
+                    &model_name,
+                    &request_hash,
+                    &ProcessedOutput::from_response(&response),
+                    &ProcessedOutput::from_response(probe_response.get_ref()),
+                );
+            }
+            Err(status) => warn!(
+                "could not probe the determinism of model {model_name}: {}",
+                status.message()
+            ),
+        }
+    });
+}
+
+/// Check if a request and its response fit in a cache entry, by their encoded size in bytes. Larger
+/// ones are forwarded without recording them, with a warning. A maximum of 0 is unlimited.
+fn fits_entry(model_name: &str, entry_bytes: usize, max_entry_bytes: u64) -> bool {
+    if max_entry_bytes == 0 || entry_bytes as u64 <= max_entry_bytes {
+        return true;
+    }
+
+    warn!(
+        "not recording request to model {model_name} of {entry_bytes} bytes, \
+         above request_collection.max_entry_bytes of {max_entry_bytes}"
+    );
+    false
+}
+
+/// Handle a response of the target server that could not be recorded: fail the request with the
+/// returned error, or log the failure so the response is still served, depending on
+/// `request_collection.persist_failures`.
+fn persist_failure(persist_failures: PersistFailures, err: anyhow::Error) -> Option<Status> {
+    match persist_failures {
+        PersistFailures::Serve => {
+            error!(
+                "could not record the response of the target server, serving it anyway: {err:#}"
+            );
+            None
+        }
+        PersistFailures::Fail => Some(ProxyError::from_write(err).into()),
+    }
+}
+
+/// The error of a request with tensors in shared memory, which can not be hashed as their contents
+/// are not part of the request.
+fn shared_memory_error(request: &ModelInferRequest) -> Option<Status> {
+    uses_shared_memory(request).then(|| {
+        Status::invalid_argument(
+            "tensors in shared memory can not be cached, send their contents in the request \
+             instead, or set request_collection.shared_memory to bypass",
+        )
+    })
+}
+
+/// Synthesize a response from the cached metadata, or else the cached config, of the requested
+/// model. A cached entry without a version is used when there is none for the requested version.
+/// Deterministic responses are seeded from the inputs hash of the processed input.
+/// Returns None when synthesizing is disabled or nothing is cached for the model.
+async fn synthesize(
+    config_store: &CacheStore<CachableModelConfig>,
+    metadata_store: &CacheStore<CachableModelMetadata>,
+    mode: SynthesizeResponses,
+    request: ModelInferRequest,
+    parsed_input: &ProcessedInput,
+) -> Result<Option<ModelInferResponse>, Status> {
+    if mode == SynthesizeResponses::Disable {
+        return Ok(None);
+    }
+
+    let seed = u64::from_le_bytes(parsed_input.inputs_hash());
+
+    for version in [request.model_version.clone(), String::new()] {
+        let metadata_request = ModelMetadataRequest {
+            name: request.model_name.clone(),
+            version: version.clone(),
+        };
+        if let Some(metadata) = metadata_store.find_output(&metadata_request, &()).await {
+            let specs = OutputSpec::from_metadata(&metadata);
+            return synthesize_response(request, &specs, mode, seed)
+                .map(Some)
+                .map_err(|err| Status::invalid_argument(err.to_string()));
+        }
+
+        let config_request = ModelConfigRequest {
+            name: request.model_name.clone(),
+            version,
+        };
+        if let Some(config) = config_store.find_output(&config_request, &()).await {
+            let specs = OutputSpec::from_config(&config);
+            return synthesize_response(request, &specs, mode, seed)
+                .map(Some)
+                .map_err(|err| Status::invalid_argument(err.to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tempdir::TempDir;
+    use tokio::net::TcpListener;
+    use tonic::codegen::tokio_stream;
+    use tonic::transport::{Endpoint, Server};
+
+    use super::*;
+    use crate::cli::Cli;
+    use crate::parsing::input::tests::raw_infer_request;
+    use crate::parsing::output::tests::BASE_INFER_OUTPUT;
+    use crate::raw_response::RawResponseServer;
+
+    /// A middleware that only accepts authorized requests, and keeps the authorization of the
+    /// forwarded requests.
+    #[derive(Default)]
+    struct Authorization {
+        forwarded: Mutex<Vec<String>>,
+    }
+
+    #[tonic::async_trait]
+    impl Middleware for Authorization {
+        async fn on_request(&self, request: &mut Request<ModelInferRequest>) -> Result<(), Status> {
+            match request.metadata().get("authorization") {
+                Some(_) => Ok(()),
+                None => Err(Status::unauthenticated("no authorization")),
+            }
+        }
+
+        async fn on_forward(&self, request: &mut Request<ModelInferRequest>) -> Result<(), Status> {
+            let authorization = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            self.forwarded
+                .lock()
+                .unwrap()
+                .push(authorization.to_string());
+            Ok(())
+        }
+    }
+
+    /// Create a service of which the store is in the directory, forwarding to the target server
+    /// when set.
+    fn service(dir: PathBuf, target: Option<ChannelPool>) -> InferenceStoreGrpcInferenceService {
+        let settings = Arc::new(RwLock::new(Settings::new(&Cli::default()).unwrap()));
+        let inference_store = Arc::new(CacheStore::new(dir.clone()));
+        let config_store = Arc::new(CacheStore::new(dir.clone()));
+        let metadata_store = Arc::new(CacheStore::new(dir.clone()));
+        let replicator = Arc::new(Replicator::new(
+            vec![],
+            String::new(),
+            inference_store.clone(),
+            config_store.clone(),
+            metadata_store.clone(),
+        ));
+        InferenceStoreGrpcInferenceService::new(
+            settings,
+            inference_store,
+            config_store,
+            metadata_store,
+            target,
+            replicator,
+            None,
+        )
+    }
+
+    /// Serve the service on a local port, and return a channel pool connected to it.
+    async fn serve(service: InferenceStoreGrpcInferenceService) -> ChannelPool {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            loop {
+                let accepted = listener.accept().await.map(|(stream, _)| stream);
+                if tx.send(accepted).await.is_err() {
+                    return;
+                }
+            }
+        });
+        tokio::spawn(
+            Server::builder()
+                .add_service(RawResponseServer::from_arc(Arc::new(service)))
+                .serve_with_incoming(ReceiverStream::new(rx)),
+        );
+
+        let endpoint = Endpoint::from_shared(format!("http://{addr}")).unwrap();
+        ChannelPool::new(vec![endpoint.connect_lazy()])
+    }
+
+    #[tokio::test]
+    async fn it_runs_the_middleware_for_unary_and_stream_requests() {
+        let target_dir = TempDir::new("inference_store_test").unwrap();
+        let dir = TempDir::new("inference_store_test").unwrap();
+        let request = raw_infer_request(vec![("INPUT0", "UINT8", vec![1], vec![1])]);
+
+        // The target server is an InferenceStore in Serve mode that has the response recorded.
+        let target = service(target_dir.path().to_path_buf(), None);
+        let match_config = target.settings.read().await.get_match_config();
+        let input = ProcessedInput::from_infer_request(request.clone(), &match_config);
+        target
+            .inference_store
+            .store(input, BASE_INFER_OUTPUT.clone(), Default::default())
+            .await
+            .unwrap();
+        let target = serve(target).await;
+
+        let middleware = Arc::new(Authorization::default());
+        let proxy =
+            service(dir.path().to_path_buf(), Some(target)).with_middleware(middleware.clone());
+
+        let unauthorized = proxy.model_infer(Request::new(request.clone())).await;
+        assert_eq!(
+            tonic::Code::Unauthenticated,
+            unauthorized.unwrap_err().code()
+        );
+        assert!(middleware.forwarded.lock().unwrap().is_empty());
+
+        // The requests of a stream are forwarded with the metadata of the stream.
+        let proxy = serve(proxy).await;
+        let mut stream_request = Request::new(tokio_stream::iter(vec![request]));
+        stream_request
+            .metadata_mut()
+            .insert("authorization", "Bearer token".parse().unwrap());
+        let mut responses = proxy
+            .client()
+            .model_stream_infer(stream_request)
+            .await
+            .unwrap()
+            .into_inner();
+        let response = responses.next().await.unwrap().unwrap();
+        assert_eq!("", response.error_message);
+        assert!(response.infer_response.is_some());
+        assert_eq!(
+            vec!["Bearer token".to_string()],
+            *middleware.forwarded.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_keeps_streaming_after_a_target_error() {
+        let target_dir = TempDir::new("inference_store_test").unwrap();
+        let dir = TempDir::new("inference_store_test").unwrap();
+        let missing = raw_infer_request(vec![("INPUT0", "UINT8", vec![1], vec![0])]);
+        let request = raw_infer_request(vec![("INPUT0", "UINT8", vec![1], vec![1])]);
+
+        // The target server only has the second request recorded, the first one fails.
+        let target = service(target_dir.path().to_path_buf(), None);
+        let match_config = target.settings.read().await.get_match_config();
+        let input = ProcessedInput::from_infer_request(request.clone(), &match_config);
+        target
+            .inference_store
+            .store(input, BASE_INFER_OUTPUT.clone(), Default::default())
+            .await
+            .unwrap();
+        let target = serve(target).await;
+
+        let proxy = serve(service(dir.path().to_path_buf(), Some(target))).await;
+        let stream_request = Request::new(tokio_stream::iter(vec![missing, request]));
+        let mut responses = proxy
+            .client()
+            .model_stream_infer(stream_request)
+            .await
+            .unwrap()
+            .into_inner();
+
+        let response = responses.next().await.unwrap().unwrap();
+        assert_eq!("could not match request", response.error_message);
+        let response = responses.next().await.unwrap().unwrap();
+        assert_eq!("", response.error_message);
+        assert!(response.infer_response.is_some());
+    }
+
+    #[tokio::test]
+    async fn it_splits_the_batches_of_streams() {
+        let dir = TempDir::new("inference_store_test").unwrap();
+        let service = service(dir.path().to_path_buf(), None);
+        service
+            .settings
+            .write()
+            .await
+            .request_matching
+            .split_batches = true;
+        let match_config = service.settings.read().await.get_match_config();
+        for sample in [1, 2] {
+            let request = raw_infer_request(vec![("INPUT0", "UINT8", vec![1, 1], vec![sample])]);
+            let input = ProcessedInput::from_infer_request(request, &match_config);
+            service
+                .inference_store
+                .store(input, BASE_INFER_OUTPUT.clone(), Default::default())
+                .await
+                .unwrap();
+        }
+
+        // The batch itself is not recorded, only its samples are.
+        let proxy = serve(service).await;
+        let batch = raw_infer_request(vec![("INPUT0", "UINT8", vec![2, 1], vec![1, 2])]);
+        let mut responses = proxy
+            .client()
+            .model_stream_infer(Request::new(tokio_stream::iter(vec![batch])))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let response = responses.next().await.unwrap().unwrap();
+        assert_eq!("", response.error_message);
+        assert_eq!(2, response.infer_response.unwrap().outputs[0].shape[0]);
+    }
+
+    #[test]
+    fn it_streams_the_kind_of_proxy_failures() {
+        let status = Status::from(ProxyError::MatchNotFound(
+            "could not match request".to_string(),
+        ));
+        let response = stream_error(&status);
+        assert_eq!("could not match request", response.error_message);
+        let parameter = response.infer_response.unwrap().parameters[ERROR_KIND_KEY].clone();
+        assert_eq!(
+            Some(Parameter::StringParam("match_not_found".to_string())),
+            Parameter::from_infer_parameter(parameter)
+        );
+
+        assert_eq!(
+            None,
+            stream_error(&Status::internal("model error")).infer_response
+        );
+    }
+
+    #[tokio::test]
+    async fn it_replays_raw_responses_as_recorded() {
+        let dir = TempDir::new("inference_store_test").unwrap();
+        let request = raw_infer_request(vec![("INPUT0", "UINT8", vec![1], vec![1])]);
+        let service = service(dir.path().to_path_buf(), None);
+
+        // The recorded response has a field that is unknown to this version, with tag 31.
+        let mut recorded = ModelInferResponse {
+            model_name: "test".to_string(),
+            model_version: "1".to_string(),
+            id: "recorded".to_string(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        recorded.extend([0xf8, 0x01, 0x07]);
+        let mut output = BASE_INFER_OUTPUT.clone();
+        output.raw_response = Some(recorded.clone().into());
+        let match_config = service.settings.read().await.get_match_config();
+        let input = ProcessedInput::from_infer_request(request.clone(), &match_config);
+        service
+            .inference_store
+            .store(input, output, Default::default())
+            .await
+            .unwrap();
+
+        let pool = serve(service).await;
+        let (response, raw_response) =
+            raw_response::model_infer(pool.channel(), Request::new(request))
+                .await
+                .unwrap();
+        assert!(raw_response.starts_with(&recorded));
+        assert_eq!("", response.get_ref().id);
     }
 }
@@ -1,9 +1,22 @@
-use crate::parsing::input::MatchConfig;
+use crate::caching::metadata::SESSION_LABEL;
+use crate::cli::Cli;
+use crate::parsing::input::{CandidateRanking, HashAlgorithm, MatchConfig, NearestNeighborConfig};
+use crate::parsing::normalization::{NormalizeConfig, RewriteConfig};
+use crate::parsing::script::MatchScript;
+use crate::parsing::scrubbing::ScrubConfig;
+use crate::parsing::synthesize::SynthesizeResponses;
 use config::{Config, Environment, File};
-use serde::Deserialize;
-use std::collections::HashMap;
+use log::{error, info};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::RwLock;
+use tonic::transport::{self, Endpoint};
 
-#[derive(Deserialize, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
 #[allow(unused)]
 pub enum ServerMode {
     // Collect responses to later be used.
@@ -13,23 +26,311 @@ pub enum ServerMode {
     // Serve cached responses.
     #[serde(alias = "serve")]
     Serve,
+
+    // Forward every request like Collect mode, and log what would be served from the cache and
+    // recorded, without writing to the cache.
+    #[serde(alias = "dry_run")]
+    DryRun,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 #[allow(unused)]
 pub struct TargetServer {
     pub host: String,
+
+    // The identity of the target server, recorded in the entries. When empty in Collect mode,
+    // the name and version reported by the target server are used.
+    pub name: String,
+
+    // The time in milliseconds the target server may take to respond to an inference request.
+    // 0 disables the timeout.
+    pub infer_timeout: u64,
+
+    // The time in milliseconds the target server may take to respond to a model config or
+    // metadata request. 0 disables the timeout.
+    pub config_timeout: u64,
+
+    // The maximum number of concurrent inference requests to the target server. Requests beyond
+    // it wait in a queue. 0 disables the limit.
+    pub max_concurrency: usize,
+
+    // The maximum number of inference requests waiting for the target server, requests that do
+    // not fit are rejected with RESOURCE_EXHAUSTED.
+    pub max_queued: usize,
+
+    // The number of channels to the target server, each with its own connection. Calls are
+    // spread over the channels round-robin.
+    pub channels: usize,
+
+    // The keepalive and flow control settings of the channel to the target server.
+    pub connection: Connection,
+
+    // Other target servers of which the outputs are compared with the output of the target server
+    // before it is recorded in Collect mode. Empty disables consensus recording.
+    pub consensus_hosts: Vec<String>,
+
+    // The number of target servers, including the target server itself, that have to agree on
+    // the output for it to be recorded. 0 requires all of them.
+    pub consensus_quorum: usize,
+
+    // The maximum difference between numeric output elements that are considered equal.
+    pub consensus_tolerance: f64,
+
+    // In Collect mode, the percentile of the recent latencies of the target server after which a
+    // duplicate inference request is sent, of which the first response is used, e.g. 95. 0
+    // disables hedging.
+    pub hedge_percentile: f64,
+}
+
+impl TargetServer {
+    pub fn model_infer_timeout(&self) -> Option<Duration> {
+        (self.infer_timeout > 0).then(|| Duration::from_millis(self.infer_timeout))
+    }
+
+    pub fn model_config_timeout(&self) -> Option<Duration> {
+        (self.config_timeout > 0).then(|| Duration::from_millis(self.config_timeout))
+    }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 #[allow(unused)]
 pub struct Server {
     pub host: String,
 
     pub port: u16,
+
+    // When true, responses contain the `inferencestore.hit` and `inferencestore.entry` parameters.
+    pub annotate_responses: bool,
+
+    // When true, the admin service to list and invalidate cache entries is exposed.
+    pub admin: bool,
+
+    // The token admin requests present, including the requests that set the log level. Every admin
+    // request is accepted when empty. Redacted when the settings are printed.
+    #[serde(serialize_with = "redacted")]
+    pub admin_token: String,
+
+    // The addresses to listen on, replacing `host` and `port` when not empty.
+    pub listeners: Vec<Listener>,
+
+    // The address to serve the HTTP liveness and readiness probes on, disabled when empty.
+    pub probe_address: String,
+
+    // The address to serve the read-only web dashboard to browse the cache on, disabled when
+    // empty.
+    pub dashboard_address: String,
+
+    // The keepalive and flow control settings of the accepted connections.
+    pub connection: Connection,
+}
+
+impl Server {
+    /// The configured listeners, or a single listener on `host` and `port` exposing all services.
+    pub fn listeners(&self) -> Vec<Listener> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+
+        vec![Listener {
+            host: self.host.clone(),
+            port: self.port,
+            services: vec![],
+        }]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[allow(unused)]
+pub struct Connection {
+    // The interval in milliseconds between HTTP/2 keepalive pings, 0 disables them.
+    pub keepalive_interval: u64,
+
+    // The time in milliseconds a keepalive ping may take to be acknowledged before the
+    // connection is closed, hyper's default of 20 seconds is used when 0.
+    pub keepalive_timeout: u64,
+
+    // The interval in milliseconds between TCP keepalive probes, 0 disables them.
+    pub tcp_keepalive: u64,
+
+    pub tcp_nodelay: bool,
+
+    // The initial HTTP/2 flow control windows of a stream and of a connection in bytes, hyper's
+    // defaults are used when 0.
+    pub initial_stream_window_size: u32,
+
+    pub initial_connection_window_size: u32,
+}
+
+impl Connection {
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        (self.keepalive_interval > 0).then(|| Duration::from_millis(self.keepalive_interval))
+    }
+
+    pub fn keepalive_timeout(&self) -> Option<Duration> {
+        (self.keepalive_timeout > 0).then(|| Duration::from_millis(self.keepalive_timeout))
+    }
+
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        (self.tcp_keepalive > 0).then(|| Duration::from_millis(self.tcp_keepalive))
+    }
+
+    fn window_size(size: u32) -> Option<u32> {
+        (size > 0).then_some(size)
+    }
+
+    /// Apply the settings to the server accepting the connections.
+    pub fn configure_server(&self, server: transport::Server) -> transport::Server {
+        server
+            .http2_keepalive_interval(self.keepalive_interval())
+            .http2_keepalive_timeout(self.keepalive_timeout())
+            .tcp_keepalive(self.tcp_keepalive())
+            .tcp_nodelay(self.tcp_nodelay)
+            .initial_stream_window_size(Self::window_size(self.initial_stream_window_size))
+            .initial_connection_window_size(Self::window_size(self.initial_connection_window_size))
+    }
+
+    /// An endpoint to `host` with the settings applied. Keepalive pings are sent on idle
+    /// connections as well, so load balancers do not drop them.
+    pub fn endpoint(&self, host: &str) -> anyhow::Result<Endpoint> {
+        let mut endpoint = Endpoint::from_shared(host.to_string())?
+            .tcp_keepalive(self.tcp_keepalive())
+            .tcp_nodelay(self.tcp_nodelay)
+            .initial_stream_window_size(Self::window_size(self.initial_stream_window_size))
+            .initial_connection_window_size(Self::window_size(self.initial_connection_window_size));
+        if let Some(interval) = self.keepalive_interval() {
+            endpoint = endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(true);
+        }
+        if let Some(timeout) = self.keepalive_timeout() {
+            endpoint = endpoint.keep_alive_timeout(timeout);
+        }
+
+        Ok(endpoint)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[allow(unused)]
+pub struct Listener {
+    // The host to bind to, IPv6 addresses are written without brackets.
+    pub host: String,
+
+    pub port: u16,
+
+    // The services exposed on this listener, all enabled services when empty.
+    #[serde(default)]
+    pub services: Vec<ListenerService>,
+}
+
+impl Listener {
+    pub fn address(&self) -> anyhow::Result<SocketAddr> {
+        let ip: IpAddr = self.host.parse()?;
+        Ok(SocketAddr::new(ip, self.port))
+    }
+
+    pub fn exposes(&self, service: ListenerService) -> bool {
+        self.services.is_empty() || self.services.contains(&service)
+    }
 }
 
-#[derive(Deserialize, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+#[allow(unused)]
+pub enum ListenerService {
+    // The inference service.
+    #[serde(alias = "inference")]
+    Inference,
+
+    // The replication service, only available in cluster mode.
+    #[serde(alias = "replication")]
+    Replication,
+
+    // The admin service, only available when `server.admin` is enabled.
+    #[serde(alias = "admin")]
+    Admin,
+
+    // The remote cache service, only available when `cluster.serve_remote_cache` is enabled.
+    #[serde(alias = "remote_cache")]
+    RemoteCache,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[allow(unused)]
+pub enum SharedMemory {
+    // Forward requests with tensors in shared memory without using or updating the cache.
+    #[serde(alias = "bypass")]
+    Bypass,
+
+    // Reject requests with tensors in shared memory with INVALID_ARGUMENT.
+    #[serde(alias = "reject")]
+    Reject,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[allow(unused)]
+pub enum NondeterministicModels {
+    // Forward the requests without serving them from or recording them in the cache.
+    #[serde(alias = "bypass")]
+    Bypass,
+
+    // Record multiple outputs per input, `samples` but at least 2.
+    #[serde(alias = "sample")]
+    Sample,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[allow(unused)]
+pub enum FlushPolicy {
+    // Sync every cache file to disk before it is used.
+    #[serde(alias = "always")]
+    Always,
+
+    // Sync the new cache files every flush_interval seconds.
+    #[serde(alias = "interval")]
+    Interval,
+
+    // Sync the new cache files when InferenceStore shuts down.
+    #[serde(alias = "shutdown")]
+    Shutdown,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[allow(unused)]
+pub enum MalformedResponses {
+    // Record responses of which the output contents do not fit their shapes, with a warning.
+    #[serde(alias = "warn")]
+    Warn,
+
+    // Do not record such responses, they are still returned to the client.
+    #[serde(alias = "refuse")]
+    Refuse,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[allow(unused)]
+pub enum IndexOverBudget {
+    // Switch to low memory mode, and shrink the entries that are already loaded.
+    #[serde(alias = "low_memory")]
+    LowMemory,
+
+    // Fail to start with an error.
+    #[serde(alias = "refuse")]
+    Refuse,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[allow(unused)]
+pub enum PersistFailures {
+    // Return the response of the target server to the client, and log the failure to record it.
+    #[serde(alias = "serve")]
+    Serve,
+
+    // Fail the request with UNKNOWN.
+    #[serde(alias = "fail")]
+    Fail,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
 #[allow(unused)]
 pub enum ParameterMatching {
     // Do not match any parameters.
@@ -45,7 +346,7 @@ pub enum ParameterMatching {
     IgnoreKeys,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 #[allow(unused)]
 pub struct RequestMatching {
     // When true, the requests id of an incoming request needs to be equal to the request id of a cached request to be considered a match.
@@ -71,15 +372,292 @@ pub struct RequestMatching {
 
     // When true, an incoming request that has a subset of outputs of a cached request, is considered matched.
     pub match_pruned_output: bool,
+
+    // The input tensors per model that are not considered when matching, e.g. a timestamp tensor.
+    pub ignored_input_tensors: HashMap<String, Vec<String>>,
+
+    // Shape patterns per input tensor, where a -1 dimension matches any size.
+    pub input_shape_patterns: HashMap<String, Vec<i64>>,
+
+    // When true, the first (batch) dimension of the input shapes is not compared.
+    pub ignore_batch_dimension: bool,
+
+    // When true, batched inference requests are split in a request per sample, of which the
+    // cached samples are served from the cache and the others are forwarded.
+    pub split_batches: bool,
+
+    // When true, the model version of an incoming request is not compared to the cached requests.
+    pub ignore_model_version: bool,
+
+    // The models for which the model version is not compared, regardless of ignore_model_version.
+    pub ignore_model_version_models: Vec<String>,
+
+    // When set, float input tensors (FP16, BF16, FP32 and FP64) are rounded to this number of significant digits before hashing.
+    pub float_significant_digits: Option<u32>,
+
+    // The BYTES input tensors per model of which the elements are JSON documents, which are canonicalized before hashing.
+    pub json_input_tensors: HashMap<String, Vec<String>>,
+
+    // The paths of Rhai match scripts per model, that replace all other matching rules of that model.
+    pub match_scripts: HashMap<String, String>,
+
+    // Nearest neighbor matching of embedding inputs per model, used in Serve mode when no exact match is found.
+    pub nearest_neighbor: HashMap<String, NearestNeighborConfig>,
+
+    // The criteria, in order of importance, used to choose between multiple matching cached requests.
+    // When empty, the first match is used.
+    pub candidate_ranking: Vec<CandidateRanking>,
+
+    // Synthesize a response from the cached model metadata or config in Serve mode when no match is found.
+    pub synthesize_responses: SynthesizeResponses,
+
+    // When true, requests to stateful models are matched by their position in the sequence instead of by their sequence parameters,
+    // and recorded sequences are replayed in order.
+    pub sequence_matching: bool,
+
+    // When true, the recorded sequence id in a response parameter is replaced by the sequence id of the incoming request.
+    pub rewrite_sequence_ids: bool,
+
+    // The algorithm used to hash the contents of input tensors: blake2, blake3 or xxh3.
+    pub hash_algorithm: HashAlgorithm,
+
+    // When true, only entries recorded against the target server identity are matched, so a
+    // cache directory can be shared between different target servers.
+    pub partition_by_target: bool,
+
+    // The normalization of incoming requests before they are matched, so existing caches keep
+    // matching when the naming conventions of clients drift.
+    pub normalization: NormalizeConfig,
+
+    // The rewriting of cached responses before they are served, so they can be adapted to newer
+    // client expectations without re-recording them.
+    pub response_rewrites: RewriteConfig,
+
+    // In Serve mode, only the infer entries recorded with all these labels are served, so one
+    // cache directory can hold multiple fixture sets.
+    pub serve_labels: BTreeMap<String, String>,
+
+    // In Serve mode, only the infer entries recorded in this session are served. Empty serves
+    // the entries of all sessions.
+    pub serve_session: String,
+
+    // In Serve mode, only the infer entries recorded before this time, in milliseconds since the
+    // unix epoch, are served, to reproduce the cache used by an older test run. 0 serves all
+    // entries.
+    pub serve_as_of: i64,
+
+    // In Serve mode, the cache directories consulted in order for the requests that miss the
+    // cache directory at the path, e.g. a shared base set behind branch-specific fixtures.
+    pub serve_fallback_paths: Vec<String>,
+
+    // The models of which the infer entries are consumed at their first hit, so identical
+    // requests that follow miss until the usage is reset through the admin service.
+    pub single_use_models: Vec<String>,
+
+    // The compiled match scripts per model.
+    #[serde(skip)]
+    pub compiled_match_scripts: HashMap<String, Arc<MatchScript>>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 #[allow(unused)]
 pub struct RequestCollection {
     pub path: String,
+
+    // The interval in seconds at which files written by other processes sharing the cache
+    // directory are loaded. 0 disables refreshing.
+    pub refresh_interval: u64,
+
+    // A directory the entries are stored in as well, e.g. a network mount shared by instances
+    // that each keep their own cache directory. Entries in it are loaded at start and at every
+    // refresh. Empty disables it.
+    pub backend_dir: String,
+
+    // The file the hit counts and last hit times of the infer entries are persisted to, e.g.
+    // `<path>/usage.json`. Empty keeps them in memory only.
+    pub usage_path: String,
+
+    // The interval in seconds at which the usage file is written, it is written at shutdown as
+    // well.
+    pub usage_interval: u64,
+
+    // The interval in seconds at which the requests that would be served and recorded are
+    // reported in DryRun mode, they are reported at shutdown as well.
+    pub dry_run_report_interval: u64,
+
+    // The scrubbing of sensitive data from requests before they are recorded.
+    pub scrubbing: ScrubConfig,
+
+    // When true, the (scrubbed) input contents are stored as well, so they can be exported.
+    pub store_inputs: bool,
+
+    // When true, new entries are written as canonical JSON with sorted keys and without the
+    // metadata that differs per recording, so recording identical traffic again gives no diff in
+    // version control.
+    pub canonical_json: bool,
+
+    // When true, only the hashes and models of the entries are kept in memory, and the inputs of
+    // candidates are read from disk while matching.
+    pub low_memory: bool,
+
+    // The maximum estimated memory in bytes of the entries loaded at start, including the entries
+    // of the fallback directories. 0 is unlimited.
+    pub max_index_bytes: u64,
+
+    // How loading is handled when the loaded entries exceed `max_index_bytes`.
+    pub index_over_budget: IndexOverBudget,
+
+    // When true, new entries are stored in a directory per model and version,
+    // `<path>/<model name>/<model version>/`, of which the entries are loaded at the first
+    // request to the model.
+    pub model_directories: bool,
+
+    // In Collect mode, read-only cache directories consulted in order for the requests that miss
+    // the cache directory at the path. New entries are only recorded to the path.
+    pub base_paths: Vec<String>,
+
+    // The number of distinct outputs recorded per input in Collect mode, for nondeterministic
+    // models. Inputs with fewer recorded outputs are forwarded to the target server.
+    pub samples: usize,
+
+    // When true, inputs that are recorded in Collect mode are sent to the target server twice,
+    // and their model is marked nondeterministic when the outputs differ. The nondeterministic
+    // models are persisted in `<path>/determinism.json`.
+    pub determinism_probes: bool,
+
+    // How the requests to models marked nondeterministic are handled in Collect mode.
+    pub nondeterministic_models: NondeterministicModels,
+
+    // Also invalidate the infer entries of a model when it is loaded or unloaded through the
+    // model repository API. Its config and metadata are always invalidated.
+    pub invalidate_infer_on_load: bool,
+
+    // How requests with tensors in shared memory are handled, their contents can not be hashed.
+    pub shared_memory: SharedMemory,
+
+    // How responses are handled of which the output contents do not fit the shapes and
+    // datatypes of the outputs, e.g. truncated responses of a misbehaving target server.
+    pub malformed_responses: MalformedResponses,
+
+    // How requests are handled of which the response of the target server could not be recorded,
+    // e.g. because it could not be serialized or written.
+    pub persist_failures: PersistFailures,
+
+    // The maximum size in bytes of a request and its response together. Larger ones are
+    // forwarded to the target server without recording them. 0 is unlimited.
+    pub max_entry_bytes: u64,
+
+    // When true, infer responses are also recorded as the exact bytes the target server encoded
+    // them in, which are replayed instead of a response built from the recorded outputs.
+    pub raw_responses: bool,
+
+    // When true, the samples of batched requests are recorded as entries of their own as well, so
+    // unbatched requests for the same samples match.
+    pub record_batch_samples: bool,
+
+    // When new cache files are synced to disk: always, at an interval or at shutdown. Deferred
+    // syncs trade durability for throughput when recording high-QPS traffic.
+    pub flush_policy: FlushPolicy,
+
+    // The interval in seconds at which new cache files are synced with the interval flush policy.
+    pub flush_interval: u64,
+
+    // When true, the configs and metadata of the models composing an ensemble are recorded
+    // together with the config of the ensemble, so Serve mode answers them for the whole
+    // ensemble.
+    pub record_ensemble_models: bool,
+
+    // The grpc status codes of target server errors that are recorded and replayed, e.g.
+    // invalid_argument. Errors are not recorded when empty.
+    pub record_errors: Vec<String>,
+
+    // Labels stored with every recorded infer entry, e.g. the git SHA or the dataset name, so the
+    // provenance of fixtures can be queried.
+    pub labels: BTreeMap<String, String>,
+
+    // The request metadata header with labels per request, as `key=value,key=value`. They are
+    // stored together with the configured labels, and replace configured labels with the same
+    // key. Empty disables it.
+    pub label_header: String,
+
+    // The session the entries are recorded in, stored as the session label. Empty records the
+    // entries without a session.
+    pub session: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[allow(unused)]
+pub struct Cluster {
+    // The addresses of the peer instances new cache entries are replicated to, e.g.
+    // http://inferencestore-1:50051. Replication is disabled when no peers are configured.
+    pub peers: Vec<String>,
+
+    // The address the peers use to reach this instance.
+    pub advertise_address: String,
+
+    // The address of a central instance consulted for the inference requests that miss the cache,
+    // before they are forwarded to the target server, e.g. http://inferencestore-central:50051.
+    // Empty disables the remote cache.
+    pub remote_cache: String,
+
+    // When true, the entries recorded by this instance are pushed to the remote cache.
+    pub remote_cache_push: bool,
+
+    // When true, this instance serves as the remote cache of other instances.
+    pub serve_remote_cache: bool,
+
+    // The time in milliseconds a lookup in the remote cache may take, after which the request is
+    // forwarded to the target server. 0 disables the timeout.
+    pub remote_cache_timeout: u64,
+
+    // The token instances present to the remote cache. The remote cache only serves requests with
+    // this token when set, and only accepts pushed entries when set. Redacted when the settings
+    // are printed.
+    #[serde(serialize_with = "redacted")]
+    pub remote_cache_token: String,
+}
+
+impl Cluster {
+    pub fn remote_cache_timeout(&self) -> Option<Duration> {
+        (self.remote_cache_timeout > 0).then(|| Duration::from_millis(self.remote_cache_timeout))
+    }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
+#[allow(unused)]
+pub struct Trace {
+    // The path of the JSONL trace of all proxied inference requests. Empty disables tracing.
+    pub path: String,
+
+    // The size in bytes at which the trace is rotated to `<path>.1`. 0 disables rotation.
+    pub max_size: u64,
+
+    // The path to write the coverage report to at shutdown, with the infer entries that were
+    // never hit and the requests that missed the cache. Empty disables the report.
+    pub coverage_report: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[allow(unused)]
+pub struct WarmupModel {
+    pub name: String,
+
+    // The model version, an empty version selects the version chosen by the target server.
+    #[serde(default)]
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[allow(unused)]
+pub struct Warmup {
+    // The models of which the config and metadata are cached at startup in Collect mode.
+    pub models: Vec<WarmupModel>,
+
+    // Paths to JSON encoded inference requests that are run at startup in Collect mode.
+    pub requests: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 #[allow(unused)]
 pub struct Settings {
     pub debug: bool,
@@ -88,16 +666,63 @@ pub struct Settings {
     pub target_server: TargetServer,
     pub request_matching: RequestMatching,
     pub request_collection: RequestCollection,
+    pub cluster: Cluster,
+    pub warmup: Warmup,
+    pub trace: Trace,
+}
+
+/// Serialize a secret as a placeholder when it is set, so printed settings do not leak it.
+fn redacted<S: Serializer>(secret: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    match secret.is_empty() {
+        true => serializer.serialize_str(""),
+        false => serializer.serialize_str("<redacted>"),
+    }
 }
 
 impl Settings {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new(cli: &Cli) -> anyhow::Result<Self> {
+        let config_file = match &cli.config {
+            Some(path) => File::with_name(path).required(true),
+            None => File::with_name("inferencestore").required(false),
+        };
+
         let s = Config::builder()
             .set_default("debug", false)?
             .set_default("mode", "collect")?
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 50051u16)?
+            .set_default("server.annotate_responses", false)?
+            .set_default("server.admin", false)?
+            .set_default("server.admin_token", "")?
+            .set_default("server.listeners", Vec::<String>::new())?
+            .set_default("server.probe_address", "")?
+            .set_default("server.dashboard_address", "")?
+            .set_default("server.connection.keepalive_interval", 0u64)?
+            .set_default("server.connection.keepalive_timeout", 0u64)?
+            .set_default("server.connection.tcp_keepalive", 0u64)?
+            .set_default("server.connection.tcp_nodelay", true)?
+            .set_default("server.connection.initial_stream_window_size", 0u64)?
+            .set_default("server.connection.initial_connection_window_size", 0u64)?
             .set_default("target_server.host", "http://localhost:8001")?
+            .set_default("target_server.name", "")?
+            .set_default("target_server.infer_timeout", 0u64)?
+            .set_default("target_server.config_timeout", 0u64)?
+            .set_default("target_server.max_concurrency", 0u64)?
+            .set_default("target_server.max_queued", 100u64)?
+            .set_default("target_server.channels", 1u64)?
+            .set_default("target_server.consensus_hosts", Vec::<String>::new())?
+            .set_default("target_server.consensus_quorum", 0u64)?
+            .set_default("target_server.consensus_tolerance", 0.0)?
+            .set_default("target_server.hedge_percentile", 0.0)?
+            .set_default("target_server.connection.keepalive_interval", 0u64)?
+            .set_default("target_server.connection.keepalive_timeout", 0u64)?
+            .set_default("target_server.connection.tcp_keepalive", 0u64)?
+            .set_default("target_server.connection.tcp_nodelay", true)?
+            .set_default("target_server.connection.initial_stream_window_size", 0u64)?
+            .set_default(
+                "target_server.connection.initial_connection_window_size",
+                0u64,
+            )?
             .set_default("request_matching.match_id", false)?
             .set_default("request_matching.parameter_matching", "disable")?
             .set_default("request_matching.parameter_keys", Vec::<String>::new())?
@@ -112,19 +737,199 @@ impl Settings {
                 HashMap::<String, Vec<String>>::new(),
             )?
             .set_default("request_matching.match_pruned_output", false)?
-            .set_default("request_collection.path", "inferencestore")
-            .unwrap()
-            .add_source(File::with_name("inferencestore").required(false))
+            .set_default(
+                "request_matching.ignored_input_tensors",
+                HashMap::<String, Vec<String>>::new(),
+            )?
+            .set_default(
+                "request_matching.input_shape_patterns",
+                HashMap::<String, Vec<i64>>::new(),
+            )?
+            .set_default("request_matching.ignore_batch_dimension", false)?
+            .set_default("request_matching.split_batches", false)?
+            .set_default("request_matching.ignore_model_version", false)?
+            .set_default(
+                "request_matching.nearest_neighbor",
+                HashMap::<String, String>::new(),
+            )?
+            .set_default("request_matching.candidate_ranking", Vec::<String>::new())?
+            .set_default("request_matching.synthesize_responses", "disable")?
+            .set_default("request_matching.sequence_matching", false)?
+            .set_default("request_matching.rewrite_sequence_ids", false)?
+            .set_default("request_matching.hash_algorithm", "blake2")?
+            .set_default("request_matching.partition_by_target", false)?
+            .set_default(
+                "request_matching.normalization.strip_parameters",
+                Vec::<String>::new(),
+            )?
+            .set_default(
+                "request_matching.normalization.model_versions",
+                HashMap::<String, String>::new(),
+            )?
+            .set_default(
+                "request_matching.normalization.rename_tensors",
+                HashMap::<String, HashMap<String, String>>::new(),
+            )?
+            .set_default("request_matching.normalization.strip_id", false)?
+            .set_default(
+                "request_matching.response_rewrites.model_versions",
+                HashMap::<String, String>::new(),
+            )?
+            .set_default(
+                "request_matching.response_rewrites.parameters",
+                HashMap::<String, HashMap<String, String>>::new(),
+            )?
+            .set_default(
+                "request_matching.response_rewrites.rename_outputs",
+                HashMap::<String, HashMap<String, String>>::new(),
+            )?
+            .set_default(
+                "request_matching.serve_labels",
+                HashMap::<String, String>::new(),
+            )?
+            .set_default("request_matching.serve_session", "")?
+            .set_default("request_matching.serve_as_of", 0i64)?
+            .set_default(
+                "request_matching.serve_fallback_paths",
+                Vec::<String>::new(),
+            )?
+            .set_default("request_matching.single_use_models", Vec::<String>::new())?
+            .set_default(
+                "request_matching.match_scripts",
+                HashMap::<String, String>::new(),
+            )?
+            .set_default(
+                "request_matching.json_input_tensors",
+                HashMap::<String, Vec<String>>::new(),
+            )?
+            .set_default(
+                "request_matching.ignore_model_version_models",
+                Vec::<String>::new(),
+            )?
+            .set_default("request_collection.path", "inferencestore")?
+            .set_default("request_collection.refresh_interval", 0u64)?
+            .set_default("request_collection.usage_path", "")?
+            .set_default("request_collection.usage_interval", 60u64)?
+            .set_default("request_collection.dry_run_report_interval", 60u64)?
+            .set_default("request_collection.backend_dir", "")?
+            .set_default("request_collection.store_inputs", false)?
+            .set_default("request_collection.canonical_json", false)?
+            .set_default("request_collection.low_memory", false)?
+            .set_default("request_collection.max_index_bytes", 0u64)?
+            .set_default("request_collection.index_over_budget", "low_memory")?
+            .set_default("request_collection.model_directories", false)?
+            .set_default("request_collection.base_paths", Vec::<String>::new())?
+            .set_default("request_collection.samples", 1u64)?
+            .set_default("request_collection.determinism_probes", false)?
+            .set_default("request_collection.nondeterministic_models", "bypass")?
+            .set_default("request_collection.invalidate_infer_on_load", false)?
+            .set_default("request_collection.shared_memory", "bypass")?
+            .set_default("request_collection.malformed_responses", "warn")?
+            .set_default("request_collection.persist_failures", "serve")?
+            .set_default("request_collection.max_entry_bytes", 0u64)?
+            .set_default("request_collection.raw_responses", false)?
+            .set_default("request_collection.record_batch_samples", false)?
+            .set_default("request_collection.flush_policy", "always")?
+            .set_default("request_collection.flush_interval", 1u64)?
+            .set_default("request_collection.record_ensemble_models", false)?
+            .set_default("request_collection.record_errors", Vec::<String>::new())?
+            .set_default(
+                "request_collection.labels",
+                HashMap::<String, String>::new(),
+            )?
+            .set_default("request_collection.label_header", "inferencestore-labels")?
+            .set_default("request_collection.session", "")?
+            .set_default(
+                "request_collection.scrubbing.drop_parameters",
+                Vec::<String>::new(),
+            )?
+            .set_default(
+                "request_collection.scrubbing.hash_parameters",
+                Vec::<String>::new(),
+            )?
+            .set_default(
+                "request_collection.scrubbing.zero_input_tensors",
+                HashMap::<String, Vec<String>>::new(),
+            )?
+            .set_default("request_collection.scrubbing.strip_request_id", false)?
+            .set_default("cluster.peers", Vec::<String>::new())?
+            .set_default("cluster.advertise_address", "")?
+            .set_default("cluster.remote_cache", "")?
+            .set_default("cluster.remote_cache_push", false)?
+            .set_default("cluster.serve_remote_cache", false)?
+            .set_default("cluster.remote_cache_timeout", 500u64)?
+            .set_default("cluster.remote_cache_token", "")?
+            .set_default("warmup.models", Vec::<String>::new())?
+            .set_default("warmup.requests", Vec::<String>::new())?
+            .set_default("trace.path", "")?
+            .set_default("trace.max_size", 100 * 1024 * 1024u64)?
+            .set_default("trace.coverage_report", "")?
+            .add_source(config_file)
             .add_source(Environment::with_prefix("APP").separator("__"))
+            .set_override_option("mode", cli.mode.clone())?
+            .set_override_option("server.port", cli.port)?
+            .set_override_option("request_collection.path", cli.store_path.clone())?
+            .set_override_option("target_server.host", cli.target.clone())?
             .build()?;
 
-        let c = s.try_deserialize()?;
+        let mut c: Settings = s.try_deserialize()?;
+
+        if !c.cluster.peers.is_empty() && c.cluster.advertise_address.is_empty() {
+            anyhow::bail!(
+                "cluster.advertise_address is required when cluster.peers are configured"
+            );
+        }
+
+        if c.target_server.consensus_quorum > c.target_server.consensus_hosts.len() + 1 {
+            anyhow::bail!(
+                "target_server.consensus_quorum of {} exceeds the {} target servers",
+                c.target_server.consensus_quorum,
+                c.target_server.consensus_hosts.len() + 1
+            );
+        }
+
+        c.request_matching.normalization.validate()?;
+
+        for (model_name, path) in &c.request_matching.match_scripts {
+            c.request_matching
+                .compiled_match_scripts
+                .insert(model_name.clone(), Arc::new(MatchScript::from_file(path)?));
+        }
 
         Ok(c)
     }
 
+    /// The labels the infer entries must be recorded with to be served in Serve mode.
+    pub fn get_serve_labels(&self) -> BTreeMap<String, String> {
+        let mut labels = self.request_matching.serve_labels.clone();
+        if !self.request_matching.serve_session.is_empty() {
+            labels.insert(
+                SESSION_LABEL.to_string(),
+                self.request_matching.serve_session.clone(),
+            );
+        }
+
+        labels
+    }
+
+    /// The labels the infer entries must be recorded with to be served in Collect mode, so the
+    /// entries of other sessions are not served while recording a session.
+    pub fn get_collect_labels(&self) -> BTreeMap<String, String> {
+        match self.request_collection.session.is_empty() {
+            true => BTreeMap::new(),
+            false => BTreeMap::from([(
+                SESSION_LABEL.to_string(),
+                self.request_collection.session.clone(),
+            )]),
+        }
+    }
+
+    pub fn get_serve_as_of(&self) -> Option<i64> {
+        (self.request_matching.serve_as_of > 0).then_some(self.request_matching.serve_as_of)
+    }
+
     pub fn get_match_config(&self) -> MatchConfig {
-        return MatchConfig {
+        MatchConfig {
             match_id: self.request_matching.match_id,
             parameter_keys: if self.request_matching.parameter_matching
                 == ParameterMatching::Disable
@@ -154,6 +959,151 @@ impl Settings {
             exclude_output_parameters: self.request_matching.output_parameter_matching
                 != ParameterMatching::MatchKeys,
             match_pruned_output: self.request_matching.match_pruned_output,
-        };
+            ignored_input_tensors: self.request_matching.ignored_input_tensors.clone(),
+            input_shape_patterns: self.request_matching.input_shape_patterns.clone(),
+            ignore_batch_dimension: self.request_matching.ignore_batch_dimension,
+            ignore_model_version: self.request_matching.ignore_model_version,
+            ignore_model_version_models: self.request_matching.ignore_model_version_models.clone(),
+            float_significant_digits: self.request_matching.float_significant_digits,
+            json_input_tensors: self.request_matching.json_input_tensors.clone(),
+            match_scripts: self.request_matching.compiled_match_scripts.clone(),
+            nearest_neighbor: self.request_matching.nearest_neighbor.clone(),
+            candidate_ranking: self.request_matching.candidate_ranking.clone(),
+            sequence_matching: self.request_matching.sequence_matching,
+            hash_algorithm: self.request_matching.hash_algorithm,
+            target_name: self
+                .request_matching
+                .partition_by_target
+                .then(|| self.target_server.name.clone()),
+        }
+    }
+}
+
+/// Reload the request matching settings every time a SIGHUP is received, so match rules can be
+/// tuned without restarting the service. All other settings are only read at startup.
+pub async fn reload_on_hangup(cli: Cli, settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    while hangup.recv().await.is_some() {
+        match Settings::new(&cli) {
+            Ok(new_settings) => {
+                settings.write().await.request_matching = new_settings.request_matching;
+                info!("Reloaded request matching settings");
+            }
+            Err(err) => error!("Could not reload config, keeping current request matching: {err}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_overrides_settings_with_cli_arguments() {
+        let settings = Settings::new(&Cli {
+            mode: Some("serve".to_string()),
+            port: Some(1234),
+            store_path: Some("other_store".to_string()),
+            target: Some("http://triton:8001".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(settings.mode == ServerMode::Serve);
+        assert_eq!(settings.server.port, 1234);
+        assert_eq!(settings.request_collection.path, "other_store");
+        assert_eq!(settings.target_server.host, "http://triton:8001");
+    }
+
+    #[test]
+    fn it_redacts_secrets_when_printed() {
+        let mut settings = Settings::new(&Cli::default()).unwrap();
+        settings.server.admin_token = "admin-secret".to_string();
+        settings.cluster.remote_cache_token = "cache-secret".to_string();
+
+        let printed = serde_json::to_string_pretty(&settings).unwrap();
+        assert!(!printed.contains("admin-secret"));
+        assert!(!printed.contains("cache-secret"));
+        assert!(printed.contains("<redacted>"));
+    }
+
+    #[test]
+    fn it_serves_the_entries_of_a_session() {
+        let mut settings = Settings::new(&Cli::default()).unwrap();
+        assert!(settings.get_serve_labels().is_empty());
+
+        settings
+            .request_matching
+            .serve_labels
+            .insert("dataset".to_string(), "val".to_string());
+        settings.request_matching.serve_session = "nightly".to_string();
+        assert_eq!(
+            BTreeMap::from([
+                ("dataset".to_string(), "val".to_string()),
+                (SESSION_LABEL.to_string(), "nightly".to_string()),
+            ]),
+            settings.get_serve_labels()
+        );
+
+        assert!(settings.get_collect_labels().is_empty());
+        settings.request_collection.session = "nightly".to_string();
+        assert_eq!(
+            BTreeMap::from([(SESSION_LABEL.to_string(), "nightly".to_string())]),
+            settings.get_collect_labels()
+        );
+
+        assert_eq!(None, settings.get_serve_as_of());
+        settings.request_matching.serve_as_of = 1700000000000;
+        assert_eq!(Some(1700000000000), settings.get_serve_as_of());
+    }
+
+    #[test]
+    fn it_configures_connections() {
+        let mut settings = Settings::new(&Cli::default()).unwrap();
+        let connection = &settings.target_server.connection;
+        assert_eq!(None, connection.keepalive_interval());
+        assert_eq!(None, connection.tcp_keepalive());
+        assert!(connection.tcp_nodelay);
+        assert!(connection.endpoint("http://localhost:8001").is_ok());
+
+        settings.server.connection.keepalive_interval = 30000;
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            settings.server.connection.keepalive_interval()
+        );
+        assert!(connection.endpoint("not a uri").is_err());
+    }
+
+    #[test]
+    fn it_listens_on_host_and_port_without_listeners() {
+        let mut settings = Settings::new(&Cli::default()).unwrap();
+
+        let listeners = settings.server.listeners();
+        assert_eq!(1, listeners.len());
+        assert_eq!("0.0.0.0:50051", listeners[0].address().unwrap().to_string());
+        assert!(listeners[0].exposes(ListenerService::Admin));
+
+        settings.server.listeners = vec![
+            Listener {
+                host: "::".to_string(),
+                port: 8001,
+                services: vec![ListenerService::Inference],
+            },
+            Listener {
+                host: "127.0.0.1".to_string(),
+                port: 8002,
+                services: vec![ListenerService::Admin],
+            },
+        ];
+
+        let listeners = settings.server.listeners();
+        assert_eq!(2, listeners.len());
+        assert_eq!("[::]:8001", listeners[0].address().unwrap().to_string());
+        assert!(listeners[0].exposes(ListenerService::Inference));
+        assert!(!listeners[0].exposes(ListenerService::Admin));
+        assert!(!listeners[1].exposes(ListenerService::Inference));
     }
 }
@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind::NotFound;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::caching::lock::{self, DirLock};
+use crate::service::inference_protocol::{
+    InferStatistics, ModelStatistics, ModelStatisticsResponse, StatisticDuration,
+};
+use crate::trace::{Outcome, TraceRecord};
+
+/// The file in the cache directory with the statistics last received from the target server.
+pub const STATISTICS_FILE: &str = "model_statistics.json";
+
+/// Counts the inference requests handled by InferenceStore per model, so statistics still show
+/// traffic when it is served from the cache. Like the target server, only successful requests
+/// are counted as inferences, every request counts as a single inference.
+#[derive(Default)]
+pub struct Statistics {
+    models: Mutex<BTreeMap<(String, String), ModelStatistics>>,
+}
+
+impl Statistics {
+    /// Count a handled request.
+    pub fn record(&self, record: &TraceRecord, latency: Duration) {
+        let mut models = self.models.lock().unwrap();
+        let statistics = models
+            .entry((record.model_name.clone(), record.model_version.clone()))
+            .or_insert_with(|| ModelStatistics {
+                name: record.model_name.clone(),
+                version: record.model_version.clone(),
+                inference_stats: Some(Default::default()),
+                ..Default::default()
+            });
+        let inference_stats = statistics
+            .inference_stats
+            .get_or_insert_with(Default::default);
+        let succeeded = record.status == format!("{:?}", tonic::Code::Ok);
+
+        match succeeded {
+            true => {
+                add(&mut inference_stats.success, latency);
+                statistics.inference_count += 1;
+                statistics.execution_count += 1;
+                statistics.last_inference = record.timestamp as u64;
+            }
+            false => add(&mut inference_stats.fail, latency),
+        }
+
+        match record.outcome {
+            Outcome::Hit | Outcome::ApproximateHit | Outcome::Synthesized => {
+                add(&mut inference_stats.cache_hit, latency)
+            }
+            Outcome::Recorded | Outcome::Forwarded => add(&mut inference_stats.cache_miss, latency),
+            Outcome::Miss => {}
+        }
+    }
+
+    /// The counted statistics of a model, or of all models when the name is empty. An empty
+    /// version matches all versions.
+    pub fn model_statistics(&self, name: &str, version: &str) -> Vec<ModelStatistics> {
+        self.models
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|statistics| is_requested(statistics, name, version))
+            .cloned()
+            .collect()
+    }
+}
+
+fn add(duration: &mut Option<StatisticDuration>, latency: Duration) {
+    let duration = duration.get_or_insert_with(Default::default);
+    duration.count += 1;
+    duration.ns += latency.as_nanos() as u64;
+}
+
+fn add_duration(duration: &mut Option<StatisticDuration>, other: &Option<StatisticDuration>) {
+    if let Some(other) = other {
+        let duration = duration.get_or_insert_with(Default::default);
+        duration.count += other.count;
+        duration.ns += other.ns;
+    }
+}
+
+fn is_requested(statistics: &ModelStatistics, name: &str, version: &str) -> bool {
+    (name.is_empty() || statistics.name == name)
+        && (version.is_empty() || statistics.version == version)
+}
+
+/// The version the target server serves requests without a version from, the latest version it
+/// has statistics of.
+fn served_version(statistics: &[ModelStatistics], name: &str) -> Option<String> {
+    statistics
+        .iter()
+        .filter(|statistics| statistics.name == name)
+        .map(|statistics| &statistics.version)
+        .max_by_key(|version| (version.parse::<u64>().ok(), version.as_str()))
+        .cloned()
+}
+
+/// Add the counted statistics to the statistics of the target server. Requests without a version
+/// are counted for the version the target server serves them from.
+pub fn merge(
+    mut statistics: Vec<ModelStatistics>,
+    counted: Vec<ModelStatistics>,
+) -> Vec<ModelStatistics> {
+    for mut counted in counted {
+        if counted.version.is_empty() {
+            if let Some(version) = served_version(&statistics, &counted.name) {
+                counted.version = version;
+            }
+        }
+        let Some(existing) = statistics
+            .iter_mut()
+            .find(|s| s.name == counted.name && s.version == counted.version)
+        else {
+            statistics.push(counted);
+            continue;
+        };
+
+        existing.inference_count += counted.inference_count;
+        existing.execution_count += counted.execution_count;
+        existing.last_inference = existing.last_inference.max(counted.last_inference);
+
+        let inference_stats = existing
+            .inference_stats
+            .get_or_insert_with(InferStatistics::default);
+        if let Some(counted) = &counted.inference_stats {
+            add_duration(&mut inference_stats.success, &counted.success);
+            add_duration(&mut inference_stats.fail, &counted.fail);
+            add_duration(&mut inference_stats.cache_hit, &counted.cache_hit);
+            add_duration(&mut inference_stats.cache_miss, &counted.cache_miss);
+        }
+    }
+
+    statistics
+}
+
+/// Read the statistics of the target server stored in the cache directory, of a model or of all
+/// models when the name is empty.
+pub fn read_cached(dir: &Path, name: &str, version: &str) -> anyhow::Result<Vec<ModelStatistics>> {
+    let response: ModelStatisticsResponse = match fs::read(dir.join(STATISTICS_FILE)) {
+        Ok(content) => serde_json::from_slice(&content)?,
+        Err(err) if err.kind() == NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(response
+        .model_stats
+        .into_iter()
+        .filter(|statistics| is_requested(statistics, name, version))
+        .collect())
+}
+
+/// Store the statistics received from the target server in the cache directory, replacing the
+/// stored statistics of the same models. The directory is locked, so concurrent writers do not
+/// drop each other's statistics.
+pub fn write_cached(dir: &Path, statistics: &[ModelStatistics]) -> anyhow::Result<()> {
+    let _lock = DirLock::exclusive(dir)?;
+    let mut cached = read_cached(dir, "", "")?;
+    cached.retain(|c| {
+        !statistics
+            .iter()
+            .any(|s| s.name == c.name && s.version == c.version)
+    });
+    cached.extend(statistics.iter().cloned());
+
+    let response = ModelStatisticsResponse {
+        model_stats: cached,
+    };
+    lock::write_replace(dir.join(STATISTICS_FILE), |writer| {
+        Ok(serde_json::to_writer_pretty(writer, &response)?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn record(outcome: Outcome, code: tonic::Code) -> TraceRecord {
+        let mut record = TraceRecord::new("test", "1");
+        record.outcome = outcome;
+        record.set_status(code);
+        record
+    }
+
+    #[test]
+    fn it_counts_requests_per_model() {
+        let statistics = Statistics::default();
+        statistics.record(
+            &record(Outcome::Hit, tonic::Code::Ok),
+            Duration::from_millis(1),
+        );
+        statistics.record(
+            &record(Outcome::Recorded, tonic::Code::Ok),
+            Duration::from_millis(3),
+        );
+        statistics.record(
+            &record(Outcome::Miss, tonic::Code::NotFound),
+            Duration::from_millis(1),
+        );
+
+        let counted = statistics.model_statistics("test", "");
+        assert_eq!(1, counted.len());
+        assert_eq!(2, counted[0].inference_count);
+
+        let inference_stats = counted[0].inference_stats.clone().unwrap();
+        assert_eq!(2, inference_stats.success.unwrap().count);
+        assert_eq!(1, inference_stats.fail.unwrap().count);
+        assert_eq!(1, inference_stats.cache_hit.unwrap().count);
+        assert_eq!(3_000_000, inference_stats.cache_miss.unwrap().ns);
+        assert!(statistics.model_statistics("other", "").is_empty());
+    }
+
+    #[test]
+    fn it_merges_with_cached_statistics() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let upstream = ModelStatistics {
+            name: "test".to_string(),
+            version: "1".to_string(),
+            inference_count: 10,
+            ..Default::default()
+        };
+        write_cached(tmp_dir.path(), std::slice::from_ref(&upstream)).unwrap();
+        write_cached(tmp_dir.path(), &[upstream]).unwrap();
+
+        let statistics = Statistics::default();
+        statistics.record(
+            &record(Outcome::Hit, tonic::Code::Ok),
+            Duration::from_millis(1),
+        );
+        let mut other = record(Outcome::Hit, tonic::Code::Ok);
+        other.model_name = "other".to_string();
+        statistics.record(&other, Duration::from_millis(1));
+
+        let merged = merge(
+            read_cached(tmp_dir.path(), "", "").unwrap(),
+            statistics.model_statistics("", ""),
+        );
+
+        assert_eq!(2, merged.len());
+        assert_eq!(11, merged[0].inference_count);
+        assert_eq!(
+            1,
+            merged[0]
+                .inference_stats
+                .clone()
+                .unwrap()
+                .cache_hit
+                .unwrap()
+                .count
+        );
+        assert_eq!("other", merged[1].name);
+    }
+
+    #[test]
+    fn it_counts_requests_without_a_version_for_the_served_version() {
+        let cached = ["1", "2", "10"].map(|version| ModelStatistics {
+            name: "test".to_string(),
+            version: version.to_string(),
+            ..Default::default()
+        });
+
+        let statistics = Statistics::default();
+        statistics.record(
+            &record(Outcome::Hit, tonic::Code::Ok),
+            Duration::from_millis(1),
+        );
+        let mut unversioned = record(Outcome::Hit, tonic::Code::Ok);
+        unversioned.model_version = String::new();
+        statistics.record(&unversioned, Duration::from_millis(1));
+
+        let merged = merge(cached.to_vec(), statistics.model_statistics("", ""));
+        let counts: Vec<(&str, u64)> = merged
+            .iter()
+            .map(|s| (s.version.as_str(), s.inference_count))
+            .collect();
+        assert_eq!(vec![("1", 1), ("2", 0), ("10", 1)], counts);
+    }
+}
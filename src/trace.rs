@@ -0,0 +1,280 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+use log::{debug, warn};
+use serde::Serialize;
+
+use crate::caching::cachestore::Source;
+use crate::caching::metadata::unix_millis;
+use crate::coverage::Coverage;
+use crate::middleware::Middleware;
+use crate::statistics::Statistics;
+
+/// How a request was handled.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    // Served from the cache.
+    Hit,
+    // Served from the cache by nearest neighbor matching.
+    ApproximateHit,
+    // A synthesized response was served.
+    Synthesized,
+    // Forwarded to the target server and recorded.
+    Recorded,
+    // Forwarded to the target server without recording it.
+    Forwarded,
+    // Not served, e.g. a cache miss in Serve mode or a failing target server.
+    Miss,
+}
+
+/// A line of the trace.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct TraceRecord {
+    // The time the request was received, in milliseconds since the unix epoch.
+    pub timestamp: i64,
+    pub model_name: String,
+    pub model_version: String,
+    // The hashes of the request, see `ProcessedInput::request_hash`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_hash: Option<String>,
+    // The id the client set on the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    pub outcome: Outcome,
+    // The file name of the cache entry the response was served from, and the time it was
+    // recorded in milliseconds since the unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_recorded_at: Option<i64>,
+    pub latency_ms: f64,
+    // The grpc status code, e.g. Ok or NotFound.
+    pub status: String,
+}
+
+impl TraceRecord {
+    pub fn new(model_name: &str, model_version: &str) -> Self {
+        Self {
+            timestamp: unix_millis(SystemTime::now()),
+            model_name: model_name.to_string(),
+            model_version: model_version.to_string(),
+            request_hash: None,
+            request_id: None,
+            outcome: Outcome::Miss,
+            entry: None,
+            entry_recorded_at: None,
+            latency_ms: 0.0,
+            status: format!("{:?}", tonic::Code::Ok),
+        }
+    }
+
+    pub fn set_status(&mut self, code: tonic::Code) {
+        self.status = format!("{code:?}");
+    }
+
+    /// Record the id of the request, requests without an id are not recorded.
+    pub fn set_request_id(&mut self, id: &str) {
+        self.request_id = (!id.is_empty()).then(|| id.to_string());
+    }
+
+    /// Link the record to the cache entry the response was served from.
+    pub fn set_source(&mut self, source: &Source) {
+        self.entry = source
+            .path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string());
+        self.entry_recorded_at = source.recorded_at;
+    }
+}
+
+/// An append-only JSONL trace of all proxied inference requests, separate from the cache. The
+/// trace is rotated to `<path>.1` when it exceeds its maximum size.
+pub struct TraceLog {
+    path: PathBuf,
+    max_size: u64,
+    file: Mutex<File>,
+}
+
+impl TraceLog {
+    pub fn open(path: PathBuf, max_size: u64) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(Self {
+            path,
+            max_size,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn write(&self, record: &TraceRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if self.max_size > 0 && file.metadata()?.len() + line.len() as u64 > self.max_size {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            fs::rename(&self.path, rotated)?;
+            *file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+
+        file.write_all(&line)?;
+
+        Ok(())
+    }
+}
+
+/// Writes its record to the trace when dropped, so every way a request is handled is traced.
+pub struct TraceGuard {
+    trace: Option<Arc<TraceLog>>,
+    statistics: Option<Arc<Statistics>>,
+    coverage: Option<Arc<Coverage>>,
+    middleware: Option<Arc<dyn Middleware>>,
+    started_at: Instant,
+    pub record: TraceRecord,
+}
+
+impl TraceGuard {
+    pub fn new(trace: Option<Arc<TraceLog>>, model_name: &str, model_version: &str) -> Self {
+        Self {
+            trace,
+            statistics: None,
+            coverage: None,
+            middleware: None,
+            started_at: Instant::now(),
+            record: TraceRecord::new(model_name, model_version),
+        }
+    }
+
+    /// Also count the request in the model statistics when dropped.
+    pub fn with_statistics(mut self, statistics: Arc<Statistics>) -> Self {
+        self.statistics = Some(statistics);
+        self
+    }
+
+    /// Also count the hit or miss of the request in the coverage when dropped, if any.
+    pub fn with_coverage(mut self, coverage: Option<Arc<Coverage>>) -> Self {
+        self.coverage = coverage;
+        self
+    }
+
+    /// Also pass the record to the middleware when dropped.
+    pub fn with_middleware(mut self, middleware: Option<Arc<dyn Middleware>>) -> Self {
+        self.middleware = middleware;
+        self
+    }
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        let latency = self.started_at.elapsed();
+        if let Some(statistics) = &self.statistics {
+            statistics.record(&self.record, latency);
+        }
+        if let Some(coverage) = &self.coverage {
+            coverage.record(&self.record);
+        }
+
+        self.record.latency_ms = latency.as_secs_f64() * 1000.0;
+        if let Some(entry) = &self.record.entry {
+            debug!(
+                "Served request {} of model {} from {entry}, recorded at {}",
+                self.record.request_id.as_deref().unwrap_or("without id"),
+                self.record.model_name,
+                self.record
+                    .entry_recorded_at
+                    .map_or("an unknown time".to_string(), |t| t.to_string()),
+            );
+        }
+        if let Some(middleware) = &self.middleware {
+            middleware.on_complete(&self.record);
+        }
+
+        let trace = match &self.trace {
+            Some(trace) => trace,
+            None => return,
+        };
+
+        if let Err(err) = trace.write(&self.record) {
+            warn!("could not write to the request trace: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn it_appends_records_as_json_lines() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("trace.jsonl");
+        let trace = Arc::new(TraceLog::open(path.clone(), 0).unwrap());
+
+        {
+            let mut guard = TraceGuard::new(Some(trace.clone()), "test", "1");
+            guard.record.outcome = Outcome::Hit;
+        }
+        {
+            let mut guard = TraceGuard::new(Some(trace), "test", "1");
+            guard.record.set_status(tonic::Code::NotFound);
+        }
+
+        let lines: Vec<serde_json::Value> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(2, lines.len());
+        assert_eq!("hit", lines[0]["outcome"]);
+        assert_eq!("Ok", lines[0]["status"]);
+        assert_eq!("miss", lines[1]["outcome"]);
+        assert_eq!("NotFound", lines[1]["status"]);
+    }
+
+    #[test]
+    fn it_links_records_to_the_served_entry() {
+        let mut record = TraceRecord::new("test", "1");
+        record.set_request_id("");
+        let value = serde_json::to_value(&record).unwrap();
+        assert!(value.get("request_id").is_none());
+        assert!(value.get("entry").is_none());
+
+        record.set_request_id("request-1");
+        record.set_source(&Source {
+            path: PathBuf::from("/cache/test/1/abc.json"),
+            recorded_at: Some(1000),
+        });
+        let value = serde_json::to_value(&record).unwrap();
+        assert_eq!("request-1", value["request_id"]);
+        assert_eq!("abc.json", value["entry"]);
+        assert_eq!(1000, value["entry_recorded_at"]);
+    }
+
+    #[test]
+    fn it_rotates_by_size() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        let path = tmp_dir.path().join("trace.jsonl");
+        let trace = TraceLog::open(path.clone(), 200).unwrap();
+        let record = TraceRecord::new("test", "1");
+
+        for _ in 0..3 {
+            trace.write(&record).unwrap();
+        }
+
+        let rotated = tmp_dir.path().join("trace.jsonl.1");
+        assert!(rotated.exists());
+        assert!(fs::metadata(&path).unwrap().len() <= 200);
+        assert!(fs::metadata(&rotated).unwrap().len() <= 200);
+    }
+}
@@ -1,5 +1,4 @@
-use std::collections::{BTreeMap, HashSet};
-use std::hash::Hash;
+use std::collections::BTreeMap;
 
 /// Compare two hashmaps based on the provided keys. The `include_keys` argument determines if the
 /// keys should be included or excluded.
@@ -10,32 +9,104 @@ use std::hash::Hash;
 /// * `map2` - The second map to compare.
 /// * `keys_to_compare` - The keys that should be compared or should not be compared.
 /// * `exclude_keys` - When false the keys provided are compared, when true the keys provided are
-/// not compared.
+///   not compared.
 ///
 pub fn btreemap_compare<K, V>(
-    map1: BTreeMap<K, V>,
-    map2: BTreeMap<K, V>,
-    keys_to_compare: Vec<K>,
+    map1: &BTreeMap<K, V>,
+    map2: &BTreeMap<K, V>,
+    keys_to_compare: &[K],
     exclude_keys: bool,
 ) -> bool
 where
-    K: Eq + Hash + Ord,
+    K: Ord,
     V: PartialEq,
 {
     if exclude_keys {
-        let keys_to_compare_set: HashSet<_> = keys_to_compare.iter().collect();
-        let map1_filtered: BTreeMap<_, _> = map1
-            .iter()
-            .filter(|(key, _)| !keys_to_compare_set.contains(key))
-            .collect();
-        let map2_filtered: BTreeMap<_, _> = map2
-            .iter()
-            .filter(|(key, _)| !keys_to_compare_set.contains(key))
-            .collect();
-        map1_filtered == map2_filtered
+        // The maps are sorted by key, so the remaining entries can be compared in order.
+        let is_compared = |(key, _): &(&K, &V)| !keys_to_compare.contains(key);
+        map1.iter()
+            .filter(is_compared)
+            .eq(map2.iter().filter(is_compared))
     } else {
         keys_to_compare
             .iter()
             .all(|key| map1.get(key) == map2.get(key))
     }
 }
+
+/// Compare two tensor shapes. When a pattern is provided, both shapes need to conform to it, where
+/// a `-1` dimension in the pattern is a wildcard that matches any size.
+///
+/// # Arguments
+///
+/// * `shape1` - The first shape to compare.
+/// * `shape2` - The second shape to compare.
+/// * `pattern` - An optional shape pattern that may contain wildcard dimensions.
+/// * `ignore_batch_dimension` - When true, the first (batch) dimension is not compared.
+///
+pub fn shape_compare(
+    shape1: &[i64],
+    shape2: &[i64],
+    pattern: Option<&[i64]>,
+    ignore_batch_dimension: bool,
+) -> bool {
+    let skip = if ignore_batch_dimension { 1 } else { 0 };
+
+    if shape1.len() != shape2.len() {
+        return false;
+    }
+
+    match pattern {
+        Some(pattern) => {
+            pattern.len() == shape1.len()
+                && pattern
+                    .iter()
+                    .zip(shape1.iter().zip(shape2))
+                    .skip(skip)
+                    .all(|(p, (d1, d2))| *p == -1 || (p == d1 && p == d2))
+        }
+        None => shape1.iter().skip(skip).eq(shape2.iter().skip(skip)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_compares_shapes() {
+        assert!(shape_compare(&[1, 2, 3], &[1, 2, 3], None, false));
+        assert!(!shape_compare(&[1, 2, 3], &[2, 2, 3], None, false));
+        assert!(!shape_compare(&[1, 2, 3], &[1, 2], None, false));
+    }
+
+    #[test]
+    fn it_ignores_the_batch_dimension() {
+        assert!(shape_compare(&[1, 2, 3], &[-1, 2, 3], None, true));
+        assert!(!shape_compare(&[1, 2, 3], &[-1, 2, 4], None, true));
+    }
+
+    #[test]
+    fn it_compares_shapes_with_wildcards() {
+        let pattern: &[i64] = &[-1, 224, 224, 3];
+
+        assert!(shape_compare(
+            &[1, 224, 224, 3],
+            &[8, 224, 224, 3],
+            Some(pattern),
+            false
+        ));
+        assert!(!shape_compare(
+            &[1, 224, 224, 3],
+            &[1, 112, 112, 3],
+            Some(pattern),
+            false
+        ));
+        assert!(!shape_compare(
+            &[1, 224, 224],
+            &[1, 224, 224],
+            Some(pattern),
+            false
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::caching::cachable::Cachable;
+use crate::caching::cachable_modelconfig::CachableModelConfig;
+use crate::caching::cachable_modelinfer::{is_output_file_name, CachableModelInfer};
+use crate::caching::cachable_modelmetadata::CachableModelMetadata;
+use crate::caching::pack;
+use crate::parsing::output::check_tensor;
+
+/// A cache file that would be skipped or refused in Serve mode.
+#[derive(PartialEq, Debug)]
+pub struct Problem {
+    pub file_name: String,
+    pub message: String,
+}
+
+/// Verify every entry in a cache directory, print a report and return whether problems were found.
+pub fn run(dir: PathBuf) -> anyhow::Result<bool> {
+    let (verified, problems) = verify_dir(&dir)?;
+
+    for problem in &problems {
+        println!("{}: {}", problem.file_name, problem.message);
+    }
+    println!(
+        "Verified {verified} entries in {}, {} invalid",
+        dir.display(),
+        problems.len()
+    );
+
+    Ok(!problems.is_empty())
+}
+
+/// The sorted subdirectories of a directory, skipping hidden ones.
+fn subdirs(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .collect();
+    dirs.sort();
+
+    Ok(dirs)
+}
+
+/// The paths of the entry files and packed entries in a directory. The files that hold the
+/// outputs of infer entries are verified through their entries.
+fn entry_paths(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".inferstore"))
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths.extend(pack::entries(dir)?);
+    paths.retain(|path| !is_output_file_name(&path.file_name().unwrap().to_string_lossy()));
+
+    Ok(paths)
+}
+
+/// Verify all entry files and packed entries in the directory and its model directories. Returns
+/// the number of verified entries and the problems found, files that are not entries are skipped.
+/// Entries are reported by their path in the directory, e.g. `simple/1/<file name>` or
+/// `pack-1.inferpack/<file name>`.
+pub fn verify_dir(dir: &Path) -> anyhow::Result<(usize, Vec<Problem>)> {
+    let mut paths = entry_paths(dir)?;
+    for model_dir in subdirs(dir)? {
+        for version_dir in subdirs(&model_dir)? {
+            paths.extend(entry_paths(&version_dir)?);
+        }
+    }
+
+    let mut problems = vec![];
+    for path in &paths {
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let verified = if CachableModelInfer::matches_file_name(file_name.clone()) {
+            verify_infer(path)
+        } else if CachableModelConfig::matches_file_name(file_name.clone()) {
+            verify_entry::<CachableModelConfig>(path).map(|_| ())
+        } else if CachableModelMetadata::matches_file_name(file_name.clone()) {
+            verify_entry::<CachableModelMetadata>(path).map(|_| ())
+        } else {
+            Err(anyhow::anyhow!("unrecognized entry file name"))
+        };
+
+        if let Err(err) = verified {
+            problems.push(Problem {
+                file_name: path.strip_prefix(dir)?.to_string_lossy().to_string(),
+                message: format!("{err:#}"),
+            });
+        }
+    }
+
+    Ok((paths.len(), problems))
+}
+
+/// Parse an entry and check that the hashes in its file name match its content.
+fn verify_entry<T: Cachable>(path: &Path) -> anyhow::Result<Box<T>> {
+    let cachable = T::from_file(path)?;
+    cachable.verify()?;
+
+    Ok(cachable)
+}
+
+/// Verify an infer entry, and check that the stored tensor contents fit their shapes and
+/// datatypes.
+fn verify_infer(path: &Path) -> anyhow::Result<()> {
+    let cachable = verify_entry::<CachableModelInfer>(path)?;
+    let input = cachable.get_input()?;
+    let output = cachable.get_output()?;
+
+    if let Some(contents) = &input.raw_input_contents {
+        if contents.len() != input.inputs.len() {
+            anyhow::bail!(
+                "{} input contents stored for {} inputs",
+                contents.len(),
+                input.inputs.len()
+            );
+        }
+        for (tensor, content) in input.inputs.iter().zip(contents) {
+            check_tensor(&tensor.datatype, &tensor.shape, content)
+                .map_err(|err| err.context(format!("input {}", tensor.name)))?;
+        }
+    }
+
+    output.check_contents()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::parsing::input::tests::BASE_INFER_INPUT;
+    use crate::parsing::output::tests::BASE_INFER_OUTPUT;
+
+    #[test]
+    fn it_reports_invalid_entries() {
+        let tmp_dir = TempDir::new("inference_store_test").unwrap();
+        // The shape of the base output is [1, 2, 3] INT64 elements.
+        let mut output = BASE_INFER_OUTPUT.clone();
+        output.raw_output_contents = vec![vec![0; 48].into()];
+        let (path, _) = <CachableModelInfer as Cachable>::new(
+            tmp_dir.path(),
+            BASE_INFER_INPUT.clone(),
+            output,
+            Default::default(),
+        )
+        .unwrap();
+        fs::write(tmp_dir.path().join("pinned.json"), "[]").unwrap();
+        assert_eq!(1, verify_dir(tmp_dir.path()).unwrap().0);
+        assert!(verify_dir(tmp_dir.path()).unwrap().1.is_empty());
+
+        let content = fs::read(&path).unwrap();
+        fs::write(&path, &content[..content.len() / 2]).unwrap();
+        fs::write(tmp_dir.path().join("other.inferstore"), "{}").unwrap();
+
+        let (verified, problems) = verify_dir(tmp_dir.path()).unwrap();
+        assert_eq!(2, verified);
+        assert_eq!(
+            vec![
+                path.file_name().unwrap().to_string_lossy().to_string(),
+                "other.inferstore".to_string()
+            ],
+            problems
+                .iter()
+                .map(|problem| problem.file_name.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+}